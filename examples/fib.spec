@@ -0,0 +1,17 @@
+# Cases for `minilang run --check-spec examples/fib.min`.  The program
+# reads numbers until the end of its input, where it stops with an
+# error.
+
+case: tenth
+input: 10
+expect-output: 55
+expect-output: 9:3: Read past the end of the input
+expect-status: 1
+
+case: first three
+input: 1 2 3
+expect-output: 1
+expect-output: 1
+expect-output: 2
+expect-output: 9:3: Read past the end of the input
+expect-status: 1
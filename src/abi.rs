@@ -0,0 +1,136 @@
+// A machine-readable description of the C code of a program.
+//
+// `c --emit-abi=FILE` writes what a generator of bindings in another
+// language needs to know about the generated file, as JSON:
+//
+// - the entry point, `int main(void)`, and the functions of the
+//   program, with their C names and prototypes;
+// - the variables, with their minilang and C types.  They are locals of
+//   `main`, not C globals, which `storage` says, so that a binding does
+//   not try to link to them;
+// - the structs (records, tuples and the string type of the runtime),
+//   with the C types of their fields, in the order of the file;
+// - the runtime helpers that the file defines.  They are `static`, so
+//   two generated files can be linked together without clashes.
+//
+// The structs and helpers are read from the generated code itself, so
+// the description cannot drift from what cgen.rs emits.
+
+use ast::Program;
+use cgen;
+use json;
+use typecheck::Symtable;
+
+pub fn to_json(program: &Program, symtable: &Symtable, ident_prefix: &str, code: &str) -> String {
+    let mut out = String::new();
+    out.push_str("{\n");
+    out.push_str("  \"version\": 1,\n");
+    out.push_str("  \"target\": \"c\",\n");
+    out.push_str("  \"entry\": {\"name\": \"main\", \"signature\": \"int main(void)\"},\n");
+    let c_names = cgen::c_functions(program, ident_prefix);
+    let functions: Vec<String> = program.funcs.iter()
+        .filter_map(|func| {
+            let c_name = &c_names[&func.id];
+            prototype(code, c_name).map(|signature| {
+                format!("    {{\"name\": {}, \"c_name\": {}, \"signature\": {}}}",
+                        json::quote(&func.id), json::quote(c_name), json::quote(&signature))
+            })
+        })
+        .collect();
+    out.push_str(&format!("  \"functions\": {},\n", array(&functions)));
+
+    let globals: Vec<String> = program.decls.iter()
+        .filter_map(|decl| symtable.get(&decl.id).map(|sym| (decl, &sym.ty)))
+        .map(|(decl, ty)| format!("    {{\"name\": {}, \"type\": {}, \"c_type\": {}, \"storage\": \"main\"}}",
+                                  json::quote(&decl.id), json::quote(&ty.to_string()),
+                                  json::quote(&cgen::c_type(ty))))
+        .collect();
+    out.push_str(&format!("  \"globals\": {},\n", array(&globals)));
+
+    let structs: Vec<String> = structs(code).into_iter()
+        .map(|(name, fields)| {
+            let fields: Vec<String> = fields.into_iter()
+                .map(|(c_type, id)| format!("{{\"name\": {}, \"c_type\": {}}}", json::quote(&id), json::quote(&c_type)))
+                .collect();
+            format!("    {{\"name\": {}, \"fields\": [{}]}}", json::quote(&name), fields.join(", "))
+        })
+        .collect();
+    out.push_str(&format!("  \"structs\": {},\n", array(&structs)));
+
+    let helpers: Vec<String> = helpers(code).into_iter()
+        .map(|(name, signature)| format!("    {{\"name\": {}, \"signature\": {}}}",
+                                         json::quote(&name), json::quote(&signature)))
+        .collect();
+    out.push_str(&format!("  \"runtime\": {}\n", array(&helpers)));
+    out.push_str("}\n");
+    out
+}
+
+fn array(items: &[String]) -> String {
+    if items.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n  ]", items.join(",\n"))
+    }
+}
+
+// The struct definitions of `code`: the name (`struct NAME`) and the C
+// type and name of each field.
+fn structs(code: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut result = Vec::new();
+    let mut current: Option<(String, Vec<(String, String)>)> = None;
+    for line in code.lines().map(str::trim) {
+        if line.starts_with("struct ") && line.ends_with(" {") {
+            current = Some((line[..line.len() - 2].to_string(), Vec::new()));
+        } else if line == "};" {
+            if let Some(def) = current.take() {
+                result.push(def);
+            }
+        } else if let Some((_, ref mut fields)) = current {
+            if let Some(field) = field(line) {
+                fields.push(field);
+            }
+        }
+    }
+    result
+}
+
+// The C type and name of a field declaration such as `char *chars;`.
+fn field(line: &str) -> Option<(String, String)> {
+    if !line.ends_with(';') {
+        return None;
+    }
+    let decl = &line[..line.len() - 1];
+    let split = match decl.rfind(|c| c == ' ' || c == '*') {
+        Some(i) => i + 1,
+        None => return None,
+    };
+    Some((decl[..split].trim().to_string(), decl[split..].to_string()))
+}
+
+// The prototype of the function `name` in `code`, without the `;`.
+fn prototype(code: &str, name: &str) -> Option<String> {
+    code.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("static ") && line.ends_with(");"))
+        .find(|line| line.find('(').map_or(false, |open| {
+            line[..open].ends_with(&format!(" {}", name)) || line[..open].ends_with(&format!("*{}", name))
+        }))
+        .map(|line| line[..line.len() - 1].to_string())
+}
+
+// The functions that `code` defines besides `main`, with their
+// signatures.
+fn helpers(code: &str) -> Vec<(String, String)> {
+    code.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("static ") && line.ends_with(") {"))
+        .filter_map(|line| {
+            let signature = line[..line.len() - 2].to_string();
+            let open = signature.find('(').unwrap();
+            let start = signature[..open].rfind(|c| c == ' ' || c == '*').map_or(0, |i| i + 1);
+            let name = signature[start..open].to_string();
+            if name.is_empty() { None } else { Some((name, signature)) }
+        })
+        .collect()
+}
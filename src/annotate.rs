@@ -0,0 +1,118 @@
+// Reprint a program with its inferred types as end-of-line comments:
+//
+//     x = y + 1;   # x:float, rhs:int
+
+use std::collections::BTreeMap;
+
+use ast::*;
+use typecheck::{self, Symtable, Exprtable, Recordtable};
+
+pub fn annotate(source: &str, program: &Program, symtable: &Symtable, exprtable: &Exprtable) -> String {
+    let mut notes: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let records = typecheck::recordtable(&program.records);
+    annotate_decls(&program.decls, symtable, exprtable, &mut notes);
+    // The bodies of functions, with the types of their own variables.
+    for func in &program.funcs {
+        let locals = typecheck::locals(func, exprtable);
+        annotate_decls(&func.decls, &locals, exprtable, &mut notes);
+        annotate_stmts(&func.stmts, &locals, exprtable, &records, &mut notes);
+    }
+    annotate_stmts(&program.stmts, symtable, exprtable, &records, &mut notes);
+
+    let lines: Vec<&str> = source.lines().map(|l| l.trim_end()).collect();
+    let width = notes.keys()
+        .filter_map(|&line| lines.get(line - 1))
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        match notes.get(&(i + 1)) {
+            Some(line_notes) => {
+                out.push_str(&format!("{:<w$}   # {}\n", line, line_notes.join(", "), w = width));
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+fn annotate_decls(decls: &[Decl], symtable: &Symtable, exprtable: &Exprtable,
+                  notes: &mut BTreeMap<usize, Vec<String>>) {
+    for decl in decls {
+        if let Some(ref init) = decl.init {
+            let note = format!("{}:{}, rhs:{}", decl.id, symtable[&decl.id].ty, exprtable[&init.node_id]);
+            notes.entry(decl.pos.line).or_insert_with(Vec::new).push(note);
+        }
+    }
+}
+
+fn annotate_stmts(stmts: &[Stmt], symtable: &Symtable, exprtable: &Exprtable,
+                  records: &Recordtable, notes: &mut BTreeMap<usize, Vec<String>>) {
+    for stmt in stmts {
+        let line = stmt.pos().line;
+        let note = match *stmt {
+            Stmt::Read(ref stmt_) => {
+                let ids: Vec<String> = stmt_.ids.iter()
+                    .map(|id| format!("{}:{}", id, symtable[id].ty))
+                    .collect();
+                ids.join(", ")
+            }
+            Stmt::Print(ref stmt_) => {
+                format!("expr:{}", exprtable[&stmt_.expr.node_id])
+            }
+            Stmt::Exit(ref stmt_) => {
+                format!("expr:{}", exprtable[&stmt_.expr.node_id])
+            }
+            Stmt::Assign(ref stmt_) => {
+                format!("{}, rhs:{}", target_note(&stmt_.id, &stmt_.fields, symtable, records),
+                        exprtable[&stmt_.expr.node_id])
+            }
+            Stmt::Destructure(ref stmt_) => {
+                let targets: Vec<String> = stmt_.targets.iter()
+                    .map(|t| target_note(&t.id, &t.fields, symtable, records))
+                    .collect();
+                format!("{}, rhs:{}", targets.join(", "), exprtable[&stmt_.expr.node_id])
+            }
+            // Calls of functions without a value have no type.
+            Stmt::Call(ref stmt_) => match exprtable.get(&stmt_.expr.node_id) {
+                Some(ty) => format!("expr:{}", ty),
+                None => continue,
+            },
+            Stmt::Return(ref stmt_) => match stmt_.expr {
+                Some(ref expr) => format!("return:{}", exprtable[&expr.node_id]),
+                None => continue,
+            },
+            Stmt::If(ref stmt_) => {
+                annotate_stmts(&stmt_.then_stmts, symtable, exprtable, records, notes);
+                annotate_stmts(&stmt_.else_stmts, symtable, exprtable, records, notes);
+                format!("cond:{}", exprtable[&stmt_.expr.node_id])
+            }
+            Stmt::While(ref stmt_) => {
+                annotate_stmts(&stmt_.stmts, symtable, exprtable, records, notes);
+                format!("cond:{}", exprtable[&stmt_.expr.node_id])
+            }
+            Stmt::For(ref stmt_) => {
+                annotate_stmts(&stmt_.stmts, symtable, exprtable, records, notes);
+                format!("{}:{}", stmt_.id, symtable[&stmt_.id].ty)
+            }
+            Stmt::Error(_) => continue,
+        };
+        notes.entry(line).or_insert_with(Vec::new).push(note);
+    }
+}
+
+// "x.y:T" for an assignment to the field y of x.
+fn target_note(id: &str, fields: &[String], symtable: &Symtable, records: &Recordtable) -> String {
+    let mut target = id.to_string();
+    let mut ty = symtable[id].ty.clone();
+    for field in fields {
+        target = format!("{}.{}", target, field);
+        ty = typecheck::field_type(records, &ty, field).expect("unknown field");
+    }
+    format!("{}:{}", target, ty)
+}
@@ -5,33 +5,145 @@ use pos::Pos;
 
 #[derive(Debug)]
 pub struct Decl {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub id: String,
+    // None when the type is inferred from the initializer.
+    pub ty: Option<Type>,
+    pub init: Option<Expr>,
+}
+
+#[derive(Debug)]
+pub struct Field {
     pub pos: Pos,
     pub id: String,
     pub ty: Type,
 }
 
+#[derive(Debug)]
+pub struct RecordDecl {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub id: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug)]
+pub struct Param {
+    pub pos: Pos,
+    pub id: String,
+    pub ty: Type,
+}
+
+// `function f(a: int): int ... return e; end`.  A function sees its
+// parameters and local variables, and the other functions, but not the
+// variables of the program.
+#[derive(Debug)]
+pub struct FuncDecl {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub id: String,
+    pub params: Vec<Param>,
+    // None when the function returns no value.
+    pub ret: Option<Type>,
+    pub decls: Vec<Decl>,
+    pub stmts: Vec<Stmt>,
+}
+
 #[derive(Debug)]
 pub struct StmtRead {
     pub pos: Pos,
-    pub id: String
+    pub node_id: u64,
+    // The variables read, in order.
+    pub ids: Vec<String>,
+    // Printed without a newline before reading.
+    pub prompt: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct StmtPrint {
     pub pos: Pos,
+    pub node_id: u64,
+    pub expr: Expr,
+    // The number of decimal places of `print e : n;`.  The expression
+    // is then printed as a float.
+    pub precision: Option<usize>,
+}
+
+// Stop the program with an exit status.
+#[derive(Debug)]
+pub struct StmtExit {
+    pub pos: Pos,
+    pub node_id: u64,
     pub expr: Expr
 }
 
 #[derive(Debug)]
 pub struct StmtAssign {
     pub pos: Pos,
+    pub node_id: u64,
     pub id: String,
+    // The fields assigned to, e.g. [y, z] for `x.y.z = e;`.
+    pub fields: Vec<String>,
     pub expr: Expr
 }
 
+impl StmtAssign {
+    // `e` if the statement is `x = x + e;`, which appends `e` to `x`
+    // when `x` is a string.
+    pub fn appended(&self) -> Option<&Expr> {
+        if !self.fields.is_empty() {
+            return None;
+        }
+        match self.expr.expr {
+            Expr_::Binop(ExprBinop { op: Binop::Add, ref expr1, ref expr2 }) => {
+                match expr1.expr {
+                    Expr_::Id(ref expr_) if expr_.id == self.id => Some(expr2),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+// A variable or one of its fields, as assigned by `x.y, z = e1, e2;`.
+#[derive(Debug)]
+pub struct LValue {
+    pub id: String,
+    pub fields: Vec<String>,
+}
+
+// Assign the elements of a tuple to several targets.
+#[derive(Debug)]
+pub struct StmtDestructure {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub targets: Vec<LValue>,
+    pub expr: Expr
+}
+
+// A call for its effects, whose value, if any, is dropped.
+#[derive(Debug)]
+pub struct StmtCall {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub expr: Expr,
+}
+
+// `return e;` in a function with a return type, `return;` in one
+// without.
+#[derive(Debug)]
+pub struct StmtReturn {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub expr: Option<Expr>,
+}
+
 #[derive(Debug)]
 pub struct StmtIf {
     pub pos: Pos,
+    pub node_id: u64,
     pub expr: Expr,
     pub then_stmts: Vec<Stmt>,
     pub else_stmts: Vec<Stmt>
@@ -40,17 +152,83 @@ pub struct StmtIf {
 #[derive(Debug)]
 pub struct StmtWhile {
     pub pos: Pos,
+    pub node_id: u64,
     pub expr: Expr,
     pub stmts: Vec<Stmt>
 }
 
+// `for i = first to last do ... done` runs its body with the integer
+// variable `i` set to `first`, then one more each time, up to `last`,
+// which is evaluated once, before the loop.  The loop stops after the
+// iteration where `i` is `last` or more, so `i` is never incremented
+// past `last` and cannot overflow.
+#[derive(Debug)]
+pub struct StmtFor {
+    pub pos: Pos,
+    pub node_id: u64,
+    pub id: String,
+    pub first: Expr,
+    pub last: Expr,
+    pub stmts: Vec<Stmt>
+}
+
+// The tokens that error recovery skipped, from `pos` up to `end`, in
+// place of a statement that did not parse (see
+// `Parser::parse_program_recovering`).
+#[derive(Debug)]
+pub struct StmtError {
+    pub pos: Pos,
+    pub end: Pos,
+    pub node_id: u64,
+}
+
 #[derive(Debug)]
 pub enum Stmt {
     Read(StmtRead),
     Print(StmtPrint),
+    Exit(StmtExit),
     Assign(StmtAssign),
+    Destructure(StmtDestructure),
+    Call(StmtCall),
+    Return(StmtReturn),
     If(StmtIf),
     While(StmtWhile),
+    For(StmtFor),
+    Error(StmtError),
+}
+
+impl Stmt {
+    pub fn pos(&self) -> Pos {
+        match *self {
+            Stmt::Read(ref stmt_) => stmt_.pos,
+            Stmt::Print(ref stmt_) => stmt_.pos,
+            Stmt::Exit(ref stmt_) => stmt_.pos,
+            Stmt::Assign(ref stmt_) => stmt_.pos,
+            Stmt::Destructure(ref stmt_) => stmt_.pos,
+            Stmt::Call(ref stmt_) => stmt_.pos,
+            Stmt::Return(ref stmt_) => stmt_.pos,
+            Stmt::If(ref stmt_) => stmt_.pos,
+            Stmt::While(ref stmt_) => stmt_.pos,
+            Stmt::For(ref stmt_) => stmt_.pos,
+            Stmt::Error(ref stmt_) => stmt_.pos,
+        }
+    }
+
+    pub fn node_id(&self) -> u64 {
+        match *self {
+            Stmt::Read(ref stmt_) => stmt_.node_id,
+            Stmt::Print(ref stmt_) => stmt_.node_id,
+            Stmt::Exit(ref stmt_) => stmt_.node_id,
+            Stmt::Assign(ref stmt_) => stmt_.node_id,
+            Stmt::Destructure(ref stmt_) => stmt_.node_id,
+            Stmt::Call(ref stmt_) => stmt_.node_id,
+            Stmt::Return(ref stmt_) => stmt_.node_id,
+            Stmt::If(ref stmt_) => stmt_.node_id,
+            Stmt::While(ref stmt_) => stmt_.node_id,
+            Stmt::For(ref stmt_) => stmt_.node_id,
+            Stmt::Error(ref stmt_) => stmt_.node_id,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -59,6 +237,38 @@ pub enum Binop {
     Sub,
     Mul,
     Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Binop {
+    pub fn is_comparison(&self) -> bool {
+        match *self {
+            Binop::Add | Binop::Sub | Binop::Mul | Binop::Div => false,
+            _ => true,
+        }
+    }
+}
+
+// The operators that evaluate their second operand only if the first
+// does not decide the result.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Logic {
+    And,
+    Or,
+}
+
+impl fmt::Display for Logic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Logic::And => write!(f, "and"),
+            Logic::Or => write!(f, "or"),
+        }
+    }
 }
 
 impl fmt::Display for Binop {
@@ -68,6 +278,12 @@ impl fmt::Display for Binop {
             Binop::Sub => write!(f, "-"),
             Binop::Mul => write!(f, "*"),
             Binop::Div => write!(f, "/"),
+            Binop::Eq => write!(f, "=="),
+            Binop::Ne => write!(f, "!="),
+            Binop::Lt => write!(f, "<"),
+            Binop::Le => write!(f, "<="),
+            Binop::Gt => write!(f, ">"),
+            Binop::Ge => write!(f, ">="),
         }
     }
 }
@@ -78,14 +294,20 @@ pub struct ExprId {
     pub id: String
 }
 
+// The type of a literal is given by its suffix: 1i32 is an int32 and
+// 1.5f32 a float32.
 #[derive(Debug)]
 pub struct ExprInt {
     pub value: i64,
+    pub ty: Type,
 }
 
 #[derive(Debug)]
 pub struct ExprFloat {
     pub value: f64,
+    pub ty: Type,
+    // The literal as written, which printers show rather than `value`.
+    pub lexeme: String,
 }
 
 #[derive(Debug)]
@@ -93,11 +315,34 @@ pub struct ExprString {
     pub value: String,
 }
 
+#[derive(Debug)]
+pub struct ExprBool {
+    pub value: bool,
+}
+
 #[derive(Debug)]
 pub struct ExprNegate {
     pub expr: Box<Expr>,
 }
 
+// The text of a value, as printed.  Only produced by desugaring string
+// interpolation.
+#[derive(Debug)]
+pub struct ExprToString {
+    pub expr: Box<Expr>,
+}
+
+#[derive(Debug)]
+pub struct ExprField {
+    pub expr: Box<Expr>,
+    pub field: String,
+}
+
+#[derive(Debug)]
+pub struct ExprTuple {
+    pub exprs: Vec<Expr>,
+}
+
 #[derive(Debug)]
 pub struct ExprBinop {
     pub op: Binop,
@@ -105,26 +350,78 @@ pub struct ExprBinop {
     pub expr2: Box<Expr>,
 }
 
+#[derive(Debug)]
+pub struct ExprLogic {
+    pub op: Logic,
+    pub expr1: Box<Expr>,
+    pub expr2: Box<Expr>,
+}
+
+#[derive(Debug)]
+pub struct ExprNot {
+    pub expr: Box<Expr>,
+}
+
+#[derive(Debug)]
+pub struct ExprCall {
+    pub id: String,
+    pub args: Vec<Expr>,
+}
+
 #[derive(Debug)]
 pub enum Expr_ {
     Id(ExprId),
     Int(ExprInt),
     Float(ExprFloat),
     String(ExprString),
+    Bool(ExprBool),
     Negate(ExprNegate),
     Binop(ExprBinop),
+    Logic(ExprLogic),
+    Not(ExprNot),
+    Field(ExprField),
+    Tuple(ExprTuple),
+    Call(ExprCall),
+    ToString(ExprToString),
+    // The tokens that error recovery skipped, in place of an expression
+    // that did not parse; they span the `pos` and `end` of the `Expr`.
+    Error,
 }
 
 
 #[derive(Debug)]
 pub struct Expr {
     pub pos: Pos,
+    pub end: Pos,
     pub node_id: u64,
     pub expr: Expr_,
 }
 
+impl Expr {
+    // The operators of the chain that the expression heads, like the
+    // two `+` of `a + b + c`, from the top down their left operands,
+    // and the leftmost operand.  The parser builds a chain in a loop,
+    // so the AST of a long chain is tall; passes walk it with this
+    // rather than by recursion, so as not to exhaust the stack.
+    pub fn left_spine(&self) -> (Vec<&Expr>, &Expr) {
+        let mut spine = Vec::new();
+        let mut e = self;
+        loop {
+            match e.expr {
+                Expr_::Binop(ExprBinop { ref expr1, .. }) | Expr_::Logic(ExprLogic { ref expr1, .. }) => {
+                    spine.push(e);
+                    e = expr1;
+                }
+                _ => return (spine, e),
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Program {
+    pub records: Vec<RecordDecl>,
     pub decls: Vec<Decl>,
+    pub funcs: Vec<FuncDecl>,
     pub stmts: Vec<Stmt>,
 }
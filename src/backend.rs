@@ -0,0 +1,172 @@
+// Code generators.
+//
+// Each target language is a `Backend`, which generates code for the
+// typed program of a pass `Context`; the `codegen` pass runs one of
+// them, chosen by name with `find` (`codegen --target=NAME`).  Adding a
+// target means implementing the trait and listing the backend in
+// `backends`.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use cgen;
+use error::Error;
+use pass::Context;
+use sourcemap::Mapping;
+
+pub struct Output {
+    pub code: String,
+    pub source_map: Vec<Mapping>,
+}
+
+pub trait Backend {
+    // The name used by `codegen --target`.
+    fn name(&self) -> &'static str;
+
+    // Generate code for the typed program of `ctx`, with the settings
+    // of `ctx` that apply to the target.
+    fn generate(&self, ctx: &Context) -> Result<Output, Error>;
+
+    // Check the generated code; the message describes the problem.
+    fn verify(&self, _output: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Prepare the generated `code` to run, using `dir` for the files
+    // this needs, and return the command that runs it.
+    fn executable(&self, _code: &str, _dir: &Path) -> Result<Command, Error> {
+        Err(Error::CannotExecute(self.name()))
+    }
+}
+
+// All the backends.
+pub fn backends() -> Vec<Box<dyn Backend>> {
+    vec![Box::new(CBackend)]
+}
+
+pub fn find(name: &str) -> Result<Box<dyn Backend>, Error> {
+    let mut all = backends();
+    match all.iter().position(|b| b.name() == name) {
+        Some(i) => Ok(all.swap_remove(i)),
+        None => Err(Error::UnknownTarget(name.to_string(), all.iter().map(|b| b.name()).collect())),
+    }
+}
+
+pub struct CBackend;
+
+impl Backend for CBackend {
+    fn name(&self) -> &'static str { "c" }
+
+    fn generate(&self, ctx: &Context) -> Result<Output, Error> {
+        let options = cgen::Options {
+            overflow: ctx.overflow,
+            max_unroll: if ctx.opt_level >= 2 { ctx.max_unroll } else { 0 },
+            compact: ctx.compact,
+            inline_strings: ctx.inline_strings,
+            ident_prefix: ctx.ident_prefix.clone(),
+            temp_names: ctx.temp_names,
+            line_comments: ctx.line_comments,
+        };
+        let (code, source_map) =
+            cgen::codegen_with_source_map(ctx.program(), &ctx.symtable, &ctx.expr_table, options);
+        Ok(Output { code: code, source_map: source_map })
+    }
+
+    // The C compiler is the one named by $CC, or cc.
+    fn executable(&self, code: &str, dir: &Path) -> Result<Command, Error> {
+        let c_path = dir.join("main.c");
+        let exe_path = dir.join("main");
+        let c_name = c_path.to_string_lossy().into_owned();
+        try!(File::create(&c_path).and_then(|mut f| f.write_all(code.as_bytes()))
+             .map_err(|e| Error::Io(c_name, e)));
+        let cc = env::var("CC").unwrap_or("cc".to_string());
+        let status = try!(Command::new(&cc).arg("-o").arg(&exe_path).arg(&c_path).status()
+                          .map_err(|e| Error::Io(cc.clone(), e)));
+        if !status.success() {
+            return Err(Error::CCompilerFailed(cc));
+        }
+        Ok(Command::new(exe_path))
+    }
+
+    // Temporaries must be defined before they are used, and blocks
+    // must be closed.
+    fn verify(&self, output: &str) -> Result<(), String> {
+        let mut defined = HashSet::new();
+        let mut depth = 0i64;
+        for (i, line) in output.lines().enumerate() {
+            let code = without_strings(line);
+            for (start, tmp) in temporaries(&code) {
+                let rest = &code[start + tmp.len()..];
+                let before = &code[..start];
+                let is_definition = rest.starts_with(" = ") && !before.trim().is_empty()
+                    && !before.contains('=');
+                if is_definition {
+                    if !defined.insert(tmp.to_string()) {
+                        return Err(format!("line {}: {} is defined twice", i + 1, tmp));
+                    }
+                } else if !defined.contains(tmp) {
+                    return Err(format!("line {}: {} is used before its definition", i + 1, tmp));
+                }
+            }
+            depth += code.matches('{').count() as i64 - code.matches('}').count() as i64;
+            if depth < 0 {
+                return Err(format!("line {}: unbalanced braces", i + 1));
+            }
+        }
+        if depth != 0 {
+            return Err("unclosed block".to_string());
+        }
+        Ok(())
+    }
+}
+
+// `line` with the contents of its string and character literals
+// blanked out.
+fn without_strings(line: &str) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in line.chars() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                    out.push(c);
+                    continue;
+                }
+                out.push(' ');
+            }
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+// The temporaries (tmp_N, or tmp_LINE_N with `--temp-names=line`)
+// mentioned in `code`, with their offsets.  Those of
+// `--temp-names=short` are not checked.
+fn temporaries(code: &str) -> Vec<(usize, &str)> {
+    let bytes = code.as_bytes();
+    let mut result = Vec::new();
+    for (start, _) in code.match_indices("tmp_") {
+        let preceded = start > 0 && (bytes[start - 1].is_ascii_alphanumeric() || bytes[start - 1] == b'_');
+        let end = start + 4 + bytes[start + 4..].iter().take_while(|&&b| b.is_ascii_digit() || b == b'_').count();
+        if !preceded && end > start + 4 {
+            result.push((start, &code[start..end]));
+        }
+    }
+    result
+}
@@ -0,0 +1,128 @@
+// Throughput of the front end, for validating performance changes.
+//
+// `bench` generates a large program (the same one for a given size, so
+// that runs are comparable), then scans, parses and type checks it a
+// number of times and reports the throughput of each phase in MB of
+// source per second.  `bench --save FILE` stores the numbers as a
+// baseline and `bench --baseline FILE` compares with one, failing when
+// a phase got slower by more than the tolerance.  Baselines depend on
+// the machine, so they are meant to be saved before a change and
+// compared after it on the same machine, not committed.
+//
+//     {"scan": 412.7, "parse": 96.3, "typecheck": 151.0}
+
+use std::time::Instant;
+
+use error::Error;
+use json;
+use parser::Parser;
+use scanner;
+use typecheck::TypeChecker;
+
+pub const PHASES: [&'static str; 3] = ["scan", "parse", "typecheck"];
+
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub phase: &'static str,
+    // The bytes of source processed, over all iterations.
+    pub bytes: usize,
+    pub secs: f64,
+}
+
+impl Measurement {
+    pub fn mb_per_sec(&self) -> f64 {
+        if self.secs == 0.0 { 0.0 } else { self.bytes as f64 / self.secs / 1e6 }
+    }
+}
+
+// A well-typed program of about `blocks` times ten statements, with
+// declarations, loops, conditionals and expressions of every scalar
+// type, so that each phase does representative work.
+pub fn generate(blocks: usize) -> String {
+    let mut out = String::new();
+    out.push_str("# Generated by `minilang bench`.\n");
+    for i in 0..blocks {
+        out.push_str(&format!("var n{}: int = {};\n", i, i % 97));
+        out.push_str(&format!("var x{}: float = {}.5;\n", i, i % 13));
+        out.push_str(&format!("var s{}: string = \"item {}\";\n", i, i));
+        out.push_str(&format!("var b{}: bool = {};\n", i, i % 2 == 0));
+    }
+    for i in 0..blocks {
+        out.push_str(&format!("while n{} < {} do\n", i, 100 + i % 7));
+        out.push_str(&format!("  n{0} = n{0} + (n{0} * 3 - 1) / 2 + 1;\n", i));
+        out.push_str(&format!("  x{0} = x{0} * 1.25 - n{0} / 4;\n", i));
+        out.push_str(&format!("  if n{0} - 2 * (n{0} / 2) == 0 then\n", i));
+        out.push_str(&format!("    s{0} = s{0} + \"${{n{0}}}\";\n", i));
+        out.push_str("  else\n");
+        out.push_str(&format!("    b{0} = x{0} > {1}.0;\n", i, i % 5));
+        out.push_str("  endif\n");
+        out.push_str("done\n");
+        out.push_str(&format!("print s{} + -s{};\n", i, i));
+    }
+    out
+}
+
+// Scan, parse and type check `source` `iterations` times, timing each
+// phase on its own.
+pub fn run(source: &str, iterations: u32) -> Result<Vec<Measurement>, Error> {
+    let mut secs = [0.0; 3];
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let tokens = try!(scanner::tokenize(source));
+        secs[0] += elapsed(start);
+
+        let start = Instant::now();
+        let program = try!(Parser::new(tokens).parse_program());
+        secs[1] += elapsed(start);
+
+        let start = Instant::now();
+        try!(TypeChecker::new().tc_program(&program));
+        secs[2] += elapsed(start);
+    }
+    let bytes = source.len() * iterations as usize;
+    Ok(PHASES.iter().zip(secs.iter())
+       .map(|(&phase, &secs)| Measurement { phase: phase, bytes: bytes, secs: secs })
+       .collect())
+}
+
+fn elapsed(start: Instant) -> f64 {
+    let elapsed = start.elapsed();
+    elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+}
+
+pub fn to_json(measurements: &[Measurement]) -> String {
+    let phases: Vec<String> = measurements.iter()
+        .map(|m| format!("{}: {:.1}", json::quote(m.phase), m.mb_per_sec()))
+        .collect();
+    format!("{{{}}}\n", phases.join(", "))
+}
+
+// The throughput of each phase in a baseline saved by `to_json`.
+pub fn parse_baseline(text: &str) -> Result<Vec<(&'static str, f64)>, Error> {
+    let value = try!(json::parse(text));
+    let mut baseline = Vec::new();
+    for &phase in PHASES.iter() {
+        match value.get(phase) {
+            Some(&json::Value::Number(n)) if n > 0.0 => baseline.push((phase, n)),
+            _ => return Err(Error::InvalidBaseline(format!("expected a positive number '{}'", phase))),
+        }
+    }
+    Ok(baseline)
+}
+
+// The phases more than `tolerance` percent slower than in `baseline`,
+// with a message saying by how much.
+pub fn regressions(measurements: &[Measurement], baseline: &[(&'static str, f64)], tolerance: f64)
+                   -> Vec<String> {
+    let mut out = Vec::new();
+    for m in measurements {
+        if let Some(&(_, before)) = baseline.iter().find(|&&(phase, _)| phase == m.phase) {
+            let change = 100.0 * (m.mb_per_sec() - before) / before;
+            if change < -tolerance {
+                out.push(format!("{}: {:.1} MB/s, {:.1}% slower than the baseline ({:.1} MB/s)",
+                                 m.phase, m.mb_per_sec(), -change, before));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,629 @@
+// A compact binary encoding of the typed AST.
+//
+// Tools that keep the results of parsing and type checking around, like
+// an editor server or a build cache, can save a program with `encode`
+// and load it with `decode` much faster than compiling it again.  `ast
+// --format=bin` prints the encoding and `ast --load=FILE` the AST of an
+// encoded file.
+//
+// The encoding starts with the magic bytes "MLAST" and a version, then
+// the program and the types of its expressions (the `Exprtable`):
+//
+// - unsigned integers (lengths, node ids, positions) are LEB128
+//   varints: 7 bits per byte, low bits first, the high bit set on all
+//   bytes but the last;
+// - signed integers are zigzag-encoded varints, so that small negative
+//   numbers stay short;
+// - floats are their 8 IEEE bytes, little-endian;
+// - strings are their length and UTF-8 bytes;
+// - options, enums and booleans start with a tag byte;
+// - lists are their length and elements.
+//
+// The encoding is not meant to last: a file of another version is
+// rejected rather than converted.
+
+use std::collections::HashMap;
+
+use ast::*;
+use error::Error;
+use parser;
+use pos::Pos;
+use typecheck::Exprtable;
+use types::Type;
+
+const MAGIC: &'static [u8] = b"MLAST";
+const VERSION: u8 = 4;
+
+// The encoding of `program` and of the types of its expressions.
+pub fn encode(program: &Program, expr_table: &Exprtable) -> Vec<u8> {
+    let mut enc = Encoder { out: MAGIC.to_vec() };
+    enc.out.push(VERSION);
+    enc.list(&program.records, Encoder::record);
+    enc.list(&program.decls, Encoder::decl);
+    enc.list(&program.funcs, Encoder::func);
+    enc.list(&program.stmts, Encoder::stmt);
+    let mut types: Vec<(&u64, &Type)> = expr_table.iter().collect();
+    types.sort_by_key(|&(id, _)| *id);
+    enc.uint(types.len() as u64);
+    for (&id, ty) in types {
+        enc.uint(id);
+        enc.ty(ty);
+    }
+    enc.out
+}
+
+// The program and expression types encoded in `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<(Program, Exprtable), Error> {
+    let mut dec = Decoder { bytes: bytes, offset: 0, depth: 0 };
+    if !bytes.starts_with(MAGIC) {
+        return Err(dec.invalid("not a binary AST"));
+    }
+    dec.offset = MAGIC.len();
+    let version = try!(dec.byte());
+    if version != VERSION {
+        return Err(dec.invalid(&format!("version {} is not supported (expected {})", version, VERSION)));
+    }
+    let program = Program {
+        records: try!(dec.list(Decoder::record)),
+        decls: try!(dec.list(Decoder::decl)),
+        funcs: try!(dec.list(Decoder::func)),
+        stmts: try!(dec.list(Decoder::stmt)),
+    };
+    let mut expr_table = HashMap::new();
+    for _ in 0..try!(dec.uint()) {
+        let id = try!(dec.uint());
+        expr_table.insert(id, try!(dec.ty()));
+    }
+    if dec.offset != bytes.len() {
+        return Err(dec.invalid("unexpected bytes after the program"));
+    }
+    Ok((program, expr_table))
+}
+
+struct Encoder {
+    out: Vec<u8>,
+}
+
+impl Encoder {
+    fn uint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.out.push((n & 0x7f) as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+
+    fn int(&mut self, n: i64) {
+        self.uint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn float(&mut self, x: f64) {
+        let bits = x.to_bits();
+        for i in 0..8 {
+            self.out.push((bits >> (8 * i)) as u8);
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    fn pos(&mut self, pos: Pos) {
+        self.uint(pos.line as u64);
+        self.uint(pos.col as u64);
+    }
+
+    fn list<T, F: Fn(&mut Encoder, &T)>(&mut self, items: &[T], f: F) {
+        self.uint(items.len() as u64);
+        for item in items {
+            f(self, item);
+        }
+    }
+
+    fn option<T, F: Fn(&mut Encoder, &T)>(&mut self, item: &Option<T>, f: F) {
+        match *item {
+            Some(ref item) => {
+                self.out.push(1);
+                f(self, item);
+            }
+            None => self.out.push(0),
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match *ty {
+            Type::Int => self.out.push(0),
+            Type::Float => self.out.push(1),
+            Type::Int32 => self.out.push(2),
+            Type::Float32 => self.out.push(3),
+            Type::String => self.out.push(4),
+            Type::Bool => self.out.push(5),
+            Type::Record(ref name) => {
+                self.out.push(6);
+                self.string(name);
+            }
+            Type::Tuple(ref elements) => {
+                self.out.push(7);
+                self.list(elements, Encoder::ty);
+            }
+        }
+    }
+
+    fn record(&mut self, record: &RecordDecl) {
+        self.pos(record.pos);
+        self.uint(record.node_id);
+        self.string(&record.id);
+        self.list(&record.fields, |enc, field| {
+            enc.pos(field.pos);
+            enc.string(&field.id);
+            enc.ty(&field.ty);
+        });
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        self.pos(decl.pos);
+        self.uint(decl.node_id);
+        self.string(&decl.id);
+        self.option(&decl.ty, Encoder::ty);
+        self.option(&decl.init, Encoder::expr);
+    }
+
+    fn func(&mut self, func: &FuncDecl) {
+        self.pos(func.pos);
+        self.uint(func.node_id);
+        self.string(&func.id);
+        self.list(&func.params, |enc, param| {
+            enc.pos(param.pos);
+            enc.string(&param.id);
+            enc.ty(&param.ty);
+        });
+        self.option(&func.ret, Encoder::ty);
+        self.list(&func.decls, Encoder::decl);
+        self.list(&func.stmts, Encoder::stmt);
+    }
+
+    fn strings(&mut self, strings: &[String]) {
+        self.list(strings, |enc, s| enc.string(s));
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let tag = match *stmt {
+            Stmt::Read(_) => 0,
+            Stmt::Print(_) => 1,
+            Stmt::Exit(_) => 2,
+            Stmt::Assign(_) => 3,
+            Stmt::Destructure(_) => 4,
+            Stmt::If(_) => 5,
+            Stmt::While(_) => 6,
+            Stmt::Error(_) => 7,
+            Stmt::Call(_) => 8,
+            Stmt::Return(_) => 9,
+            Stmt::For(_) => 10,
+        };
+        self.out.push(tag);
+        self.pos(stmt.pos());
+        self.uint(stmt.node_id());
+        match *stmt {
+            Stmt::Read(ref stmt_) => {
+                self.strings(&stmt_.ids);
+                self.option(&stmt_.prompt, |enc, s| enc.string(s));
+            }
+            Stmt::Print(ref stmt_) => {
+                self.expr(&stmt_.expr);
+                self.option(&stmt_.precision, |enc, &n| enc.uint(n as u64));
+            }
+            Stmt::Exit(ref stmt_) => self.expr(&stmt_.expr),
+            Stmt::Assign(ref stmt_) => {
+                self.string(&stmt_.id);
+                self.strings(&stmt_.fields);
+                self.expr(&stmt_.expr);
+            }
+            Stmt::Destructure(ref stmt_) => {
+                self.list(&stmt_.targets, |enc, target| {
+                    enc.string(&target.id);
+                    enc.strings(&target.fields);
+                });
+                self.expr(&stmt_.expr);
+            }
+            Stmt::If(ref stmt_) => {
+                self.expr(&stmt_.expr);
+                self.list(&stmt_.then_stmts, Encoder::stmt);
+                self.list(&stmt_.else_stmts, Encoder::stmt);
+            }
+            Stmt::While(ref stmt_) => {
+                self.expr(&stmt_.expr);
+                self.list(&stmt_.stmts, Encoder::stmt);
+            }
+            Stmt::Error(ref stmt_) => self.pos(stmt_.end),
+            Stmt::Call(ref stmt_) => self.expr(&stmt_.expr),
+            Stmt::Return(ref stmt_) => self.option(&stmt_.expr, Encoder::expr),
+            Stmt::For(ref stmt_) => {
+                self.string(&stmt_.id);
+                self.expr(&stmt_.first);
+                self.expr(&stmt_.last);
+                self.list(&stmt_.stmts, Encoder::stmt);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let tag = match expr.expr {
+            Expr_::Id(_) => 0,
+            Expr_::Int(_) => 1,
+            Expr_::Float(_) => 2,
+            Expr_::String(_) => 3,
+            Expr_::Bool(_) => 4,
+            Expr_::Negate(_) => 5,
+            Expr_::Binop(_) => 6,
+            Expr_::Field(_) => 7,
+            Expr_::Tuple(_) => 8,
+            Expr_::ToString(_) => 9,
+            Expr_::Error => 10,
+            Expr_::Logic(_) => 11,
+            Expr_::Not(_) => 12,
+            Expr_::Call(_) => 13,
+        };
+        self.out.push(tag);
+        self.pos(expr.pos);
+        self.pos(expr.end);
+        self.uint(expr.node_id);
+        match expr.expr {
+            Expr_::Id(ref expr_) => self.string(&expr_.id),
+            Expr_::Int(ref expr_) => {
+                self.int(expr_.value);
+                self.ty(&expr_.ty);
+            }
+            Expr_::Float(ref expr_) => {
+                self.float(expr_.value);
+                self.ty(&expr_.ty);
+                self.string(&expr_.lexeme);
+            }
+            Expr_::String(ref expr_) => self.string(&expr_.value),
+            Expr_::Bool(ref expr_) => self.out.push(expr_.value as u8),
+            Expr_::Negate(ref expr_) => self.expr(&expr_.expr),
+            Expr_::Binop(ref expr_) => {
+                self.out.push(binop_tag(expr_.op));
+                self.expr(&expr_.expr1);
+                self.expr(&expr_.expr2);
+            }
+            Expr_::Logic(ref expr_) => {
+                self.out.push(if expr_.op == Logic::And { 0 } else { 1 });
+                self.expr(&expr_.expr1);
+                self.expr(&expr_.expr2);
+            }
+            Expr_::Not(ref expr_) => self.expr(&expr_.expr),
+            Expr_::Field(ref expr_) => {
+                self.expr(&expr_.expr);
+                self.string(&expr_.field);
+            }
+            Expr_::Tuple(ref expr_) => self.list(&expr_.exprs, Encoder::expr),
+            Expr_::ToString(ref expr_) => self.expr(&expr_.expr),
+            Expr_::Call(ref expr_) => {
+                self.string(&expr_.id);
+                self.list(&expr_.args, Encoder::expr);
+            }
+            Expr_::Error => {}
+        }
+    }
+}
+
+// The operators, by tag.
+const BINOPS: [Binop; 10] = [Binop::Add, Binop::Sub, Binop::Mul, Binop::Div, Binop::Eq,
+                             Binop::Ne, Binop::Lt, Binop::Le, Binop::Gt, Binop::Ge];
+
+fn binop_tag(op: Binop) -> u8 {
+    match op {
+        Binop::Add => 0,
+        Binop::Sub => 1,
+        Binop::Mul => 2,
+        Binop::Div => 3,
+        Binop::Eq => 4,
+        Binop::Ne => 5,
+        Binop::Lt => 6,
+        Binop::Le => 7,
+        Binop::Gt => 8,
+        Binop::Ge => 9,
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    // The number of statements and expressions being decoded, which
+    // the parser's limit bounds in a valid encoding.
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn invalid(&self, msg: &str) -> Error {
+        Error::InvalidBinaryAst(self.offset, msg.to_string())
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        match self.bytes.get(self.offset) {
+            Some(&b) => {
+                self.offset += 1;
+                Ok(b)
+            }
+            None => Err(self.invalid("unexpected end of data")),
+        }
+    }
+
+    fn tag(&mut self, count: u8) -> Result<u8, Error> {
+        let tag = try!(self.byte());
+        if tag >= count {
+            self.offset -= 1;
+            return Err(self.invalid(&format!("invalid tag {}", tag)));
+        }
+        Ok(tag)
+    }
+
+    fn uint(&mut self) -> Result<u64, Error> {
+        let mut n = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = try!(self.byte());
+            if shift > 63 || (shift == 63 && b > 1) {
+                return Err(self.invalid("integer too large"));
+            }
+            n |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        let n = try!(self.uint());
+        if n > usize::max_value() as u64 {
+            return Err(self.invalid("integer too large"));
+        }
+        Ok(n as usize)
+    }
+
+    // A precision of `print`, within the parser's limit.
+    fn precision(&mut self) -> Result<usize, Error> {
+        let n = try!(self.usize());
+        if n > parser::MAX_PRECISION {
+            return Err(self.invalid("precision too large"));
+        }
+        Ok(n)
+    }
+
+    fn int(&mut self) -> Result<i64, Error> {
+        let n = try!(self.uint());
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn float(&mut self) -> Result<f64, Error> {
+        let mut bits = 0u64;
+        for i in 0..8 {
+            bits |= (try!(self.byte()) as u64) << (8 * i);
+        }
+        Ok(f64::from_bits(bits))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = try!(self.usize());
+        if len > self.bytes.len() - self.offset {
+            return Err(self.invalid("unexpected end of data"));
+        }
+        match String::from_utf8(self.bytes[self.offset..self.offset + len].to_vec()) {
+            Ok(s) => {
+                self.offset += len;
+                Ok(s)
+            }
+            Err(_) => Err(self.invalid("invalid UTF-8 in a string")),
+        }
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        Ok(try!(self.tag(2)) == 1)
+    }
+
+    fn pos(&mut self) -> Result<Pos, Error> {
+        Ok(Pos { line: try!(self.usize()), col: try!(self.usize()) })
+    }
+
+    // A list of items.  Every item takes at least a byte, which bounds
+    // the length of a valid list.
+    fn list<T, F: Fn(&mut Decoder<'a>) -> Result<T, Error>>(&mut self, f: F) -> Result<Vec<T>, Error> {
+        let len = try!(self.usize());
+        if len > self.bytes.len() - self.offset {
+            return Err(self.invalid("unexpected end of data"));
+        }
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(try!(f(self)));
+        }
+        Ok(items)
+    }
+
+    fn option<T, F: Fn(&mut Decoder<'a>) -> Result<T, Error>>(&mut self, f: F) -> Result<Option<T>, Error> {
+        if try!(self.bool()) {
+            f(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, Error> {
+        Ok(match try!(self.tag(8)) {
+            0 => Type::Int,
+            1 => Type::Float,
+            2 => Type::Int32,
+            3 => Type::Float32,
+            4 => Type::String,
+            5 => Type::Bool,
+            6 => Type::record(&try!(self.string())),
+            _ => {
+                let elements = try!(self.list(Decoder::ty));
+                if elements.len() < 2 {
+                    return Err(self.invalid("tuple type with fewer than two elements"));
+                }
+                Type::tuple(&elements)
+            }
+        })
+    }
+
+    fn record(&mut self) -> Result<RecordDecl, Error> {
+        Ok(RecordDecl {
+            pos: try!(self.pos()),
+            node_id: try!(self.uint()),
+            id: try!(self.string()),
+            fields: try!(self.list(|dec| Ok(Field {
+                pos: try!(dec.pos()),
+                id: try!(dec.string()),
+                ty: try!(dec.ty()),
+            }))),
+        })
+    }
+
+    fn decl(&mut self) -> Result<Decl, Error> {
+        Ok(Decl {
+            pos: try!(self.pos()),
+            node_id: try!(self.uint()),
+            id: try!(self.string()),
+            ty: try!(self.option(Decoder::ty)),
+            init: try!(self.option(Decoder::expr)),
+        })
+    }
+
+    fn func(&mut self) -> Result<FuncDecl, Error> {
+        Ok(FuncDecl {
+            pos: try!(self.pos()),
+            node_id: try!(self.uint()),
+            id: try!(self.string()),
+            params: try!(self.list(|dec| Ok(Param {
+                pos: try!(dec.pos()),
+                id: try!(dec.string()),
+                ty: try!(dec.ty()),
+            }))),
+            ret: try!(self.option(Decoder::ty)),
+            decls: try!(self.list(Decoder::decl)),
+            stmts: try!(self.list(Decoder::stmt)),
+        })
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, Error> {
+        self.list(Decoder::string)
+    }
+
+    fn enter(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > parser::DEFAULT_MAX_DEPTH {
+            return Err(self.invalid("program nested too deeply"));
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, Error> {
+        try!(self.enter());
+        let stmt = try!(self.stmt_kind());
+        self.depth -= 1;
+        Ok(stmt)
+    }
+
+    fn stmt_kind(&mut self) -> Result<Stmt, Error> {
+        let tag = try!(self.tag(11));
+        let pos = try!(self.pos());
+        let node_id = try!(self.uint());
+        Ok(match tag {
+            0 => Stmt::Read(StmtRead {
+                pos: pos,
+                node_id: node_id,
+                ids: try!(self.strings()),
+                prompt: try!(self.option(Decoder::string)),
+            }),
+            1 => Stmt::Print(StmtPrint {
+                pos: pos,
+                node_id: node_id,
+                expr: try!(self.expr()),
+                precision: try!(self.option(Decoder::precision)),
+            }),
+            2 => Stmt::Exit(StmtExit { pos: pos, node_id: node_id, expr: try!(self.expr()) }),
+            3 => Stmt::Assign(StmtAssign {
+                pos: pos,
+                node_id: node_id,
+                id: try!(self.string()),
+                fields: try!(self.strings()),
+                expr: try!(self.expr()),
+            }),
+            4 => Stmt::Destructure(StmtDestructure {
+                pos: pos,
+                node_id: node_id,
+                targets: try!(self.list(|dec| Ok(LValue { id: try!(dec.string()), fields: try!(dec.strings()) }))),
+                expr: try!(self.expr()),
+            }),
+            5 => Stmt::If(StmtIf {
+                pos: pos,
+                node_id: node_id,
+                expr: try!(self.expr()),
+                then_stmts: try!(self.list(Decoder::stmt)),
+                else_stmts: try!(self.list(Decoder::stmt)),
+            }),
+            6 => Stmt::While(StmtWhile {
+                pos: pos,
+                node_id: node_id,
+                expr: try!(self.expr()),
+                stmts: try!(self.list(Decoder::stmt)),
+            }),
+            7 => Stmt::Error(StmtError { pos: pos, end: try!(self.pos()), node_id: node_id }),
+            8 => Stmt::Call(StmtCall { pos: pos, node_id: node_id, expr: try!(self.expr()) }),
+            9 => Stmt::Return(StmtReturn { pos: pos, node_id: node_id, expr: try!(self.option(Decoder::expr)) }),
+            _ => Stmt::For(StmtFor {
+                pos: pos,
+                node_id: node_id,
+                id: try!(self.string()),
+                first: try!(self.expr()),
+                last: try!(self.expr()),
+                stmts: try!(self.list(Decoder::stmt)),
+            }),
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        try!(self.enter());
+        let expr = try!(self.expr_kind());
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn expr_kind(&mut self) -> Result<Expr, Error> {
+        let tag = try!(self.tag(14));
+        let pos = try!(self.pos());
+        let end = try!(self.pos());
+        let node_id = try!(self.uint());
+        let expr = match tag {
+            0 => Expr_::Id(ExprId { id: try!(self.string()) }),
+            1 => Expr_::Int(ExprInt { value: try!(self.int()), ty: try!(self.ty()) }),
+            2 => Expr_::Float(ExprFloat {
+                value: try!(self.float()),
+                ty: try!(self.ty()),
+                lexeme: try!(self.string()),
+            }),
+            3 => Expr_::String(ExprString { value: try!(self.string()) }),
+            4 => Expr_::Bool(ExprBool { value: try!(self.bool()) }),
+            5 => Expr_::Negate(ExprNegate { expr: Box::new(try!(self.expr())) }),
+            6 => Expr_::Binop(ExprBinop {
+                op: BINOPS[try!(self.tag(BINOPS.len() as u8)) as usize],
+                expr1: Box::new(try!(self.expr())),
+                expr2: Box::new(try!(self.expr())),
+            }),
+            7 => Expr_::Field(ExprField { expr: Box::new(try!(self.expr())), field: try!(self.string()) }),
+            8 => Expr_::Tuple(ExprTuple { exprs: try!(self.list(Decoder::expr)) }),
+            9 => Expr_::ToString(ExprToString { expr: Box::new(try!(self.expr())) }),
+            11 => Expr_::Logic(ExprLogic {
+                op: if try!(self.tag(2)) == 0 { Logic::And } else { Logic::Or },
+                expr1: Box::new(try!(self.expr())),
+                expr2: Box::new(try!(self.expr())),
+            }),
+            12 => Expr_::Not(ExprNot { expr: Box::new(try!(self.expr())) }),
+            13 => Expr_::Call(ExprCall { id: try!(self.string()), args: try!(self.list(Decoder::expr)) }),
+            _ => Expr_::Error,
+        };
+        Ok(Expr { pos: pos, end: end, node_id: node_id, expr: expr })
+    }
+}
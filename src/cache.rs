@@ -0,0 +1,109 @@
+// A cache of generated code for `build`.
+//
+// The output of a backend only depends on the typed program, the
+// backend and its options, so `build` hashes those into a key and keeps
+// the output in a file named after the key.  A later build of unchanged
+// sources finds the file and skips code generation.  The key includes
+// the version of the compiler, whose code generation may change.
+//
+// The cache lives in `.minilang-cache` next to the manifest, or in
+// $MINILANG_CACHE_DIR.  Nothing is ever evicted; `cache clean` empties
+// it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use ast::Program;
+use error::Error;
+use typecheck::{self, Exprtable, Symtable};
+
+const DIR_NAME: &'static str = ".minilang-cache";
+const EXTENSION: &'static str = "out";
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    // The cache of the project whose manifest is in `base_dir`.
+    pub fn for_project(base_dir: &Path) -> Cache {
+        let dir = match env::var_os("MINILANG_CACHE_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => base_dir.join(DIR_NAME),
+        };
+        Cache { dir: dir }
+    }
+
+    // The output stored under `key`, if any.  An unreadable entry is
+    // treated as missing.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let mut buf = String::new();
+        match File::open(self.path(key)).and_then(|mut file| file.read_to_string(&mut buf)) {
+            Ok(_) => Some(buf),
+            Err(_) => None,
+        }
+    }
+
+    // Store `output` under `key`.  The entry is written to a temporary
+    // file first so that a build that is interrupted, or that runs at
+    // the same time, never sees half of it.
+    pub fn put(&self, key: &str, output: &str) -> Result<(), Error> {
+        let io_error = |path: &Path| {
+            let path = path.to_string_lossy().into_owned();
+            move |e| Error::Io(path, e)
+        };
+        try!(fs::create_dir_all(&self.dir).map_err(io_error(&self.dir)));
+        let tmp = self.dir.join(format!("{}.tmp", key));
+        try!(File::create(&tmp).and_then(|mut file| file.write_all(output.as_bytes())).map_err(io_error(&tmp)));
+        let path = self.path(key);
+        fs::rename(&tmp, &path).map_err(io_error(&path))
+    }
+
+    // Remove every entry and return how many there were.
+    pub fn clean(&self) -> Result<usize, Error> {
+        let io_error = |e| Error::Io(self.dir.to_string_lossy().into_owned(), e);
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(0),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = try!(entry.map_err(&io_error)).path();
+            if path.extension().map_or(false, |ext| ext == EXTENSION || ext == "tmp") {
+                try!(fs::remove_file(&path).map_err(&io_error));
+                removed += 1;
+            }
+        }
+        let _ = fs::remove_dir(&self.dir);
+        Ok(removed)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, EXTENSION))
+    }
+}
+
+// The key of the output of `backend` with `options` (e.g. the Debug
+// text of cgen::Options) for a typed program.
+pub fn key(program: &Program, symtable: &Symtable, expr_table: &Exprtable, backend: &str, options: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    let mut add = |text: &str| {
+        hasher.write(text.as_bytes());
+        hasher.write_u8(0);
+    };
+    add(env!("CARGO_PKG_VERSION"));
+    add(backend);
+    add(options);
+    add(&format!("{:?}", program));
+    for (id, symbol) in typecheck::symbols_in_order(symtable) {
+        add(&format!("{} {:?}", id, symbol.ty));
+    }
+    let mut types: Vec<_> = expr_table.iter().collect();
+    types.sort_by_key(|&(id, _)| *id);
+    add(&format!("{:?}", types));
+    format!("{:016x}", hasher.finish())
+}
@@ -0,0 +1,133 @@
+// The control flow graph of a program or of a function body, for the
+// analyses of dataflow.rs.
+//
+// There is one node per parameter, per declaration, per simple statement (read, print,
+// exit, assignment, destructuring) and per condition of an if or while
+// statement, plus an entry and an exit node.  A for statement has two:
+// a statement node that evaluates the bounds and sets the variable,
+// and a condition node that tests it and, after an iteration,
+// increments it.  Nodes are numbered in program order, so the
+// predecessors of a loop condition that come before it enter the loop,
+// and those that come after it are the ends of the body.
+
+use ast::*;
+
+pub const ENTRY: usize = 0;
+pub const EXIT: usize = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Entry,
+    Exit,
+    // A parameter, set on entry to a function.
+    Param(&'a Param),
+    Decl(&'a Decl),
+    Stmt(&'a Stmt),
+    // The condition of an if, while or for statement.
+    Cond(&'a Stmt),
+}
+
+#[derive(Debug)]
+pub struct Cfg<'a> {
+    pub nodes: Vec<Node<'a>>,
+    pub succs: Vec<Vec<usize>>,
+    pub preds: Vec<Vec<usize>>,
+}
+
+impl<'a> Cfg<'a> {
+    // The graph of the main body of `program`; each function has its own.
+    pub fn new(program: &'a Program) -> Self {
+        Cfg::body(&[], &program.decls, &program.stmts)
+    }
+
+    // The graph of the body of `func`, whose return statements go to
+    // the exit node.
+    pub fn function(func: &'a FuncDecl) -> Self {
+        Cfg::body(&func.params, &func.decls, &func.stmts)
+    }
+
+    fn body(params: &'a [Param], decls: &'a [Decl], stmts: &'a [Stmt]) -> Self {
+        let mut cfg = Cfg { nodes: Vec::new(), succs: Vec::new(), preds: Vec::new() };
+        cfg.add(Node::Entry);
+        cfg.add(Node::Exit);
+        let mut last = vec![ENTRY];
+        let nodes: Vec<Node<'a>> = params.iter().map(Node::Param)
+            .chain(decls.iter().map(Node::Decl))
+            .collect();
+        for node in nodes {
+            let node = cfg.add(node);
+            cfg.connect(&last, node);
+            last = vec![node];
+        }
+        let last = cfg.stmts(stmts, last);
+        cfg.connect(&last, EXIT);
+        cfg
+    }
+
+    // The node of the condition of an if or while statement, or of a
+    // simple statement.
+    pub fn node_of(&self, node_id: u64) -> Option<usize> {
+        self.nodes.iter().position(|node| match *node {
+            Node::Stmt(stmt) | Node::Cond(stmt) => stmt.node_id() == node_id,
+            _ => false,
+        })
+    }
+
+    fn add(&mut self, node: Node<'a>) -> usize {
+        self.nodes.push(node);
+        self.succs.push(Vec::new());
+        self.preds.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    fn connect(&mut self, from: &[usize], to: usize) {
+        for &node in from {
+            self.succs[node].push(to);
+            self.preds[to].push(node);
+        }
+    }
+
+    // Add the nodes of `stmts`, which follow the nodes `last`, and
+    // return the nodes that the following statement follows.
+    fn stmts(&mut self, stmts: &'a [Stmt], mut last: Vec<usize>) -> Vec<usize> {
+        for stmt in stmts {
+            last = match *stmt {
+                Stmt::If(ref stmt_) => {
+                    let cond = self.add(Node::Cond(stmt));
+                    self.connect(&last, cond);
+                    let mut out = self.stmts(&stmt_.then_stmts, vec![cond]);
+                    out.extend(self.stmts(&stmt_.else_stmts, vec![cond]));
+                    out
+                }
+                Stmt::While(ref stmt_) => {
+                    let cond = self.add(Node::Cond(stmt));
+                    self.connect(&last, cond);
+                    let body = self.stmts(&stmt_.stmts, vec![cond]);
+                    self.connect(&body, cond);
+                    vec![cond]
+                }
+                Stmt::For(ref stmt_) => {
+                    let init = self.add(Node::Stmt(stmt));
+                    self.connect(&last, init);
+                    let cond = self.add(Node::Cond(stmt));
+                    self.connect(&[init], cond);
+                    let body = self.stmts(&stmt_.stmts, vec![cond]);
+                    self.connect(&body, cond);
+                    vec![cond]
+                }
+                Stmt::Exit(_) | Stmt::Return(_) => {
+                    let node = self.add(Node::Stmt(stmt));
+                    self.connect(&last, node);
+                    self.connect(&[node], EXIT);
+                    Vec::new()
+                }
+                _ => {
+                    let node = self.add(Node::Stmt(stmt));
+                    self.connect(&last, node);
+                    vec![node]
+                }
+            };
+        }
+        last
+    }
+}
@@ -1,117 +1,1230 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
 use ast::*;
+use emitter::Emitter;
+use ice;
+use interp::Value;
+use pos::Pos;
+use query::Node;
+use sourcemap::Mapping;
 use types::Type;
-use typecheck::{Symtable, Exprtable};
+use typecheck::{self, Symtable, Exprtable, Recordtable};
+use unroll;
 
 pub struct Generator<'a> {
     tmp_counter: i32,
     symtable: &'a Symtable,
     exprtable: &'a Exprtable,
+    records: Recordtable,
+    // The tuple types whose struct has been emitted.
+    tuples: Vec<Type>,
+    emitter: Emitter,
+    // The declaration or statement being generated.
+    origin: Option<(u64, Pos)>,
+    mappings: Vec<Mapping>,
+    options: Options,
+    // The constants known when each while loop is entered, by node id;
+    // computed only when loops are unrolled.
+    loop_constants: HashMap<u64, HashMap<String, Value>>,
+    // The C name of each function.
+    functions: HashMap<String, String>,
+    // The parameter types of each function.
+    params: HashMap<String, Vec<Type>>,
+    // The parameters and locals of the function being generated, if
+    // any; the other variables are in `symtable`.
+    locals: Option<Symtable>,
+    // The return type of the function being generated, if it has one.
+    ret: Option<Type>,
+    // The variables of `main` or of the function being generated, in
+    // declaration order.
+    variables: Vec<(String, Type)>,
+    // The temporaries of the current statement that hold references to
+    // strings, released at the end of the statement.
+    owned: Vec<(String, Type)>,
+    // The C variable of each string literal, unless literals are
+    // inlined.
+    literals: HashMap<String, String>,
+    // The number of temporaries of each source line, for
+    // `TempNames::Line`.
+    line_temps: HashMap<usize, usize>,
+}
+
+// Settings of the generated code.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub overflow: Overflow,
+    // Counting loops that run at most this many times are unrolled
+    // (see unroll.rs); 0 disables unrolling.
+    pub max_unroll: usize,
+    // No indentation and no blank lines.
+    pub compact: bool,
+    // Emit each string literal where it is used, rather than each
+    // distinct one once before `main`.
+    pub inline_strings: bool,
+    // Prepended to the C names of variables, e.g. `v_`, so that they
+    // cannot clash with C keywords, the runtime or the temporaries.
+    pub ident_prefix: String,
+    pub temp_names: TempNames,
+    // Emit a `/* line N */` comment above the code of each declaration
+    // and statement.
+    pub line_comments: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            overflow: Overflow::Wrap,
+            max_unroll: 0,
+            compact: false,
+            inline_strings: false,
+            ident_prefix: String::new(),
+            temp_names: TempNames::Numbered,
+            line_comments: false,
+        }
+    }
+}
+
+// How temporaries are named.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TempNames {
+    // `tmp_1`, `tmp_2`, ... in the order of the file.
+    Numbered,
+    // `t1`, `t2`, ...
+    Short,
+    // `tmp_12_1`, `tmp_12_2`, ... for the temporaries of source line 12.
+    Line,
+}
+
+impl TempNames {
+    pub fn from_name(name: &str) -> Option<TempNames> {
+        match name {
+            "numbered" => Some(TempNames::Numbered),
+            "short" => Some(TempNames::Short),
+            "line" => Some(TempNames::Line),
+            _ => None,
+        }
+    }
+}
+
+// What integer arithmetic does when its result does not fit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    // Wrap around, like two's complement hardware.
+    Wrap,
+    // Abort with the source position, like the interpreter.
+    Checked,
+}
+
+impl Overflow {
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        match name {
+            "wrap" => Some(Overflow::Wrap),
+            "checked" => Some(Overflow::Checked),
+            _ => None,
+        }
+    }
+}
+
+// Helpers used by programs that manipulate strings.  Strings are
+// immutable and reference counted: a variable or temporary holds a
+// reference, `mini_assign` retains the new value of a variable before
+// releasing the old one, and a string is freed with its last reference.
+// Literals have a negative count and are never freed.  `mini_append`
+// grows a string that nothing else refers to in place, doubling its
+// capacity, so that `s = s + piece;` in a loop takes linear time.
+const STRING_RUNTIME: &'static str = r#"
+struct mini_string {
+    long refs;
+    size_t len;
+    size_t cap;
+    char *chars;
+};
+
+static struct mini_string mini_empty = {-1, 0, 0, ""};
+static struct mini_string mini_true = {-1, 4, 4, "true"};
+static struct mini_string mini_false = {-1, 5, 5, "false"};
+
+static struct mini_string *mini_new(size_t n) {
+    struct mini_string *s = malloc(sizeof(struct mini_string) + n + 1);
+    s->refs = 1;
+    s->len = n;
+    s->cap = n;
+    s->chars = (char *) (s + 1);
+    s->chars[n] = '\0';
+    return s;
+}
+
+static struct mini_string *mini_retain(struct mini_string *s) {
+    if (s->refs > 0) {
+        s->refs++;
+    }
+    return s;
+}
+
+static void mini_release(struct mini_string *s) {
+    if (s != NULL && s->refs > 0 && --s->refs == 0) {
+        free(s);
+    }
+}
+
+static void mini_assign(struct mini_string **target, struct mini_string *s) {
+    mini_retain(s);
+    mini_release(*target);
+    *target = s;
+}
+
+static struct mini_string *mini_concat(struct mini_string *a, struct mini_string *b) {
+    struct mini_string *s = mini_new(a->len + b->len);
+    memcpy(s->chars, a->chars, a->len);
+    memcpy(s->chars + a->len, b->chars, b->len);
+    return s;
+}
+
+static void mini_append(struct mini_string **target, struct mini_string *b) {
+    struct mini_string *s = *target;
+    size_t n = b->len;
+    if (s->refs != 1) {
+        *target = mini_concat(s, b);
+        mini_release(s);
+        return;
+    }
+    if (s->len + n > s->cap) {
+        int self = b == s;
+        s->cap = 2 * (s->len + n);
+        s = realloc(s, sizeof(struct mini_string) + s->cap + 1);
+        s->chars = (char *) (s + 1);
+        if (self) {
+            b = s;
+        }
+    }
+    memcpy(s->chars + s->len, b->chars, n);
+    s->len += n;
+    s->chars[s->len] = '\0';
+    *target = s;
+}
+
+static struct mini_string *mini_reverse(struct mini_string *a) {
+    size_t n = a->len;
+    struct mini_string *s = mini_new(n);
+    size_t i;
+    for (i = 0; i < n; i++) {
+        s->chars[i] = a->chars[n - 1 - i];
+    }
+    return s;
+}
+
+static int mini_compare(struct mini_string *a, struct mini_string *b) {
+    int c = memcmp(a->chars, b->chars, a->len < b->len ? a->len : b->len);
+    if (c != 0) {
+        return c;
+    }
+    return (a->len > b->len) - (a->len < b->len);
+}
+
+static struct mini_string *mini_int_to_string(long long n) {
+    struct mini_string *s = mini_new(snprintf(NULL, 0, "%lld", n));
+    sprintf(s->chars, "%lld", n);
+    return s;
+}
+
+static struct mini_string *mini_float_to_string(double x) {
+    struct mini_string *s = mini_new(snprintf(NULL, 0, "%f", x));
+    sprintf(s->chars, "%f", x);
+    return s;
+}
+
+static struct mini_string *mini_read_string(void) {
+    char buf[1024];
+    struct mini_string *s;
+    if (scanf("%1023s", buf) != 1) {
+        buf[0] = '\0';
+    }
+    s = mini_new(strlen(buf));
+    strcpy(s->chars, buf);
+    return s;
+}
+"#;
+
+// Used by programs that read bool variables: true and false are read
+// as words.
+const BOOL_RUNTIME: &'static str = r#"
+static int mini_read_bool(void) {
+    char buf[8];
+    if (scanf("%7s", buf) != 1) {
+        return 0;
+    }
+    return strcmp(buf, "true") == 0;
+}
+"#;
+
+// Used by programs compiled with checked overflow: the report matches
+// the interpreter's error messages.
+const CHECKED_RUNTIME: &'static str = r#"
+static void mini_arith_error(const char *pos, const char *message) {
+    printf("%s: %s\n", pos, message);
+    exit(1);
+}
+"#;
+
+// The checked arithmetic helpers for one integer type; TYPE and SUFFIX
+// are replaced by the C type and the suffix of the helper names.
+const CHECKED_HELPERS: &'static str = r#"
+static TYPE mini_add_SUFFIX(TYPE a, TYPE b, const char *pos) {
+    TYPE r;
+    if (__builtin_add_overflow(a, b, &r)) mini_arith_error(pos, "Integer overflow");
+    return r;
+}
+static TYPE mini_sub_SUFFIX(TYPE a, TYPE b, const char *pos) {
+    TYPE r;
+    if (__builtin_sub_overflow(a, b, &r)) mini_arith_error(pos, "Integer overflow");
+    return r;
+}
+static TYPE mini_mul_SUFFIX(TYPE a, TYPE b, const char *pos) {
+    TYPE r;
+    if (__builtin_mul_overflow(a, b, &r)) mini_arith_error(pos, "Integer overflow");
+    return r;
+}
+static TYPE mini_neg_SUFFIX(TYPE a, const char *pos) {
+    TYPE r;
+    if (__builtin_sub_overflow((TYPE) 0, a, &r)) mini_arith_error(pos, "Integer overflow");
+    return r;
+}
+static TYPE mini_div_SUFFIX(TYPE a, TYPE b, const char *pos) {
+    if (b == 0) mini_arith_error(pos, "Division by zero");
+    if (b == -1) return mini_neg_SUFFIX(a, pos);
+    return a / b;
+}
+"#;
+
+pub fn codegen(program: &Program, symtable: &Symtable, exprtable: &Exprtable) -> String {
+    codegen_with_source_map(program, symtable, exprtable, Options::default()).0
 }
 
-pub fn codegen(program: &Program, symtable: &Symtable, exprtable: &Exprtable) {
+// Generate C code along with the source map of its lines.
+pub fn codegen_with_source_map(program: &Program, symtable: &Symtable, exprtable: &Exprtable,
+                               options: Options) -> (String, Vec<Mapping>) {
+    let functions = c_functions(program, &options.ident_prefix);
     let mut generator = Generator {
         tmp_counter: 0,
         symtable: symtable,
         exprtable: exprtable,
+        records: typecheck::recordtable(&program.records),
+        tuples: Vec::new(),
+        emitter: Emitter::new(options.compact),
+        origin: None,
+        mappings: Vec::new(),
+        options: options,
+        loop_constants: HashMap::new(),
+        functions: functions,
+        params: program.funcs.iter()
+            .map(|func| (func.id.clone(), func.params.iter().map(|param| param.ty.clone()).collect()))
+            .collect(),
+        locals: None,
+        ret: None,
+        variables: Vec::new(),
+        owned: Vec::new(),
+        literals: HashMap::new(),
+        line_temps: HashMap::new(),
     };
     generator.codegen_program(program);
+    (generator.emitter.finish(), generator.mappings)
+}
+
+// The C names of the functions of `program`, whose variables are named
+// with `ident_prefix`: `fn_` and the name of the function, with
+// underscores appended while that is the C name of a variable (e.g. a
+// variable `fn_f` next to a function `f`) or of another function.  The
+// temporaries and runtime helpers never start with `fn_`.
+pub fn c_functions(program: &Program, ident_prefix: &str) -> HashMap<String, String> {
+    let mut taken: HashSet<String> = program.decls.iter().map(|decl| &decl.id)
+        .chain(program.funcs.iter().flat_map(|func| {
+            func.params.iter().map(|param| &param.id).chain(func.decls.iter().map(|decl| &decl.id))
+        }))
+        .map(|id| format!("{}{}", ident_prefix, id))
+        .collect();
+    let natural: HashSet<String> = program.funcs.iter().map(|func| format!("fn_{}", func.id)).collect();
+    let mut names = HashMap::new();
+    for func in &program.funcs {
+        let mut name = format!("fn_{}", func.id);
+        if taken.contains(&name) {
+            while taken.contains(&name) || natural.contains(&name) {
+                name.push('_');
+            }
+        }
+        taken.insert(name.clone());
+        names.insert(func.id.clone(), name);
+    }
+    names
+}
+
+pub fn c_type(ty: &Type) -> String {
+    match *ty {
+        Type::Int => "long long".to_string(),
+        Type::Float => "double".to_string(),
+        Type::Int32 => "int".to_string(),
+        Type::Float32 => "float".to_string(),
+        Type::String => "struct mini_string *".to_string(),
+        Type::Bool => "int".to_string(),
+        Type::Record(ref name) => format!("struct {}", name),
+        Type::Tuple(_) => format!("struct mini_tuple_{}", mangle(ty)),
+    }
+}
+
+// A tuple type is a struct with fields f0, f1, ... whose name encodes
+// the element types, so that equal tuple types share a struct.
+fn mangle(ty: &Type) -> String {
+    match *ty {
+        Type::Int => "i".to_string(),
+        Type::Float => "f".to_string(),
+        Type::Int32 => "i32".to_string(),
+        Type::Float32 => "f32".to_string(),
+        Type::String => "s".to_string(),
+        Type::Bool => "b".to_string(),
+        Type::Record(ref name) => format!("R{}{}", name.len(), name),
+        Type::Tuple(ref elements) => {
+            let inner: Vec<String> = elements.iter().map(mangle).collect();
+            format!("T{}E", inner.concat())
+        }
+    }
+}
+
+fn printf_format(ty: &Type) -> &'static str {
+    match *ty {
+        Type::Int => "%lld",
+        Type::Float | Type::Float32 => "%f",
+        Type::Int32 => "%d",
+        Type::String | Type::Bool => "%s",
+        Type::Record(_) | Type::Tuple(_) => unreachable!(),
+    }
+}
+
+// The suffix of the checked arithmetic helpers for an integer type.
+fn helper_suffix(ty: &Type) -> &'static str {
+    match *ty {
+        Type::Int32 => "i",
+        _ => "ll",
+    }
+}
+
+// The C expression for the variable `id` or one of its fields.
+fn c_lvalue(id: &str, fields: &[String]) -> String {
+    let mut target = id.to_string();
+    for field in fields {
+        target.push('.');
+        target.push_str(field);
+    }
+    target
+}
+
+// Return `s` as a C string literal.
+fn c_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '?' => out.push_str("\\?"),
+            // Three digits, so that a digit after it is not part of
+            // the escape.
+            '\0' => out.push_str("\\000"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
 
 impl<'a> Generator<'a> {
+    fn emit(&mut self, line: &str) {
+        let lineno = self.emitter.line(line);
+        if line.trim().is_empty() {
+            return;
+        }
+        if let Some((node_id, pos)) = self.origin {
+            self.mappings.push(Mapping { line: lineno, node_id: node_id, pos: pos });
+        }
+    }
+
     fn codegen_program(&mut self, program: &Program) {
-        println!("#include <stdio.h>");
-        println!("int main(void) {{");
+        let locals: Vec<Symtable> = program.funcs.iter().map(|func| typecheck::locals(func, self.exprtable)).collect();
+        let variables: Vec<Type> = self.symtable.values().chain(locals.iter().flat_map(|l| l.values()))
+            .map(|sym| sym.ty.clone())
+            .chain(program.funcs.iter().filter_map(|func| func.ret.clone()))
+            .collect();
+        let uses_strings = variables.iter().any(|ty| !self.strings("", ty).is_empty())
+            || self.exprtable.values().any(|ty| *ty == Type::String);
+        let uses_bools = variables.iter().any(|ty| *ty == Type::Bool);
+
+        let checked = self.options.overflow == Overflow::Checked;
 
-        self.codegen_decls();
+        self.emit("#include <stdio.h>");
+        if uses_strings || uses_bools || checked {
+            self.emit("#include <stdlib.h>");
+        }
+        if uses_strings || uses_bools {
+            self.emit("#include <string.h>");
+        }
+        if checked {
+            for line in CHECKED_RUNTIME.lines() {
+                self.emit(line);
+            }
+            for ty in &[Type::Int, Type::Int32] {
+                let helpers = CHECKED_HELPERS.replace("TYPE", &c_type(ty))
+                    .replace("SUFFIX", helper_suffix(ty));
+                for line in helpers.lines() {
+                    self.emit(line);
+                }
+            }
+        }
+        if uses_strings {
+            for line in STRING_RUNTIME.lines() {
+                self.emit(line);
+            }
+        }
+        if uses_bools {
+            for line in BOOL_RUNTIME.lines() {
+                self.emit(line);
+            }
+        }
+        if self.options.max_unroll > 0 {
+            self.loop_constants = unroll::loop_constants(program);
+        }
+        self.codegen_records(&program.records);
+        self.codegen_tuples(program, &locals);
+        if !self.options.inline_strings {
+            self.codegen_literals(program);
+        }
+        self.codegen_functions(&program.funcs, locals);
+        self.emit("");
+        self.emit("int main(void) {");
+
+        self.codegen_decls(&program.decls);
+        self.emitter.blank();
         self.codegen_stmts(&program.stmts);
 
-        println!("}}");
+        self.release_variables();
+        self.emit("return 0;");
+        self.emit("}");
     }
 
-    fn codegen_decls(&mut self) {
-        for (id, ty) in self.symtable {
-            match *ty {
-                Type::Int => { println!("int {};", id); }
-                Type::Float => { println!("float {};", id); }
+    fn codegen_records(&mut self, records: &[RecordDecl]) {
+        for record in records {
+            self.origin = Some((record.node_id, record.pos));
+            for field in &record.fields {
+                self.define_tuple(&field.ty);
+            }
+            self.emit("");
+            self.emit(&format!("struct {} {{", record.id));
+            for field in &record.fields {
+                self.emit(&format!("{} {};", c_type(&field.ty), field.id));
+            }
+            self.emit("};");
+        }
+        self.origin = None;
+    }
+
+    // Emit each distinct string literal of the program once, in the
+    // order where they first appear.  Literals are never freed or
+    // modified (their count of references is -1), so the uses of the
+    // same text can share one.
+    fn codegen_literals(&mut self, program: &Program) {
+        let mut values = Vec::new();
+        let mut nodes: Vec<Node> = program.decls.iter().map(Node::Decl)
+            .chain(program.funcs.iter().map(Node::Func))
+            .chain(program.stmts.iter().map(Node::Stmt))
+            .collect();
+        nodes.reverse();
+        while let Some(node) = nodes.pop() {
+            if let Node::Expr(&Expr { expr: Expr_::String(ref expr_), .. }) = node {
+                if !values.contains(&&expr_.value) {
+                    values.push(&expr_.value);
+                }
+            }
+            let mut children = node.children();
+            children.reverse();
+            nodes.extend(children);
+        }
+        if !values.is_empty() {
+            self.emit("");
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            let literal = format!("lit_{}", i + 1);
+            self.emit(&format!("static struct mini_string {} = {{-1, {}, {}, {}}};",
+                               literal, value.len(), value.len(), c_string(value)));
+            self.literals.insert(value.clone(), literal);
+        }
+    }
+
+    // Emit the structs of the tuple types of the variables, functions
+    // and expressions, in the order where they first appear.
+    fn codegen_tuples(&mut self, program: &Program, locals: &[Symtable]) {
+        let symtable = self.symtable;
+        for decl in &program.decls {
+            self.define_tuple(&symtable[&decl.id].ty);
+        }
+        for (func, locals) in program.funcs.iter().zip(locals) {
+            for param in &func.params {
+                self.define_tuple(&param.ty);
+            }
+            if let Some(ref ret) = func.ret {
+                self.define_tuple(ret);
+            }
+            for decl in &func.decls {
+                self.define_tuple(&locals[&decl.id].ty);
+            }
+        }
+        let exprtable = self.exprtable;
+        let mut exprs: Vec<(&u64, &Type)> = exprtable.iter().collect();
+        exprs.sort_by_key(|&(node_id, _)| *node_id);
+        for (_, ty) in exprs {
+            self.define_tuple(ty);
+        }
+    }
+
+    // Emit the struct of `ty` if it is a tuple type, after those of its
+    // elements.
+    fn define_tuple(&mut self, ty: &Type) {
+        if let Type::Tuple(ref elements) = *ty {
+            if self.tuples.contains(ty) {
+                return;
+            }
+            for element in elements.iter() {
+                self.define_tuple(element);
+            }
+            self.tuples.push(ty.clone());
+            self.emit("");
+            self.emit(&format!("{} {{", c_type(ty)));
+            for (i, element) in elements.iter().enumerate() {
+                self.emit(&format!("{} f{};", c_type(element), i));
+            }
+            self.emit("};");
+        }
+    }
+
+    // Every function is declared before the definitions, so that they
+    // can call each other in any order, and themselves.  Functions only
+    // see their parameters and locals, which are released when they
+    // return; string parameters are retained on entry, since they can
+    // be assigned.
+    fn codegen_functions(&mut self, funcs: &[FuncDecl], locals: Vec<Symtable>) {
+        if funcs.is_empty() {
+            return;
+        }
+        self.emit("");
+        for func in funcs {
+            self.origin = Some((func.node_id, func.pos));
+            let line = format!("{};", self.signature(func));
+            self.emit(&line);
+        }
+        for (func, locals) in funcs.iter().zip(locals) {
+            ice::at(func.pos);
+            self.origin = Some((func.node_id, func.pos));
+            self.emit("");
+            self.line_comment(func.pos);
+            let line = format!("{} {{", self.signature(func));
+            self.emit(&line);
+            self.locals = Some(locals);
+            self.ret = func.ret.clone();
+            let mut variables = Vec::new();
+            for param in &func.params {
+                let var = self.var(&param.id);
+                for string in self.strings(&var, &param.ty) {
+                    self.emit(&format!("mini_retain({});", string));
+                }
+                variables.push((var, param.ty.clone()));
+            }
+            let globals = mem::replace(&mut self.variables, variables);
+            self.codegen_decls(&func.decls);
+            if !func.decls.is_empty() {
+                self.emitter.blank();
+            }
+            self.codegen_stmts(&func.stmts);
+            // A function with a return type always ends with `return`.
+            if func.ret.is_none() {
+                self.origin = Some((func.node_id, func.pos));
+                self.release_variables();
+            }
+            self.emit("}");
+            self.variables = globals;
+            self.locals = None;
+            self.ret = None;
+        }
+        self.origin = None;
+    }
+
+    fn signature(&self, func: &FuncDecl) -> String {
+        let params: Vec<String> = func.params.iter()
+            .map(|param| format!("{} {}", c_type(&param.ty), self.var(&param.id)))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let ret = func.ret.as_ref().map_or("void".to_string(), c_type);
+        format!("{} {}({})", ret, self.functions[&func.id], params)
+    }
+
+    // The type of the variable `id` where code is being generated.
+    fn var_type(&self, id: &str) -> Type {
+        match self.locals {
+            Some(ref locals) => locals[id].ty.clone(),
+            None => self.symtable[id].ty.clone(),
+        }
+    }
+
+    // Declare the C variable `var` with the zero value of `ty`.
+    fn declare(&mut self, var: &str, ty: &Type) {
+        let zero = match *ty {
+            Type::Int | Type::Int32 => "0",
+            Type::Float | Type::Float32 => "0.0",
+            Type::String => "&mini_empty",
+            Type::Bool => "0",
+            Type::Record(_) | Type::Tuple(_) => "{0}",
+        };
+        let line = format!("{} {} = {};", c_type(ty), var, zero);
+        self.emit(&line);
+        self.init_string_fields(var, ty);
+    }
+
+    fn codegen_decls(&mut self, decls: &[Decl]) {
+        for decl in decls {
+            self.origin = Some((decl.node_id, decl.pos));
+            ice::at(decl.pos);
+            self.line_comment(decl.pos);
+            let ty = self.var_type(&decl.id);
+            let var = self.var(&decl.id);
+            self.declare(&var, &ty);
+            self.variables.push((var, ty));
+            if let Some(ref init) = decl.init {
+                let tmp = self.codegen_expr(init);
+                let init_ty = &self.exprtable[&init.node_id];
+                self.codegen_assign(&decl.id, &[], tmp, init_ty);
+                self.release_temps();
+            }
+        }
+        self.origin = None;
+    }
+
+    // `{0}` leaves the string fields of records and tuples NULL; make
+    // them empty.
+    fn init_string_fields(&mut self, lvalue: &str, ty: &Type) {
+        if *ty == Type::String {
+            return;
+        }
+        for field in self.strings(lvalue, ty) {
+            self.emit(&format!("{} = &mini_empty;", field));
+        }
+    }
+
+    // The strings held by a value of type `ty` in `lvalue`: itself, or
+    // its fields or elements that are strings, recursively.
+    fn strings(&self, lvalue: &str, ty: &Type) -> Vec<String> {
+        let fields: Vec<(String, Type)> = match *ty {
+            Type::String => return vec![lvalue.to_string()],
+            Type::Record(ref name) => self.records[&name[..]].clone(),
+            Type::Tuple(ref elements) => {
+                elements.iter().enumerate().map(|(i, t)| (format!("f{}", i), t.clone())).collect()
+            }
+            _ => return Vec::new(),
+        };
+        let mut strings = Vec::new();
+        for (id, field_ty) in fields {
+            strings.extend(self.strings(&format!("{}.{}", lvalue, id), &field_ty));
+        }
+        strings
+    }
+
+    // Release the string temporaries of the current statement.
+    fn release_temps(&mut self) {
+        let owned: Vec<(String, Type)> = self.owned.drain(..).collect();
+        for (tmp, ty) in owned {
+            for string in self.strings(&tmp, &ty) {
+                self.emit(&format!("mini_release({});", string));
+            }
+        }
+    }
+
+    // Release the strings of the variables when the program or the
+    // function ends.
+    fn release_variables(&mut self) {
+        let variables = self.variables.clone();
+        for (id, ty) in variables {
+            for string in self.strings(&id, &ty) {
+                self.emit(&format!("mini_release({});", string));
             }
         }
     }
 
     fn codegen_stmts(&mut self, stmts: &[Stmt]) {
         for stmt in stmts {
-            self.codegen_stmt(&stmt);
+            match (self.unroll_count(stmt), stmt) {
+                (Some(n), &Stmt::While(ref stmt_)) => {
+                    let outer = self.origin;
+                    self.origin = Some((stmt.node_id(), stmt.pos()));
+                    for _ in 0..n {
+                        self.codegen_stmts(&stmt_.stmts);
+                    }
+                    self.origin = outer;
+                }
+                _ => self.codegen_stmt(&stmt),
+            }
+        }
+    }
+
+    // The number of times to repeat the body of `stmt` instead of
+    // emitting a loop, if it is a short enough counting loop.
+    fn unroll_count(&self, stmt: &Stmt) -> Option<usize> {
+        match (stmt, self.loop_constants.get(&stmt.node_id())) {
+            (&Stmt::While(ref stmt_), Some(constants)) => {
+                unroll::trip_count(constants, stmt_, self.options.max_unroll)
+            }
+            _ => None,
         }
     }
 
     fn codegen_stmt(&mut self, stmt: &Stmt) {
+        ice::at(stmt.pos());
+        let outer = self.origin;
+        self.origin = Some((stmt.node_id(), stmt.pos()));
+        self.line_comment(stmt.pos());
         match *stmt {
             Stmt::Read(ref stmt_) => {
-                match self.symtable.get(&stmt_.id) {
-                    Some(ty) => { println!("scanf(\"%{}\", &{});", ty.format_letter(), stmt_.id); }
-                    None => { println!("/* read error */"); }
+                if let Some(ref prompt) = stmt_.prompt {
+                    self.emit(&format!("fwrite({}, 1, {}, stdout);", c_string(prompt), prompt.len()));
+                    self.emit("fflush(stdout);");
+                }
+                for id in &stmt_.ids {
+                    let ty = self.var_type(id);
+                    let id = self.var(id);
+                    let line = match ty {
+                        Type::Int => format!("scanf(\"%lld\", &{});", id),
+                        Type::Float => format!("scanf(\"%lf\", &{});", id),
+                        Type::Int32 => format!("scanf(\"%d\", &{});", id),
+                        Type::Float32 => format!("scanf(\"%f\", &{});", id),
+                        Type::String => {
+                            self.emit(&format!("mini_release({});", id));
+                            format!("{} = mini_read_string();", id)
+                        }
+                        Type::Bool => format!("{} = mini_read_bool();", id),
+                        Type::Record(_) | Type::Tuple(_) => unreachable!(),
+                    };
+                    self.emit(&line);
                 }
             }
             Stmt::Print(ref stmt_) => {
                 let tmp = self.codegen_expr(&stmt_.expr);
-                match self.exprtable.get(&stmt_.expr) {
-                    Some(ty) => { println!("printf(\"%{}\\n\", {});", ty.format_letter(), tmp); }
-                    None => { println!("/* read error */"); }
+                let ty = &self.exprtable[&stmt_.expr.node_id];
+                let line = match stmt_.precision {
+                    Some(n) => format!("printf(\"%.{}f\\n\", (double) {});", n, tmp),
+                    // Strings may contain NUL characters.
+                    None if *ty == Type::String => {
+                        format!("fwrite({0}->chars, 1, {0}->len, stdout); putchar('\\n');", tmp)
+                    }
+                    None => {
+                        let arg = match *ty {
+                            Type::Bool => format!("{} ? \"true\" : \"false\"", tmp),
+                            _ => tmp,
+                        };
+                        format!("printf(\"{}\\n\", {});", printf_format(ty), arg)
+                    }
+                };
+                self.emit(&line);
+            }
+            Stmt::Exit(ref stmt_) => {
+                let tmp = self.codegen_expr(&stmt_.expr);
+                self.release_temps();
+                self.release_variables();
+                self.emit(&format!("return (int) {};", tmp));
+            }
+            // The value is copied to a temporary of the return type
+            // before the variables it may come from are released; it
+            // holds its own references to its strings, which the caller
+            // releases.
+            Stmt::Return(ref stmt_) => {
+                match (self.ret.clone(), &stmt_.expr) {
+                    (Some(ret), &Some(ref expr)) => {
+                        let tmp = self.codegen_expr(expr);
+                        let value = self.new_tmp();
+                        self.declare(&value, &ret);
+                        let ty = self.exprtable[&expr.node_id].clone();
+                        self.codegen_copy(value.clone(), &ret, tmp, &ty);
+                        self.release_temps();
+                        self.release_variables();
+                        self.emit(&format!("return {};", value));
+                    }
+                    _ => {
+                        self.release_variables();
+                        self.emit("return;");
+                    }
                 }
             }
             Stmt::Assign(ref stmt_) => {
+                match stmt_.appended() {
+                    Some(piece) if self.var_type(&stmt_.id) == Type::String => {
+                        let tmp = self.codegen_expr(piece);
+                        let var = self.var(&stmt_.id);
+                        self.emit(&format!("mini_append(&{}, {});", var, tmp));
+                    }
+                    _ => {
+                        let tmp = self.codegen_expr(&stmt_.expr);
+                        let value_ty = self.exprtable[&stmt_.expr.node_id].clone();
+                        self.codegen_assign(&stmt_.id, &stmt_.fields, tmp, &value_ty);
+                    }
+                }
+            }
+            Stmt::Destructure(ref stmt_) => {
+                // The tuple is built in a temporary before any target is
+                // assigned, so `x, y = y, x;` swaps.
                 let tmp = self.codegen_expr(&stmt_.expr);
-                println!("{} = {};", stmt_.id, tmp);
+                let elements = match self.exprtable[&stmt_.expr.node_id] {
+                    Type::Tuple(ref elements) => elements.clone(),
+                    _ => unreachable!(),
+                };
+                for (i, target) in stmt_.targets.iter().enumerate() {
+                    let value = format!("{}.f{}", tmp, i);
+                    self.codegen_assign(&target.id, &target.fields, value, &elements[i]);
+                }
+            }
+            // A call without a value is a C statement of its own.
+            Stmt::Call(ref stmt_) => {
+                match stmt_.expr.expr {
+                    Expr_::Call(ref call) if !self.exprtable.contains_key(&stmt_.expr.node_id) => {
+                        let call = self.codegen_call(call);
+                        self.emit(&format!("{};", call));
+                    }
+                    _ => {
+                        self.codegen_expr(&stmt_.expr);
+                    }
+                }
             }
             Stmt::If(ref stmt_) => {
                 let tmp = self.codegen_expr(&stmt_.expr);
-                println!("if ({}) {{", tmp);
+                self.release_temps();
+                self.emit(&format!("if ({}) {{", tmp));
                 self.codegen_stmts(&stmt_.then_stmts);
-                println!("}} else {{");
-                self.codegen_stmts(&stmt_.else_stmts);
-                println!("}}");
+                if !stmt_.else_stmts.is_empty() {
+                    self.emit("} else {");
+                    self.codegen_stmts(&stmt_.else_stmts);
+                }
+                self.emit("}");
             }
             Stmt::While(ref stmt_) => {
+                // The condition is re-evaluated at the top of every
+                // iteration, so its temporaries live inside the loop.
+                self.emit("while (1) {");
                 let tmp = self.codegen_expr(&stmt_.expr);
-                println!("while ({}) {{", tmp);
+                self.release_temps();
+                self.emit(&format!("if (!{}) break;", tmp));
+                self.codegen_stmts(&stmt_.stmts);
+                self.emit("}");
+            }
+            Stmt::For(ref stmt_) => {
+                // Both bounds are evaluated before the variable is set,
+                // and the last one is kept in a temporary.  The variable
+                // is only incremented while it is below the limit, so it
+                // cannot overflow.
+                let first = self.codegen_expr(&stmt_.first);
+                let last = self.codegen_expr(&stmt_.last);
+                let ty = self.var_type(&stmt_.id);
+                let limit = self.new_tmp();
+                self.declare(&limit, &ty);
+                let last_ty = self.exprtable[&stmt_.last.node_id].clone();
+                self.codegen_copy(limit.clone(), &ty, last, &last_ty);
+                let first_ty = self.exprtable[&stmt_.first.node_id].clone();
+                self.codegen_assign(&stmt_.id, &[], first, &first_ty);
+                self.release_temps();
+                let var = self.var(&stmt_.id);
+                self.emit(&format!("for (; {0} <= {1}; {0}++) {{", var, limit));
                 self.codegen_stmts(&stmt_.stmts);
-                println!("}}");
+                self.emit(&format!("if ({} >= {}) break;", var, limit));
+                self.emit("}");
+            }
+            Stmt::Error(_) => unreachable!("code generation of a program with syntax errors"),
+        }
+        self.release_temps();
+        self.origin = outer;
+    }
+
+    fn codegen_assign(&mut self, id: &str, fields: &[String], value: String, value_ty: &Type) {
+        let mut ty = self.var_type(id);
+        for field in fields {
+            ty = typecheck::field_type(&self.records, &ty, field).expect("unknown field");
+        }
+        let target = c_lvalue(&self.var(id), fields);
+        self.codegen_copy(target, &ty, value, value_ty);
+    }
+
+    // Tuples of ints can be assigned to tuples of floats, but their
+    // structs differ, so such tuples are copied element by element.
+    // Records and tuples that hold strings are copied field by field,
+    // so that every string is retained.
+    fn codegen_copy(&mut self, target: String, ty: &Type, value: String, value_ty: &Type) {
+        let holds_strings = !self.strings(&target, ty).is_empty();
+        match (ty, value_ty) {
+            (&Type::String, _) => self.emit(&format!("mini_assign(&{}, {});", target, value)),
+            (&Type::Tuple(ref ts), &Type::Tuple(ref us)) if ty != value_ty || holds_strings => {
+                for (i, (t, u)) in ts.iter().zip(us.iter()).enumerate() {
+                    self.codegen_copy(format!("{}.f{}", target, i), t, format!("{}.f{}", value, i), u);
+                }
+            }
+            (&Type::Record(ref name), _) if holds_strings => {
+                for (field, field_ty) in self.records[&name[..]].clone() {
+                    self.codegen_copy(format!("{}.{}", target, field), &field_ty,
+                                      format!("{}.{}", value, field), &field_ty);
+                }
+            }
+            _ => self.emit(&format!("{} = {};", target, value)),
+        }
+    }
+
+    // `value`, of type `value_ty`, as a value of type `ty`.  C converts
+    // numbers by itself, but a tuple of ints must be copied element by
+    // element into a tuple of floats.
+    fn converted(&mut self, value: String, value_ty: &Type, ty: &Type) -> String {
+        match (ty, value_ty) {
+            (&Type::Tuple(_), &Type::Tuple(_)) if ty != value_ty => {
+                let tmp = self.new_tmp();
+                self.emit(&format!("{} {} = {{0}};", c_type(ty), tmp));
+                self.codegen_copy(tmp.clone(), ty, value, value_ty);
+                if !self.strings(&tmp, ty).is_empty() {
+                    self.owned.push((tmp.clone(), ty.clone()));
+                }
+                tmp
             }
+            _ => value,
         }
     }
 
+    // The C call, after the code computing its arguments.
+    fn codegen_call(&mut self, call: &ExprCall) -> String {
+        let params = self.params[&call.id].clone();
+        let mut args = Vec::new();
+        for (arg, ty) in call.args.iter().zip(params) {
+            let tmp = self.codegen_expr(arg);
+            let arg_ty = self.exprtable[&arg.node_id].clone();
+            args.push(self.converted(tmp, &arg_ty, &ty));
+        }
+        format!("{}({})", self.functions[&call.id], args.join(", "))
+    }
+
     fn new_tmp(&mut self) -> String {
         self.tmp_counter += 1;
-        let tmp = format!("tmp_{}", self.tmp_counter);
-        tmp.to_string()
+        match self.options.temp_names {
+            TempNames::Numbered => format!("tmp_{}", self.tmp_counter),
+            TempNames::Short => format!("t{}", self.tmp_counter),
+            TempNames::Line => {
+                let line = self.origin.map_or(0, |(_, pos)| pos.line);
+                let n = self.line_temps.entry(line).or_insert(0);
+                *n += 1;
+                format!("tmp_{}_{}", line, n)
+            }
+        }
+    }
+
+    // The C name of the variable `id`.
+    fn var(&self, id: &str) -> String {
+        format!("{}{}", self.options.ident_prefix, id)
+    }
+
+    fn line_comment(&mut self, pos: Pos) {
+        if self.options.line_comments {
+            self.emit(&format!("/* line {} */", pos.line));
+        }
     }
 
+    // Generate the code computing `expr` and return the name of the
+    // C variable that holds its value.  The temporaries hold their own
+    // references to strings, released at the end of the statement.
+    // Operands are evaluated from left to right, as in the interpreter:
+    // each one gets its own temporary, declared in that order, so the
+    // C expressions that combine them have nothing left to evaluate
+    // but variables.  Variables are read in place, which is only right
+    // as long as expressions cannot assign them; calls cannot, since a
+    // function only sees its own variables.
     fn codegen_expr(&mut self, expr: &Expr) -> String {
+        let ty = self.exprtable[&expr.node_id].clone();
+        // Whether the value is a new string, rather than one to retain.
+        let mut fresh = false;
+        let value = match expr.expr {
+            Expr_::Id(ref expr_) => { return self.var(&expr_.id); }
+            Expr_::Int(ref expr_) if expr_.ty == Type::Int32 => { expr_.value.to_string() }
+            // C has no literal for the smallest long long, only for its
+            // magnitude, which is too large.
+            Expr_::Int(ref expr_) if expr_.value == i64::MIN => { "(-9223372036854775807LL - 1)".to_string() }
+            Expr_::Int(ref expr_) => { format!("{}LL", expr_.value) }
+            Expr_::Float(ref expr_) if expr_.ty == Type::Float32 => { format!("{:?}f", expr_.value as f32) }
+            Expr_::Float(ref expr_) => { format!("{:?}", expr_.value) }
+            Expr_::String(ref expr_) if self.literals.contains_key(&expr_.value) => {
+                format!("&{}", self.literals[&expr_.value])
+            }
+            Expr_::String(ref expr_) => {
+                self.tmp_counter += 1;
+                let literal = format!("lit_{}", self.tmp_counter);
+                let n = expr_.value.len();
+                self.emit(&format!("static struct mini_string {} = {{-1, {}, {}, {}}};",
+                                   literal, n, n, c_string(&expr_.value)));
+                format!("&{}", literal)
+            }
+            Expr_::Bool(ref expr_) => { (if expr_.value { "1" } else { "0" }).to_string() }
+            Expr_::Field(ref expr_) => {
+                let id1 = self.codegen_expr(&expr_.expr);
+                format!("{}.{}", id1, expr_.field)
+            }
+            Expr_::Tuple(ref expr_) => {
+                let ids: Vec<String> = expr_.exprs.iter().map(|e| self.codegen_expr(e)).collect();
+                format!("{{{}}}", ids.join(", "))
+            }
+            Expr_::Call(ref expr_) => {
+                fresh = true;
+                self.codegen_call(expr_)
+            }
+            Expr_::ToString(ref expr_) => {
+                let id1 = self.codegen_expr(&expr_.expr);
+                match self.exprtable[&expr_.expr.node_id] {
+                    Type::Int | Type::Int32 => {
+                        fresh = true;
+                        format!("mini_int_to_string({})", id1)
+                    }
+                    Type::Float | Type::Float32 => {
+                        fresh = true;
+                        format!("mini_float_to_string({})", id1)
+                    }
+                    Type::Bool => format!("{} ? &mini_true : &mini_false", id1),
+                    _ => id1,
+                }
+            }
+            Expr_::Error => unreachable!("code generation of a program with syntax errors"),
+            Expr_::Negate(ref expr_) => {
+                let id1 = self.codegen_expr(&expr_.expr);
+                match ty {
+                    Type::String => {
+                        fresh = true;
+                        format!("mini_reverse({})", id1)
+                    }
+                    Type::Int | Type::Int32 => match self.options.overflow {
+                        Overflow::Wrap => {
+                            format!("({}) -(unsigned {}) {}", c_type(&ty), c_type(&ty), id1)
+                        }
+                        Overflow::Checked => {
+                            format!("mini_neg_{}({}, \"{}\")", helper_suffix(&ty), id1, expr.pos)
+                        }
+                    },
+                    _ => format!("-{}", id1),
+                }
+            }
+            Expr_::Binop(_) | Expr_::Logic(_) => return self.codegen_chain(expr),
+            Expr_::Not(ref expr_) => {
+                let id1 = self.codegen_expr(&expr_.expr);
+                format!("!{}", id1)
+            }
+        };
+        self.bind(value, ty, fresh)
+    }
+
+    // Store a value in a new temporary, which owns its strings: those
+    // of a `fresh` value are new, and the others are retained.
+    fn bind(&mut self, value: String, ty: Type, fresh: bool) -> String {
         let tmp = self.new_tmp();
-        let ty_str = match self.exprtable.get(expr) {
-            Some(&Type::Int) => "int",
-            Some(&Type::Float) => "float",
-            None => "/* fail */",
+        let line = format!("{} {} = {};", c_type(&ty), tmp, value);
+        self.emit(&line);
+        let strings = self.strings(&tmp, &ty);
+        if !strings.is_empty() {
+            if !fresh {
+                for string in strings {
+                    self.emit(&format!("mini_retain({});", string));
+                }
+            }
+            self.owned.push((tmp.clone(), ty));
+        }
+        tmp
+    }
+
+    // A chain of operators is generated from its leftmost operand up,
+    // in a loop (see `Expr::left_spine`).
+    fn codegen_chain(&mut self, expr: &Expr) -> String {
+        let (spine, leftmost) = expr.left_spine();
+        let mut id1 = self.codegen_expr(leftmost);
+        for e in spine.into_iter().rev() {
+            id1 = match e.expr {
+                Expr_::Binop(ref expr_) => self.codegen_binop(e, expr_, id1),
+                Expr_::Logic(ref expr_) => self.codegen_logic(e, expr_, id1),
+                _ => unreachable!(),
+            };
+        }
+        id1
+    }
+
+    fn codegen_binop(&mut self, expr: &Expr, expr_: &ExprBinop, id1: String) -> String {
+        let ty = self.exprtable[&expr.node_id].clone();
+        let id2 = self.codegen_expr(&expr_.expr2);
+        let operand_ty = self.exprtable[&expr_.expr1.node_id].clone();
+        let mut fresh = false;
+        let value = match (&ty, expr_.op) {
+            (&Type::Bool, op) if operand_ty == Type::String => {
+                format!("mini_compare({}, {}) {} 0", id1, id2, op)
+            }
+            (&Type::String, Binop::Add) => {
+                fresh = true;
+                format!("mini_concat({}, {})", id1, id2)
+            }
+            (&Type::String, Binop::Sub) => {
+                let reversed = self.new_tmp();
+                self.emit(&format!("{} {} = mini_reverse({});", c_type(&ty), reversed, id2));
+                self.owned.push((reversed.clone(), ty.clone()));
+                fresh = true;
+                format!("mini_concat({}, {})", id1, reversed)
+            }
+            (&Type::Int, op) | (&Type::Int32, op) => self.integer_binop(&ty, op, &id1, &id2, expr.pos),
+            (_, op) => format!("{} {} {}", id1, op, id2),
         };
-        match *expr {
-            Expr::Int(ref expr_) => { println!("{} {} = {};", ty_str, tmp, expr_.value); }
-            Expr::Float(ref expr_) => { println!("{} {} = {};", ty_str, tmp, expr_.value.0); }
-            Expr::Id(ref expr_) => { return expr_.id.clone(); }
-            Expr::Negate(ref expr_) => {
-                let id1 = self.codegen_expr(&expr_.expr);
-                println!("{} {} = -{};", ty_str, tmp, id1);
-            }
-            Expr::Binop(ref expr_) => {
-                let op_char = match expr_.op {
-                    Binop::Add => '+',
-                    Binop::Sub => '-',
-                    Binop::Mul => '*',
-                    Binop::Div => '/',
-                };
-                let id1 = self.codegen_expr(&expr_.expr1);
-                let id2 = self.codegen_expr(&expr_.expr2);
-                println!("{} {} = {} {} {};", ty_str, tmp, id1, op_char, id2);
+        self.bind(value, ty, fresh)
+    }
+
+    // The right operand is computed in a block that runs only if the
+    // left one, `id1`, does not decide the result; the strings of its
+    // temporaries are released at the end of the block, where they go
+    // out of scope.
+    fn codegen_logic(&mut self, expr: &Expr, expr_: &ExprLogic, id1: String) -> String {
+        let ty = self.exprtable[&expr.node_id].clone();
+        let tmp = self.new_tmp();
+        self.emit(&format!("{} {} = {};", c_type(&ty), tmp, id1));
+        match expr_.op {
+            Logic::And => self.emit(&format!("if ({}) {{", tmp)),
+            Logic::Or => self.emit(&format!("if (!{}) {{", tmp)),
+        }
+        let outer = self.owned.len();
+        let id2 = self.codegen_expr(&expr_.expr2);
+        self.emit(&format!("{} = {};", tmp, id2));
+        let inner: Vec<(String, Type)> = self.owned.drain(outer..).collect();
+        for (owned, owned_ty) in inner {
+            for string in self.strings(&owned, &owned_ty) {
+                self.emit(&format!("mini_release({});", string));
             }
         }
+        self.emit("}");
         tmp
     }
+
+    // Signed overflow is undefined in C, so wrapping arithmetic is done
+    // on unsigned operands and checked arithmetic calls the helpers.
+    fn integer_binop(&self, ty: &Type, op: Binop, id1: &str, id2: &str, pos: Pos) -> String {
+        let name = match op {
+            Binop::Add => "add",
+            Binop::Sub => "sub",
+            Binop::Mul => "mul",
+            Binop::Div => "div",
+            _ => return format!("{} {} {}", id1, op, id2),
+        };
+        match (self.options.overflow, op) {
+            (Overflow::Wrap, Binop::Div) => format!("{} / {}", id1, id2),
+            (Overflow::Wrap, _) => {
+                let t = c_type(ty);
+                format!("({}) ((unsigned {}) {} {} (unsigned {}) {})", t, t, id1, op, t, id2)
+            }
+            (Overflow::Checked, _) => {
+                format!("mini_{}_{}({}, {}, \"{}\")", name, helper_suffix(ty), id1, id2, pos)
+            }
+        }
+    }
 }
@@ -0,0 +1,209 @@
+//! Compiling a program in one call.
+//!
+//! `Compiler` runs the standard pipeline of the `pass` module with the
+//! given settings, and returns what each phase produced, so that
+//! library users need not wire the phases together:
+//!
+//! ```ignore
+//! let compilation = Compiler::new()
+//!     .source("var x: int = 2; print x * 21;")
+//!     .strict(true)
+//!     .optimize(2)
+//!     .target(Target::C)
+//!     .compile();
+//! for diagnostic in &compilation.diagnostics {
+//!     println!("{}", diagnostic);
+//! }
+//! match compilation.error {
+//!     None => print!("{}", compilation.output.unwrap()),
+//!     Some(err) => println!("{}", err),
+//! }
+//! ```
+//!
+//! `compile_expression` checks a single expression against the
+//! variables of a program instead, for tools that evaluate what a user
+//! types while a program runs:
+//!
+//! ```ignore
+//! let records = typecheck::recordtable(&compilation.program.unwrap().records);
+//! let typed = try!(Compiler::new().source("x * 2 + y")
+//!     .compile_expression(&compilation.symtable, &records));
+//! println!("{}", typed.ty);
+//! ```
+//!
+//! The nodes of the AST have ids that depend only on the tokens of the
+//! program (see `Parser::next_id`), so a tool that keeps ASTs or its
+//! own data by node id across runs can ask a later compilation of the
+//! same program for `type_of(node_id)`, and for the declaration of a
+//! variable with `decl_of(name, Scope::Global)`.
+
+use ast::{Decl, Expr, Program};
+use backend::{Backend, CBackend};
+use cgen::Overflow;
+use diagnostic::Diagnostic;
+use error::Error;
+use manifest::Target;
+use parser::{Parser, Syntax};
+use pass::{self, Context};
+use scanner::{self, IdentifierRules};
+use source;
+use sourcemap::Mapping;
+use token::Token;
+use typecheck::{self, Exprtable, Recordtable, Scope, Symtable, TypeChecker};
+use types::Type;
+
+/// The settings of a compilation.
+pub struct Compiler {
+    source: String,
+    syntax: Syntax,
+    identifiers: IdentifierRules,
+    strict: bool,
+    opt_level: u32,
+    overflow: Overflow,
+    target: Target,
+}
+
+/// The results of a compilation.  The phases before the one that
+/// failed, if any, have filled in their fields.
+pub struct Compilation {
+    pub tokens: Vec<Token>,
+    /// The AST, unless parsing stopped early; the parts of the program
+    /// with syntax errors are `Stmt::Error` and `Expr_::Error` nodes.
+    pub program: Option<Program>,
+    /// The types of the variables, if the program type checked.
+    pub symtable: Symtable,
+    /// The types of the expressions of `program`, by node id.
+    pub expr_table: Exprtable,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The generated code.
+    pub output: Option<String>,
+    pub source_map: Vec<Mapping>,
+    /// Why the compilation stopped, if it did not complete.
+    pub error: Option<Error>,
+}
+
+/// A type checked expression.
+pub struct TypedExpression {
+    pub expr: Expr,
+    pub ty: Type,
+    /// The types of `expr` and its subexpressions, by node id.  The
+    /// node ids start at 0, so they may be those of other nodes of the
+    /// program.
+    pub expr_table: Exprtable,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            source: String::new(),
+            syntax: Syntax::Classic,
+            identifiers: IdentifierRules::default(),
+            strict: false,
+            opt_level: 0,
+            overflow: Overflow::Wrap,
+            target: Target::C,
+        }
+    }
+
+    /// The program text; a byte order mark at its start is skipped.
+    pub fn source<S: Into<String>>(mut self, source: S) -> Self {
+        let source = source.into();
+        self.source = source::strip_bom(&source).to_string();
+        self
+    }
+
+    pub fn syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// The characters allowed in identifiers.
+    pub fn identifiers(mut self, identifiers: IdentifierRules) -> Self {
+        self.identifiers = identifiers;
+        self
+    }
+
+    /// Require bool conditions.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The optimization level, as for `c -O`.
+    pub fn optimize(mut self, level: u32) -> Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// What integer arithmetic does on overflow in the generated code.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn compile(self) -> Compilation {
+        let backend: Box<dyn Backend> = match self.target {
+            Target::C => Box::new(CBackend),
+        };
+        let mut ctx = Context::new(self.source);
+        ctx.syntax = self.syntax;
+        ctx.identifiers = self.identifiers;
+        ctx.strict = self.strict;
+        ctx.opt_level = self.opt_level;
+        ctx.overflow = self.overflow;
+        let error = pass::pipeline(backend).run(&mut ctx).err();
+        Compilation {
+            tokens: ctx.tokens,
+            program: ctx.program,
+            symtable: ctx.symtable,
+            expr_table: ctx.expr_table,
+            diagnostics: ctx.diagnostics,
+            output: ctx.output,
+            source_map: ctx.source_map,
+            error: error,
+        }
+    }
+
+    /// Scan, parse and type check the source as a single expression
+    /// that may use the variables of `symtable` and the records of
+    /// `records` (e.g. those of a `Compilation`), with the syntax,
+    /// identifier rules and strictness of the compiler.
+    pub fn compile_expression(self, symtable: &Symtable, records: &Recordtable)
+                              -> Result<TypedExpression, Error> {
+        let tokens = try!(scanner::tokenize_with(&self.source, &self.identifiers));
+        let mut parser = Parser::new(tokens);
+        parser.set_syntax(self.syntax);
+        let expr = try!(parser.parse_expression());
+        let mut tc = TypeChecker::new();
+        tc.set_strict(self.strict);
+        tc.symtable = symtable.clone();
+        tc.records = records.clone();
+        let ty = try!(tc.tc_expression(&expr));
+        Ok(TypedExpression { expr: expr, ty: ty, expr_table: tc.expr_table })
+    }
+}
+
+impl Compilation {
+    /// Whether the program compiled.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The type of the expression or declaration of `program` with id
+    /// `node_id`, if it type checked.
+    pub fn type_of(&self, node_id: u64) -> Option<Type> {
+        self.program.as_ref().and_then(|program| {
+            typecheck::type_of(program, &self.symtable, &self.expr_table, node_id)
+        })
+    }
+
+    /// The declaration of the variable `name` in `scope`.
+    pub fn decl_of(&self, name: &str, scope: Scope) -> Option<&Decl> {
+        self.program.as_ref().and_then(|program| typecheck::decl_of(program, name, scope))
+    }
+}
@@ -0,0 +1,192 @@
+// The conformance test runner.
+//
+// A conformance directory holds test programs `NAME.min`, each with its
+// expected output `NAME.out`, and optionally its input `NAME.in` and
+// its expected exit status `NAME.status` (0 by default).  A run error
+// is part of the output, as printed by `minilang run`, with status 1.
+//
+// Every program is run by the interpreter, which defines the expected
+// behavior, and by every backend of backend.rs whose code can be run.
+// `TARGET.skip` lists the programs that a target is known to fail, one
+// per line, with `#` comments.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{self, Stdio};
+
+use backend::{self, Backend};
+use cgen::Overflow;
+use error::Error;
+use interp::{self, Env};
+use parser::{Parser, Syntax};
+use pass::{self, Context};
+use scanner;
+
+pub struct Case {
+    pub name: String,
+    pub source: String,
+    pub input: String,
+    pub output: String,
+    pub status: i32,
+}
+
+// The results of one target.
+pub struct Scoreboard {
+    pub target: String,
+    pub passed: usize,
+    // The failed programs, with the reason.
+    pub failed: Vec<(String, String)>,
+    pub skipped: Vec<String>,
+}
+
+// The programs of `dir`, sorted by name.
+pub fn load(dir: &Path) -> Result<Vec<Case>, Error> {
+    let dir_name = dir.to_string_lossy().into_owned();
+    let entries = try!(fs::read_dir(dir).map_err(|e| Error::Io(dir_name.clone(), e)));
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = try!(entry.map_err(|e| Error::Io(dir_name.clone(), e))).path();
+        if path.extension().map_or(false, |ext| ext == "min") {
+            names.push(path.file_stem().unwrap().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+
+    let mut cases = Vec::new();
+    for name in names {
+        let status = try!(read_optional(&dir.join(format!("{}.status", name))));
+        let status = match status.trim() {
+            "" => 0,
+            s => try!(s.parse().map_err(|_| Error::InvalidArgument("status", s.to_string()))),
+        };
+        cases.push(Case {
+            source: try!(read(&dir.join(format!("{}.min", name)))),
+            input: try!(read_optional(&dir.join(format!("{}.in", name)))),
+            output: try!(read(&dir.join(format!("{}.out", name)))),
+            status: status,
+            name: name,
+        });
+    }
+    Ok(cases)
+}
+
+// Run the programs of `dir` with the interpreter and every backend.
+pub fn run(dir: &Path, syntax: Syntax) -> Result<Vec<Scoreboard>, Error> {
+    let cases = try!(load(dir));
+    let scratch = env::temp_dir().join(format!("minilang-conformance-{}", process::id()));
+    let scratch_name = scratch.to_string_lossy().into_owned();
+    try!(fs::create_dir_all(&scratch).map_err(|e| Error::Io(scratch_name, e)));
+
+    let mut scoreboards = Vec::new();
+    let skip = try!(skip_list(dir, "interp"));
+    scoreboards.push(score("interp", &cases, &skip, |case| Ok(interpret(case, syntax))));
+    for backend in backend::backends() {
+        let skip = try!(skip_list(dir, backend.name()));
+        scoreboards.push(score(backend.name(), &cases, &skip,
+                               |case| execute(&*backend, case, syntax, &scratch)));
+    }
+    let _ = fs::remove_dir_all(&scratch);
+    Ok(scoreboards)
+}
+
+fn score<F>(target: &str, cases: &[Case], skip: &HashSet<String>, mut run: F) -> Scoreboard
+    where F: FnMut(&Case) -> Result<(String, i32), Error>
+{
+    let mut scoreboard = Scoreboard {
+        target: target.to_string(),
+        passed: 0,
+        failed: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for case in cases {
+        if skip.contains(&case.name) {
+            scoreboard.skipped.push(case.name.clone());
+            continue;
+        }
+        match run(case) {
+            Ok((ref output, status)) if *output == case.output && status == case.status => {
+                scoreboard.passed += 1;
+            }
+            Ok((output, status)) => {
+                let reason = if status != case.status {
+                    format!("exit status {}, expected {}", status, case.status)
+                } else {
+                    format!("output {:?}, expected {:?}", output, case.output)
+                };
+                scoreboard.failed.push((case.name.clone(), reason));
+            }
+            Err(err) => scoreboard.failed.push((case.name.clone(), err.to_string())),
+        }
+    }
+    scoreboard
+}
+
+fn interpret(case: &Case, syntax: Syntax) -> (String, i32) {
+    let mut env = Env::new();
+    env.set_input(&case.input);
+    let result = scanner::tokenize(&case.source)
+        .and_then(|tokens| {
+            let mut parser = Parser::new(tokens);
+            parser.set_syntax(syntax);
+            parser.parse_program()
+        })
+        .and_then(|program| interp::eval_program(&program, &mut env));
+    let mut output = env.output().to_string();
+    match result {
+        Ok(()) => (output, env.exit_status().unwrap_or(0) as i32),
+        Err(err) => {
+            output.push_str(&format!("{}\n", err));
+            (output, 1)
+        }
+    }
+}
+
+// Compile with checked arithmetic, so that overflows are reported like
+// in the interpreter.
+fn execute(backend: &dyn Backend, case: &Case, syntax: Syntax, scratch: &Path)
+           -> Result<(String, i32), Error> {
+    let mut ctx = Context::new(case.source.clone());
+    ctx.syntax = syntax;
+    ctx.overflow = Overflow::Checked;
+    try!(pass::pipeline(try!(backend::find(backend.name()))).run(&mut ctx));
+
+    let scratch_name = scratch.to_string_lossy().into_owned();
+    let mut command = try!(backend.executable(ctx.output.as_ref().unwrap(), scratch));
+    let mut child = try!(command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()
+                         .map_err(|e| Error::Io(scratch_name.clone(), e)));
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        let _ = stdin.write_all(case.input.as_bytes());
+    }
+    let result = try!(child.wait_with_output().map_err(|e| Error::Io(scratch_name, e)));
+    let stdout = String::from_utf8_lossy(&result.stdout).into_owned();
+    Ok((stdout, result.status.code().unwrap_or(-1)))
+}
+
+fn skip_list(dir: &Path, target: &str) -> Result<HashSet<String>, Error> {
+    let text = try!(read_optional(&dir.join(format!("{}.skip", target))));
+    Ok(text.lines()
+       .map(|line| line.split('#').next().unwrap().trim())
+       .filter(|name| !name.is_empty())
+       .map(|name| name.to_string())
+       .collect())
+}
+
+pub fn read(path: &Path) -> Result<String, Error> {
+    let name = path.to_string_lossy().into_owned();
+    let mut text = String::new();
+    let mut file = try!(File::open(path).map_err(|e| Error::Io(name.clone(), e)));
+    try!(file.read_to_string(&mut text).map_err(|e| Error::Io(name, e)));
+    Ok(text)
+}
+
+pub fn read_optional(path: &Path) -> Result<String, Error> {
+    if path.exists() {
+        read(path)
+    } else {
+        Ok(String::new())
+    }
+}
@@ -0,0 +1,94 @@
+// Evaluation of constant expressions at compile time.
+//
+// An expression is constant when it is made only of literals, or of
+// variables whose value is known (see the constant propagation of
+// dataflow.rs).  The language has no named constants, arrays or case
+// labels yet; the type checker uses `eval` on declaration initializers,
+// so that it rejects `var x: int = 1 / 0;` instead of letting it fail
+// at run time.  The arithmetic is the interpreter's, so the errors are
+// the same ones, with the position of the offending operator.
+
+use std::collections::HashMap;
+
+use ast::*;
+use error::Error;
+use interp::{self, Value};
+use types::Type;
+
+// The value of `expr`, or None if it is not constant.  `expr` must be
+// well typed.
+pub fn eval(expr: &Expr) -> Result<Option<Value>, Error> {
+    eval_with(expr, &HashMap::new())
+}
+
+// The value of `expr` when the variables of `constants` have the given
+// values, or None if it is not constant.
+pub fn eval_with(expr: &Expr, constants: &HashMap<String, Value>) -> Result<Option<Value>, Error> {
+    let value = match expr.expr {
+        Expr_::Id(ref expr_) => return Ok(constants.get(&expr_.id).cloned()),
+        Expr_::Int(ref expr_) if expr_.ty == Type::Int32 => Value::Int32(expr_.value as i32),
+        Expr_::Int(ref expr_) => Value::Int(expr_.value),
+        Expr_::Float(ref expr_) if expr_.ty == Type::Float32 => Value::Float32(expr_.value as f32),
+        Expr_::Float(ref expr_) => Value::Float(expr_.value),
+        Expr_::String(ref expr_) => Value::String(expr_.value.clone()),
+        Expr_::Bool(ref expr_) => Value::Bool(expr_.value),
+        Expr_::Negate(ref expr_) => {
+            match try!(eval_with(&expr_.expr, constants)) {
+                Some(v) => try!(interp::eval_negate(v, expr.pos)),
+                None => return Ok(None),
+            }
+        }
+        Expr_::Binop(_) | Expr_::Logic(_) => return eval_chain(expr, constants),
+        Expr_::Not(ref expr_) => {
+            match try!(eval_with(&expr_.expr, constants)) {
+                Some(Value::Bool(b)) => Value::Bool(!b),
+                _ => return Ok(None),
+            }
+        }
+        Expr_::Tuple(ref expr_) => {
+            let mut values = Vec::new();
+            for e in &expr_.exprs {
+                match try!(eval_with(e, constants)) {
+                    Some(v) => values.push(v),
+                    None => return Ok(None),
+                }
+            }
+            Value::Tuple(values)
+        }
+        Expr_::Field(_) | Expr_::ToString(_) | Expr_::Call(_) | Expr_::Error => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+// A chain of operators is evaluated from its leftmost operand up, in a
+// loop (see `Expr::left_spine`).
+fn eval_chain(expr: &Expr, constants: &HashMap<String, Value>) -> Result<Option<Value>, Error> {
+    let (spine, leftmost) = expr.left_spine();
+    let mut v1 = try!(eval_with(leftmost, constants));
+    for e in spine.into_iter().rev() {
+        v1 = match e.expr {
+            // Both operands are evaluated, so that an error in the
+            // second one is reported even if the first is not constant.
+            Expr_::Binop(ref expr_) => {
+                let v2 = try!(eval_with(&expr_.expr2, constants));
+                match (v1, v2) {
+                    (Some(v1), Some(v2)) => Some(try!(interp::eval_binop(expr_.op, v1, v2, e.pos))),
+                    _ => None,
+                }
+            }
+            // Like the interpreter, the right operand is not evaluated
+            // when the left one decides the result, so that an error in
+            // it is not reported.
+            Expr_::Logic(ref expr_) => {
+                match (expr_.op, v1) {
+                    (Logic::And, Some(Value::Bool(false))) => Some(Value::Bool(false)),
+                    (Logic::Or, Some(Value::Bool(true))) => Some(Value::Bool(true)),
+                    (_, Some(_)) => try!(eval_with(&expr_.expr2, constants)),
+                    (_, None) => None,
+                }
+            }
+            _ => unreachable!(),
+        };
+    }
+    Ok(v1)
+}
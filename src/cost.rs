@@ -0,0 +1,80 @@
+// A cost model of the interpreter, for comparing algorithms.
+//
+// Counting steps (`--max-steps`) treats `x = a / b;` and `print x;`
+// alike.  The model gives each statement and operation a weight, and
+// the interpreter adds up the weights of what a run executes, in total
+// and within each loop.  Two implementations of an algorithm can then be
+// compared on the same input by their costs, which unlike running times
+// do not depend on the machine.  The weights are rough relative costs,
+// not predictions of time.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use pos::Pos;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CostModel {
+    // Per variable read.
+    pub read: u64,
+    pub print: u64,
+    // Per variable or field assigned.
+    pub assign: u64,
+    // Per condition of an if, while or for evaluated, and per `and` or
+    // `or`.
+    pub branch: u64,
+    pub exit: u64,
+    // Per function call, arguments excluded.
+    pub call: u64,
+    // Addition, subtraction, negation and comparison of numbers.
+    pub arithmetic: u64,
+    pub multiply: u64,
+    pub divide: u64,
+    // Concatenating, reversing or converting to a string.
+    pub string: u64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            read: 10,
+            print: 10,
+            assign: 1,
+            branch: 1,
+            exit: 1,
+            call: 5,
+            arithmetic: 1,
+            multiply: 3,
+            divide: 10,
+            string: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoopCost {
+    // The number of times the body ran.
+    pub iterations: u64,
+    // The cost of the whole loop, conditions and nested loops included.
+    pub cost: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CostReport {
+    pub total: u64,
+    // The loops that ran, by position.  A loop that runs several times
+    // (inside another loop) adds up its runs.
+    pub loops: BTreeMap<Pos, LoopCost>,
+}
+
+impl fmt::Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "total cost: {}", self.total));
+        for (pos, cost) in &self.loops {
+            let share = if self.total == 0 { 0.0 } else { 100.0 * cost.cost as f64 / self.total as f64 };
+            try!(writeln!(f, "{}: loop: {} iteration{}, cost {} ({:.1}%)", pos, cost.iterations,
+                          if cost.iterations == 1 { "" } else { "s" }, cost.cost, share));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+// A lossless concrete syntax tree.
+//
+// Unlike the AST, the CST keeps every token with its exact text, and
+// every run of whitespace and every comment ("trivia") attached to the
+// token that follows it; the trivia at the end of the file belongs to
+// the Eof token.  Concatenating the text of all the leaves therefore
+// reproduces the source byte for byte, which is what tools that
+// rewrite programs need.
+//
+// The tree is built by interpreting the grammar of grammar.rs, so its
+// interior nodes are named after the grammar rules.  `to_ast` converts
+// it to the AST by running the regular parser on its tokens.
+//
+// The parser reports what it recognizes to a `Handler` as it goes: the
+// start and end of each rule and every token.  The tree is built by one
+// handler; tools that only look at the events, such as searching a very
+// large generated program for some construct, can use `parse_events`
+// with their own handler and never build the tree.
+
+use std::fmt;
+
+use ast::Program;
+use error::Error;
+use grammar::{self, Sym};
+use parser::{self, Parser, Syntax};
+use pos::Pos;
+use scanner;
+use token::{Token, TokenType};
+
+// A range of byte offsets in the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct CstToken {
+    pub token: Token,
+    pub text: String,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+    Node(CstNode),
+    Token(CstToken),
+}
+
+#[derive(Debug, Clone)]
+pub struct CstNode {
+    // The grammar rule that produced the node.
+    pub kind: &'static str,
+    pub children: Vec<Element>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cst {
+    pub root: CstNode,
+    pub syntax: Syntax,
+}
+
+// What `parse_events` reports.  `enter` gets the rule (e.g.
+// "statement" or "expression") and the byte offset where its text
+// starts, and `exit` its span, which is empty for a rule that matched
+// no token.  The tokens come with their leading trivia, in order.
+pub trait Handler {
+    fn enter(&mut self, _rule: &'static str, _start: usize) {}
+    fn exit(&mut self, _rule: &'static str, _span: Span) {}
+    fn token(&mut self, _tok: &CstToken) {}
+}
+
+pub fn parse(source: &str, syntax: Syntax) -> Result<Cst, Error> {
+    let mut tree = TreeBuilder { stack: Vec::new(), root: None };
+    try!(parse_events(source, syntax, &mut tree));
+    Ok(Cst { root: tree.root.unwrap(), syntax: syntax })
+}
+
+// Parse `source`, reporting to `handler`.  The events before an error
+// are reported.
+pub fn parse_events<H: Handler>(source: &str, syntax: Syntax, handler: &mut H) -> Result<(), Error> {
+    let tokens = try!(scanner::tokenize(source));
+    let mut builder = Builder { syntax: syntax, tokens: attach_trivia(source, tokens), index: 0,
+                                handler: handler, emitted: 0, last_end: 0, depth: 0 };
+    builder.rule("program")
+}
+
+struct TreeBuilder {
+    // The nodes being built, innermost last.
+    stack: Vec<CstNode>,
+    root: Option<CstNode>,
+}
+
+impl Handler for TreeBuilder {
+    fn enter(&mut self, rule: &'static str, _start: usize) {
+        self.stack.push(CstNode { kind: rule, children: Vec::new() });
+    }
+
+    fn exit(&mut self, _rule: &'static str, _span: Span) {
+        let node = self.stack.pop().unwrap();
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(Element::Node(node)),
+            None => self.root = Some(node),
+        }
+    }
+
+    fn token(&mut self, tok: &CstToken) {
+        self.stack.last_mut().unwrap().children.push(Element::Token(tok.clone()));
+    }
+}
+
+impl Cst {
+    // The source text, reconstructed from the leaves of the tree.
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        self.root.write_source(&mut out);
+        out
+    }
+
+    pub fn to_ast(&self) -> Result<Program, Error> {
+        let mut tokens = Vec::new();
+        self.root.collect_tokens(&mut tokens);
+        let mut parser = Parser::new(tokens);
+        parser.set_syntax(self.syntax);
+        parser.parse_program()
+    }
+}
+
+impl CstNode {
+    pub fn span(&self) -> Span {
+        let mut spans = self.children.iter().map(|child| child.span());
+        let first = spans.next().unwrap_or(Span { start: 0, end: 0 });
+        let last = spans.last().unwrap_or(first);
+        Span { start: first.start, end: last.end }
+    }
+
+    fn write_source(&self, out: &mut String) {
+        for child in &self.children {
+            match *child {
+                Element::Node(ref node) => node.write_source(out),
+                Element::Token(ref tok) => {
+                    for trivia in &tok.leading {
+                        out.push_str(&trivia.text);
+                    }
+                    out.push_str(&tok.text);
+                }
+            }
+        }
+    }
+
+    fn collect_tokens(&self, out: &mut Vec<Token>) {
+        for child in &self.children {
+            match *child {
+                Element::Node(ref node) => node.collect_tokens(out),
+                Element::Token(ref tok) => out.push(tok.token.clone()),
+            }
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let span = self.span();
+        try!(writeln!(f, "{:w$}{}@{}..{}", "", self.kind, span.start, span.end, w = depth * 2));
+        for child in &self.children {
+            match *child {
+                Element::Node(ref node) => try!(node.fmt_indented(f, depth + 1)),
+                Element::Token(ref tok) => {
+                    for trivia in &tok.leading {
+                        let kind = match trivia.kind {
+                            TriviaKind::Whitespace => "whitespace",
+                            TriviaKind::Comment => "comment",
+                        };
+                        try!(writeln!(f, "{:w$}{} {:?}@{}..{}", "", kind, trivia.text,
+                                      trivia.span.start, trivia.span.end, w = depth * 2 + 2));
+                    }
+                    try!(writeln!(f, "{:w$}{} {:?}@{}..{}", "", tok.token.typ, tok.text,
+                                  tok.span.start, tok.span.end, w = depth * 2 + 2));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Element {
+    // The span of the element, without leading trivia.
+    pub fn span(&self) -> Span {
+        match *self {
+            Element::Node(ref node) => node.span(),
+            Element::Token(ref tok) => tok.span,
+        }
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.root.fmt_indented(f, 0)
+    }
+}
+
+// Recover the text of the tokens and of the trivia between them from
+// the token positions.
+pub fn attach_trivia(source: &str, tokens: Vec<Token>) -> Vec<CstToken> {
+    let mut cursor = Cursor { source: source, offset: 0, pos: Pos { line: 1, col: 1 } };
+    let mut result = Vec::new();
+    for tok in tokens {
+        let trivia_start = cursor.offset;
+        let start = cursor.advance_to(tok.pos);
+        let end = cursor.advance_to(tok.end);
+        result.push(CstToken {
+            leading: split_trivia(&source[trivia_start..start], trivia_start),
+            text: source[start..end].to_string(),
+            span: Span { start: start, end: end },
+            token: tok,
+        });
+    }
+    result
+}
+
+struct Cursor<'a> {
+    source: &'a str,
+    offset: usize,
+    pos: Pos,
+}
+
+impl<'a> Cursor<'a> {
+    // Move to `pos`, counting lines and columns like the scanner does,
+    // and return the byte offset of `pos`.
+    fn advance_to(&mut self, pos: Pos) -> usize {
+        let mut chars = self.source[self.offset..].chars();
+        while self.pos < pos {
+            match chars.next() {
+                Some(c) => {
+                    self.offset += c.len_utf8();
+                    if c == '\n' {
+                        self.pos.line += 1;
+                        self.pos.col = 1;
+                    } else {
+                        self.pos.col += 1;
+                    }
+                }
+                None => break,
+            }
+        }
+        self.offset
+    }
+}
+
+fn split_trivia(text: &str, offset: usize) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let mut rest = text;
+    let mut start = offset;
+    while !rest.is_empty() {
+        let (kind, len) = if rest.starts_with('#') {
+            (TriviaKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else {
+            (TriviaKind::Whitespace, rest.find('#').unwrap_or(rest.len()))
+        };
+        trivia.push(Trivia {
+            kind: kind,
+            text: rest[..len].to_string(),
+            span: Span { start: start, end: start + len },
+        });
+        rest = &rest[len..];
+        start += len;
+    }
+    trivia
+}
+
+struct Builder<'a, H: Handler + 'a> {
+    syntax: Syntax,
+    tokens: Vec<CstToken>,
+    index: usize,
+    handler: &'a mut H,
+    // The number of tokens reported, and the end of the last one.
+    emitted: usize,
+    last_end: usize,
+    // The nesting of the rules being parsed.
+    depth: usize,
+}
+
+// The rules nested in one level of the parser, at most: `(e)` nests
+// nine, from expression down to atom and expression_list.  The nesting
+// of the rules is bounded like that of the parser, so that deep input
+// is an error rather than a stack overflow.
+const RULES_PER_LEVEL: usize = 10;
+
+impl<'a, H: Handler> Builder<'a, H> {
+    fn peek(&self) -> TokenType {
+        self.tokens[self.index].token.typ
+    }
+
+    fn starts(&self, seq: &'static [Sym]) -> bool {
+        grammar::first_of_seq(self.syntax, seq).0.contains(&self.peek())
+    }
+
+    // Pick the alternative that starts with the next token, or else the
+    // one that can be empty.
+    fn choose(&self, alts: &'static [&'static [Sym]]) -> Option<&'static [Sym]> {
+        if alts.len() == 1 {
+            return Some(alts[0]);
+        }
+        alts.iter().cloned().find(|alt| self.starts(alt))
+            .or_else(|| alts.iter().cloned().find(|alt| grammar::first_of_seq(self.syntax, alt).1))
+    }
+
+    fn rule(&mut self, name: &'static str) -> Result<(), Error> {
+        if self.depth >= parser::DEFAULT_MAX_DEPTH * RULES_PER_LEVEL {
+            return Err(Error::NestingTooDeep(self.tokens[self.index].token.pos, parser::DEFAULT_MAX_DEPTH));
+        }
+        self.depth += 1;
+        let rule = grammar::rule(self.syntax, name);
+        let first = self.tokens[self.index].span.start;
+        let emitted = self.emitted;
+        self.handler.enter(rule.name, first);
+        match self.choose(rule.alts) {
+            Some(alt) => try!(self.seq(alt)),
+            None => return Err(self.unexpected(rule.alts)),
+        }
+        let span = if self.emitted > emitted {
+            Span { start: first, end: self.last_end }
+        } else {
+            Span { start: 0, end: 0 }
+        };
+        self.handler.exit(rule.name, span);
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn seq(&mut self, seq: &'static [Sym]) -> Result<(), Error> {
+        for sym in seq {
+            match *sym {
+                Sym::T(t) => {
+                    if self.peek() != t {
+                        return Err(Error::UnexpectedToken(self.tokens[self.index].token.clone(), vec![t]));
+                    }
+                    self.handler.token(&self.tokens[self.index]);
+                    self.emitted += 1;
+                    self.last_end = self.tokens[self.index].span.end;
+                    if t != TokenType::Eof {
+                        self.index += 1;
+                    }
+                }
+                Sym::N(name) => try!(self.rule(name)),
+                Sym::Opt(inner) => {
+                    if self.starts(inner) {
+                        try!(self.seq(inner));
+                    }
+                }
+                Sym::Rep(inner) => {
+                    while self.starts(inner) {
+                        try!(self.seq(inner));
+                    }
+                }
+                Sym::Alt(alts) => {
+                    match self.choose(alts) {
+                        Some(alt) => try!(self.seq(alt)),
+                        None => return Err(self.unexpected(alts)),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unexpected(&self, alts: &'static [&'static [Sym]]) -> Error {
+        let mut expected = Vec::new();
+        for alt in alts {
+            for t in grammar::first_of_seq(self.syntax, alt).0 {
+                if !expected.contains(&t) {
+                    expected.push(t);
+                }
+            }
+        }
+        Error::UnexpectedToken(self.tokens[self.index].token.clone(), expected)
+    }
+}
@@ -0,0 +1,350 @@
+// Dataflow analyses over the control flow graph of cfg.rs.
+//
+// An analysis gives the direction in which facts flow, the fact at the
+// entry (forward) or exit (backward) of the program, and the transfer
+// function of a node; `solve` computes the fact before and after every
+// node with a worklist.  Facts are lattices: `join` combines the facts
+// of the paths that meet at a node, and `bottom` is the fact of the
+// paths that have not been reached yet.
+//
+// Three analyses are defined here: liveness, definite assignment and
+// constant propagation.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use ast::*;
+use cfg::{self, Cfg, Node};
+use consteval;
+use interp::Value;
+use typecheck::Recordtable;
+use types::Type;
+
+pub trait Lattice: Clone + PartialEq {
+    fn bottom() -> Self;
+    fn join(&self, other: &Self) -> Self;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+pub trait Analysis {
+    type Fact: Lattice;
+
+    fn direction(&self) -> Direction;
+
+    // The fact before the entry node (forward) or after the exit node
+    // (backward).
+    fn boundary(&self) -> Self::Fact;
+
+    // The fact after `node` given the fact before it (forward), or the
+    // fact before `node` given the fact after it (backward).
+    fn transfer(&self, node: &Node, fact: &Self::Fact) -> Self::Fact;
+}
+
+// The facts before and after each node, in execution order, whatever
+// the direction of the analysis.
+#[derive(Debug)]
+pub struct Solution<F> {
+    pub before: Vec<F>,
+    pub after: Vec<F>,
+}
+
+pub fn solve<A: Analysis>(cfg: &Cfg, analysis: &A) -> Solution<A::Fact> {
+    let n = cfg.nodes.len();
+    let mut before = vec![A::Fact::bottom(); n];
+    let mut after = vec![A::Fact::bottom(); n];
+    let forward = analysis.direction() == Direction::Forward;
+
+    let mut worklist: VecDeque<usize> = (0..n).collect();
+    let mut queued = vec![true; n];
+    while let Some(node) = worklist.pop_front() {
+        queued[node] = false;
+        let (inputs, boundary, changed) = if forward {
+            (&cfg.preds[node], node == cfg::ENTRY, &cfg.succs[node])
+        } else {
+            (&cfg.succs[node], node == cfg::EXIT, &cfg.preds[node])
+        };
+        let incoming = if boundary {
+            analysis.boundary()
+        } else {
+            let facts = if forward { &after } else { &before };
+            inputs.iter().fold(A::Fact::bottom(), |acc, &i| acc.join(&facts[i]))
+        };
+        let outgoing = analysis.transfer(&cfg.nodes[node], &incoming);
+        let (inside, outside) = if forward {
+            (&mut before, &mut after)
+        } else {
+            (&mut after, &mut before)
+        };
+        inside[node] = incoming;
+        if outside[node] != outgoing {
+            outside[node] = outgoing;
+            for &next in changed {
+                if !queued[next] {
+                    queued[next] = true;
+                    worklist.push_back(next);
+                }
+            }
+        }
+    }
+    Solution { before: before, after: after }
+}
+
+// The variables that a node reads and the variables that it overwrites
+// entirely.  Assigning a field reads the rest of the variable.
+fn uses_and_defs(node: &Node) -> (HashSet<String>, HashSet<String>) {
+    let mut uses = HashSet::new();
+    let mut defs = HashSet::new();
+    match *node {
+        Node::Entry | Node::Exit => {}
+        Node::Param(param) => {
+            defs.insert(param.id.clone());
+        }
+        Node::Decl(decl) => {
+            if let Some(ref init) = decl.init {
+                expr_uses(init, &mut uses);
+            }
+            defs.insert(decl.id.clone());
+        }
+        Node::Cond(stmt) => {
+            match *stmt {
+                Stmt::If(ref stmt_) => expr_uses(&stmt_.expr, &mut uses),
+                Stmt::While(ref stmt_) => expr_uses(&stmt_.expr, &mut uses),
+                Stmt::For(ref stmt_) => {
+                    uses.insert(stmt_.id.clone());
+                    defs.insert(stmt_.id.clone());
+                }
+                _ => {}
+            }
+        }
+        Node::Stmt(stmt) => {
+            match *stmt {
+                Stmt::Read(ref stmt_) => defs.extend(stmt_.ids.iter().cloned()),
+                Stmt::Print(ref stmt_) => expr_uses(&stmt_.expr, &mut uses),
+                Stmt::Exit(ref stmt_) => expr_uses(&stmt_.expr, &mut uses),
+                Stmt::Call(ref stmt_) => expr_uses(&stmt_.expr, &mut uses),
+                Stmt::Return(ref stmt_) => {
+                    if let Some(ref expr) = stmt_.expr {
+                        expr_uses(expr, &mut uses);
+                    }
+                }
+                Stmt::Assign(ref stmt_) => {
+                    expr_uses(&stmt_.expr, &mut uses);
+                    if stmt_.fields.is_empty() {
+                        defs.insert(stmt_.id.clone());
+                    } else {
+                        uses.insert(stmt_.id.clone());
+                    }
+                }
+                Stmt::Destructure(ref stmt_) => {
+                    expr_uses(&stmt_.expr, &mut uses);
+                    for target in &stmt_.targets {
+                        if target.fields.is_empty() {
+                            defs.insert(target.id.clone());
+                        } else {
+                            uses.insert(target.id.clone());
+                        }
+                    }
+                }
+                Stmt::For(ref stmt_) => {
+                    expr_uses(&stmt_.first, &mut uses);
+                    expr_uses(&stmt_.last, &mut uses);
+                    defs.insert(stmt_.id.clone());
+                }
+                Stmt::If(_) | Stmt::While(_) | Stmt::Error(_) => {}
+            }
+        }
+    }
+    (uses, defs)
+}
+
+// The operands of a chain of operators are looked at in a loop (see
+// `Expr::left_spine`).
+fn expr_uses(expr: &Expr, uses: &mut HashSet<String>) {
+    let (spine, expr) = expr.left_spine();
+    for op in spine {
+        match op.expr {
+            Expr_::Binop(ref expr_) => expr_uses(&expr_.expr2, uses),
+            Expr_::Logic(ref expr_) => expr_uses(&expr_.expr2, uses),
+            _ => unreachable!(),
+        }
+    }
+    match expr.expr {
+        Expr_::Id(ref expr_) => { uses.insert(expr_.id.clone()); }
+        Expr_::Negate(ref expr_) => expr_uses(&expr_.expr, uses),
+        Expr_::ToString(ref expr_) => expr_uses(&expr_.expr, uses),
+        Expr_::Field(ref expr_) => expr_uses(&expr_.expr, uses),
+        Expr_::Tuple(ref expr_) => {
+            for e in &expr_.exprs {
+                expr_uses(e, uses);
+            }
+        }
+        Expr_::Call(ref expr_) => {
+            for e in &expr_.args {
+                expr_uses(e, uses);
+            }
+        }
+        Expr_::Binop(_) | Expr_::Logic(_) => unreachable!(),
+        Expr_::Not(ref expr_) => expr_uses(&expr_.expr, uses),
+        Expr_::Int(_) | Expr_::Float(_) | Expr_::String(_) | Expr_::Bool(_) | Expr_::Error => {}
+    }
+}
+
+// Liveness: the variables whose current value may be read later.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Live(pub HashSet<String>);
+
+impl Lattice for Live {
+    fn bottom() -> Self {
+        Live(HashSet::new())
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Live(self.0.union(&other.0).cloned().collect())
+    }
+}
+
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Fact = Live;
+
+    fn direction(&self) -> Direction { Direction::Backward }
+
+    fn boundary(&self) -> Live { Live::bottom() }
+
+    fn transfer(&self, node: &Node, fact: &Live) -> Live {
+        let (uses, defs) = uses_and_defs(node);
+        let mut live: HashSet<String> = fact.0.difference(&defs).cloned().collect();
+        live.extend(uses);
+        Live(live)
+    }
+}
+
+// Definite assignment: the variables that have been given a value on
+// every path, rather than holding the zero value of their type.  None
+// stands for the paths that have not been reached yet.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assigned(pub Option<HashSet<String>>);
+
+impl Lattice for Assigned {
+    fn bottom() -> Self {
+        Assigned(None)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (&None, _) => other.clone(),
+            (_, &None) => self.clone(),
+            (&Some(ref a), &Some(ref b)) => Assigned(Some(a.intersection(b).cloned().collect())),
+        }
+    }
+}
+
+pub struct DefiniteAssignment;
+
+impl Analysis for DefiniteAssignment {
+    type Fact = Assigned;
+
+    fn direction(&self) -> Direction { Direction::Forward }
+
+    fn boundary(&self) -> Assigned { Assigned(Some(HashSet::new())) }
+
+    fn transfer(&self, node: &Node, fact: &Assigned) -> Assigned {
+        let mut assigned = match fact.0 {
+            Some(ref assigned) => assigned.clone(),
+            None => return Assigned(None),
+        };
+        match *node {
+            // A declaration without initializer gives the zero value.
+            Node::Decl(decl) if decl.init.is_none() => {}
+            _ => assigned.extend(uses_and_defs(node).1),
+        }
+        Assigned(Some(assigned))
+    }
+}
+
+// Constant propagation: the variables whose value is the same on every
+// path.  None stands for the paths that have not been reached yet.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constants(pub Option<HashMap<String, Value>>);
+
+impl Lattice for Constants {
+    fn bottom() -> Self {
+        Constants(None)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (&None, _) => other.clone(),
+            (_, &None) => self.clone(),
+            (&Some(ref a), &Some(ref b)) => {
+                let common = a.iter()
+                    .filter(|&(id, value)| b.get(id) == Some(value))
+                    .map(|(id, value)| (id.clone(), value.clone()))
+                    .collect();
+                Constants(Some(common))
+            }
+        }
+    }
+}
+
+pub struct ConstantPropagation;
+
+impl Analysis for ConstantPropagation {
+    type Fact = Constants;
+
+    fn direction(&self) -> Direction { Direction::Forward }
+
+    fn boundary(&self) -> Constants { Constants(Some(HashMap::new())) }
+
+    fn transfer(&self, node: &Node, fact: &Constants) -> Constants {
+        let mut constants = match fact.0 {
+            Some(ref constants) => constants.clone(),
+            None => return Constants(None),
+        };
+        match *node {
+            Node::Decl(decl) => {
+                let value = match (&decl.ty, &decl.init) {
+                    (_, &Some(ref init)) => consteval::eval_with(init, &constants).unwrap_or(None),
+                    (&Some(Type::Record(_)), &None) | (&Some(Type::Tuple(_)), &None) => None,
+                    (&Some(ref ty), &None) => Some(Value::zero(ty, &Recordtable::new())),
+                    (None, &None) => None,
+                };
+                set(&mut constants, &decl.id, value);
+            }
+            Node::Stmt(&Stmt::Assign(ref stmt_)) => {
+                let value = if stmt_.fields.is_empty() {
+                    consteval::eval_with(&stmt_.expr, &constants).unwrap_or(None)
+                } else {
+                    None
+                };
+                set(&mut constants, &stmt_.id, value);
+            }
+            Node::Stmt(&Stmt::Destructure(ref stmt_)) => {
+                for target in &stmt_.targets {
+                    constants.remove(&target.id);
+                }
+            }
+            _ => {
+                for id in uses_and_defs(node).1 {
+                    constants.remove(&id);
+                }
+            }
+        }
+        Constants(Some(constants))
+    }
+}
+
+fn set(constants: &mut HashMap<String, Value>, id: &str, value: Option<Value>) {
+    match value {
+        Some(value) => { constants.insert(id.to_string(), value); }
+        None => { constants.remove(id); }
+    }
+}
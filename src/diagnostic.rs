@@ -0,0 +1,172 @@
+//! Diagnostics reported by passes that do not abort compilation.
+
+use std::fmt;
+
+use json;
+use pos::Pos;
+use source::FileId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// The warnings, by stable ID and name.  `# minilang: allow` comments
+/// (see suppress.rs) and `--allow`/`--deny` refer to them by either.
+/// An ID is never reused or renumbered: a new warning takes the next
+/// one.
+pub const LINTS: [(&'static str, &'static str); 3] = [
+    ("W0001", "lossy-conversion"),
+    ("W0002", "endless-loop"),
+    ("W0003", "invalid-directive"),
+];
+
+/// The name of the warning with the given name or ID.
+pub fn find_lint(name_or_id: &str) -> Option<&'static str> {
+    LINTS.iter().find(|&&(id, name)| id == name_or_id || name == name_or_id).map(|&(_, name)| name)
+}
+
+/// The ID of the warning named `lint`, one of `LINTS`.
+pub fn lint_id(lint: &str) -> &'static str {
+    LINTS.iter().find(|&&(_, name)| name == lint).map(|&(id, _)| id).expect("unknown lint")
+}
+
+/// The names and IDs of the warnings, for error messages.
+pub fn lint_list() -> String {
+    let lints: Vec<String> = LINTS.iter().map(|&(id, name)| format!("{} ({})", name, id)).collect();
+    lints.join(", ")
+}
+
+/// A message attached to a source position, with other positions
+/// that explain it (e.g. the first declaration of a duplicate
+/// variable).
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The file of `pos` and the labels, if the diagnostic was reported
+    /// with a `SourceManager` (see source.rs).
+    pub file: Option<FileId>,
+    pub pos: Pos,
+    pub message: String,
+    pub labels: Vec<Label>,
+    /// The name of the warning, one of `LINTS`.
+    pub lint: Option<&'static str>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// A secondary position of a diagnostic.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub pos: Pos,
+    pub message: String,
+}
+
+/// A change of the source that would fix a diagnostic: `replacement`
+/// takes the place of the text from `start` up to `end`.  `fix` makes
+/// the changes that are machine-applicable, those that are certainly
+/// what the programmer meant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub start: Pos,
+    pub end: Pos,
+    pub replacement: String,
+    pub message: String,
+    pub applicable: bool,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, pos: Pos, message: String) -> Self {
+        Diagnostic { severity: severity, file: None, pos: pos, message: message, labels: Vec::new(), lint: None,
+                     suggestions: Vec::new() }
+    }
+
+    pub fn error(pos: Pos, message: String) -> Self {
+        Diagnostic::new(Severity::Error, pos, message)
+    }
+
+    pub fn warning(pos: Pos, message: String) -> Self {
+        Diagnostic::new(Severity::Warning, pos, message)
+    }
+
+    pub fn note(pos: Pos, message: String) -> Self {
+        Diagnostic::new(Severity::Note, pos, message)
+    }
+
+    pub fn with_label(mut self, pos: Pos, message: String) -> Self {
+        self.labels.push(Label { pos: pos, message: message });
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    pub fn with_lint(mut self, lint: &'static str) -> Self {
+        self.lint = Some(lint);
+        self
+    }
+
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        self
+    }
+}
+
+/// The diagnostic on one line, with the ID and name of the warning, followed
+/// by its labels and suggestions, indented.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}: {}: {}", self.pos, self.severity, self.message));
+        if let Some(lint) = self.lint {
+            try!(write!(f, " [{} {}]", lint_id(lint), lint));
+        }
+        for label in &self.labels {
+            try!(write!(f, "\n    {}: {}", label.pos, label.message));
+        }
+        for suggestion in &self.suggestions {
+            try!(write!(f, "\n    {}: help: {}", suggestion.start, suggestion.message));
+        }
+        Ok(())
+    }
+}
+
+/// The diagnostics as a JSON array.
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::from("[");
+    for (i, d) in diagnostics.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        let labels: Vec<String> = d.labels.iter()
+            .map(|label| format!("{{\"line\": {}, \"col\": {}, \"message\": {}}}",
+                                 label.pos.line, label.pos.col, json::quote(&label.message)))
+            .collect();
+        let suggestions: Vec<String> = d.suggestions.iter()
+            .map(|s| format!("{{\"line\": {}, \"col\": {}, \"end_line\": {}, \"end_col\": {}, \
+                              \"replacement\": {}, \"message\": {}, \"applicable\": {}}}",
+                             s.start.line, s.start.col, s.end.line, s.end.col,
+                             json::quote(&s.replacement), json::quote(&s.message), s.applicable))
+            .collect();
+        let (code, lint) = match d.lint {
+            Some(lint) => (json::quote(lint_id(lint)), json::quote(lint)),
+            None => ("null".to_string(), "null".to_string()),
+        };
+        out.push_str(&format!("  {{\"severity\": \"{}\", \"code\": {}, \"lint\": {}, \"line\": {}, \"col\": {}, \
+                               \"message\": {}, \"labels\": [{}], \"suggestions\": [{}]}}",
+                              d.severity, code, lint, d.pos.line, d.pos.col, json::quote(&d.message),
+                              labels.join(", "), suggestions.join(", ")));
+    }
+    out.push_str("\n]\n");
+    out
+}
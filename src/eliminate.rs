@@ -0,0 +1,125 @@
+// Removal of unused global variables before code generation.
+//
+// A declaration is removed when its variable is never mentioned by the
+// statements or by the initializers of the other declarations, and its
+// initializer (if any) is constant, so that removing it cannot remove a
+// run-time error.  Multi-module builds are the main beneficiaries: a
+// module often declares variables that the program does not use.
+//
+// Functions cannot mention the globals, so only the program body
+// counts.  The functions that are not reachable from the body in the
+// call graph should be removed here too; for now they are all kept.
+
+use std::collections::HashSet;
+use std::mem;
+
+use ast::*;
+use consteval;
+use pos::Pos;
+use typecheck::Symtable;
+
+// Remove the unused globals of `program` and their symbols, and return
+// their positions and names in declaration order.
+pub fn dead_globals(program: &mut Program, symtable: &mut Symtable) -> Vec<(Pos, String)> {
+    let used = used_variables(program);
+    let mut removed = Vec::new();
+    let decls = mem::replace(&mut program.decls, Vec::new());
+    for decl in decls {
+        let pure = match decl.init {
+            Some(ref init) => consteval::eval(init).map(|v| v.is_some()).unwrap_or(false),
+            None => true,
+        };
+        if pure && !used.contains(&decl.id) {
+            symtable.remove(&decl.id);
+            removed.push((decl.pos, decl.id));
+        } else {
+            program.decls.push(decl);
+        }
+    }
+    removed
+}
+
+// The variables mentioned by the statements and the initializers of
+// `program`.
+pub fn used_variables(program: &Program) -> HashSet<String> {
+    let mut used = HashSet::new();
+    for decl in &program.decls {
+        if let Some(ref init) = decl.init {
+            expr_uses(init, &mut used);
+        }
+    }
+    stmts_use(&program.stmts, &mut used);
+    used
+}
+
+fn stmts_use(stmts: &[Stmt], used: &mut HashSet<String>) {
+    for stmt in stmts {
+        match *stmt {
+            Stmt::Read(ref stmt_) => used.extend(stmt_.ids.iter().cloned()),
+            Stmt::Print(ref stmt_) => expr_uses(&stmt_.expr, used),
+            Stmt::Exit(ref stmt_) => expr_uses(&stmt_.expr, used),
+            Stmt::Call(ref stmt_) => expr_uses(&stmt_.expr, used),
+            Stmt::Return(ref stmt_) => {
+                if let Some(ref expr) = stmt_.expr {
+                    expr_uses(expr, used);
+                }
+            }
+            Stmt::Assign(ref stmt_) => {
+                used.insert(stmt_.id.clone());
+                expr_uses(&stmt_.expr, used);
+            }
+            Stmt::Destructure(ref stmt_) => {
+                used.extend(stmt_.targets.iter().map(|target| target.id.clone()));
+                expr_uses(&stmt_.expr, used);
+            }
+            Stmt::If(ref stmt_) => {
+                expr_uses(&stmt_.expr, used);
+                stmts_use(&stmt_.then_stmts, used);
+                stmts_use(&stmt_.else_stmts, used);
+            }
+            Stmt::While(ref stmt_) => {
+                expr_uses(&stmt_.expr, used);
+                stmts_use(&stmt_.stmts, used);
+            }
+            Stmt::For(ref stmt_) => {
+                used.insert(stmt_.id.clone());
+                expr_uses(&stmt_.first, used);
+                expr_uses(&stmt_.last, used);
+                stmts_use(&stmt_.stmts, used);
+            }
+            Stmt::Error(_) => {}
+        }
+    }
+}
+
+// The operands of a chain of operators are looked at in a loop (see
+// `Expr::left_spine`).
+fn expr_uses(expr: &Expr, used: &mut HashSet<String>) {
+    let (spine, expr) = expr.left_spine();
+    for op in spine {
+        match op.expr {
+            Expr_::Binop(ref expr_) => expr_uses(&expr_.expr2, used),
+            Expr_::Logic(ref expr_) => expr_uses(&expr_.expr2, used),
+            _ => unreachable!(),
+        }
+    }
+    match expr.expr {
+        Expr_::Id(ref expr_) => { used.insert(expr_.id.clone()); }
+        Expr_::Negate(ref expr_) => expr_uses(&expr_.expr, used),
+        Expr_::ToString(ref expr_) => expr_uses(&expr_.expr, used),
+        Expr_::Field(ref expr_) => expr_uses(&expr_.expr, used),
+        Expr_::Tuple(ref expr_) => {
+            for e in &expr_.exprs {
+                expr_uses(e, used);
+            }
+        }
+        Expr_::Call(ref expr_) => {
+            for e in &expr_.args {
+                expr_uses(e, used);
+            }
+        }
+        Expr_::Binop(_) | Expr_::Logic(_) => unreachable!(),
+        Expr_::Not(ref expr_) => expr_uses(&expr_.expr, used),
+        Expr_::Int(_) | Expr_::Float(_) | Expr_::String(_) | Expr_::Bool(_) | Expr_::Error => {}
+    }
+}
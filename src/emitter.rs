@@ -0,0 +1,67 @@
+// Writes generated code line by line, indenting it by block depth.
+//
+// A line that ends with '{' opens a block and a line that starts with
+// '}' closes one, so the code generators never deal with indentation.
+// Blank lines separate top-level definitions and the groups that the
+// generators ask for with `blank`; there is never more than one in a
+// row, nor one at the start.  Compact output has neither indentation
+// nor blank lines.
+
+pub struct Emitter {
+    out: String,
+    lines: usize,
+    depth: usize,
+    compact: bool,
+    // Whether a blank line goes before the next line.
+    blank_pending: bool,
+}
+
+const INDENT: &'static str = "    ";
+
+impl Emitter {
+    pub fn new(compact: bool) -> Self {
+        Emitter { out: String::new(), lines: 0, depth: 0, compact: compact, blank_pending: false }
+    }
+
+    // Write a line, without its leading whitespace, and return its
+    // number.  An empty line is the same as `blank`.
+    pub fn line(&mut self, text: &str) -> usize {
+        let text = text.trim_start();
+        if text.is_empty() {
+            self.blank();
+            return self.lines;
+        }
+        if text.starts_with('}') {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        if self.blank_pending && !self.compact && self.lines > 0 && !text.starts_with('}') {
+            self.out.push('\n');
+            self.lines += 1;
+        }
+        self.blank_pending = false;
+        if !self.compact {
+            for _ in 0..self.depth {
+                self.out.push_str(INDENT);
+            }
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.lines += 1;
+        if text.ends_with('{') {
+            self.depth += 1;
+        } else if self.depth == 0 && text.starts_with('}') {
+            // The end of a top-level definition.
+            self.blank();
+        }
+        self.lines
+    }
+
+    // Ask for a blank line before the next line.
+    pub fn blank(&mut self) {
+        self.blank_pending = true;
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
@@ -1,8 +1,11 @@
 use std::fmt;
 use std::fmt::Display;
+use std::io;
 
 use ast;
+use parser;
 use pos::Pos;
+use scanner;
 use token::{Token, TokenType};
 use types::Type;
 
@@ -14,17 +17,120 @@ pub enum Error {
     // Scanner errors
     IllegalCharacter(Pos, char),
     UnterminatedString(Pos),
+    LiteralTooLong(Pos, Pos, usize),
+    InvalidEncoding(Pos),
+    InvalidEscape(Pos, char),
 
     // Parser errors
     UnexpectedToken(Token, Vec<TokenType>), // Token contains position
     InvalidIntLiteral(Pos, String),
     InvalidFloatLiteral(Pos, String),
+    PrecisionTooLarge(Pos, String),
+    IntLiteralOverflow(Pos, Pos, String, Type),
+    MissingInitializer(Pos, String),
+    NestingTooDeep(Pos, usize),
+    ChainTooLong(Pos, usize),
+    TooManyNodes(Pos, usize),
+    SyntaxErrorNode(Pos),
+    NestedDefinition(Pos),
 
     // Typechecking errors
     UnexpectedType { pos: Pos, expected: Type, actual: Type },
     IllTypedBinop { pos: Pos, op: ast::Binop, lhs: Type, rhs: Type },
+    IllTypedUnop { pos: Pos, op: &'static str, ty: Type },
     DuplicateVariable(Pos, String),
     UndeclaredVariable(Pos, String),
+    DuplicateRecord(Pos, String),
+    DuplicateField(Pos, String),
+    UnknownType(Pos, String),
+    UnknownField(Pos, Type, String),
+    UnsupportedOperation(Pos, &'static str, Type),
+    ArityMismatch(Pos, usize, usize),
+    DuplicateFunction(Pos, String),
+    UndeclaredFunction(Pos, String),
+    ArgumentCount(Pos, String, usize, usize),
+    NoValue(Pos, String),
+    MissingReturn(Pos, String),
+    MissingReturnValue(Pos, String, Type),
+    UnexpectedReturn(Pos, String),
+    ReturnOutsideFunction(Pos),
+    ExitInFunction(Pos),
+
+    // Driver errors
+    Io(String, io::Error),
+    InvalidPosition(String),
+    InvalidArgument(&'static str, String),
+    ConflictingArguments(&'static str, &'static str),
+    NoExpressionAt(Pos),
+    InvalidSelector(String, String),
+    InvalidManifest(usize, String),
+    InvalidBinaryAst(usize, String),
+    InvalidJson(usize, String),
+    InvalidSession(String),
+    InvalidBaseline(String),
+    InvalidSpec(String),
+    InvalidToken(String),
+    InFile(String, Box<Error>),
+    UnknownPass(String, Vec<&'static str>),
+    UnknownTarget(String, Vec<&'static str>),
+    PassFailed(&'static str),
+    CCompilerFailed(String),
+    CannotExecute(&'static str),
+
+    // Runtime errors
+    DivisionByZero(Pos),
+    IntegerOverflow(Pos),
+    EndOfInput(Pos),
+    InvalidInput(Pos, String, Type),
+    LimitExceeded(Pos, String),
+
+    // Internal errors
+    PassVerification(&'static str, String),
+}
+
+impl Error {
+    // The source position of the error, if it has one.
+    pub fn pos(&self) -> Option<Pos> {
+        match *self {
+            Error::IllegalCharacter(pos, _) | Error::UnterminatedString(pos) | Error::InvalidEncoding(pos) |
+            Error::InvalidEscape(pos, _) |
+            Error::InvalidIntLiteral(pos, _) | Error::InvalidFloatLiteral(pos, _) |
+            Error::PrecisionTooLarge(pos, _) |
+            Error::MissingInitializer(pos, _) | Error::DuplicateVariable(pos, _) |
+            Error::NestingTooDeep(pos, _) | Error::ChainTooLong(pos, _) | Error::TooManyNodes(pos, _) |
+            Error::SyntaxErrorNode(pos) |
+            Error::UndeclaredVariable(pos, _) | Error::DuplicateRecord(pos, _) |
+            Error::DuplicateField(pos, _) | Error::UnknownType(pos, _) |
+            Error::UnknownField(pos, _, _) | Error::UnsupportedOperation(pos, _, _) |
+            Error::ArityMismatch(pos, _, _) | Error::NoExpressionAt(pos) | Error::NestedDefinition(pos) |
+            Error::DuplicateFunction(pos, _) | Error::UndeclaredFunction(pos, _) |
+            Error::ArgumentCount(pos, _, _, _) | Error::NoValue(pos, _) | Error::MissingReturn(pos, _) |
+            Error::MissingReturnValue(pos, _, _) | Error::UnexpectedReturn(pos, _) |
+            Error::ReturnOutsideFunction(pos) | Error::ExitInFunction(pos) |
+            Error::DivisionByZero(pos) | Error::IntegerOverflow(pos) | Error::EndOfInput(pos) |
+            Error::InvalidInput(pos, _, _) | Error::LimitExceeded(pos, _) => Some(pos),
+            Error::UnexpectedType { pos, .. } | Error::IllTypedBinop { pos, .. } |
+            Error::IllTypedUnop { pos, .. } => Some(pos),
+            Error::LiteralTooLong(pos, _, _) | Error::IntLiteralOverflow(pos, _, _, _) => Some(pos),
+            Error::UnexpectedToken(ref tok, _) => Some(tok.pos),
+            _ => None,
+        }
+    }
+
+    // The description of the error, without its position.
+    pub fn message(&self) -> String {
+        let text = self.to_string();
+        match self.pos() {
+            Some(pos) => {
+                let prefix = format!("{}: ", pos);
+                if text.starts_with(&prefix) {
+                    return text[prefix.len()..].to_string();
+                }
+                text
+            }
+            None => text,
+        }
+    }
 }
 
 impl Display for Error {
@@ -40,33 +146,162 @@ impl Display for Error {
                 write!(f, "{}: unterminated string literal", pos)
             }
 
+            Error::LiteralTooLong(pos, end, len) => {
+                write!(f, "{}: Numeric literal too long: {} characters, up to {}; the limit is {}",
+                       pos, len, end, scanner::MAX_LITERAL_LENGTH)
+            }
+
+            Error::InvalidEscape(pos, c) => {
+                write!(f, "{}: Invalid escape sequence: '\\{}'. Expected: \\n, \\t, \\0, \\\\ or \\\"", pos, c)
+            }
+
+            Error::InvalidEncoding(pos) => {
+                write!(f, "{}: Invalid UTF-8; source files must be encoded in UTF-8", pos)
+            }
+
             Error::UnexpectedToken(ref tok, ref choices) => {
-                let _ = write!(f, "{}: Unexpected token. Found: {}. Expected: ", tok.pos, tok);
-                let mut not_first = false;
-                for choice in choices {
-                    if not_first {
-                        let _ = write!(f, ", ");
+                try!(write!(f, "{}: Unexpected token. Found: {}. Expected: ", tok.pos, tok));
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ", "));
                     }
-                    not_first = true;
-                    let _ = write!(f, "{}", choice);
+                    try!(write!(f, "{}", choice));
                 }
-                write!(f, "")
+                Ok(())
             }
             Error::InvalidIntLiteral(pos, ref s) =>
                 write!(f, "{}: Invalid integer literal: '{}'", pos, s),
             Error::InvalidFloatLiteral(pos, ref s) =>
                 write!(f, "{}: Invalid float literal: '{}'", pos, s),
+            Error::PrecisionTooLarge(pos, ref s) =>
+                write!(f, "{}: Precision too large: {} digits; the limit is {}", pos, s, parser::MAX_PRECISION),
+            Error::IntLiteralOverflow(pos, end, ref s, ref ty) =>
+                write!(f, "{}: Integer literal '{}' (up to {}) does not fit in {}", pos, s, end, ty),
+            Error::NestingTooDeep(pos, max) =>
+                write!(f, "{}: Program nested too deeply: more than {} levels of expressions and statements", pos, max),
+            Error::ChainTooLong(pos, max) =>
+                write!(f, "{}: Expression too long: more than {} operators in chains like 'a + b + c'", pos, max),
+            Error::TooManyNodes(pos, max) =>
+                write!(f, "{}: Program too large: more than {} expressions and statements", pos, max),
+            Error::SyntaxErrorNode(pos) =>
+                write!(f, "{}: This part of the program has a syntax error", pos),
+            Error::NestedDefinition(pos) =>
+                write!(f, "{}: Records and functions can only be defined at the top level of the program", pos),
+            Error::MissingInitializer(pos, ref id) =>
+                write!(f, "{}: Cannot infer the type of '{}' without an initializer; write 'var {}: TYPE;' or 'var {} = EXPR;'",
+                       pos, id, id, id),
 
-            Error::UnexpectedType { pos, expected, actual } =>
+            Error::UnexpectedType { pos, ref expected, ref actual } =>
                 write!(f, "{}: Unexpected type. Found: {}. Expected: {}.", pos, actual, expected),
 
-            Error::IllTypedBinop { pos, op, lhs, rhs } =>
+            Error::IllTypedBinop { pos, op, ref lhs, ref rhs } =>
                 write!(f, "{}: Operation '{}' not supported between {} and {}", pos, op, lhs, rhs),
 
+            Error::IllTypedUnop { pos, op, ref ty } =>
+                write!(f, "{}: Operation '{}' not supported on {}", pos, op, ty),
+
             Error::DuplicateVariable(pos, ref id) =>
                 write!(f, "{}: Duplicate variable declaration: {}", pos, id),
             Error::UndeclaredVariable(pos, ref id) =>
                 write!(f, "{}: Undeclared variable: {}", pos, id),
+
+            Error::DuplicateRecord(pos, ref id) =>
+                write!(f, "{}: Duplicate record: {}", pos, id),
+
+            Error::DuplicateField(pos, ref id) =>
+                write!(f, "{}: Duplicate field: {}", pos, id),
+
+            Error::UnknownType(pos, ref id) =>
+                write!(f, "{}: Unknown type: {}", pos, id),
+
+            Error::UnknownField(pos, ref ty, ref id) =>
+                write!(f, "{}: Type {} has no field {}", pos, ty, id),
+
+            Error::UnsupportedOperation(pos, op, ref ty) =>
+                write!(f, "{}: Cannot {} a value of type {}", pos, op, ty),
+
+            Error::ArityMismatch(pos, expected, actual) =>
+                write!(f, "{}: Expected {} values, found {}", pos, expected, actual),
+
+            Error::DuplicateFunction(pos, ref id) =>
+                write!(f, "{}: Duplicate function: {}", pos, id),
+            Error::UndeclaredFunction(pos, ref id) =>
+                write!(f, "{}: Undeclared function: {}", pos, id),
+            Error::ArgumentCount(pos, ref id, expected, actual) =>
+                write!(f, "{}: Function {} takes {} argument{}, found {}", pos, id, expected,
+                       if expected == 1 { "" } else { "s" }, actual),
+            Error::NoValue(pos, ref id) =>
+                write!(f, "{}: Function {} returns no value", pos, id),
+            Error::MissingReturn(pos, ref id) =>
+                write!(f, "{}: Function {} has a return type but can end without 'return'", pos, id),
+            Error::MissingReturnValue(pos, ref id, ref ty) =>
+                write!(f, "{}: Function {} must return a value of type {}", pos, id, ty),
+            Error::UnexpectedReturn(pos, ref id) =>
+                write!(f, "{}: Function {} has no return type and cannot return a value", pos, id),
+            Error::ReturnOutsideFunction(pos) =>
+                write!(f, "{}: 'return' can only be used in a function", pos),
+            Error::ExitInFunction(pos) =>
+                write!(f, "{}: 'exit' can only be used in the statements of the program, not in a function", pos),
+
+            Error::Io(ref path, ref err) =>
+                write!(f, "{}: {}", path, err),
+            Error::InvalidPosition(ref s) =>
+                write!(f, "Invalid position: '{}'. Expected: LINE:COL", s),
+            Error::InvalidArgument(arg, ref value) =>
+                write!(f, "Invalid value for {}: '{}'", arg, value),
+            Error::ConflictingArguments(arg1, arg2) =>
+                write!(f, "{} cannot be used with {}", arg1, arg2),
+            Error::NoExpressionAt(pos) =>
+                write!(f, "{}: No expression at this position", pos),
+            Error::InvalidSelector(ref sel, ref msg) =>
+                write!(f, "Invalid selector '{}': {}", sel, msg),
+            Error::InvalidManifest(0, ref msg) =>
+                write!(f, "Invalid manifest: {}", msg),
+            Error::InvalidManifest(line, ref msg) =>
+                write!(f, "{}: Invalid manifest: {}", line, msg),
+            Error::InvalidBinaryAst(offset, ref msg) =>
+                write!(f, "Invalid binary AST at byte {}: {}", offset, msg),
+            Error::InvalidJson(offset, ref msg) =>
+                write!(f, "Invalid JSON at byte {}: {}", offset, msg),
+            Error::InvalidSession(ref msg) =>
+                write!(f, "Invalid session: {}", msg),
+            Error::InvalidBaseline(ref msg) =>
+                write!(f, "Invalid benchmark baseline: {}", msg),
+            Error::InvalidSpec(ref msg) =>
+                write!(f, "Invalid spec: {}", msg),
+            Error::InvalidToken(ref msg) =>
+                write!(f, "Invalid token: {}", msg),
+            // Errors with a position print it first, after the path.
+            Error::InFile(ref path, ref err) => match **err {
+                Error::InvalidManifest(line, _) if line > 0 => write!(f, "{}:{}", path, err),
+                _ if err.pos().is_some() => write!(f, "{}:{}", path, err),
+                _ => write!(f, "{}: {}", path, err),
+            },
+            Error::UnknownPass(ref name, ref passes) =>
+                write!(f, "Unknown pass: '{}'. Expected: {}", name, passes.join(", ")),
+            Error::UnknownTarget(ref name, ref targets) =>
+                write!(f, "Unknown target: '{}'. Expected: {}", name, targets.join(", ")),
+            Error::PassFailed(pass) =>
+                write!(f, "Aborting after pass '{}' due to previous errors", pass),
+            Error::CCompilerFailed(ref cc) =>
+                write!(f, "The C compiler '{}' failed on the generated code", cc),
+            Error::CannotExecute(target) =>
+                write!(f, "The code generated for target '{}' cannot be run", target),
+
+            Error::DivisionByZero(pos) =>
+                write!(f, "{}: Division by zero", pos),
+            Error::IntegerOverflow(pos) =>
+                write!(f, "{}: Integer overflow", pos),
+            Error::EndOfInput(pos) =>
+                write!(f, "{}: Read past the end of the input", pos),
+            Error::InvalidInput(pos, ref word, ref ty) =>
+                write!(f, "{}: Invalid input for a variable of type {}: '{}'", pos, ty, word),
+
+            Error::LimitExceeded(pos, ref what) =>
+                write!(f, "{}: Resource limit exceeded: {}", pos, what),
+
+            Error::PassVerification(pass, ref msg) =>
+                write!(f, "Internal error: verification failed after pass '{}': {}", pass, msg),
         }
     }
 }
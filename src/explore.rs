@@ -0,0 +1,165 @@
+// A compiler-explorer page: one program at every stage, side by side.
+//
+// `explore FILE` writes an HTML page with four columns: the source, the
+// typed AST (each node with its node id and, for expressions and
+// declarations, its type), the control flow graph of cfg.rs, which is
+// the closest thing to an IR that the compiler has, and the code of the
+// backend (`--target`, C by default).  Every element of the last three
+// columns carries the node id of the AST node it comes from and the
+// source line of that node, through the source map for the generated
+// code, so that hovering over it highlights the same node in the other
+// columns and its line in the source; hovering over a source line
+// highlights everything that comes from it.  The page is a single file
+// without external resources, to be opened in a browser in class.
+
+use ast::Program;
+use cfg::{Cfg, Node as CfgNode};
+use pos::Pos;
+use query::Node;
+use scanner;
+use sourcemap::Mapping;
+use typecheck::{Exprtable, Symtable};
+
+const STYLE: &'static str = "
+body { font-family: sans-serif; margin: 0; }
+h1 { font-size: 1.1em; margin: 0.5em; }
+main { display: grid; grid-template-columns: repeat(4, 1fr); gap: 0.5em; margin: 0.5em; }
+h2 { font-size: 1em; margin: 0 0 0.3em 0; }
+pre { margin: 0; padding: 0.3em; background: #f6f6f6; overflow-x: auto; font-size: 0.85em; }
+pre > div { white-space: pre; }
+.lineno { color: #999; user-select: none; }
+.type { color: #277; }
+.id { color: #999; }
+.hl { background: #ffe38a; }
+";
+
+const SCRIPT: &'static str = "
+function highlight(selector, on) {
+  document.querySelectorAll(selector).forEach(function (e) { e.classList.toggle('hl', on); });
+}
+document.querySelectorAll('[data-line]').forEach(function (e) {
+  var selector = e.dataset.node ? '[data-node=\"' + e.dataset.node + '\"], .src[data-line=\"' + e.dataset.line + '\"]'
+                                : '[data-line=\"' + e.dataset.line + '\"]';
+  e.addEventListener('mouseover', function (ev) { ev.stopPropagation(); highlight(selector, true); });
+  e.addEventListener('mouseout', function () { highlight(selector, false); });
+});
+";
+
+pub fn page(title: &str, source: &str, program: &Program, symtable: &Symtable, exprtable: &Exprtable,
+            target: &str, code: &str, source_map: &[Mapping]) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n", escape(title), STYLE));
+    out.push_str(&format!("<h1>{}</h1>\n<main>\n", escape(title)));
+
+    column(&mut out, "Source", &source_lines(source));
+
+    let mut ast = Vec::new();
+    for decl in &program.decls {
+        ast_lines(Node::Decl(decl), 0, symtable, exprtable, &mut ast);
+    }
+    for func in &program.funcs {
+        ast_lines(Node::Func(func), 0, symtable, exprtable, &mut ast);
+    }
+    for stmt in &program.stmts {
+        ast_lines(Node::Stmt(stmt), 0, symtable, exprtable, &mut ast);
+    }
+    column(&mut out, "Typed AST", &ast);
+
+    column(&mut out, "Control flow graph", &cfg_lines(&Cfg::new(program)));
+
+    let code: Vec<String> = code.lines().enumerate()
+        .map(|(i, text)| {
+            let text = format!("<span class=\"lineno\">{:>4} </span>{}", i + 1, escape(text));
+            match source_map.iter().find(|m| m.line == i + 1) {
+                Some(m) => linked(m.node_id, m.pos, &text),
+                None => format!("<div>{}</div>", text),
+            }
+        })
+        .collect();
+    column(&mut out, &format!("Generated code ({})", target), &code);
+
+    out.push_str(&format!("</main>\n<script>{}</script>\n</body>\n</html>\n", SCRIPT));
+    out
+}
+
+fn column(out: &mut String, heading: &str, lines: &[String]) {
+    out.push_str(&format!("<section>\n<h2>{}</h2>\n<pre>\n", escape(heading)));
+    for line in lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</section>\n");
+}
+
+fn source_lines(source: &str) -> Vec<String> {
+    source.lines().enumerate()
+        .map(|(i, text)| format!("<div class=\"src\" data-line=\"{0}\"><span class=\"lineno\">{0:>4} </span>{1}</div>",
+                                 i + 1, escape(text)))
+        .collect()
+}
+
+// The node and its descendants, one per line, indented by depth.
+fn ast_lines(node: Node, depth: usize, symtable: &Symtable, exprtable: &Exprtable, out: &mut Vec<String>) {
+    let (node_id, ty) = match node {
+        Node::Decl(decl) => (decl.node_id, symtable.get(&decl.id).map(|sym| sym.ty.clone())),
+        Node::Func(func) => (func.node_id, func.ret.clone()),
+        Node::Stmt(stmt) => (stmt.node_id(), None),
+        Node::Expr(expr) => (expr.node_id, exprtable.get(&expr.node_id).cloned()),
+    };
+    let mut text = format!("{:w$}{}", "", escape(&node.describe()), w = depth * 2);
+    if let Some(value) = node.attr("value") {
+        let value = if node.name() == Some("string") { scanner::quote(&value) } else { value };
+        text.push_str(&format!(" {}", escape(&value)));
+    }
+    if let Some(ty) = ty {
+        text.push_str(&format!(" <span class=\"type\">: {}</span>", escape(&ty.to_string())));
+    }
+    text.push_str(&format!(" <span class=\"id\">#{}</span>", node_id));
+    out.push(linked(node_id, node.pos(), &text));
+    for child in node.children() {
+        ast_lines(child, depth + 1, symtable, exprtable, out);
+    }
+}
+
+// The nodes of the graph with their successors.
+fn cfg_lines(cfg: &Cfg) -> Vec<String> {
+    cfg.nodes.iter().enumerate()
+        .map(|(i, node)| {
+            let succs: Vec<String> = cfg.succs[i].iter().map(|s| format!("n{}", s)).collect();
+            let arrow = if succs.is_empty() { String::new() } else { format!(" -&gt; {}", succs.join(", ")) };
+            let (label, ast) = match *node {
+                CfgNode::Entry => ("entry".to_string(), None),
+                CfgNode::Exit => ("exit".to_string(), None),
+                CfgNode::Param(param) => (format!("param {}", param.id), None),
+                CfgNode::Decl(decl) => (Node::Decl(decl).describe(), Some((decl.node_id, decl.pos))),
+                CfgNode::Stmt(stmt) => (Node::Stmt(stmt).describe(), Some((stmt.node_id(), stmt.pos()))),
+                CfgNode::Cond(stmt) => (format!("cond of {}", Node::Stmt(stmt).describe()),
+                                        Some((stmt.node_id(), stmt.pos()))),
+            };
+            let text = format!("n{}: {}{}", i, escape(&label), arrow);
+            match ast {
+                Some((node_id, pos)) => linked(node_id, pos, &text),
+                None => format!("<div>{}</div>", text),
+            }
+        })
+        .collect()
+}
+
+fn linked(node_id: u64, pos: Pos, html: &str) -> String {
+    format!("<div data-node=\"{}\" data-line=\"{}\">{}</div>", node_id, pos.line, html)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
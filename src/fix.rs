@@ -0,0 +1,127 @@
+// Applying the suggestions of diagnostics.
+//
+// `fix` compiles a program up to type checking, makes the changes that
+// the machine-applicable suggestions of the diagnostics ask for, and
+// starts over until there are none, since a change can bring out
+// other errors: the type errors of a program are only reported once it
+// parses.
+
+use std::cmp;
+
+use diagnostic::Suggestion;
+use pass::{self, Context};
+use parser::Syntax;
+use pos::Pos;
+use scanner::IdentifierRules;
+
+// The number of rounds after which `fix` gives up, in case fixes undo
+// each other.
+const MAX_ROUNDS: usize = 100;
+
+// The program with its fixes, and the suggestions applied, in order.
+pub fn fix(source: &str, syntax: Syntax, identifiers: IdentifierRules, strict: bool)
+           -> (String, Vec<Suggestion>) {
+    let mut source = source.to_string();
+    let mut applied = Vec::new();
+    for _ in 0..MAX_ROUNDS {
+        let mut ctx = Context::new(source.clone());
+        ctx.syntax = syntax;
+        ctx.identifiers = identifiers;
+        ctx.strict = strict;
+        let mut pm = pass::standard_pipeline();
+        let _ = pm.stop_after("typecheck");
+        let _ = pm.run(&mut ctx);
+        let suggestions: Vec<Suggestion> = ctx.diagnostics.into_iter()
+            .flat_map(|d| d.suggestions)
+            .filter(|s| s.applicable)
+            .collect();
+        if suggestions.is_empty() {
+            break;
+        }
+        let (fixed, used) = apply(&source, suggestions);
+        source = fixed;
+        applied.extend(used);
+    }
+    (source, applied)
+}
+
+// Make the changes of `suggestions`, except those that overlap an
+// earlier one or repeat it, and return the result with the suggestions
+// used.
+pub fn apply(source: &str, mut suggestions: Vec<Suggestion>) -> (String, Vec<Suggestion>) {
+    suggestions.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+    let mut out = String::new();
+    let mut used = Vec::new();
+    let mut copied = 0;
+    for suggestion in suggestions {
+        let start = offset(source, suggestion.start);
+        let end = cmp::max(start, offset(source, suggestion.end));
+        if start < copied || used.last() == Some(&suggestion) {
+            continue;
+        }
+        out.push_str(&source[copied..start]);
+        out.push_str(&suggestion.replacement);
+        copied = end;
+        used.push(suggestion);
+    }
+    out.push_str(&source[copied..]);
+    (out, used)
+}
+
+// The byte offset of `pos` in `source`, counting lines and columns like
+// the scanner does.
+fn offset(source: &str, pos: Pos) -> usize {
+    let mut current = Pos { line: 1, col: 1 };
+    for (i, c) in source.char_indices() {
+        if current >= pos {
+            return i;
+        }
+        if c == '\n' {
+            current.line += 1;
+            current.col = 1;
+        } else {
+            current.col += 1;
+        }
+    }
+    source.len()
+}
+
+// The name of `candidates` closest to `name`, if there is only one and
+// it differs by at most a third of the letters of `name`.
+pub fn similar_name<'a, I: Iterator<Item = &'a String>>(name: &str, candidates: I) -> Option<&'a String> {
+    let limit = cmp::max(1, name.chars().count() / 3);
+    let mut best = None;
+    let mut best_distance = limit + 1;
+    let mut unique = false;
+    for candidate in candidates {
+        let distance = edit_distance(name, candidate);
+        if distance < best_distance {
+            best = Some(candidate);
+            best_distance = distance;
+            unique = true;
+        } else if distance == best_distance {
+            unique = false;
+        }
+    }
+    if unique { best } else { None }
+}
+
+// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == b[j] {
+                diagonal
+            } else {
+                1 + cmp::min(diagonal, cmp::min(above, row[j]))
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,482 @@
+// A formatter for Minilang programs.
+//
+// `fmt FILE` prints the program laid out the same way whatever the
+// source looked like: one declaration or statement per line, blocks
+// indented by two spaces, one space around binary operators and after
+// commas.  It works on the CST, so that comments are kept: a comment
+// on a line of its own stays on a line of its own before what follows
+// it, and one at the end of a line stays at the end of that line.
+// Single blank lines between statements are kept too.
+//
+// Expressions are laid out by pretty.rs: one that does not fit in the
+// maximum width (`--max-width`, 80 by default) is broken before its
+// operators, the outermost ones first, and the contents of parentheses
+// go on lines of their own, indented:
+//
+//     total = first_quantity * first_price
+//       + second_quantity * second_price
+//       - discount;
+//
+// `fmt --range FIRST:LAST` only lays out the declarations and
+// statements that are within those lines, outermost first, and leaves
+// the rest of the file as it is, byte for byte; `range_edits` gives the
+// changes as edits, for an editor's "format selection".
+//
+// The formatted program is scanned again to check that it has the same
+// tokens as the source.
+
+use std::collections::HashSet;
+use std::mem;
+
+use cst::{self, CstNode, CstToken, Element, Span, TriviaKind};
+use error::Error;
+use parser::Syntax;
+use pretty::{self, Doc, concat, group, hardline, line, nest, softline, text};
+use scanner;
+use token::TokenType;
+
+pub const DEFAULT_MAX_WIDTH: usize = 80;
+
+const INDENT: usize = 2;
+
+pub fn format(source: &str, syntax: Syntax, max_width: usize) -> Result<String, Error> {
+    let tree = try!(cst::parse(source, syntax));
+    let mut formatter = Formatter { syntax: syntax, handled: HashSet::new() };
+    let doc = formatter.program(&tree.root);
+    let mut out = pretty::render(&doc, max_width);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    try!(check_tokens(source, &out));
+    Ok(out)
+}
+
+// A replacement of the text in `span`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Span,
+    pub text: String,
+}
+
+// The program with only the lines `first` to `last` (from 1) laid out.
+pub fn format_range(source: &str, syntax: Syntax, max_width: usize, first: usize, last: usize)
+                    -> Result<String, Error> {
+    let mut out = source.to_string();
+    for edit in try!(range_edits(source, syntax, max_width, first, last)).iter().rev() {
+        out = format!("{}{}{}", &out[..edit.span.start], edit.text, &out[edit.span.end..]);
+    }
+    try!(check_tokens(source, &out));
+    Ok(out)
+}
+
+// The edits that lay out the runs of declarations and statements that
+// are within the lines `first` to `last`, in the order of the source.
+// Each run keeps the indentation of its first line.
+pub fn range_edits(source: &str, syntax: Syntax, max_width: usize, first: usize, last: usize)
+                   -> Result<Vec<Edit>, Error> {
+    let tree = try!(cst::parse(source, syntax));
+    let lines = Lines { starts: line_starts(source), first: first, last: last };
+    let mut runs = Vec::new();
+    select(&tree.root, &lines, &mut runs);
+
+    let mut edits = Vec::new();
+    for run in runs {
+        let span = Span { start: run[0].span().start, end: run[run.len() - 1].span().end };
+        let line_start = lines.starts[lines.line(span.start) - 1];
+        let indent = source[line_start..span.start].chars().count();
+        let mut formatter = Formatter { syntax: syntax, handled: HashSet::new() };
+        let doc = formatter.run(&run);
+        let prefix: String = (0..indent).map(|_| ' ').collect();
+        let formatted = pretty::render(&concat(vec![text(prefix), nest(indent, doc)]), max_width);
+        let formatted = formatted[indent..].to_string();
+        if formatted != &source[span.start..span.end] {
+            edits.push(Edit { span: span, text: formatted });
+        }
+    }
+    Ok(edits)
+}
+
+// The byte offsets where the lines of a source start, and a range of
+// them.
+struct Lines {
+    starts: Vec<usize>,
+    first: usize,
+    last: usize,
+}
+
+impl Lines {
+    // The line of a byte offset, from 1.
+    fn line(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    fn within(&self, span: Span) -> bool {
+        span.end > span.start && self.line(span.start) >= self.first && self.line(span.end - 1) <= self.last
+    }
+
+    fn overlaps(&self, span: Span) -> bool {
+        span.end > span.start && self.line(span.start) <= self.last && self.line(span.end - 1) >= self.first
+    }
+}
+
+fn line_starts(source: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    starts
+}
+
+// Collect the runs of adjacent declarations, statements, records and
+// fields within the lines, not looking inside those that are.
+fn select<'a>(node: &'a CstNode, lines: &Lines, runs: &mut Vec<Vec<&'a CstNode>>) {
+    let mut run = Vec::new();
+    for child in &node.children {
+        match *child {
+            Element::Node(ref child) if is_item(child) && lines.within(child.span()) => {
+                run.push(child);
+                continue;
+            }
+            Element::Node(ref child) => {
+                flush(&mut run, runs);
+                if lines.overlaps(child.span()) {
+                    select(child, lines, runs);
+                }
+            }
+            Element::Token(_) => flush(&mut run, runs),
+        }
+    }
+    flush(&mut run, runs);
+}
+
+fn flush<'a>(run: &mut Vec<&'a CstNode>, runs: &mut Vec<Vec<&'a CstNode>>) {
+    if !run.is_empty() {
+        runs.push(mem::replace(run, Vec::new()));
+    }
+}
+
+fn is_item(node: &CstNode) -> bool {
+    match node.kind {
+        "record" | "field" | "declaration" | "function" | "statement" => true,
+        _ => false,
+    }
+}
+
+// The formatter must only change the trivia.
+fn check_tokens(source: &str, formatted: &str) -> Result<(), Error> {
+    let before = try!(scanner::tokenize(source));
+    let after = try!(scanner::tokenize(formatted));
+    let differ = before.iter().zip(&after).find(|&(a, b)| a.typ != b.typ || a.lexeme != b.lexeme);
+    match differ {
+        None if before.len() == after.len() => Ok(()),
+        _ => {
+            let pos = differ.map_or(before.last().unwrap().pos, |(a, _)| a.pos);
+            Err(Error::PassVerification("fmt", format!("{}: the formatted program has other tokens", pos)))
+        }
+    }
+}
+
+struct Formatter {
+    syntax: Syntax,
+    // The tokens, by offset, whose comments were already laid out as
+    // lines of their own.
+    handled: HashSet<usize>,
+}
+
+impl Formatter {
+    fn program(&mut self, root: &CstNode) -> Doc {
+        let (items, end) = split_end(&root.children);
+        match end {
+            Some(end) => self.lines(&items, end, true),
+            None => concat(items.iter().map(|item| self.node(item)).collect()),
+        }
+    }
+
+    // The items of a block (statements, declarations, fields), each on
+    // a line of its own after the comments before it, and the comments
+    // before `end`, the token that closes the block.  Each line is
+    // preceded by a line break, except the first one of the file.
+    fn lines(&mut self, items: &[&CstNode], end: &CstToken, top: bool) -> Doc {
+        let mut docs = Vec::new();
+        let mut first = top;
+        for item in items {
+            if let Some(tok) = first_token(item) {
+                self.comments(tok, true, &mut first, &mut docs);
+            }
+            if !first {
+                docs.push(hardline());
+            }
+            docs.push(self.node(item));
+            first = false;
+        }
+        self.comments(end, false, &mut first, &mut docs);
+        concat(docs)
+    }
+
+    // A run of items laid out like `lines`, without the line break and
+    // the comments before the first one, which are not part of the run.
+    fn run(&mut self, items: &[&CstNode]) -> Doc {
+        let mut docs = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            if let Some(tok) = first_token(item) {
+                if i == 0 {
+                    self.handled.insert(tok.span.start);
+                } else {
+                    self.comments(tok, true, &mut false, &mut docs);
+                }
+            }
+            if i > 0 {
+                docs.push(hardline());
+            }
+            docs.push(self.node(item));
+        }
+        concat(docs)
+    }
+
+    // The comments before `tok`: one that follows code on its line is
+    // put at the end of the current line, the others on lines of their
+    // own.  A blank line before a comment or, if `before_item`, before
+    // the item is kept, except at the start of a block.
+    fn comments(&mut self, tok: &CstToken, before_item: bool, first: &mut bool, docs: &mut Vec<Doc>) {
+        let mut newlines = 0;
+        for trivia in &tok.leading {
+            match trivia.kind {
+                TriviaKind::Whitespace => newlines += trivia.text.matches('\n').count(),
+                TriviaKind::Comment => {
+                    let comment = trivia.text.trim_end();
+                    if newlines == 0 && !*first {
+                        docs.push(text(format!(" {}", comment)));
+                    } else {
+                        if !*first {
+                            if newlines > 1 && !docs.is_empty() {
+                                docs.push(hardline());
+                            }
+                            docs.push(hardline());
+                        }
+                        docs.push(text(comment));
+                        *first = false;
+                    }
+                    newlines = 0;
+                }
+            }
+        }
+        if before_item && newlines > 1 && !docs.is_empty() && !*first {
+            docs.push(hardline());
+        }
+        self.handled.insert(tok.span.start);
+    }
+
+    // A token, after the comments before it if they were not laid out
+    // yet; they can only be put on lines of their own.
+    fn token(&mut self, tok: &CstToken) -> Doc {
+        if self.handled.contains(&tok.span.start) {
+            return text(tok.text.clone());
+        }
+        let mut docs = Vec::new();
+        for trivia in tok.leading.iter().filter(|trivia| trivia.kind == TriviaKind::Comment) {
+            docs.push(text(trivia.text.trim_end()));
+            docs.push(hardline());
+        }
+        docs.push(text(tok.text.clone()));
+        concat(docs)
+    }
+
+    fn node(&mut self, node: &CstNode) -> Doc {
+        match node.kind {
+            "expression" | "conjunction" | "comparison" | "sum" | "term" => self.operators(node),
+            "negation" => self.negation(node),
+            "expression_list" | "arguments" => self.expr_list(node),
+            "factor" => self.adjacent(&node.children),
+            "atom" => self.atom(node),
+            _ => self.sequence(node),
+        }
+    }
+
+    // Declarations, statements and the like: their tokens separated by
+    // spaces where they read best, and the items of their blocks on
+    // lines of their own.
+    fn sequence(&mut self, node: &CstNode) -> Doc {
+        let mut docs = Vec::new();
+        let mut prev = None;
+        let mut i = 0;
+        while i < node.children.len() {
+            let (first, last, doc) = match node.children[i] {
+                Element::Token(ref tok) => (tok.token.typ, tok.token.typ, self.token(tok)),
+                Element::Node(ref child) => {
+                    let first = first_token(child).map_or(TokenType::Eof, |tok| tok.token.typ);
+                    let last = last_token(child).map_or(TokenType::Eof, |tok| tok.token.typ);
+                    (first, last, self.node(child))
+                }
+            };
+            if prev.map_or(false, |prev| space_between(node.kind, prev, first)) {
+                docs.push(text(" "));
+            }
+            docs.push(doc);
+            prev = Some(last);
+            i += 1;
+            if self.opens_block(last) || self.opens_body(node, i) {
+                let (items, end) = split_end(&node.children[i..]);
+                if let Some(end) = end {
+                    docs.push(nest(INDENT, self.lines(&items, end, false)));
+                    docs.push(hardline());
+                    i += items.len();
+                    prev = None;
+                }
+            }
+        }
+        concat(docs)
+    }
+
+    fn opens_block(&self, typ: TokenType) -> bool {
+        match (self.syntax, typ) {
+            (_, TokenType::LBrace) => true,
+            (Syntax::Classic, TokenType::Then) | (Syntax::Classic, TokenType::Do) |
+            (Syntax::Classic, TokenType::Else) => true,
+            _ => false,
+        }
+    }
+
+    // A classic function has no token before its body, which starts
+    // after the header with the first declaration or statement, or else
+    // with `end`.
+    fn opens_body(&self, node: &CstNode, i: usize) -> bool {
+        if self.syntax != Syntax::Classic || node.kind != "function" {
+            return false;
+        }
+        let in_header = match node.children[i - 1] {
+            Element::Node(ref child) => !is_item(child),
+            Element::Token(_) => true,
+        };
+        in_header && match node.children.get(i) {
+            Some(&Element::Node(ref child)) => is_item(child),
+            Some(&Element::Token(ref tok)) => tok.token.typ == TokenType::End,
+            None => false,
+        }
+    }
+
+    // `a + b - c`, `a * b` and `a < b`, broken before the operators if
+    // they do not fit on one line.
+    fn operators(&mut self, node: &CstNode) -> Doc {
+        let mut children = node.children.iter();
+        let first = match children.next() {
+            Some(child) => self.element(child),
+            None => return concat(vec![]),
+        };
+        let mut rest = Vec::new();
+        for child in children {
+            match *child {
+                Element::Token(ref op) => {
+                    rest.push(line());
+                    rest.push(self.token(op));
+                    rest.push(text(" "));
+                }
+                Element::Node(ref operand) => rest.push(self.node(operand)),
+            }
+        }
+        if rest.is_empty() {
+            first
+        } else {
+            group(concat(vec![first, nest(INDENT, concat(rest))]))
+        }
+    }
+
+    // `not` and its operand, or a comparison.
+    fn negation(&mut self, node: &CstNode) -> Doc {
+        match node.children.len() {
+            2 => {
+                let not = self.element(&node.children[0]);
+                let operand = self.element(&node.children[1]);
+                concat(vec![not, text(" "), operand])
+            }
+            _ => self.adjacent(&node.children),
+        }
+    }
+
+    // `a, b, c`, broken after the commas.
+    fn expr_list(&mut self, node: &CstNode) -> Doc {
+        if node.children.len() == 1 {
+            return self.element(&node.children[0]);
+        }
+        let mut docs = Vec::new();
+        for child in &node.children {
+            let doc = self.element(child);
+            let comma = match *child {
+                Element::Token(ref tok) => tok.token.typ == TokenType::Comma,
+                Element::Node(_) => false,
+            };
+            docs.push(doc);
+            if comma {
+                docs.push(line());
+            }
+        }
+        group(concat(docs))
+    }
+
+    fn atom(&mut self, node: &CstNode) -> Doc {
+        let open = match node.children.first() {
+            Some(&Element::Token(ref tok)) => tok.token.typ,
+            _ => return self.adjacent(&node.children),
+        };
+        match (open, node.children.len()) {
+            (TokenType::LParen, 3) => {
+                let open = self.element(&node.children[0]);
+                let inner = self.element(&node.children[1]);
+                let close = self.element(&node.children[2]);
+                group(concat(vec![open, nest(INDENT, concat(vec![softline(), inner])), softline(), close]))
+            }
+            // Negations and interpolated strings.
+            _ => self.adjacent(&node.children),
+        }
+    }
+
+    // Elements without spaces between them.
+    fn adjacent(&mut self, children: &[Element]) -> Doc {
+        concat(children.iter().map(|child| self.element(child)).collect())
+    }
+
+    fn element(&mut self, element: &Element) -> Doc {
+        match *element {
+            Element::Token(ref tok) => self.token(tok),
+            Element::Node(ref node) => self.node(node),
+        }
+    }
+}
+
+// Split elements into the nodes that come first and the token that
+// follows them.
+fn split_end(children: &[Element]) -> (Vec<&CstNode>, Option<&CstToken>) {
+    let mut items = Vec::new();
+    for child in children {
+        match *child {
+            Element::Node(ref node) => items.push(node),
+            Element::Token(ref tok) => return (items, Some(tok)),
+        }
+    }
+    (items, None)
+}
+
+fn first_token(node: &CstNode) -> Option<&CstToken> {
+    node.children.iter().filter_map(|child| match *child {
+        Element::Token(ref tok) => Some(tok),
+        Element::Node(ref node) => first_token(node),
+    }).next()
+}
+
+fn last_token(node: &CstNode) -> Option<&CstToken> {
+    node.children.iter().rev().filter_map(|child| match *child {
+        Element::Token(ref tok) => Some(tok),
+        Element::Node(ref node) => last_token(node),
+    }).next()
+}
+
+// Whether a space separates two tokens in a rule of kind `kind`, such
+// as `var x: int = 1;` and `print x : 2;`.
+fn space_between(kind: &str, prev: TokenType, next: TokenType) -> bool {
+    match (prev, next) {
+        (_, TokenType::Semicolon) | (_, TokenType::Comma) | (_, TokenType::RParen) | (_, TokenType::Dot) => false,
+        (_, TokenType::Colon) => kind == "statement",
+        (TokenType::LParen, _) | (TokenType::Dot, _) | (TokenType::Id, TokenType::LParen) => false,
+        _ => true,
+    }
+}
@@ -0,0 +1,328 @@
+// Grading student programs against the reference implementation.
+//
+// A grading directory holds submissions `NAME.min` (or `NAME.mini`),
+// each with an optional input `NAME.in` and expected output `NAME.out`,
+// and a specification `grade.spec` in the manifest format:
+//
+//     stop-after = "run"        # the last phase to run
+//     timeout = 5               # seconds for the run phase
+//     max-steps = 1000000       # statements for the run phase
+//     max-call-depth = 1000     # nested calls for the run phase
+//     expect = "pass"           # the result of unlisted submissions
+//     expect.bad_if = "parse"   # the phase at which bad_if must fail
+//
+// The phases are the passes of the standard pipeline (scan, parse,
+// typecheck, eliminate, codegen) followed by `run`, which interprets
+// the program and compares its output with `NAME.out`, if there is
+// one.  A submission's result is "pass" if every phase up to
+// `stop-after` succeeds, and the name of the phase that failed
+// otherwise.  The compile phases always terminate quickly; only the
+// run phase needs the limits.
+//
+// `batch` grades a single program the other way around: it runs the
+// program on each test case of a directory, `NAME.in` with the
+// expected output `NAME.out` and exit status `NAME.status` (0 by
+// default), like the conformance programs.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use backend::CBackend;
+use conformance;
+use diagnostic::Severity;
+use error::Error;
+use ast::Program;
+use interp::{self, Env, Limits};
+use json;
+use manifest::{self, Value};
+use parser::Syntax;
+use scanner::IdentifierRules;
+use pass::{CodegenPass, Context, EliminatePass, ParsePass, Pass, ScanPass, TypecheckPass};
+
+pub const PHASES: [&'static str; 6] = ["scan", "parse", "typecheck", "eliminate", "codegen", "run"];
+
+pub struct Spec {
+    pub stop_after: String,
+    pub limits: Limits,
+    pub expect: String,
+    // The expected results of the listed submissions.
+    pub expected: Vec<(String, String)>,
+}
+
+// The result of one submission.
+pub struct Grade {
+    pub name: String,
+    pub expected: String,
+    pub actual: String,
+    // The error that stopped the submission, if any.
+    pub message: String,
+    pub millis: u64,
+}
+
+impl Grade {
+    pub fn ok(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+impl Spec {
+    pub fn new() -> Self {
+        Spec {
+            stop_after: "run".to_string(),
+            limits: Limits::default().timeout(Duration::from_secs(10)),
+            expect: "pass".to_string(),
+            expected: Vec::new(),
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Spec, Error> {
+        let mut spec = Spec::new();
+        for (lineno, key, value) in try!(manifest::entries(src)) {
+            match (&key[..], value) {
+                ("stop-after", Value::Str(phase)) => spec.stop_after = try!(phase_name(phase, lineno)),
+                ("timeout", Value::Int(secs)) => spec.limits.timeout = Some(Duration::from_secs(secs as u64)),
+                ("max-steps", Value::Int(n)) => spec.limits.max_steps = Some(n as u64),
+                ("max-call-depth", Value::Int(n)) => spec.limits = spec.limits.max_call_depth(n as usize),
+                ("expect", Value::Str(result)) => spec.expect = try!(result_name(result, lineno)),
+                (key, Value::Str(result)) if key.starts_with("expect.") => {
+                    let result = try!(result_name(result, lineno));
+                    spec.expected.push((key["expect.".len()..].to_string(), result));
+                }
+                ("stop-after", _) | ("timeout", _) | ("max-steps", _) | ("max-call-depth", _) | ("expect", _) => {
+                    return Err(Error::InvalidManifest(lineno, format!("wrong type of value for '{}'", key)));
+                }
+                _ => return Err(Error::InvalidManifest(lineno, format!("unknown key '{}'", key))),
+            }
+        }
+        Ok(spec)
+    }
+
+    fn expected(&self, name: &str) -> &str {
+        self.expected.iter()
+            .find(|&&(ref n, _)| n == name)
+            .map_or(&self.expect[..], |&(_, ref result)| &result[..])
+    }
+}
+
+fn phase_name(name: String, lineno: usize) -> Result<String, Error> {
+    if PHASES.contains(&&name[..]) {
+        Ok(name)
+    } else {
+        Err(Error::InvalidManifest(lineno, format!("unknown phase '{}'; expected one of {}",
+                                                   name, PHASES.join(", "))))
+    }
+}
+
+fn result_name(name: String, lineno: usize) -> Result<String, Error> {
+    if name == "pass" {
+        Ok(name)
+    } else {
+        phase_name(name, lineno)
+    }
+}
+
+// Grade the submissions of `dir` with the specification `dir/grade.spec`,
+// or the defaults if there is none.
+pub fn run(dir: &Path, syntax: Syntax, identifiers: IdentifierRules, strict: bool)
+           -> Result<Vec<Grade>, Error> {
+    let spec_path = dir.join("grade.spec");
+    let spec_name = spec_path.to_string_lossy().into_owned();
+    let spec = try!(Spec::parse(&try!(conformance::read_optional(&spec_path)))
+                    .map_err(|e| Error::InFile(spec_name, Box::new(e))));
+
+    let dir_name = dir.to_string_lossy().into_owned();
+    let entries = try!(fs::read_dir(dir).map_err(|e| Error::Io(dir_name.clone(), e)));
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = try!(entry.map_err(|e| Error::Io(dir_name.clone(), e))).path();
+        if path.extension().map_or(false, |ext| ext == "min" || ext == "mini") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut grades = Vec::new();
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let source = try!(conformance::read(&path));
+        let input = try!(conformance::read_optional(&dir.join(format!("{}.in", name))));
+        let output_path = dir.join(format!("{}.out", name));
+        let output = if output_path.exists() { Some(try!(conformance::read(&output_path))) } else { None };
+
+        let start = Instant::now();
+        let mut ctx = Context::new(source);
+        ctx.syntax = syntax;
+        ctx.identifiers = identifiers;
+        ctx.strict = strict;
+        let (actual, message) = grade(&spec, ctx, &input, output.as_ref().map(|s| &s[..]));
+        let elapsed = start.elapsed();
+        grades.push(Grade {
+            expected: spec.expected(&name).to_string(),
+            actual: actual.to_string(),
+            message: message,
+            millis: elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000,
+            name: name,
+        });
+    }
+    Ok(grades)
+}
+
+// Run a submission up to `spec.stop_after`, and return the phase that
+// failed ("pass" if none did) with its error.
+fn grade(spec: &Spec, mut ctx: Context, input: &str, output: Option<&str>) -> (&'static str, String) {
+    for &phase in PHASES.iter() {
+        let result = match phase {
+            "run" => run_phase(&ctx, spec.limits, input, output),
+            _ => {
+                let mut pass: Box<dyn Pass> = match phase {
+                    "scan" => Box::new(ScanPass),
+                    "parse" => Box::new(ParsePass),
+                    "typecheck" => Box::new(TypecheckPass),
+                    "eliminate" => Box::new(EliminatePass),
+                    _ => Box::new(CodegenPass { backend: Box::new(CBackend) }),
+                };
+                match pass.run(&mut ctx) {
+                    Ok(()) => match ctx.diagnostics.iter().find(|d| d.severity == Severity::Error) {
+                        Some(diagnostic) => Err(diagnostic.to_string()),
+                        None => Ok(()),
+                    },
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+        };
+        if let Err(message) = result {
+            return (phase, message);
+        }
+        if phase == spec.stop_after {
+            break;
+        }
+    }
+    ("pass", String::new())
+}
+
+fn run_phase(ctx: &Context, limits: Limits, input: &str, output: Option<&str>) -> Result<(), String> {
+    let mut env = Env::new();
+    env.set_limits(limits);
+    env.set_strict(ctx.strict);
+    env.set_input(input);
+    if let Err(err) = interp::eval_program(ctx.program(), &mut env) {
+        return Err(err.to_string());
+    }
+    match output {
+        Some(expected) if env.output() != expected => Err("the output differs from the expected output".to_string()),
+        _ => Ok(()),
+    }
+}
+
+// A test case of `batch`.
+pub struct Fixture {
+    pub name: String,
+    pub input: String,
+    pub output: String,
+    pub status: i32,
+}
+
+// The result of a test case of `batch`.
+pub struct Outcome {
+    pub name: String,
+    // Why the case failed, if it did.
+    pub failure: Option<String>,
+    pub millis: u64,
+}
+
+// The test cases of `dir`, one per `NAME.out`, sorted by name.
+pub fn fixtures(dir: &Path) -> Result<Vec<Fixture>, Error> {
+    let dir_name = dir.to_string_lossy().into_owned();
+    let entries = try!(fs::read_dir(dir).map_err(|e| Error::Io(dir_name.clone(), e)));
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = try!(entry.map_err(|e| Error::Io(dir_name.clone(), e))).path();
+        if path.extension().map_or(false, |ext| ext == "out") {
+            names.push(path.file_stem().unwrap().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+
+    let mut fixtures = Vec::new();
+    for name in names {
+        let status = try!(conformance::read_optional(&dir.join(format!("{}.status", name))));
+        let status = match status.trim() {
+            "" => 0,
+            s => try!(s.parse().map_err(|_| Error::InvalidArgument("status", s.to_string()))),
+        };
+        fixtures.push(Fixture {
+            input: try!(conformance::read_optional(&dir.join(format!("{}.in", name)))),
+            output: try!(conformance::read(&dir.join(format!("{}.out", name)))),
+            status: status,
+            name: name,
+        });
+    }
+    Ok(fixtures)
+}
+
+// Run `program` with the interpreter on each fixture, within `limits`.
+// A run error is part of the output, as printed by `minilang run`,
+// with status 1.
+pub fn batch(program: &Program, fixtures: &[Fixture], limits: Limits, strict: bool) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for fixture in fixtures {
+        let start = Instant::now();
+        let mut env = Env::new();
+        env.set_limits(limits);
+        env.set_strict(strict);
+        env.set_input(&fixture.input);
+        let result = interp::eval_program(program, &mut env);
+        let mut output = env.output().to_string();
+        let status = match result {
+            Ok(()) => env.exit_status().unwrap_or(0) as i32,
+            Err(err) => {
+                output.push_str(&format!("{}\n", err));
+                1
+            }
+        };
+        let elapsed = start.elapsed();
+        let failure = if output != fixture.output {
+            Some(format!("output {:?}, expected {:?}", output, fixture.output))
+        } else if status != fixture.status {
+            Some(format!("exit status {}, expected {}", status, fixture.status))
+        } else {
+            None
+        };
+        outcomes.push(Outcome {
+            name: fixture.name.clone(),
+            failure: failure,
+            millis: elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000,
+        });
+    }
+    outcomes
+}
+
+pub fn to_csv(grades: &[Grade]) -> String {
+    let mut out = String::from("name,expected,actual,ok,millis,message\n");
+    for grade in grades {
+        out.push_str(&format!("{},{},{},{},{},{}\n", csv_field(&grade.name), grade.expected, grade.actual,
+                              grade.ok(), grade.millis, csv_field(&grade.message)));
+    }
+    out
+}
+
+pub fn to_json(grades: &[Grade]) -> String {
+    let mut out = String::from("[");
+    for (i, grade) in grades.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!("  {{\"name\": {}, \"expected\": {}, \"actual\": {}, \"ok\": {}, \"millis\": {}, \"message\": {}}}",
+                              json::quote(&grade.name), json::quote(&grade.expected), json::quote(&grade.actual),
+                              grade.ok(), grade.millis, json::quote(&grade.message)));
+    }
+    out.push_str("\n]\n");
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
@@ -0,0 +1,371 @@
+// The grammar of Minilang, as data.
+//
+// This description is the single source of truth for the syntax: the
+// `grammar` subcommand prints it (doc/grammar.ebnf is its output) and
+// the parser uses its FIRST sets to list the expected tokens in error
+// messages.  The parser itself is still written by hand and must be
+// kept in agreement with it.
+
+use std::collections::HashSet;
+
+use parser::Syntax;
+use token::TokenType;
+use token::TokenType::{Int, Float, InterpStart, InterpMid, InterpEnd, Id, Plus, Minus, Star,
+                       Slash, Equal, LParen, RParen, LBrace, RBrace, Colon, Semicolon, If,
+                       Then, Else, EndIf, While, Do, Done, For, To, Read, Prompt, Print, Exit, Var,
+                       TypeInt, TypeFloat, TypeInt32, TypeInt64, TypeFloat32, TypeFloat64,
+                       TypeString, TypeBool, True, False, And, Or, Not, EqualEqual, NotEqual, Less,
+                       LessEqual, Greater, GreaterEqual, Comma, Dot, Record, Function, Return,
+                       Void, End, Eof};
+
+pub enum Sym {
+    // A token.
+    T(TokenType),
+    // A reference to another rule.
+    N(&'static str),
+    // [ ... ]
+    Opt(&'static [Sym]),
+    // { ... }
+    Rep(&'static [Sym]),
+    // ( ... | ... )
+    Alt(&'static [&'static [Sym]]),
+}
+
+use self::Sym::*;
+
+pub struct Rule {
+    pub name: &'static str,
+    pub alts: &'static [&'static [Sym]],
+}
+
+pub static LEXICAL: &'static str = r##"lower-case     = "a" | "b" | ... | "y" | "z" .
+upper-case     = "A" | "B" | ... | "Y" | "Z" .
+digit          = "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" .
+non-zero-digit = "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" .
+
+id             = (lower-case | upper-case | "_") { (lower-case | upper-case | "_" | digit) } .
+int_literal    = ("0" | non-zero-digit { digit }) ["i32" | "i64" | "f32" | "f64"] .
+float_literal  = (("0" | non-zero-digit { digit }) "." { digit } | "." digit { digit }) ["f32" | "f64"] .
+string_literal = '"' { string_char } '"' .
+interp_start   = '"' { string_char } "${" .
+interp_mid     = "}" { string_char } "${" .
+interp_end     = "}" { string_char } '"' .
+string_char    = any character except '"' and '\', or "$" not followed by "{", or escape .
+escape         = '\' ("n" | "t" | "0" | '\' | '"') .
+comment        = "#" { any character except newline } .
+"##;
+
+pub static GRAMMAR: &'static [Rule] = &[
+    Rule {
+        name: "program",
+        alts: &[&[Rep(&[N("record")]), Rep(&[N("declaration")]), Rep(&[N("function")]), Rep(&[N("statement")]),
+                  T(Eof)]],
+    },
+    Rule { name: "record", alts: &[&[T(Record), T(Id), T(LBrace), Rep(&[N("field")]), T(RBrace)]] },
+    Rule { name: "field", alts: &[&[T(Id), T(Colon), N("type"), T(Semicolon)]] },
+    Rule {
+        name: "declaration",
+        alts: &[&[T(Var), T(Id), Alt(&[&[T(Colon), N("type"), Opt(&[T(Equal), N("expression")])],
+                                       &[T(Equal), N("expression")]]), T(Semicolon)]],
+    },
+    Rule {
+        name: "type",
+        alts: &[&[T(TypeInt)], &[T(TypeFloat)], &[T(TypeInt32)], &[T(TypeInt64)], &[T(TypeFloat32)],
+                &[T(TypeFloat64)], &[T(TypeString)], &[T(TypeBool)], &[T(Id)],
+                &[T(LParen), N("type"), T(Comma), N("type"), Rep(&[T(Comma), N("type")]), T(RParen)]],
+    },
+    Rule {
+        name: "function",
+        alts: &[&[T(Function), T(Id), T(LParen), Opt(&[N("parameter"), Rep(&[T(Comma), N("parameter")])]), T(RParen),
+                  Opt(&[T(Colon), Alt(&[&[N("type")], &[T(Void)]])]), Rep(&[N("declaration")]), Rep(&[N("statement")]),
+                  T(End)]],
+    },
+    Rule { name: "parameter", alts: &[&[T(Id), T(Colon), N("type")]] },
+    Rule {
+        name: "statement",
+        alts: &[
+            &[T(Id), Alt(&[&[T(LParen), Opt(&[N("arguments")]), T(RParen)],
+                           &[Rep(&[T(Dot), T(Id)]), Rep(&[T(Comma), N("lvalue")]), T(Equal), N("expression_list")]]),
+              T(Semicolon)],
+            &[T(Print), N("expression"), Opt(&[T(Colon), T(Int)]), T(Semicolon)],
+            &[T(Exit), N("expression"), T(Semicolon)],
+            &[T(Return), Opt(&[N("expression")]), T(Semicolon)],
+            &[T(Read), T(Id), Rep(&[T(Comma), T(Id)]), Opt(&[T(Prompt), T(TokenType::String)]), T(Semicolon)],
+            &[T(If), N("expression"), T(Then), Rep(&[N("statement")]),
+              Opt(&[T(Else), Rep(&[N("statement")])]), T(EndIf)],
+            &[T(While), N("expression"), T(Do), Rep(&[N("statement")]), T(Done)],
+            &[T(For), T(Id), T(Equal), N("expression"), T(To), N("expression"), T(Do), Rep(&[N("statement")]),
+              T(Done)],
+        ],
+    },
+    Rule { name: "lvalue", alts: &[&[T(Id), Rep(&[T(Dot), T(Id)])]] },
+    Rule { name: "expression_list", alts: &[&[N("expression"), Rep(&[T(Comma), N("expression")])]] },
+    Rule { name: "arguments", alts: &[&[N("expression"), Rep(&[T(Comma), N("expression")])]] },
+    Rule { name: "expression", alts: &[&[N("conjunction"), Rep(&[T(Or), N("conjunction")])]] },
+    Rule { name: "conjunction", alts: &[&[N("negation"), Rep(&[T(And), N("negation")])]] },
+    Rule { name: "negation", alts: &[&[T(Not), N("negation")], &[N("comparison")]] },
+    Rule {
+        name: "comparison",
+        alts: &[&[N("sum"), Opt(&[Alt(&[&[T(EqualEqual)], &[T(NotEqual)], &[T(Less)], &[T(LessEqual)],
+                                         &[T(Greater)], &[T(GreaterEqual)]]), N("sum")])]],
+    },
+    Rule { name: "sum", alts: &[&[N("term"), Rep(&[Alt(&[&[T(Plus)], &[T(Minus)]]), N("term")])]] },
+    Rule { name: "term", alts: &[&[N("factor"), Rep(&[Alt(&[&[T(Star)], &[T(Slash)]]), N("factor")])]] },
+    Rule { name: "factor", alts: &[&[N("atom"), Rep(&[T(Dot), T(Id)])]] },
+    Rule {
+        name: "atom",
+        alts: &[
+            &[T(Int)],
+            &[T(Float)],
+            &[T(TokenType::String)],
+            &[T(InterpStart), N("expression"), Rep(&[T(InterpMid), N("expression")]), T(InterpEnd)],
+            &[T(True)],
+            &[T(False)],
+            &[T(Id), Opt(&[T(LParen), Opt(&[N("arguments")]), T(RParen)])],
+            &[T(LParen), N("expression_list"), T(RParen)],
+            &[T(Minus), N("sum")],
+        ],
+    },
+];
+
+// The rules of the braces syntax that differ from GRAMMAR.
+pub static BRACES_GRAMMAR: &'static [Rule] = &[
+    Rule { name: "program", alts: &[&[Rep(&[N("declaration")]), Rep(&[N("statement")]), T(Eof)]] },
+    Rule {
+        name: "declaration",
+        alts: &[
+            &[T(Record), T(Id), Alt(&[&[T(LBrace), Rep(&[N("field")]), T(RBrace)],
+                                      &[T(Id), Alt(&[&[Opt(&[T(Equal), N("expression")]), T(Semicolon)],
+                                                     &[N("function")]])]])],
+            &[Alt(&[&[T(TypeInt)], &[T(TypeFloat)], &[T(TypeInt32)], &[T(TypeInt64)], &[T(TypeFloat32)],
+                    &[T(TypeFloat64)], &[T(TypeString)], &[T(TypeBool)]]),
+              T(Id), Alt(&[&[Opt(&[T(Equal), N("expression")]), T(Semicolon)], &[N("function")]])],
+            &[T(Var), T(Id), T(Equal), N("expression"), T(Semicolon)],
+            &[T(Void), T(Id), N("function")],
+        ],
+    },
+    Rule {
+        name: "function",
+        alts: &[&[T(LParen), Opt(&[N("parameter"), Rep(&[T(Comma), N("parameter")])]), T(RParen),
+                  T(LBrace), Rep(&[N("declaration")]), Rep(&[N("statement")]), T(RBrace)]],
+    },
+    Rule { name: "parameter", alts: &[&[N("type"), T(Id)]] },
+    Rule { name: "field", alts: &[&[N("type"), T(Id), T(Semicolon)]] },
+    Rule {
+        name: "type",
+        alts: &[&[T(TypeInt)], &[T(TypeFloat)], &[T(TypeInt32)], &[T(TypeInt64)], &[T(TypeFloat32)],
+                &[T(TypeFloat64)], &[T(TypeString)], &[T(TypeBool)], &[T(Record), T(Id)],
+                &[T(LParen), N("type"), T(Comma), N("type"), Rep(&[T(Comma), N("type")]), T(RParen)]],
+    },
+    Rule {
+        name: "statement",
+        alts: &[
+            &[T(Id), Alt(&[&[T(LParen), Opt(&[N("arguments")]), T(RParen)],
+                           &[Rep(&[T(Dot), T(Id)]), Rep(&[T(Comma), N("lvalue")]), T(Equal), N("expression_list")]]),
+              T(Semicolon)],
+            &[T(Print), N("expression"), Opt(&[T(Colon), T(Int)]), T(Semicolon)],
+            &[T(Exit), N("expression"), T(Semicolon)],
+            &[T(Return), Opt(&[N("expression")]), T(Semicolon)],
+            &[T(Read), T(Id), Rep(&[T(Comma), T(Id)]), Opt(&[T(Prompt), T(TokenType::String)]), T(Semicolon)],
+            &[T(If), T(LParen), N("expression"), T(RParen), N("block"), Opt(&[T(Else), N("block")])],
+            &[T(While), T(LParen), N("expression"), T(RParen), N("block")],
+            &[T(For), T(LParen), T(Id), T(Equal), N("expression"), T(To), N("expression"), T(RParen), N("block")],
+        ],
+    },
+    Rule { name: "block", alts: &[&[T(LBrace), Rep(&[N("statement")]), T(RBrace)]] },
+];
+
+// The rule called `name` in the given syntax.
+pub fn rule(syntax: Syntax, name: &str) -> &'static Rule {
+    let overrides: &'static [Rule] = match syntax {
+        Syntax::Classic => &[],
+        Syntax::Braces => BRACES_GRAMMAR,
+    };
+    overrides.iter().chain(GRAMMAR.iter())
+        .find(|r| r.name == name)
+        .expect("unknown grammar rule")
+}
+
+// The tokens that can start the rule `name`, in grammar order.
+pub fn first(name: &str) -> Vec<TokenType> {
+    first_in(Syntax::Classic, name)
+}
+
+pub fn first_in(syntax: Syntax, name: &str) -> Vec<TokenType> {
+    let mut tokens = Vec::new();
+    first_rule(syntax, rule(syntax, name), &mut tokens, &mut HashSet::new());
+    tokens
+}
+
+// The tokens that can start `seq`, and whether `seq` can be empty.
+pub fn first_of_seq(syntax: Syntax, seq: &'static [Sym]) -> (Vec<TokenType>, bool) {
+    let mut tokens = Vec::new();
+    let nullable = first_seq(syntax, seq, &mut tokens, &mut HashSet::new());
+    (tokens, nullable)
+}
+
+// Add the FIRST set of a rule to `tokens`; return whether it is nullable.
+fn first_rule(syntax: Syntax, r: &'static Rule, tokens: &mut Vec<TokenType>,
+              visiting: &mut HashSet<&'static str>) -> bool {
+    if !visiting.insert(r.name) {
+        return false;
+    }
+    let mut nullable = false;
+    for alt in r.alts {
+        nullable |= first_seq(syntax, alt, tokens, visiting);
+    }
+    visiting.remove(r.name);
+    nullable
+}
+
+fn first_seq(syntax: Syntax, seq: &'static [Sym], tokens: &mut Vec<TokenType>,
+             visiting: &mut HashSet<&'static str>) -> bool {
+    for sym in seq {
+        let nullable = match *sym {
+            T(Eof) => true,
+            T(t) => {
+                if !tokens.contains(&t) {
+                    tokens.push(t);
+                }
+                false
+            }
+            N(name) => first_rule(syntax, rule(syntax, name), tokens, visiting),
+            Opt(inner) | Rep(inner) => {
+                first_seq(syntax, inner, tokens, visiting);
+                true
+            }
+            Alt(alts) => {
+                let mut nullable = false;
+                for alt in alts {
+                    nullable |= first_seq(syntax, alt, tokens, visiting);
+                }
+                nullable
+            }
+        };
+        if !nullable {
+            return false;
+        }
+    }
+    true
+}
+
+// The grammar in EBNF, followed by the rules of the braces syntax.
+pub fn to_ebnf() -> String {
+    let mut out = String::new();
+    out.push_str("(* Regular expressions *)\n");
+    out.push_str(LEXICAL);
+    out.push_str("\n\n(* Context-free grammar *)\n");
+    ebnf_rules(GRAMMAR, &mut out);
+    out.push_str("\n\n(* Alternative syntax (--syntax=braces).  Only the productions below\n");
+    out.push_str("   differ from the classic syntax; both produce the same AST. *)\n");
+    ebnf_rules(BRACES_GRAMMAR, &mut out);
+    out
+}
+
+fn ebnf_rules(rules: &[Rule], out: &mut String) {
+    let width = rules.iter().map(|r| r.name.len()).max().unwrap_or(0).max(11);
+    for (i, r) in rules.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        for (j, alt) in r.alts.iter().enumerate() {
+            if j == 0 {
+                out.push_str(&format!("{:<w$} = ", r.name, w = width));
+            } else {
+                out.push_str(&format!("\n{:<w$} | ", "", w = width));
+            }
+            out.push_str(&ebnf_seq(alt));
+        }
+        out.push_str(" .\n");
+    }
+}
+
+fn ebnf_seq(seq: &[Sym]) -> String {
+    let parts: Vec<String> = seq.iter().filter_map(|sym| {
+        match *sym {
+            T(Eof) => None,
+            T(t) => Some(ebnf_terminal(t)),
+            N(name) => Some(name.to_string()),
+            Opt(inner) => Some(format!("[{}]", ebnf_seq(inner))),
+            Rep(inner) => Some(format!("{{ {} }}", ebnf_seq(inner))),
+            Alt(alts) => {
+                let alts: Vec<String> = alts.iter().map(|a| ebnf_seq(a)).collect();
+                Some(format!("({})", alts.join(" | ")))
+            }
+        }
+    }).collect();
+    parts.join(" ")
+}
+
+fn ebnf_terminal(t: TokenType) -> String {
+    match t {
+        Int => "int_literal".to_string(),
+        Float => "float_literal".to_string(),
+        TokenType::String => "string_literal".to_string(),
+        InterpStart => "interp_start".to_string(),
+        InterpMid => "interp_mid".to_string(),
+        InterpEnd => "interp_end".to_string(),
+        Id => "id".to_string(),
+        t => format!("\"{}\"", t.text().unwrap_or("")),
+    }
+}
+
+// The classic grammar as an ANTLR 4 grammar.
+pub fn to_antlr() -> String {
+    let mut out = String::from("grammar Minilang;\n");
+    for r in GRAMMAR {
+        let alts: Vec<String> = r.alts.iter().map(|a| antlr_seq(a)).collect();
+        out.push_str(&format!("\n{}\n    : {}\n    ;\n", antlr_rule_name(r.name), alts.join("\n    | ")));
+    }
+    out.push_str(r##"
+ID : [a-zA-Z_] [a-zA-Z_0-9]* ;
+INT_LITERAL : ('0' | [1-9] [0-9]*) ('i32' | 'i64' | 'f32' | 'f64')? ;
+FLOAT_LITERAL : ('0' | [1-9] [0-9]*) '.' [0-9]* ('f32' | 'f64')? ;
+STRING_LITERAL : '"' (ESCAPE | ~["\\])* '"' ;
+INTERP_START : '"' (ESCAPE | ~["$\\])* '${' ;
+INTERP_MID : '}' (ESCAPE | ~["$\\])* '${' ;
+INTERP_END : '}' (ESCAPE | ~["$\\])* '"' ;
+fragment ESCAPE : '\\' [nt0\\"] ;
+COMMENT : '#' ~[\n]* -> skip ;
+WS : [ \t\r\n]+ -> skip ;
+"##);
+    out
+}
+
+fn antlr_seq(seq: &[Sym]) -> String {
+    let parts: Vec<String> = seq.iter().map(|sym| {
+        match *sym {
+            T(t) => antlr_terminal(t),
+            N(name) => antlr_rule_name(name),
+            Opt(inner) => format!("({})?", antlr_seq(inner)),
+            Rep(inner) => format!("({})*", antlr_seq(inner)),
+            Alt(alts) => {
+                let alts: Vec<String> = alts.iter().map(|a| antlr_seq(a)).collect();
+                format!("({})", alts.join(" | "))
+            }
+        }
+    }).collect();
+    parts.join(" ")
+}
+
+// ANTLR rule names cannot be keywords of its target languages.
+fn antlr_rule_name(name: &str) -> String {
+    match name {
+        "type" => "type_".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn antlr_terminal(t: TokenType) -> String {
+    match t {
+        Int => "INT_LITERAL".to_string(),
+        Float => "FLOAT_LITERAL".to_string(),
+        TokenType::String => "STRING_LITERAL".to_string(),
+        InterpStart => "INTERP_START".to_string(),
+        InterpMid => "INTERP_MID".to_string(),
+        InterpEnd => "INTERP_END".to_string(),
+        Id => "ID".to_string(),
+        Eof => "EOF".to_string(),
+        t => format!("'{}'", t.text().unwrap_or("")),
+    }
+}
@@ -0,0 +1,71 @@
+//! Reporting internal compiler errors.
+//!
+//! A panic in the compiler is a bug, whatever the input.  `install`
+//! replaces Rust's panic message with one that says so, names the
+//! phase that was running and the source position it was working on,
+//! and asks for a bug report.  The phases record where they are with
+//! `enter_phase` and `at`, which are cheap enough to call for every
+//! statement.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::env;
+use std::io::{Write, stderr};
+use std::panic::{self, Location};
+
+use pos::Pos;
+
+thread_local! {
+    static PHASE: Cell<Option<&'static str>> = Cell::new(None);
+    static POSITION: Cell<Option<Pos>> = Cell::new(None);
+}
+
+const ISSUES: &'static str = "https://github.com/gnuvince/minilang-rs/issues";
+
+/// Record that the phase `name` starts.
+pub fn enter_phase(name: &'static str) {
+    PHASE.with(|phase| phase.set(Some(name)));
+    POSITION.with(|position| position.set(None));
+}
+
+/// Record that the current phase is working on the code at `pos`.
+pub fn at(pos: Pos) {
+    POSITION.with(|position| position.set(Some(pos)));
+}
+
+/// Report panics as internal compiler errors.  Rust's own message and
+/// backtrace follow when `RUST_BACKTRACE` is set.
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = writeln!(stderr(), "{}", report(info.payload(), info.location()));
+        if env::var_os("RUST_BACKTRACE").is_some() {
+            default_hook(info);
+        }
+    }));
+}
+
+fn report(payload: &(dyn Any + Send), location: Option<&Location>) -> String {
+    let message = match payload.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => "unknown panic".to_string(),
+        },
+    };
+    let mut out = format!("error: internal compiler error: {}", message);
+    let phase = PHASE.with(|phase| phase.get());
+    let position = POSITION.with(|position| position.get());
+    match (phase, position) {
+        (Some(phase), Some(pos)) => out.push_str(&format!("\n  in the {} phase, at {}", phase, pos)),
+        (Some(phase), None) => out.push_str(&format!("\n  in the {} phase", phase)),
+        (None, Some(pos)) => out.push_str(&format!("\n  at {}", pos)),
+        (None, None) => {}
+    }
+    if let Some(location) = location {
+        out.push_str(&format!("\n  raised at {}:{}", location.file(), location.line()));
+    }
+    out.push_str(&format!("\nThis is a bug in the compiler.  Please report it at {}\n\
+                           with the smallest program that still triggers it.", ISSUES));
+    out
+}
@@ -0,0 +1,220 @@
+//! Memoized queries on the front end, for editors.
+//!
+//! A `Database` answers the queries `tokens`, `program`, `typed`,
+//! `type_of`, `decl_of` and `diagnostics` for the files of a
+//! `SourceManager`, and remembers each answer with the text it was
+//! computed from.  When the text of a file changes, only that file is
+//! scanned again, and if its tokens
+//! and `# minilang:` directives did not change (an edit of whitespace
+//! or of a comment), the AST, the types and the diagnostics are kept;
+//! otherwise they are computed again when next asked for.  Each file is
+//! a program of its own, so an edit never invalidates another file.
+//! `format_range` is not memoized: it answers an editor's range
+//! formatting request with edits of the current text.
+//!
+//! ```ignore
+//! let mut sources = SourceManager::new(Box::new(source::Memory::new()));
+//! let file = sources.add("main.min", text);
+//! let mut db = Database::new();
+//! for d in db.diagnostics(&sources, file) {
+//!     println!("{}", sources.render(&d));
+//! }
+//! sources.set_text(file, edited);
+//! let diagnostics = db.diagnostics(&sources, file);
+//! ```
+
+use std::collections::HashMap;
+
+use ast::{Decl, Program};
+use diagnostic::Diagnostic;
+use error::Error;
+use format::{self, Edit};
+use pass::{Context, Pass, ParsePass, TypecheckPass};
+use parser::Syntax;
+use pos::Pos;
+use scanner::{self, IdentifierRules};
+use source::{FileId, SourceManager};
+use token::{Token, TokenType};
+use typecheck::{self, Exprtable, Scope, Symtable};
+use types::Type;
+
+/// How many times each phase ran, to check what an edit invalidated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub scans: usize,
+    pub parses: usize,
+    pub typechecks: usize,
+}
+
+/// The memoized results of the front end, by file.
+pub struct Database {
+    pub syntax: Syntax,
+    pub identifiers: IdentifierRules,
+    pub strict: bool,
+    pub stats: Stats,
+    files: HashMap<FileId, Memo>,
+}
+
+// The state of the passes on one file, for the text it was computed
+// from.
+struct Memo {
+    text: String,
+    ctx: Context,
+    parsed: bool,
+    // Whether the program has syntax errors.
+    broken: bool,
+    checked: bool,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Database {
+            syntax: Syntax::Classic,
+            identifiers: IdentifierRules::default(),
+            strict: false,
+            stats: Stats::default(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// The tokens of the file; none if it does not scan.
+    pub fn tokens(&mut self, sources: &SourceManager, file: FileId) -> &[Token] {
+        &self.memo(sources, file).ctx.tokens
+    }
+
+    /// The AST of the file, with error nodes where it does not parse.
+    pub fn program(&mut self, sources: &SourceManager, file: FileId) -> Option<&Program> {
+        self.parse(sources, file);
+        self.files[&file].ctx.program.as_ref()
+    }
+
+    /// The types of the variables and expressions of the file, if it
+    /// type checks.
+    pub fn typed(&mut self, sources: &SourceManager, file: FileId) -> Option<(&Symtable, &Exprtable)> {
+        self.check(sources, file);
+        let ctx = &self.files[&file].ctx;
+        if ctx.program.is_some() && !ctx.has_errors() {
+            Some((&ctx.symtable, &ctx.expr_table))
+        } else {
+            None
+        }
+    }
+
+    /// The type of the expression or declaration of the file with id
+    /// `node_id`, if the file type checks; the ids do not change with
+    /// edits of whitespace or comments.
+    pub fn type_of(&mut self, sources: &SourceManager, file: FileId, node_id: u64) -> Option<Type> {
+        self.check(sources, file);
+        let ctx = &self.files[&file].ctx;
+        match ctx.program {
+            Some(ref program) if !ctx.has_errors() => {
+                typecheck::type_of(program, &ctx.symtable, &ctx.expr_table, node_id)
+            }
+            _ => None,
+        }
+    }
+
+    /// The declaration of the variable `name` in `scope` of the file.
+    pub fn decl_of(&mut self, sources: &SourceManager, file: FileId, name: &str, scope: Scope) -> Option<&Decl> {
+        self.program(sources, file).and_then(|program| typecheck::decl_of(program, name, scope))
+    }
+
+    /// The errors and warnings of the file, up to type checking, as
+    /// `c` reports them.
+    pub fn diagnostics(&mut self, sources: &SourceManager, file: FileId) -> Vec<Diagnostic> {
+        self.check(sources, file);
+        self.files[&file].ctx.diagnostics.iter().map(|d| d.clone().with_file(file)).collect()
+    }
+
+    /// The edits that lay out the lines `first` to `last` of the file,
+    /// for an editor's range formatting; see `format::range_edits`.
+    pub fn format_range(&self, sources: &SourceManager, file: FileId, first: usize, last: usize,
+                        max_width: usize) -> Result<Vec<Edit>, Error> {
+        format::range_edits(sources.text(file), self.syntax, max_width, first, last)
+    }
+
+    /// Forget the results of a file that was closed.
+    pub fn remove(&mut self, file: FileId) {
+        self.files.remove(&file);
+    }
+
+    fn memo(&mut self, sources: &SourceManager, file: FileId) -> &mut Memo {
+        let text = sources.text(file);
+        if self.files.get(&file).map_or(true, |memo| memo.text != text) {
+            let mut ctx = self.context(text);
+            self.stats.scans += 1;
+            match scanner::tokenize_with(text, &ctx.identifiers) {
+                Ok(tokens) => ctx.tokens = tokens,
+                Err(err) => {
+                    let pos = err.pos().unwrap_or(Pos { line: 1, col: 1 });
+                    ctx.report(Diagnostic::error(pos, err.message()));
+                }
+            }
+            let unchanged = self.files.get(&file).map_or(false, |memo| {
+                // Only syntax errors depend on where the end of the file is.
+                let eof_matters = memo.broken;
+                !ctx.has_errors() && same_tokens(&memo.ctx.tokens, &ctx.tokens, eof_matters)
+                    && directives(&memo.text) == directives(text)
+            });
+            if unchanged {
+                let memo = self.files.get_mut(&file).unwrap();
+                memo.text = text.to_string();
+                memo.ctx.source = text.to_string();
+            } else {
+                self.files.insert(file, Memo { text: text.to_string(), ctx: ctx, parsed: false, broken: false, checked: false });
+            }
+        }
+        self.files.get_mut(&file).unwrap()
+    }
+
+    fn parse(&mut self, sources: &SourceManager, file: FileId) {
+        let mut parses = 0;
+        {
+            let memo = self.memo(sources, file);
+            if !memo.parsed && !memo.ctx.has_errors() {
+                // The parse pass reports syntax errors as diagnostics.
+                let _ = ParsePass.run(&mut memo.ctx);
+                memo.broken = memo.ctx.has_errors();
+                parses += 1;
+            }
+            memo.parsed = true;
+        }
+        self.stats.parses += parses;
+    }
+
+    fn check(&mut self, sources: &SourceManager, file: FileId) {
+        self.parse(sources, file);
+        let memo = self.files.get_mut(&file).unwrap();
+        // A program with syntax errors is type checked too, but for its
+        // error nodes.
+        if !memo.checked && memo.ctx.program.is_some() {
+            let _ = TypecheckPass.run(&mut memo.ctx);
+            self.stats.typechecks += 1;
+        }
+        memo.checked = true;
+    }
+
+    fn context(&self, text: &str) -> Context {
+        let mut ctx = Context::new(text.to_string());
+        ctx.syntax = self.syntax;
+        ctx.identifiers = self.identifiers;
+        ctx.strict = self.strict;
+        ctx
+    }
+}
+
+// Whether two token lists are the same, positions included, except
+// that of the end of the file unless `eof_matters`.
+fn same_tokens(a: &[Token], b: &[Token], eof_matters: bool) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(x, y)| {
+            x.typ == y.typ && x.lexeme == y.lexeme
+                && ((x.pos == y.pos && x.end == y.end) || (x.typ == TokenType::Eof && !eof_matters))
+        })
+}
+
+// The lines of `text` that may hold `# minilang:` directives, with
+// their line numbers, since the suppressions depend on them.
+fn directives(text: &str) -> Vec<(usize, &str)> {
+    text.lines().enumerate().filter(|&(_, line)| line.contains("minilang:")).collect()
+}
@@ -0,0 +1,224 @@
+// Counting loops that never end.
+//
+// For a loop of the shape
+//
+//     while i < END do
+//         ...
+//         i = i + STEP;
+//         ...
+//     done
+//
+// where `i` is a number that the body assigns only there, END and STEP
+// are constant expressions (as in unroll.rs) and the body does not
+// exit, the loop provably never ends once it is entered when
+//
+// - STEP moves `i` away from END, or is zero (`i < 10` with
+//   `i = i - 1`);
+// - the condition is `i != END` and `i` jumps over END (from 0 by
+//   steps of 3 to 10, or from 0.0 by steps of 0.1 to 1.0, which floats
+//   cannot hit exactly);
+// - `i` is a float that stops changing before it reaches END, because
+//   adding STEP no longer changes numbers that large (`i < 1e20` with
+//   `i = i + 1.0`).
+//
+// An int loop does end with an overflow, which is no better.
+
+use std::collections::HashMap;
+
+use ast::*;
+use error::Error;
+use interp::{self, Value};
+use pos::Pos;
+use typecheck::{self, Exprtable, Symtable};
+use unroll;
+
+// The number of iterations of a `!=` loop that are run to see whether
+// the counter hits the bound.
+const MAX_ITERATIONS: usize = 10000;
+
+// The loops of `program` that never end, with the reason.  The
+// variables of a function are looked up among its locals.
+pub fn endless_loops(program: &Program, symtable: &Symtable, exprtable: &Exprtable) -> Vec<(Pos, String)> {
+    let constants = unroll::loop_constants(program);
+    let mut result = Vec::new();
+    for func in &program.funcs {
+        check_stmts(&func.stmts, &typecheck::locals(func, exprtable), &constants, &mut result);
+    }
+    check_stmts(&program.stmts, symtable, &constants, &mut result);
+    result.sort_by_key(|&(pos, _)| pos);
+    result
+}
+
+fn check_stmts(stmts: &[Stmt], symtable: &Symtable, constants: &HashMap<u64, HashMap<String, Value>>,
+               out: &mut Vec<(Pos, String)>) {
+    for stmt in stmts {
+        match *stmt {
+            Stmt::If(ref stmt_) => {
+                check_stmts(&stmt_.then_stmts, symtable, constants, out);
+                check_stmts(&stmt_.else_stmts, symtable, constants, out);
+            }
+            Stmt::While(ref stmt_) => {
+                let empty = HashMap::new();
+                let entering = constants.get(&stmt_.node_id).unwrap_or(&empty);
+                if let Some(reason) = check(stmt_, symtable, entering).unwrap_or(None) {
+                    out.push((stmt_.pos, format!("this loop never ends once it is entered: {}", reason)));
+                }
+                check_stmts(&stmt_.stmts, symtable, constants, out);
+            }
+            Stmt::For(ref stmt_) => check_stmts(&stmt_.stmts, symtable, constants, out),
+            _ => {}
+        }
+    }
+}
+
+// Why the loop never ends, if it provably does not.  An error means
+// that the constants cannot be evaluated, e.g. they overflow.
+fn check(stmt: &StmtWhile, symtable: &Symtable, constants: &HashMap<String, Value>)
+         -> Result<Option<String>, Error> {
+    if stmt.stmts.iter().any(exits) {
+        return Ok(None);
+    }
+    let stable: HashMap<String, Value> = constants.iter()
+        .filter(|&(id, _)| !stmt.stmts.iter().any(|s| unroll::assigns(s, id)))
+        .map(|(id, value)| (id.clone(), value.clone()))
+        .collect();
+
+    // The condition, with the counter on the left.
+    let (op, id, bound) = match stmt.expr.expr {
+        Expr_::Binop(ref binop) => match (&binop.expr1.expr, &binop.expr2.expr) {
+            (&Expr_::Id(ref id), _) => (binop.op, &id.id[..], &binop.expr2),
+            (_, &Expr_::Id(ref id)) => (flip(binop.op), &id.id[..], &binop.expr1),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let ty = match symtable.get(id) {
+        Some(symbol) if symbol.ty.is_numeric() => &symbol.ty,
+        _ => return Ok(None),
+    };
+    let pos = stmt.pos;
+    let bound = match unroll::constant(bound, &stable) {
+        Some(value) => try!(interp::coerce(value, ty, pos)),
+        None => return Ok(None),
+    };
+    let step = match step(stmt, id, &stable) {
+        Some(Ok(value)) => try!(interp::coerce(value, ty, pos)),
+        Some(Err(err)) => return Err(err),
+        None => return Ok(None),
+    };
+    let start = match constants.get(id) {
+        Some(value) => Some(try!(interp::coerce(value.clone(), ty, pos))),
+        None => None,
+    };
+    // A loop that is never entered is harmless.
+    if let Some(ref start) = start {
+        if !holds(op, start, &bound) {
+            return Ok(None);
+        }
+    }
+
+    let zero = try!(interp::coerce(Value::Int(0), ty, pos));
+    let (up, down) = (holds(Binop::Gt, &step, &zero), holds(Binop::Lt, &step, &zero));
+    let cond = format!("{} {} {}", id, op, show(&bound));
+    let moves = format!("'{}' changes by {}", id, show(&step));
+    match op {
+        Binop::Lt | Binop::Le if !up => Ok(Some(format!("{} but the loop runs while {}", moves, cond))),
+        Binop::Gt | Binop::Ge if !down => Ok(Some(format!("{} but the loop runs while {}", moves, cond))),
+        Binop::Eq | Binop::Ne if !up && !down => Ok(Some(format!("{} and the loop runs while {}", moves, cond))),
+        Binop::Lt | Binop::Le | Binop::Gt | Binop::Ge => {
+            // Just short of the bound, adding the step must still
+            // change the counter.
+            let near = try!(interp::eval_binop(Binop::Sub, bound.clone(), step.clone(), pos));
+            let next = try!(interp::eval_binop(Binop::Add, near.clone(), step.clone(), pos));
+            if holds(Binop::Eq, &near, &next) {
+                Ok(Some(format!("adding {} to '{}' stops changing it before {}", show(&step), id, cond)))
+            } else {
+                Ok(None)
+            }
+        }
+        Binop::Ne => match start {
+            Some(start) => skips(id, start, &bound, &step, up, pos),
+            None => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+// The constant that the only assignment to `id` in the body of `stmt`,
+// `id = id + STEP` or `id = id - STEP`, adds to it.
+fn step(stmt: &StmtWhile, id: &str, constants: &HashMap<String, Value>) -> Option<Result<Value, Error>> {
+    let mut assignments = stmt.stmts.iter().filter(|s| unroll::assigns(s, id));
+    let assign = match (assignments.next(), assignments.next()) {
+        (Some(&Stmt::Assign(ref assign)), None) if assign.fields.is_empty() => assign,
+        _ => return None,
+    };
+    match assign.expr.expr {
+        Expr_::Binop(ref binop) if unroll::is_id(&binop.expr1, id) => {
+            match (binop.op, unroll::constant(&binop.expr2, constants)) {
+                (Binop::Add, Some(value)) => Some(Ok(value)),
+                (Binop::Sub, Some(value)) => Some(interp::eval_negate(value, stmt.pos)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Why `id`, going from `start` by `step`, never equals `bound`, if it
+// provably does not.
+fn skips(id: &str, start: Value, bound: &Value, step: &Value, up: bool, pos: Pos)
+         -> Result<Option<String>, Error> {
+    let reason = format!("'{}' goes from {} in steps of {} and never equals {}",
+                         id, show(&start), show(step), show(bound));
+    // Past the bound, the counter only moves further away.
+    let past = if up { Binop::Gt } else { Binop::Lt };
+    let mut value = start;
+    for _ in 0..MAX_ITERATIONS {
+        if holds(Binop::Eq, &value, bound) {
+            return Ok(None);
+        }
+        if holds(past, &value, bound) {
+            return Ok(Some(reason));
+        }
+        value = try!(interp::eval_binop(Binop::Add, value, step.clone(), pos));
+    }
+    Ok(None)
+}
+
+fn holds(op: Binop, v1: &Value, v2: &Value) -> bool {
+    match interp::eval_binop(op, v1.clone(), v2.clone(), Pos { line: 0, col: 0 }) {
+        Ok(Value::Bool(b)) => b,
+        _ => false,
+    }
+}
+
+// The comparison with its operands swapped.
+fn flip(op: Binop) -> Binop {
+    match op {
+        Binop::Lt => Binop::Gt,
+        Binop::Le => Binop::Ge,
+        Binop::Gt => Binop::Lt,
+        Binop::Ge => Binop::Le,
+        op => op,
+    }
+}
+
+// Whether `stmt` may end the program or return from the function.
+fn exits(stmt: &Stmt) -> bool {
+    match *stmt {
+        Stmt::Exit(_) | Stmt::Return(_) => true,
+        Stmt::If(ref stmt_) => stmt_.then_stmts.iter().chain(&stmt_.else_stmts).any(exits),
+        Stmt::While(ref stmt_) => stmt_.stmts.iter().any(exits),
+        Stmt::For(ref stmt_) => stmt_.stmts.iter().any(exits),
+        _ => false,
+    }
+}
+
+// A number as written in a program, rather than as printed.
+fn show(value: &Value) -> String {
+    match *value {
+        Value::Float(x) => format!("{:?}", x),
+        Value::Float32(x) => format!("{:?}", x),
+        ref value => value.to_string(),
+    }
+}
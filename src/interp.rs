@@ -0,0 +1,1148 @@
+//! A tree-walking interpreter, usable from other Rust programs.
+//!
+//! The host application talks to the program through an `Env`: it can
+//! bind variables before the program runs and read them afterwards,
+//! provide the input consumed by `read`, and capture what `print`
+//! writes.
+//!
+//! ```ignore
+//! let mut env = Env::new();
+//! env.bind("x", Value::Float(2.0));
+//! env.set_input("10");
+//! try!(minilang::eval("var n: int; read n; print x * n;", &mut env));
+//! assert_eq!(env.output(), "20.000000\n");
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use ast::*;
+use cost::{CostModel, CostReport, LoopCost};
+use error::Error;
+use ice;
+use json;
+use parser::Parser;
+use pos::Pos;
+use scanner;
+use typecheck::{self, Exprtable, Recordtable, Symtable, TypeChecker};
+use types::Type;
+
+/// The deepest nesting of calls unless `Limits::max_call_depth` says
+/// otherwise.  The frames of the calls live on the heap, not on the
+/// Rust stack, so the depth does not depend on the stack of the thread
+/// that runs the interpreter.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// A runtime value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Int32(i32),
+    Float32(f32),
+    String(String),
+    Bool(bool),
+    Record(Rc<str>, Vec<(String, Value)>),
+    Tuple(Vec<Value>),
+}
+
+impl Value {
+    pub fn ty(&self) -> Type {
+        match *self {
+            Value::Int(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::Int32(_) => Type::Int32,
+            Value::Float32(_) => Type::Float32,
+            Value::String(_) => Type::String,
+            Value::Bool(_) => Type::Bool,
+            Value::Record(ref name, _) => Type::Record(name.clone()),
+            Value::Tuple(ref values) => {
+                Type::tuple(&values.iter().map(Value::ty).collect::<Vec<_>>())
+            }
+        }
+    }
+
+    /// The initial value of variables of type `ty`.  The fields of
+    /// records and the elements of tuples are initialized recursively.
+    pub fn zero(ty: &Type, records: &Recordtable) -> Value {
+        match *ty {
+            Type::Int => Value::Int(0),
+            Type::Float => Value::Float(0.0),
+            Type::Int32 => Value::Int32(0),
+            Type::Float32 => Value::Float32(0.0),
+            Type::String => Value::String(String::new()),
+            Type::Bool => Value::Bool(false),
+            Type::Record(ref name) => {
+                let fields = records[&name[..]].iter()
+                    .map(|&(ref id, ref ty)| (id.clone(), Value::zero(ty, records)))
+                    .collect();
+                Value::Record(name.clone(), fields)
+            }
+            Type::Tuple(ref elements) => {
+                Value::Tuple(elements.iter().map(|ty| Value::zero(ty, records)).collect())
+            }
+        }
+    }
+
+    /// The value of a field of a record.
+    pub fn field(&self, field: &str) -> Option<&Value> {
+        match *self {
+            Value::Record(_, ref fields) => {
+                fields.iter().find(|&&(ref id, _)| id == field).map(|&(_, ref value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    /// The value as JSON: numbers, strings and booleans as such
+    /// (infinite and NaN floats as `null`), records as objects and
+    /// tuples as arrays.
+    pub fn to_json(&self) -> String {
+        match *self {
+            Value::Int(n) => n.to_string(),
+            Value::Int32(n) => n.to_string(),
+            Value::Float(x) if x.is_finite() => format!("{:?}", x),
+            Value::Float32(x) if x.is_finite() => format!("{:?}", x),
+            Value::Float(_) | Value::Float32(_) => "null".to_string(),
+            Value::String(ref s) => json::quote(s),
+            Value::Bool(b) => b.to_string(),
+            Value::Record(_, ref fields) => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|&(ref id, ref value)| format!("{}: {}", json::quote(id), value.to_json()))
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            Value::Tuple(ref values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_json()).collect();
+                format!("[{}]", values.join(", "))
+            }
+        }
+    }
+
+    fn field_mut(&mut self, field: &str) -> &mut Value {
+        match *self {
+            Value::Record(_, ref mut fields) => {
+                &mut fields.iter_mut().find(|&&mut (ref id, _)| id == field).expect("unknown field").1
+            }
+            _ => panic!("field of a non-record value"),
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        match *self {
+            Value::Int(n) => n != 0,
+            Value::Float(x) => x != 0.0,
+            Value::Int32(n) => n != 0,
+            Value::Float32(x) => x != 0.0,
+            Value::String(ref s) => !s.is_empty(),
+            Value::Bool(b) => b,
+            Value::Record(..) | Value::Tuple(_) => true,
+        }
+    }
+}
+
+// Floats are printed like C's "%f" so that the interpreter and the C
+// backend produce the same output.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:.6}", x),
+            Value::Int32(n) => write!(f, "{}", n),
+            Value::Float32(x) => write!(f, "{:.6}", x),
+            Value::String(ref s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Record(ref name, ref fields) => {
+                try!(write!(f, "{} {{", name));
+                for (i, &(ref id, ref value)) in fields.iter().enumerate() {
+                    try!(write!(f, "{} {}: {}", if i == 0 { "" } else { "," }, id, value));
+                }
+                write!(f, " }}")
+            }
+            Value::Tuple(ref values) => {
+                try!(write!(f, "("));
+                for (i, value) in values.iter().enumerate() {
+                    try!(write!(f, "{}{}", if i == 0 { "" } else { ", " }, value));
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Limits on the resources that a program may use, e.g. to run
+/// untrusted programs that may loop forever.  `None` means unlimited,
+/// except for the depth of calls, which is always bounded.  Start from
+/// `Limits::default()` and set the limits with the methods below: the
+/// struct has private fields, so that adding a limit does not break
+/// the callers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Maximum number of executed statements and loop iterations.
+    pub max_steps: Option<u64>,
+    /// Maximum number of bytes printed, newlines included.
+    pub max_output: Option<usize>,
+    /// Maximum running time.
+    pub timeout: Option<Duration>,
+    // Maximum number of nested calls; `None` means `MAX_CALL_DEPTH`.
+    max_call_depth: Option<usize>,
+}
+
+impl Limits {
+    pub fn max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop at a call nested more than `depth` deep, instead of
+    /// `MAX_CALL_DEPTH`.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+}
+
+/// The interface between a running program and its host.
+pub struct Env<'a> {
+    vars: HashMap<String, Value>,
+    input: VecDeque<String>,
+    output: String,
+    limits: Limits,
+    strict: bool,
+    exit_status: Option<i64>,
+    on_read: Option<Box<dyn FnMut() -> Option<String> + 'a>>,
+    on_print: Option<Box<dyn FnMut(&str) + 'a>>,
+    on_prompt: Option<Box<dyn FnMut(&str) + 'a>>,
+    cost_model: Option<CostModel>,
+    costs: CostReport,
+}
+
+impl<'a> Env<'a> {
+    pub fn new() -> Self {
+        Env {
+            vars: HashMap::new(),
+            input: VecDeque::new(),
+            output: String::new(),
+            limits: Limits::default(),
+            strict: false,
+            exit_status: None,
+            on_read: None,
+            on_print: None,
+            on_prompt: None,
+            cost_model: None,
+            costs: CostReport::default(),
+        }
+    }
+
+    /// Bind a variable before running a program.  If the program
+    /// declares the variable, the binding is its initial value;
+    /// otherwise the program can use it as if it had been declared.
+    pub fn bind(&mut self, id: &str, value: Value) {
+        self.vars.insert(id.to_string(), value);
+    }
+
+    /// The value of a variable, e.g. after the program has run.
+    pub fn get(&self, id: &str) -> Option<&Value> {
+        self.vars.get(id)
+    }
+
+    /// The variables of the program and their values.
+    pub fn vars(&self) -> &HashMap<String, Value> {
+        &self.vars
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Require bool conditions, as `TypeChecker::set_strict` does.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Provide the input of `read` statements: one value per
+    /// whitespace-separated word.
+    pub fn set_input(&mut self, input: &str) {
+        self.input = input.split_whitespace().map(|s| s.to_string()).collect();
+    }
+
+    /// Call `f` to get the next input word instead of using the input
+    /// given to `set_input`.  `f` returns None at the end of the input.
+    pub fn on_read<F: FnMut() -> Option<String> + 'a>(&mut self, f: F) {
+        self.on_read = Some(Box::new(f));
+    }
+
+    /// Call `f` with every printed line (without its newline) instead
+    /// of capturing the output.
+    pub fn on_print<F: FnMut(&str) + 'a>(&mut self, f: F) {
+        self.on_print = Some(Box::new(f));
+    }
+
+    /// Call `f` with the prompts of `read ... prompt` statements
+    /// instead of capturing them.  Prompts have no newline.
+    pub fn on_prompt<F: FnMut(&str) + 'a>(&mut self, f: F) {
+        self.on_prompt = Some(Box::new(f));
+    }
+
+    /// Add up the cost of what the program executes, weighted by
+    /// `model` (see cost.rs).
+    pub fn set_cost_model(&mut self, model: CostModel) {
+        self.cost_model = Some(model);
+    }
+
+    /// The cost of the program so far, if there is a cost model.
+    pub fn cost_report(&self) -> Option<&CostReport> {
+        self.cost_model.map(|_| &self.costs)
+    }
+
+    /// The status given to `exit`, if the program stopped with it.
+    pub fn exit_status(&self) -> Option<i64> {
+        self.exit_status
+    }
+
+    /// The output captured so far.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// The variables and their values as a JSON array, sorted by name,
+    /// for graders that check the final state of a program rather
+    /// than only what it printed.
+    pub fn to_json(&self) -> String {
+        let mut vars: Vec<(&String, &Value)> = self.vars.iter().collect();
+        vars.sort_by_key(|&(id, _)| id);
+        let mut out = String::from("[");
+        for (i, &(id, value)) in vars.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str(&format!("  {{\"name\": {}, \"type\": {}, \"value\": {}}}",
+                                  json::quote(id), json::quote(&value.ty().to_string()), value.to_json()));
+        }
+        out.push_str("\n]\n");
+        out
+    }
+
+    fn read(&mut self) -> Option<String> {
+        match self.on_read {
+            Some(ref mut f) => f(),
+            None => self.input.pop_front(),
+        }
+    }
+
+    fn prompt(&mut self, text: &str) {
+        match self.on_prompt {
+            Some(ref mut f) => f(text),
+            None => self.output.push_str(text),
+        }
+    }
+
+    fn print(&mut self, line: &str) {
+        match self.on_print {
+            Some(ref mut f) => f(line),
+            None => {
+                self.output.push_str(line);
+                self.output.push('\n');
+            }
+        }
+    }
+}
+
+/// Scan, parse, type check and run a program.
+pub fn eval(source: &str, env: &mut Env) -> Result<(), Error> {
+    let tokens = try!(scanner::tokenize(source));
+    let mut parser = Parser::new(tokens);
+    let program = try!(parser.parse_program());
+    eval_program(&program, env)
+}
+
+/// Type check and run a parsed program.
+pub fn eval_program(program: &Program, env: &mut Env) -> Result<(), Error> {
+    let mut tc = TypeChecker::new();
+    tc.set_strict(env.strict);
+    for (id, value) in &env.vars {
+        if !program.decls.iter().any(|decl| &decl.id == id) {
+            tc.declare_extern(id, value.ty());
+        }
+    }
+    try!(tc.tc_program(program));
+    ice::enter_phase("run");
+    run(program, &tc.symtable, &tc.expr_table, env)
+}
+
+/// Run a type checked program, given the types of its variables and
+/// expressions.
+pub fn run(program: &Program, symtable: &Symtable, exprtable: &Exprtable, env: &mut Env) -> Result<(), Error> {
+    let mut interp = Interpreter {
+        env: env,
+        symtable: symtable,
+        functions: program.funcs.iter()
+            .map(|func| (func.id.clone(), (func, typecheck::locals(func, exprtable))))
+            .collect(),
+        frames: Vec::new(),
+        tasks: vec![Task::Stmts(&program.stmts), Task::Decls(&program.decls)],
+        values: Vec::new(),
+        loops: Vec::new(),
+        records: typecheck::recordtable(&program.records),
+        steps: 0,
+        output_bytes: 0,
+        start: Instant::now(),
+    };
+    interp.run_tasks()
+}
+
+/// Convert a value to be stored in a variable of type `ty`.
+pub fn coerce(value: Value, ty: &Type, pos: Pos) -> Result<Value, Error> {
+    match (value, ty) {
+        (value, ty) if ty.is_numeric() && Type::widen(&value.ty(), ty).as_ref() == Some(ty) => Ok(widen(value, ty)),
+        (Value::Tuple(ref values), &Type::Tuple(ref elements)) if values.len() == elements.len() => {
+            let mut result = Vec::new();
+            for (value, ty) in values.iter().zip(elements.iter()) {
+                result.push(try!(coerce(value.clone(), ty, pos)));
+            }
+            Ok(Value::Tuple(result))
+        }
+        (value, ty) => {
+            if value.ty() == *ty {
+                Ok(value)
+            } else {
+                Err(Error::UnexpectedType { pos: pos, expected: ty.clone(), actual: value.ty() })
+            }
+        }
+    }
+}
+
+struct Interpreter<'e, 'a: 'e> {
+    env: &'e mut Env<'a>,
+    symtable: &'e Symtable,
+    // The functions by name, with the types of their parameters and
+    // locals.
+    functions: HashMap<String, (&'e FuncDecl, Symtable)>,
+    // The calls being run, innermost last.  The statements of the
+    // program use the variables of `env`.
+    frames: Vec<Frame<'e>>,
+    // What is left to run, next last, and the values of the
+    // expressions evaluated so far.  Statements, expressions and calls
+    // are run from these stacks rather than by recursion, so that deep
+    // recursion in the program and long chains of operators do not use
+    // up the stack of the host thread.
+    tasks: Vec<Task<'e>>,
+    values: Vec<Value>,
+    // The loops being run, innermost last.
+    loops: Vec<LoopRun>,
+    records: Recordtable,
+    steps: u64,
+    output_bytes: usize,
+    start: Instant,
+}
+
+// The parameters and locals of a call, and the value it returned once
+// it ran `return`.
+struct Frame<'e> {
+    func: &'e FuncDecl,
+    vars: HashMap<String, Value>,
+    returned: Option<Value>,
+}
+
+// The cost of the run when a loop started, and its iterations so far.
+struct LoopRun {
+    before: u64,
+    iterations: u64,
+}
+
+// A step of the run.  The tasks that follow the evaluation of an
+// expression take its value off the stack of values.
+enum Task<'e> {
+    Decls(&'e [Decl]),
+    Stmts(&'e [Stmt]),
+    Stmt(&'e Stmt),
+    Expr(&'e Expr),
+    // Give a declared variable the value of its initializer.
+    Define(&'e Decl, Type),
+    Print(&'e StmtPrint),
+    Exit,
+    Assign(&'e StmtAssign),
+    Append(&'e str),
+    Destructure(&'e StmtDestructure),
+    // Drop the values above the given height, e.g. the value of a call
+    // made as a statement.
+    Discard(usize),
+    Return(Type, Pos),
+    If(&'e StmtIf),
+    WhileTest(&'e StmtWhile),
+    WhileBody(&'e StmtWhile),
+    WhileNext(&'e StmtWhile),
+    ForStart(&'e StmtFor),
+    // The next iteration of a `for` loop, with its last value.
+    ForNext(&'e StmtFor, Value),
+    // Record the cost of the loop at this position.
+    LoopEnd(Pos),
+    Coerce(Type, Pos),
+    Negate(Pos),
+    Binop(Binop, Pos),
+    Logic(&'e ExprLogic),
+    Not,
+    Field(&'e str),
+    ToString,
+    Tuple(usize),
+    // Start a call once its arguments are evaluated, and end it.
+    Enter(&'e FuncDecl, Pos),
+    Leave,
+}
+
+impl<'e, 'a> Interpreter<'e, 'a> {
+    // The variables in scope: those of the innermost call, or those
+    // of the program.
+    fn vars(&self) -> &HashMap<String, Value> {
+        match self.frames.last() {
+            Some(frame) => &frame.vars,
+            None => &self.env.vars,
+        }
+    }
+
+    fn vars_mut(&mut self) -> &mut HashMap<String, Value> {
+        match self.frames.last_mut() {
+            Some(frame) => &mut frame.vars,
+            None => &mut self.env.vars,
+        }
+    }
+
+    fn var_type(&self, id: &str) -> Type {
+        match self.frames.last() {
+            Some(frame) => self.functions[&frame.func.id].1[id].ty.clone(),
+            None => self.symtable[id].ty.clone(),
+        }
+    }
+
+    // Account for one statement or loop iteration at `pos`.
+    fn step(&mut self, pos: Pos) -> Result<(), Error> {
+        ice::at(pos);
+        self.steps += 1;
+        if let Some(max) = self.env.limits.max_steps {
+            if self.steps > max {
+                return Err(Error::LimitExceeded(pos, format!("more than {} steps", max)));
+            }
+        }
+        if let Some(timeout) = self.env.limits.timeout {
+            if self.steps % 256 == 0 && self.start.elapsed() > timeout {
+                let ms = timeout.as_secs() * 1000 + timeout.subsec_nanos() as u64 / 1_000_000;
+                return Err(Error::LimitExceeded(pos, format!("ran for more than {} ms", ms)));
+            }
+        }
+        Ok(())
+    }
+
+    // Add the weight of an operation to the cost of the run.
+    fn charge<F: Fn(&CostModel) -> u64>(&mut self, weight: F) {
+        if let Some(ref model) = self.env.cost_model {
+            self.env.costs.total += weight(model);
+        }
+    }
+
+    fn count_output(&mut self, bytes: usize, pos: Pos) -> Result<(), Error> {
+        self.output_bytes += bytes;
+        if let Some(max) = self.env.limits.max_output {
+            if self.output_bytes > max {
+                return Err(Error::LimitExceeded(pos, format!("more than {} bytes of output", max)));
+            }
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.values.pop().expect("a value for the task")
+    }
+
+    // Run the tasks until there are none left.  After an error, the
+    // loops being run still get their cost recorded.
+    fn run_tasks(&mut self) -> Result<(), Error> {
+        while let Some(task) = self.tasks.pop() {
+            if let Err(err) = self.run_task(task) {
+                self.unwind(false);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // Drop the tasks that are left in the current call, or all of them,
+    // as `return` and `exit` do.
+    fn unwind(&mut self, to_call: bool) {
+        while let Some(task) = self.tasks.pop() {
+            match task {
+                Task::LoopEnd(pos) => self.end_loop(pos),
+                Task::Leave if to_call => return self.leave(),
+                _ => {}
+            }
+        }
+    }
+
+    fn start_loop(&mut self, pos: Pos) {
+        self.loops.push(LoopRun { before: self.env.costs.total, iterations: 0 });
+        self.tasks.push(Task::LoopEnd(pos));
+    }
+
+    fn end_loop(&mut self, pos: Pos) {
+        let run = self.loops.pop().expect("a loop ends after it starts");
+        if self.env.cost_model.is_some() {
+            let cost = self.env.costs.total - run.before;
+            let entry = self.env.costs.loops.entry(pos).or_insert_with(LoopCost::default);
+            entry.iterations += run.iterations;
+            entry.cost += cost;
+        }
+    }
+
+    // Run the body of a loop once more.
+    fn iterate(&mut self, stmts: &'e [Stmt]) {
+        self.loops.last_mut().expect("an iteration in a loop").iterations += 1;
+        self.tasks.push(Task::Stmts(stmts));
+    }
+
+    // The value of a call, if it returned one, goes to the caller.
+    fn leave(&mut self) {
+        let frame = self.frames.pop().expect("a call ends after it starts");
+        if let Some(value) = frame.returned {
+            self.values.push(value);
+        }
+    }
+
+    fn ret(&mut self, value: Option<Value>) {
+        if self.frames.is_empty() {
+            return;
+        }
+        if let Some(value) = value {
+            self.frames.last_mut().unwrap().returned = Some(value);
+        }
+        self.unwind(true);
+    }
+
+    fn run_task(&mut self, task: Task<'e>) -> Result<(), Error> {
+        match task {
+            // Variables bound by the host keep their value; the others
+            // get the value of their initializer, or zero.  The host
+            // cannot bind the locals of a function.
+            Task::Decls(decls) => {
+                if let Some((decl, rest)) = decls.split_first() {
+                    self.tasks.push(Task::Decls(rest));
+                    let ty = self.var_type(&decl.id);
+                    let bound = if self.frames.is_empty() { self.env.vars.get(&decl.id).cloned() } else { None };
+                    match (bound, &decl.init) {
+                        (Some(value), _) => {
+                            let value = try!(coerce(value, &ty, decl.pos));
+                            self.vars_mut().insert(decl.id.clone(), value);
+                        }
+                        (None, &Some(ref init)) => {
+                            self.tasks.push(Task::Define(decl, ty));
+                            self.tasks.push(Task::Expr(init));
+                        }
+                        (None, &None) => {
+                            let value = Value::zero(&ty, &self.records);
+                            self.vars_mut().insert(decl.id.clone(), value);
+                        }
+                    }
+                }
+            }
+            Task::Define(decl, ty) => {
+                let value = self.pop();
+                let value = try!(coerce(value, &ty, decl.pos));
+                self.vars_mut().insert(decl.id.clone(), value);
+            }
+            Task::Stmts(stmts) => {
+                if let Some((stmt, rest)) = stmts.split_first() {
+                    self.tasks.push(Task::Stmts(rest));
+                    self.tasks.push(Task::Stmt(stmt));
+                }
+            }
+            Task::Stmt(stmt) => try!(self.run_stmt(stmt)),
+            Task::Expr(expr) => try!(self.eval_expr(expr)),
+            Task::Print(stmt) => {
+                let line = match (stmt.precision, self.pop()) {
+                    (Some(n), value) => match widen(value, &Type::Float) {
+                        Value::Float(x) => format!("{:.*}", n, x),
+                        _ => unreachable!(),
+                    },
+                    (None, value) => value.to_string(),
+                };
+                try!(self.count_output(line.len() + 1, stmt.pos));
+                self.env.print(&line);
+            }
+            Task::Exit => {
+                match self.pop() {
+                    Value::Int(n) => self.env.exit_status = Some(n),
+                    Value::Int32(n) => self.env.exit_status = Some(n as i64),
+                    _ => unreachable!(),
+                }
+                self.unwind(false);
+            }
+            Task::Assign(stmt) => {
+                let value = self.pop();
+                try!(self.assign(&stmt.id, &stmt.fields, value, stmt.pos));
+            }
+            // `s = s + piece;` appends to `s` in place rather than
+            // copying it, so that building a string in a loop takes
+            // linear time.
+            Task::Append(id) => {
+                let piece = self.pop();
+                self.charge(|m| m.string);
+                if let (Some(&mut Value::String(ref mut s)), Value::String(piece)) = (self.vars_mut().get_mut(id), piece) {
+                    s.push_str(&piece);
+                }
+            }
+            Task::Destructure(stmt) => {
+                let values = match self.pop() {
+                    Value::Tuple(values) => values,
+                    _ => unreachable!(),
+                };
+                for (target, value) in stmt.targets.iter().zip(values) {
+                    try!(self.assign(&target.id, &target.fields, value, stmt.pos));
+                }
+            }
+            Task::Discard(height) => self.values.truncate(height),
+            // The value is converted to the return type.
+            Task::Return(ty, pos) => {
+                let value = self.pop();
+                let value = try!(coerce(value, &ty, pos));
+                self.ret(Some(value));
+            }
+            Task::If(stmt) => {
+                if self.pop().is_true() {
+                    self.tasks.push(Task::Stmts(&stmt.then_stmts));
+                } else {
+                    self.tasks.push(Task::Stmts(&stmt.else_stmts));
+                }
+            }
+            Task::WhileTest(stmt) => {
+                self.charge(|m| m.branch);
+                self.tasks.push(Task::WhileBody(stmt));
+                self.tasks.push(Task::Expr(&stmt.expr));
+            }
+            Task::WhileBody(stmt) => {
+                if self.pop().is_true() {
+                    self.tasks.push(Task::WhileNext(stmt));
+                    self.iterate(&stmt.stmts);
+                }
+            }
+            Task::WhileNext(stmt) => {
+                try!(self.step(stmt.pos));
+                self.tasks.push(Task::WhileTest(stmt));
+            }
+            // The variable is compared with `last` before it is
+            // incremented, so that it never goes past it.
+            Task::ForStart(stmt) => {
+                let last = self.pop();
+                let first = self.pop();
+                self.charge(|m| m.assign);
+                try!(self.assign(&stmt.id, &[], first, stmt.pos));
+                self.charge(|m| m.branch);
+                if try!(eval_binop(Binop::Le, self.vars()[&stmt.id].clone(), last.clone(), stmt.pos)).is_true() {
+                    self.tasks.push(Task::ForNext(stmt, last));
+                    self.iterate(&stmt.stmts);
+                }
+            }
+            Task::ForNext(stmt, last) => {
+                self.charge(|m| m.branch);
+                let i = self.vars()[&stmt.id].clone();
+                if !try!(eval_binop(Binop::Ge, i.clone(), last.clone(), stmt.pos)).is_true() {
+                    self.charge(|m| m.arithmetic + m.assign);
+                    let one = if self.var_type(&stmt.id) == Type::Int32 { Value::Int32(1) } else { Value::Int(1) };
+                    let next = try!(eval_binop(Binop::Add, i, one, stmt.pos));
+                    try!(self.assign(&stmt.id, &[], next, stmt.pos));
+                    try!(self.step(stmt.pos));
+                    self.tasks.push(Task::ForNext(stmt, last));
+                    self.iterate(&stmt.stmts);
+                }
+            }
+            Task::LoopEnd(pos) => self.end_loop(pos),
+            Task::Coerce(ty, pos) => {
+                let value = self.pop();
+                let value = try!(coerce(value, &ty, pos));
+                self.values.push(value);
+            }
+            Task::Negate(pos) => {
+                let v = self.pop();
+                match v {
+                    Value::String(_) => self.charge(|m| m.string),
+                    _ => self.charge(|m| m.arithmetic),
+                }
+                let value = try!(eval_negate(v, pos));
+                self.values.push(value);
+            }
+            Task::Binop(op, pos) => {
+                let v2 = self.pop();
+                let v1 = self.pop();
+                match (op, &v1) {
+                    (Binop::Mul, _) => self.charge(|m| m.multiply),
+                    (Binop::Div, _) => self.charge(|m| m.divide),
+                    (Binop::Add, &Value::String(_)) => self.charge(|m| m.string),
+                    _ => self.charge(|m| m.arithmetic),
+                }
+                let value = try!(eval_binop(op, v1, v2, pos));
+                self.values.push(value);
+            }
+            // The right operand is evaluated only if the left one does
+            // not decide the result.
+            Task::Logic(expr) => {
+                match (expr.op, self.pop().is_true()) {
+                    (Logic::And, false) => self.values.push(Value::Bool(false)),
+                    (Logic::Or, true) => self.values.push(Value::Bool(true)),
+                    _ => self.tasks.push(Task::Expr(&expr.expr2)),
+                }
+            }
+            Task::Not => {
+                let v = self.pop();
+                self.charge(|m| m.arithmetic);
+                self.values.push(Value::Bool(!v.is_true()));
+            }
+            Task::Field(field) => {
+                let value = self.pop().field(field).expect("unknown field").clone();
+                self.values.push(value);
+            }
+            Task::ToString => {
+                let value = self.pop().to_string();
+                self.values.push(Value::String(value));
+            }
+            Task::Tuple(n) => {
+                let at = self.values.len() - n;
+                let values = self.values.split_off(at);
+                self.values.push(Value::Tuple(values));
+            }
+            Task::Enter(func, pos) => try!(self.enter(func, pos)),
+            Task::Leave => self.leave(),
+        }
+        Ok(())
+    }
+
+    // Start a call with the arguments, already converted to the types
+    // of the parameters, on the stack of values.
+    fn enter(&mut self, func: &'e FuncDecl, pos: Pos) -> Result<(), Error> {
+        let max_depth = self.env.limits.max_call_depth.unwrap_or(MAX_CALL_DEPTH);
+        if self.frames.len() >= max_depth {
+            return Err(Error::LimitExceeded(pos, format!("stack overflow: more than {} nested calls", max_depth)));
+        }
+        let at = self.values.len() - func.params.len();
+        let args = self.values.split_off(at);
+        let vars = func.params.iter().map(|param| param.id.clone()).zip(args).collect();
+        self.charge(|m| m.call);
+        self.frames.push(Frame { func: func, vars: vars, returned: None });
+        self.tasks.push(Task::Leave);
+        self.tasks.push(Task::Stmts(&func.stmts));
+        self.tasks.push(Task::Decls(&func.decls));
+        Ok(())
+    }
+
+    fn run_stmt(&mut self, stmt: &'e Stmt) -> Result<(), Error> {
+        try!(self.step(stmt.pos()));
+        match *stmt {
+            Stmt::Read(ref stmt_) => {
+                self.charge(|m| m.read * stmt_.ids.len() as u64);
+                if let Some(ref prompt) = stmt_.prompt {
+                    try!(self.count_output(prompt.len(), stmt_.pos));
+                    self.env.prompt(prompt);
+                }
+                for id in &stmt_.ids {
+                    let ty = self.var_type(id);
+                    let word = try!(self.env.read().ok_or(Error::EndOfInput(stmt_.pos)));
+                    let value = match ty {
+                        Type::Int => word.parse().ok().map(Value::Int),
+                        Type::Float => word.parse().ok().map(Value::Float),
+                        Type::Int32 => word.parse().ok().map(Value::Int32),
+                        Type::Float32 => word.parse().ok().map(Value::Float32),
+                        Type::String => Some(Value::String(word.clone())),
+                        Type::Bool => word.parse().ok().map(Value::Bool),
+                        Type::Record(_) | Type::Tuple(_) => None,
+                    };
+                    let value = try!(value.ok_or(Error::InvalidInput(stmt_.pos, word, ty)));
+                    self.vars_mut().insert(id.clone(), value);
+                }
+            }
+            Stmt::Print(ref stmt_) => {
+                self.charge(|m| m.print);
+                self.tasks.push(Task::Print(stmt_));
+                self.tasks.push(Task::Expr(&stmt_.expr));
+            }
+            Stmt::Exit(ref stmt_) => {
+                self.charge(|m| m.exit);
+                self.tasks.push(Task::Exit);
+                self.tasks.push(Task::Expr(&stmt_.expr));
+            }
+            Stmt::Assign(ref stmt_) => {
+                self.charge(|m| m.assign);
+                match stmt_.appended() {
+                    Some(piece) if self.vars()[&stmt_.id].ty() == Type::String => {
+                        self.tasks.push(Task::Append(&stmt_.id));
+                        self.tasks.push(Task::Expr(piece));
+                    }
+                    _ => {
+                        self.tasks.push(Task::Assign(stmt_));
+                        self.tasks.push(Task::Expr(&stmt_.expr));
+                    }
+                }
+            }
+            // The whole right-hand side is evaluated before any target
+            // is assigned, so that `x, y = y, x;` swaps.
+            Stmt::Destructure(ref stmt_) => {
+                self.charge(|m| m.assign * stmt_.targets.len() as u64);
+                self.tasks.push(Task::Destructure(stmt_));
+                self.tasks.push(Task::Expr(&stmt_.expr));
+            }
+            Stmt::Call(ref stmt_) => {
+                let height = self.values.len();
+                self.tasks.push(Task::Discard(height));
+                self.tasks.push(Task::Expr(&stmt_.expr));
+            }
+            Stmt::Return(ref stmt_) => {
+                match (self.frames.last().and_then(|frame| frame.func.ret.clone()), &stmt_.expr) {
+                    (Some(ty), &Some(ref expr)) => {
+                        self.tasks.push(Task::Return(ty, expr.pos));
+                        self.tasks.push(Task::Expr(expr));
+                    }
+                    _ => self.ret(None),
+                }
+            }
+            Stmt::If(ref stmt_) => {
+                self.charge(|m| m.branch);
+                self.tasks.push(Task::If(stmt_));
+                self.tasks.push(Task::Expr(&stmt_.expr));
+            }
+            Stmt::While(ref stmt_) => {
+                self.start_loop(stmt_.pos);
+                self.tasks.push(Task::WhileTest(stmt_));
+            }
+            Stmt::For(ref stmt_) => {
+                let ty = self.var_type(&stmt_.id);
+                self.start_loop(stmt_.pos);
+                self.tasks.push(Task::ForStart(stmt_));
+                self.tasks.push(Task::Coerce(ty.clone(), stmt_.last.pos));
+                self.tasks.push(Task::Expr(&stmt_.last));
+                self.tasks.push(Task::Coerce(ty, stmt_.first.pos));
+                self.tasks.push(Task::Expr(&stmt_.first));
+            }
+            Stmt::Error(ref stmt_) => return Err(Error::SyntaxErrorNode(stmt_.pos)),
+        }
+        Ok(())
+    }
+
+    // Store `value` in the variable `id`, or in one of its fields.
+    fn assign(&mut self, id: &str, fields: &[String], value: Value, pos: Pos) -> Result<(), Error> {
+        let mut ty = self.var_type(id);
+        for field in fields {
+            ty = typecheck::field_type(&self.records, &ty, field).expect("unknown field");
+        }
+        let value = try!(coerce(value, &ty, pos));
+        let mut slot = self.vars_mut().get_mut(id).expect("unbound variable");
+        for field in fields {
+            slot = slot.field_mut(field);
+        }
+        *slot = value;
+        Ok(())
+    }
+
+    // Push the value of a literal or variable, or the tasks that
+    // compute the value of `expr`.
+    fn eval_expr(&mut self, expr: &'e Expr) -> Result<(), Error> {
+        let value = match expr.expr {
+            Expr_::Id(ref expr_) => self.vars()[&expr_.id].clone(),
+            Expr_::Int(ref expr_) if expr_.ty == Type::Int32 => Value::Int32(expr_.value as i32),
+            Expr_::Int(ref expr_) => Value::Int(expr_.value),
+            Expr_::Float(ref expr_) if expr_.ty == Type::Float32 => Value::Float32(expr_.value as f32),
+            Expr_::Float(ref expr_) => Value::Float(expr_.value),
+            Expr_::String(ref expr_) => Value::String(expr_.value.clone()),
+            Expr_::Bool(ref expr_) => Value::Bool(expr_.value),
+            Expr_::Error => return Err(Error::SyntaxErrorNode(expr.pos)),
+            Expr_::Negate(ref expr_) => {
+                self.tasks.push(Task::Negate(expr.pos));
+                self.tasks.push(Task::Expr(&expr_.expr));
+                return Ok(());
+            }
+            // The left operand is evaluated first; this is the order
+            // that every backend must follow.
+            Expr_::Binop(ref expr_) => {
+                self.tasks.push(Task::Binop(expr_.op, expr.pos));
+                self.tasks.push(Task::Expr(&expr_.expr2));
+                self.tasks.push(Task::Expr(&expr_.expr1));
+                return Ok(());
+            }
+            Expr_::Logic(ref expr_) => {
+                self.charge(|m| m.branch);
+                self.tasks.push(Task::Logic(expr_));
+                self.tasks.push(Task::Expr(&expr_.expr1));
+                return Ok(());
+            }
+            Expr_::Not(ref expr_) => {
+                self.tasks.push(Task::Not);
+                self.tasks.push(Task::Expr(&expr_.expr));
+                return Ok(());
+            }
+            Expr_::Field(ref expr_) => {
+                self.tasks.push(Task::Field(&expr_.field));
+                self.tasks.push(Task::Expr(&expr_.expr));
+                return Ok(());
+            }
+            Expr_::ToString(ref expr_) => {
+                self.charge(|m| m.string);
+                self.tasks.push(Task::ToString);
+                self.tasks.push(Task::Expr(&expr_.expr));
+                return Ok(());
+            }
+            Expr_::Tuple(ref expr_) => {
+                self.tasks.push(Task::Tuple(expr_.exprs.len()));
+                for e in expr_.exprs.iter().rev() {
+                    self.tasks.push(Task::Expr(e));
+                }
+                return Ok(());
+            }
+            // The arguments are converted to the types of the
+            // parameters as they are evaluated.
+            Expr_::Call(ref expr_) => {
+                let func = self.functions[&expr_.id].0;
+                self.tasks.push(Task::Enter(func, expr.pos));
+                for (arg, param) in expr_.args.iter().zip(&func.params).rev() {
+                    self.tasks.push(Task::Coerce(param.ty.clone(), arg.pos));
+                    self.tasks.push(Task::Expr(arg));
+                }
+                return Ok(());
+            }
+        };
+        self.values.push(value);
+        Ok(())
+    }
+}
+
+// Convert a number to a wider type.
+fn widen(value: Value, ty: &Type) -> Value {
+    match (value, ty) {
+        (Value::Int32(n), &Type::Int) => Value::Int(n as i64),
+        (Value::Int32(n), &Type::Float32) => Value::Float32(n as f32),
+        (Value::Int32(n), &Type::Float) => Value::Float(n as f64),
+        (Value::Int(n), &Type::Float) => Value::Float(n as f64),
+        (Value::Float32(x), &Type::Float) => Value::Float(x as f64),
+        (value, _) => value,
+    }
+}
+
+/// Negate a number or reverse a string.
+pub fn eval_negate(v: Value, pos: Pos) -> Result<Value, Error> {
+    match v {
+        Value::Int(n) => n.checked_neg().map(Value::Int).ok_or(Error::IntegerOverflow(pos)),
+        Value::Float(x) => Ok(Value::Float(-x)),
+        Value::Int32(n) => n.checked_neg().map(Value::Int32).ok_or(Error::IntegerOverflow(pos)),
+        Value::Float32(x) => Ok(Value::Float32(-x)),
+        Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+        v => Err(Error::IllTypedUnop { pos: pos, op: "-", ty: v.ty() }),
+    }
+}
+
+/// Apply a binary operator.  Numbers of different types are converted
+/// to their common type, as in the type checker.
+pub fn eval_binop(op: Binop, v1: Value, v2: Value, pos: Pos) -> Result<Value, Error> {
+    let (v1, v2) = match Type::widen(&v1.ty(), &v2.ty()) {
+        Some(ty) => (widen(v1, &ty), widen(v2, &ty)),
+        None => (v1, v2),
+    };
+    if op.is_comparison() {
+        return compare(op, v1, v2, pos);
+    }
+    match (v1, v2) {
+        (Value::Int(a), Value::Int(b)) => {
+            let result = match op {
+                Binop::Add => a.checked_add(b),
+                Binop::Sub => a.checked_sub(b),
+                Binop::Mul => a.checked_mul(b),
+                Binop::Div => {
+                    if b == 0 {
+                        return Err(Error::DivisionByZero(pos));
+                    }
+                    a.checked_div(b)
+                }
+                _ => unreachable!(),
+            };
+            result.map(Value::Int).ok_or(Error::IntegerOverflow(pos))
+        }
+        (Value::Int32(a), Value::Int32(b)) => {
+            let result = match op {
+                Binop::Add => a.checked_add(b),
+                Binop::Sub => a.checked_sub(b),
+                Binop::Mul => a.checked_mul(b),
+                Binop::Div => {
+                    if b == 0 {
+                        return Err(Error::DivisionByZero(pos));
+                    }
+                    a.checked_div(b)
+                }
+                _ => unreachable!(),
+            };
+            result.map(Value::Int32).ok_or(Error::IntegerOverflow(pos))
+        }
+        (Value::Float32(a), Value::Float32(b)) => {
+            Ok(Value::Float32(match op {
+                Binop::Add => a + b,
+                Binop::Sub => a - b,
+                Binop::Mul => a * b,
+                Binop::Div => a / b,
+                _ => unreachable!(),
+            }))
+        }
+        (Value::String(a), Value::String(b)) => {
+            match op {
+                Binop::Add => Ok(Value::String(a + &b)),
+                Binop::Sub => Ok(Value::String(a + &b.chars().rev().collect::<String>())),
+                _ => Err(Error::IllTypedBinop { pos: pos, op: op, lhs: Type::String, rhs: Type::String }),
+            }
+        }
+        (v1, v2) => {
+            let a = try!(as_float(v1, op, pos));
+            let b = try!(as_float(v2, op, pos));
+            Ok(Value::Float(match op {
+                Binop::Add => a + b,
+                Binop::Sub => a - b,
+                Binop::Mul => a * b,
+                Binop::Div => a / b,
+                _ => unreachable!(),
+            }))
+        }
+    }
+}
+
+// Comparisons involving NaN are false, except for !=, as in C.
+fn compare(op: Binop, v1: Value, v2: Value, pos: Pos) -> Result<Value, Error> {
+    let ord = match (v1, v2) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(&b)),
+        (Value::Int32(a), Value::Int32(b)) => Some(a.cmp(&b)),
+        (Value::Float32(a), Value::Float32(b)) => a.partial_cmp(&b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(&b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(&b)),
+        (v1, v2) => {
+            let a = try!(as_float(v1, op, pos));
+            let b = try!(as_float(v2, op, pos));
+            a.partial_cmp(&b)
+        }
+    };
+    Ok(Value::Bool(match op {
+        Binop::Eq => ord == Some(Ordering::Equal),
+        Binop::Ne => ord != Some(Ordering::Equal),
+        Binop::Lt => ord == Some(Ordering::Less),
+        Binop::Le => ord == Some(Ordering::Less) || ord == Some(Ordering::Equal),
+        Binop::Gt => ord == Some(Ordering::Greater),
+        Binop::Ge => ord == Some(Ordering::Greater) || ord == Some(Ordering::Equal),
+        _ => unreachable!(),
+    }))
+}
+
+fn as_float(v: Value, op: Binop, pos: Pos) -> Result<f64, Error> {
+    match v {
+        Value::Int(n) => Ok(n as f64),
+        Value::Float(x) => Ok(x),
+        v => Err(Error::IllTypedBinop { pos: pos, op: op, lhs: v.ty(), rhs: Type::Float }),
+    }
+}
@@ -0,0 +1,227 @@
+// Helpers for writing JSON by hand, and a small reader.
+
+use std::char;
+
+use error::Error;
+
+// Return `s` as a quoted JSON string.
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// A JSON value, as read by `parse`.  Object members keep their order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    // The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v),
+            _ => None,
+        }
+    }
+}
+
+// Read a JSON document, for files that the compiler writes itself and
+// reads back.
+pub fn parse(text: &str) -> Result<Value, Error> {
+    let mut reader = Reader { text: text, offset: 0 };
+    let value = try!(reader.value(0));
+    reader.skip_whitespace();
+    if reader.offset < text.len() {
+        return Err(reader.invalid("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+// Arrays and objects nested deeper than this are rejected rather than
+// exhausting the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Reader<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn invalid(&self, msg: &str) -> Error {
+        Error::InvalidJson(self.offset, msg.to_string())
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.find(|c| c != ' ' && c != '\t' && c != '\n' && c != '\r').unwrap_or(rest.len());
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(s) {
+            self.offset += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Error> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.invalid(&format!("expected '{}'", s)))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.invalid("nested too deeply"));
+        }
+        self.skip_whitespace();
+        if self.eat("null") {
+            Ok(Value::Null)
+        } else if self.eat("true") {
+            Ok(Value::Bool(true))
+        } else if self.eat("false") {
+            Ok(Value::Bool(false))
+        } else if self.eat("[") {
+            let mut items = Vec::new();
+            if !self.eat("]") {
+                loop {
+                    items.push(try!(self.value(depth + 1)));
+                    if self.eat("]") {
+                        break;
+                    }
+                    try!(self.expect(","));
+                }
+            }
+            Ok(Value::Array(items))
+        } else if self.eat("{") {
+            let mut members = Vec::new();
+            if !self.eat("}") {
+                loop {
+                    self.skip_whitespace();
+                    let key = try!(self.string());
+                    try!(self.expect(":"));
+                    members.push((key, try!(self.value(depth + 1))));
+                    if self.eat("}") {
+                        break;
+                    }
+                    try!(self.expect(","));
+                }
+            }
+            Ok(Value::Object(members))
+        } else if self.rest().starts_with('"') {
+            self.string().map(Value::String)
+        } else {
+            self.number()
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let rest = self.rest();
+        let len = rest.find(|c: char| !(c.is_digit(10) || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E'))
+            .unwrap_or(rest.len());
+        match rest[..len].parse() {
+            Ok(n) if len > 0 => {
+                self.offset += len;
+                Ok(Value::Number(n))
+            }
+            _ => Err(self.invalid("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        if !self.rest().starts_with('"') {
+            return Err(self.invalid("expected a string"));
+        }
+        self.offset += 1;
+        let mut out = String::new();
+        loop {
+            let c = match self.rest().chars().next() {
+                Some(c) => c,
+                None => return Err(self.invalid("unterminated string")),
+            };
+            self.offset += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = match self.rest().chars().next() {
+                        Some(escape) => escape,
+                        None => return Err(self.invalid("unterminated string")),
+                    };
+                    self.offset += 1;
+                    match escape {
+                        '"' | '\\' | '/' => out.push(escape),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => out.push(try!(self.unicode_escape())),
+                        _ => {
+                            self.offset -= 1;
+                            return Err(self.invalid("invalid escape"));
+                        }
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(self.invalid("control character in a string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    // The character of `\uXXXX`, or of a surrogate pair of them.
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let high = try!(self.hex4());
+        let code = if high >= 0xd800 && high < 0xdc00 {
+            if !self.rest().starts_with("\\u") {
+                return Err(self.invalid("unpaired surrogate"));
+            }
+            self.offset += 2;
+            let low = try!(self.hex4());
+            if low < 0xdc00 || low >= 0xe000 {
+                return Err(self.invalid("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.invalid("invalid character escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self.rest().get(..4).and_then(|s| u32::from_str_radix(s, 16).ok());
+        match digits {
+            Some(n) => {
+                self.offset += 4;
+                Ok(n)
+            }
+            None => Err(self.invalid("expected four hex digits")),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! The Minilang compiler as a library.  The phases can be used one by
+//! one, or driven by the `PassManager` of the `pass` module, to which
+//! library users can add their own passes.
+//!
+//! The front end does not panic, whatever its input: `scanner::tokenize`,
+//! `Parser::parse_program` (on any tokens) and `TypeChecker::tc_program`
+//! (on any AST that the parser returns) report bad input as an `Error`;
+//! tests/no_panic.rs checks this on random and corrupted input.
+//! The parser rejects programs nested deeply enough, or large enough,
+//! to exhaust the stack (see `Parser::set_max_depth`): with the default
+//! limits, `Compiler::compile` and `eval` run on the 2 MB stack of a
+//! spawned thread; tests/stack.rs checks this.
+
+pub mod error;
+pub mod ice;
+pub mod pos;
+pub mod source;
+pub mod token;
+pub mod scanner;
+pub mod types;
+pub mod ast;
+pub mod grammar;
+pub mod parser;
+pub mod cst;
+pub mod pretty;
+pub mod format;
+pub mod consteval;
+pub mod typecheck;
+pub mod typeat;
+pub mod query;
+pub mod metrics;
+pub mod annotate;
+pub mod json;
+pub mod binast;
+pub mod cfg;
+pub mod dataflow;
+pub mod eliminate;
+pub mod unroll;
+pub mod induction;
+pub mod emitter;
+pub mod cgen;
+pub mod sourcemap;
+pub mod abi;
+pub mod explore;
+pub mod manifest;
+pub mod cache;
+pub mod diagnostic;
+pub mod fix;
+pub mod suppress;
+pub mod backend;
+pub mod pass;
+pub mod compiler;
+pub mod incremental;
+pub mod cost;
+pub mod interp;
+pub mod session;
+pub mod spec;
+pub mod conformance;
+pub mod grade;
+pub mod bench;
+
+pub use interp::{eval, Env, Value};
+pub use compiler::Compiler;
@@ -1,35 +1,145 @@
 extern crate clap;
-use clap::{App, SubCommand};
-
-mod error;
-mod pos;
-mod token;
-mod scanner;
-mod types;
-mod ast;
-mod parser;
-mod typecheck;
-// mod cgen;
-
-use token::{Token, TokenType};
-use error::Error;
-use scanner::Scanner;
-use parser::Parser;
-use typecheck::TypeChecker;
-
-use std::io::{Read, stdin};
+extern crate minilang;
+use clap::{App, Arg, SubCommand};
+
+use minilang::{abi, annotate, backend, bench, binast, cache, cgen, conformance, cst, eliminate, explore, fix, format, grade, grammar, ice, interp, metrics, json, pass, scanner, sourcemap, spec, typeat, typecheck};
+use minilang::token::Token;
+use minilang::error::Error;
+use minilang::pos::Pos;
+use minilang::ast::{Expr_, Program};
+use minilang::cost::CostModel;
+use minilang::session::{Input, Session};
+use minilang::interp::{Env, Limits};
+use minilang::parser::{Parser, Syntax};
+use minilang::typecheck::TypeChecker;
+use minilang::query::Selector;
+use minilang::manifest::{self, Manifest, Target};
+use minilang::pass::Context;
+use minilang::backend::{Backend, CBackend};
+use minilang::cache::Cache;
+use minilang::source::{self, FileLoader, SourceManager};
+use minilang::diagnostic::{self, Diagnostic};
+use minilang::cgen::{Overflow, TempNames};
+use minilang::scanner::IdentifierRules;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, Read, Write, stdin, stdout, stderr};
+use std::path::Path;
 use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
 
+// Entries printed like a map, in their order.
+struct OrderedMap<K, V>(Vec<(K, V)>);
 
-struct CompileManager;
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OrderedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.0.iter().map(|&(ref k, ref v)| (k, v))).finish()
+    }
+}
+
+struct CompileManager {
+    syntax: Syntax,
+    identifiers: IdentifierRules,
+    strict: bool,
+}
 
 enum CompileAction {
     Scan,
+    ScanBench(u32),
     DisplayTokens,
+    DisplayTokensWithContext,
     Parse,
     DisplayAst,
-    Typecheck,
+    AstBinary,
+    LoadAst(String),
+    Typecheck(Option<String>),
     TypeTables,
+    Annotate,
+    TypeAt(String, String),
+    Query(String, Vec<String>),
+    Metrics(Vec<String>, bool),
+    CodegenC(COptions),
+    Build(BuildOptions),
+    CacheClean(String),
+    Run(String, RunOptions),
+    RunBatch(String, String, Limits),
+    RunSpec(String, Limits),
+    Grammar(String),
+    Cst(bool),
+    CstEvents,
+    Conformance(String),
+    Grade(String, bool),
+    Fix(String, bool),
+    Format(String, FormatOptions),
+    Explore(String, String, Option<String>),
+    Bench(BenchOptions),
+}
+
+// The options of the `run` subcommand, except --batch.
+struct RunOptions {
+    limits: Limits,
+    via_c: bool,
+    cost_report: bool,
+    record: Option<String>,
+    replay: Option<String>,
+    // Whether to print the final values of the variables as JSON.
+    dump_env: bool,
+}
+
+// The options of the `build` subcommand.
+struct BuildOptions {
+    manifest: String,
+    show_eliminated: bool,
+    deps: Option<String>,
+    cache: bool,
+}
+
+// The options of the `fmt` subcommand.
+struct FormatOptions {
+    max_width: usize,
+    // The first and last lines to lay out, if not all of them.
+    range: Option<(usize, usize)>,
+    write: bool,
+}
+
+// The options of the `bench` subcommand.
+struct BenchOptions {
+    size: usize,
+    iterations: u32,
+    save: Option<String>,
+    baseline: Option<String>,
+    // The slowdown, in percent, above which a phase has regressed.
+    tolerance: f64,
+}
+
+// The options of the `codegen` and `c` subcommands.
+struct COptions {
+    target: String,
+    stop_after: Option<String>,
+    time_passes: bool,
+    verify: bool,
+    sourcemap: Option<String>,
+    abi: Option<String>,
+    overflow: Overflow,
+    opt_level: u32,
+    max_unroll: usize,
+    compact: bool,
+    inline_strings: bool,
+    ident_prefix: String,
+    temp_names: TempNames,
+    line_comments: bool,
+    show_eliminated: bool,
+    max_errors: usize,
+    deny_warnings: bool,
+    allowed: Vec<&'static str>,
+    denied: Vec<&'static str>,
+    json_diagnostics: bool,
 }
 
 impl CompileManager {
@@ -41,35 +151,91 @@ impl CompileManager {
     fn perform_action(&self, action: CompileAction) {
         match action {
             CompileAction::Scan => { self.scan(false).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::ScanBench(iterations) => { self.scan_bench(iterations).unwrap_or_else(|e| self.error(e)) }
             CompileAction::DisplayTokens => { self.scan(true).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::DisplayTokensWithContext => {
+                self.tokens_with_context().unwrap_or_else(|e| self.error(e))
+            }
             CompileAction::Parse => { self.parse(false).unwrap_or_else(|e| self.error(e)) }
             CompileAction::DisplayAst => { self.parse(true).unwrap_or_else(|e| self.error(e)) }
-            CompileAction::Typecheck => { self.typecheck(false).unwrap_or_else(|e| self.error(e)) }
-            CompileAction::TypeTables => { self.typecheck(true).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::AstBinary => { self.ast_binary().unwrap_or_else(|e| self.error(e)) }
+            CompileAction::LoadAst(ref path) => { self.load_ast(path).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Typecheck(ref symbols) => {
+                self.typecheck(false, symbols.as_ref().map(|s| &s[..])).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::TypeTables => { self.typecheck(true, None).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Annotate => { self.annotate().unwrap_or_else(|e| self.error(e)) }
+            CompileAction::TypeAt(ref path, ref position) => {
+                self.type_at(path, position).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::Query(ref selector, ref paths) => {
+                self.query(selector, paths).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::Metrics(ref paths, as_json) => {
+                self.metrics(paths, as_json).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::CodegenC(ref options) => { self.codegen_c(options).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Build(ref options) => { self.build(options).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::CacheClean(ref path) => { self.cache_clean(path).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Run(ref path, ref options) if options.via_c => {
+                self.run_via_c(path).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::Run(ref path, ref options) => { self.run(path, options).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::RunBatch(ref path, ref dir, limits) => {
+                self.run_batch(path, dir, limits).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::RunSpec(ref path, limits) => { self.run_spec(path, limits).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Grammar(ref format) => { self.grammar(format).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Cst(check) => { self.cst(check).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::CstEvents => { self.cst_events().unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Conformance(ref dir) => { self.conformance(dir).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Grade(ref dir, as_json) => { self.grade(dir, as_json).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Fix(ref path, apply) => { self.fix(path, apply).unwrap_or_else(|e| self.error(e)) }
+            CompileAction::Format(ref path, ref options) => {
+                self.format(path, options).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::Explore(ref path, ref target, ref output) => {
+                self.explore(path, target, output.as_ref().map(|s| &s[..])).unwrap_or_else(|e| self.error(e))
+            }
+            CompileAction::Bench(ref options) => { self.bench(options).unwrap_or_else(|e| self.error(e)) }
         }
     }
 
-    fn get_tokens(&self) -> Result<Vec<Token>, Error> {
-        let mut stdin = stdin();
-        let mut buf = String::new();
-        let _ = stdin.read_to_string(&mut buf);
-        let mut scanner = Scanner::new(&buf);
+    fn read_stdin(&self) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        try!(stdin().read_to_end(&mut buf).map_err(|e| Error::Io("<stdin>".to_string(), e)));
+        source::decode(buf)
+    }
 
-        let mut tokens = Vec::new();
-        loop {
-            let tok = try!(scanner.next_token());
-            let is_eof = tok.typ == TokenType::Eof;
-            tokens.push(tok);
-            if is_eof {
-                break;
-            }
-        }
-        Ok(tokens)
+    fn read_file(&self, path: &str) -> Result<String, Error> {
+        source::Disk.load(path)
+    }
+
+    fn write_file(&self, path: &str, contents: &str) -> Result<(), Error> {
+        let mut file = try!(File::create(path).map_err(|e| Error::Io(path.to_string(), e)));
+        file.write_all(contents.as_bytes()).map_err(|e| Error::Io(path.to_string(), e))
+    }
+
+    fn get_tokens(&self, src: &str) -> Result<Vec<Token>, Error> {
+        scanner::tokenize_with(src, &self.identifiers)
+    }
+
+    fn parse_source(&self, src: &str) -> Result<Program, Error> {
+        let tokens = try!(self.get_tokens(src));
+        let mut parser = Parser::new(tokens);
+        parser.set_syntax(self.syntax);
+        parser.parse_program()
+    }
+
+    fn type_checker(&self) -> TypeChecker {
+        let mut tc = TypeChecker::new();
+        tc.set_strict(self.strict);
+        tc
     }
 
     // TODO(vfoley): don't build token vector if `display_tokens == false`.
     fn scan(&self, display_tokens: bool) -> Result<(), Error> {
-        let tokens = try!(self.get_tokens());
+        let tokens = try!(self.get_tokens(&try!(self.read_stdin())));
         if display_tokens {
             for tok in tokens.iter() {
                 println!("{:?}", tok);
@@ -78,72 +244,1316 @@ impl CompileManager {
         Ok(())
     }
 
+    // Print each token with its byte span and the source text it was
+    // scanned from.
+    fn tokens_with_context(&self) -> Result<(), Error> {
+        let src = try!(self.read_stdin());
+        let tokens = try!(self.get_tokens(&src));
+        for tok in cst::attach_trivia(&src, tokens) {
+            println!("{}..{} {:?} {:?}", tok.span.start, tok.span.end, tok.text, tok.token);
+        }
+        Ok(())
+    }
+
+    // Scan the program `iterations` times and report the throughput.
+    fn scan_bench(&self, iterations: u32) -> Result<(), Error> {
+        let src = try!(self.read_stdin());
+        let start = Instant::now();
+        let mut tokens = 0;
+        for _ in 0..iterations {
+            tokens += try!(scanner::tokenize_with(&src, &self.identifiers)).len();
+        }
+        let elapsed = start.elapsed();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        let bytes = src.len() as f64 * iterations as f64;
+        println!("{} iterations, {:.3} ms", iterations, secs * 1e3);
+        println!("{:.1} MB/s, {:.0} tokens/s", bytes / secs / 1e6, tokens as f64 / secs);
+        Ok(())
+    }
 
     fn parse(&self, display_ast: bool) -> Result<(), Error> {
-        let tokens = try!(self.get_tokens());
-        let mut parser = Parser::new(tokens);
-        let ast = try!(parser.parse_program());
+        let ast = try!(self.parse_source(&try!(self.read_stdin())));
         if display_ast {
             println!("{:#?}", ast);
         }
         Ok(())
     }
 
-    fn typecheck(&self, display_tables: bool) -> Result<(), Error> {
-        let tokens = try!(self.get_tokens());
-        let mut parser = Parser::new(tokens);
-        let ast = try!(parser.parse_program());
-        let mut tc = TypeChecker::new();
+    // Print the binary encoding of the typed AST (see binast.rs).
+    fn ast_binary(&self) -> Result<(), Error> {
+        let ast = try!(self.parse_source(&try!(self.read_stdin())));
+        let mut tc = self.type_checker();
+        try!(tc.tc_program(&ast));
+        let bytes = binast::encode(&ast, &tc.expr_table);
+        stdout().write_all(&bytes).map_err(|e| Error::Io("<stdout>".to_string(), e))
+    }
+
+    // Print the AST of a file written by `ast --format=bin`.
+    fn load_ast(&self, path: &str) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        let mut file = try!(File::open(path).map_err(|e| Error::Io(path.to_string(), e)));
+        try!(file.read_to_end(&mut bytes).map_err(|e| Error::Io(path.to_string(), e)));
+        let (ast, _) = try!(binast::decode(&bytes).map_err(|e| Error::InFile(path.to_string(), Box::new(e))));
+        println!("{:#?}", ast);
+        Ok(())
+    }
+
+    fn typecheck(&self, display_tables: bool, symbols_path: Option<&str>) -> Result<(), Error> {
+        let ast = try!(self.parse_source(&try!(self.read_stdin())));
+        let mut tc = self.type_checker();
         try!(tc.tc_program(&ast));
         if display_tables {
+            // Symbols in declaration order and expressions by node id,
+            // so that the output is the same from one run to the next.
+            let mut exprs: Vec<_> = tc.expr_table.iter().collect();
+            exprs.sort_by_key(|&(node_id, _)| *node_id);
             println!("SYMBOL TABLE");
-            println!("{:#?}", tc.symtable);
+            println!("{:#?}", OrderedMap(typecheck::symbols_in_order(&tc.symtable)));
             println!("EXPRESSION TABLE");
-            println!("{:#?}", tc.expr_table);
+            println!("{:#?}", OrderedMap(exprs));
+        }
+        if let Some(path) = symbols_path {
+            try!(self.write_file(path, &typecheck::symbols_json(&tc.symtable)));
+        }
+        Ok(())
+    }
+
+    fn annotate(&self) -> Result<(), Error> {
+        let src = try!(self.read_stdin());
+        let ast = try!(self.parse_source(&src));
+        let mut tc = self.type_checker();
+        try!(tc.tc_program(&ast));
+        print!("{}", annotate::annotate(&src, &ast, &tc.symtable, &tc.expr_table));
+        Ok(())
+    }
+
+    fn type_at(&self, path: &str, position: &str) -> Result<(), Error> {
+        let pos = try!(parse_position(position));
+        let src = try!(self.read_file(path));
+        let ast = try!(self.parse_source(&src));
+        let mut tc = self.type_checker();
+        try!(tc.tc_program(&ast));
+
+        let expr = try!(typeat::expr_at(&ast, pos).ok_or(Error::NoExpressionAt(pos)));
+        // The call of a function without a value has no type.
+        let ty = try!(tc.expr_table.get(&expr.node_id).ok_or_else(|| match expr.expr {
+            Expr_::Call(ref call) => Error::NoValue(expr.pos, call.id.clone()),
+            _ => Error::NoExpressionAt(expr.pos),
+        }));
+        println!("{}-{}: {}", expr.pos, expr.end, ty);
+        if let Expr_::Id(ref expr_) = expr.expr {
+            if let Some(sym) = tc.symtable.get(&expr_.id) {
+                println!("{} declared at {}", expr_.id, sym.pos);
+            }
+        }
+        Ok(())
+    }
+
+    fn query(&self, selector: &str, paths: &[String]) -> Result<(), Error> {
+        let selector = try!(Selector::parse(selector));
+        for path in paths {
+            let src = try!(self.read_file(path));
+            let ast = try!(self.parse_source(&src));
+            for node in selector.select(&ast) {
+                println!("{}:{}: {}", path, node.pos(), node.describe());
+            }
+        }
+        Ok(())
+    }
+
+    // The metrics of each file, then of each of its functions.
+    fn metrics(&self, paths: &[String], as_json: bool) -> Result<(), Error> {
+        let mut results = Vec::new();
+        for path in paths {
+            let src = try!(self.read_file(path));
+            let ast = try!(self.parse_source(&src));
+            let funcs: Vec<(String, metrics::Metrics)> = ast.funcs.iter()
+                .map(|func| (func.id.clone(), metrics::function_metrics(func)))
+                .collect();
+            results.push((path, metrics::program_metrics(&ast), funcs));
+        }
+
+        if as_json {
+            let fields = |m: &metrics::Metrics| {
+                format!("\"cyclomatic\": {}, \"max_depth\": {}, \"stmts\": {}, \"vars\": {}",
+                        m.cyclomatic, m.max_depth, m.stmts, m.vars)
+            };
+            println!("[");
+            for (i, &(path, ref m, ref funcs)) in results.iter().enumerate() {
+                let funcs: Vec<String> = funcs.iter()
+                    .map(|&(ref id, ref m)| format!("{{\"name\": {}, {}}}", json::quote(id), fields(m)))
+                    .collect();
+                println!("  {{\"file\": {}, {}, \"functions\": [{}]}}{}",
+                         json::quote(path), fields(m), funcs.join(", "),
+                         if i + 1 < results.len() { "," } else { "" });
+            }
+            println!("]");
+        } else {
+            let mut rows = Vec::new();
+            for &(path, ref m, ref funcs) in &results {
+                rows.push((path.to_string(), m));
+                rows.extend(funcs.iter().map(|&(ref id, ref m)| (format!("{}:{}", path, id), m)));
+            }
+            let width = rows.iter().map(|&(ref name, _)| name.len()).max().unwrap_or(0).max(4);
+            println!("{:<w$}  {:>10}  {:>9}  {:>5}  {:>4}", "file", "cyclomatic", "max_depth", "stmts", "vars", w = width);
+            for &(ref name, m) in &rows {
+                println!("{:<w$}  {:>10}  {:>9}  {:>5}  {:>4}", name, m.cyclomatic, m.max_depth, m.stmts, m.vars, w = width);
+            }
+        }
+        Ok(())
+    }
+
+    fn codegen_c(&self, options: &COptions) -> Result<(), Error> {
+        let mut pm = pass::pipeline(try!(backend::find(&options.target)));
+        if let Some(ref name) = options.stop_after {
+            try!(pm.stop_after(name));
+        }
+        pm.time_passes(options.time_passes);
+        if options.verify {
+            pm.verify(true);
+        }
+
+        let mut ctx = Context::new(try!(self.read_stdin()));
+        ctx.syntax = self.syntax;
+        ctx.identifiers = self.identifiers;
+        ctx.strict = self.strict;
+        ctx.overflow = options.overflow;
+        ctx.opt_level = options.opt_level;
+        ctx.max_unroll = options.max_unroll;
+        ctx.compact = options.compact;
+        ctx.inline_strings = options.inline_strings;
+        ctx.ident_prefix = options.ident_prefix.clone();
+        ctx.temp_names = options.temp_names;
+        ctx.line_comments = options.line_comments;
+        ctx.show_eliminated = options.show_eliminated;
+        ctx.max_errors = options.max_errors;
+        ctx.deny_warnings = options.deny_warnings;
+        ctx.allowed = options.allowed.clone();
+        ctx.denied = options.denied.clone();
+        let result = pm.run(&mut ctx);
+        if options.json_diagnostics {
+            let _ = write!(stderr(), "{}", diagnostic::to_json(&ctx.diagnostics));
+        } else {
+            for diagnostic in &ctx.diagnostics {
+                let _ = writeln!(stderr(), "{}", diagnostic);
+            }
+        }
+        try!(result);
+        if let Some(ref output) = ctx.output {
+            print!("{}", output);
+            if let Some(ref path) = options.sourcemap {
+                let json = sourcemap::to_json(&ctx.source_map, "<stdin>", "<stdout>");
+                try!(self.write_file(path, &json));
+            }
+            if let Some(ref path) = options.abi {
+                try!(self.write_file(path, &abi::to_json(ctx.program(), &ctx.symtable, &ctx.ident_prefix, output)));
+            }
+        }
+        Ok(())
+    }
+
+    // Compile the project described by a manifest.  The source files
+    // are checked in order, as if they were one program, and their
+    // paths are relative to the manifest's directory.  With `--emit-deps`,
+    // a Make rule for the output file is written too.  The output is
+    // taken from the cache when the typed program has not changed.
+    fn build(&self, options: &BuildOptions) -> Result<(), Error> {
+        let manifest_path = &options.manifest[..];
+        let mut sources = SourceManager::new(Box::new(source::Disk));
+        let manifest_file = try!(sources.load(manifest_path));
+        let in_manifest = |e| Error::InFile(manifest_path.to_string(), Box::new(e));
+        let manifest = try!(Manifest::parse(sources.text(manifest_file)).map_err(in_manifest));
+        if options.deps.is_some() && manifest.output.is_none() {
+            return Err(in_manifest(Error::InvalidManifest(
+                0, "--emit-deps needs an 'output' file, the target of the rule".to_string())));
+        }
+        let base_dir = Path::new(manifest_path).parent().unwrap_or(Path::new("."));
+
+        let mut program = Program { records: Vec::new(), decls: Vec::new(), funcs: Vec::new(), stmts: Vec::new() };
+        let mut tc = self.type_checker();
+        let mut next_id = 0;
+        let mut paths = vec![manifest_path.to_string()];
+        // The file that declares each variable.
+        let mut decl_files = HashMap::new();
+        for source in &manifest.sources {
+            let path = base_dir.join(source).to_string_lossy().into_owned();
+            paths.push(path.clone());
+            let file = try!(sources.load(&path));
+            let in_source = |e| sources.in_file(file, e);
+            let tokens = try!(self.get_tokens(sources.text(file)).map_err(&in_source));
+            let mut parser = Parser::with_first_id(tokens, next_id);
+            parser.set_syntax(self.syntax);
+            let ast = try!(parser.parse_program().map_err(&in_source));
+            try!(tc.tc_program(&ast).map_err(&in_source));
+            next_id = parser.next_node_id();
+            for decl in &ast.decls {
+                decl_files.insert(decl.id.clone(), file);
+            }
+            program.records.extend(ast.records);
+            program.decls.extend(ast.decls);
+            program.funcs.extend(ast.funcs);
+            program.stmts.extend(ast.stmts);
+        }
+
+        let removed = eliminate::dead_globals(&mut program, &mut tc.symtable);
+        if options.show_eliminated {
+            for (pos, id) in removed {
+                let note = Diagnostic::note(pos, format!("removed unused variable '{}'", id))
+                    .with_file(decl_files[&id]);
+                let _ = writeln!(stderr(), "{}", sources.render(&note));
+            }
+        }
+
+        let c_options = cgen::Options {
+            overflow: Overflow::Wrap,
+            max_unroll: if manifest.opt_level >= 2 { pass::DEFAULT_MAX_UNROLL } else { 0 },
+            compact: false,
+            inline_strings: false,
+            ident_prefix: String::new(),
+            temp_names: TempNames::Numbered,
+            line_comments: false,
+        };
+        let cache = Cache::for_project(base_dir);
+        let key = cache::key(&program, &tc.symtable, &tc.expr_table, &format!("{:?}", manifest.target),
+                             &format!("{:?}", c_options));
+        let cached = if options.cache { cache.get(&key) } else { None };
+        let output = match cached {
+            Some(output) => output,
+            None => {
+                let output = match manifest.target {
+                    Target::C => cgen::codegen_with_source_map(&program, &tc.symtable, &tc.expr_table, c_options).0,
+                };
+                if options.cache {
+                    try!(cache.put(&key, &output));
+                }
+                output
+            }
+        };
+
+        let out_path = manifest.output.as_ref().map(|path| base_dir.join(path).to_string_lossy().into_owned());
+        match out_path {
+            Some(ref out_path) => { try!(self.write_file(out_path, &output)); }
+            None => { print!("{}", output); }
+        }
+        if let (Some(ref deps_path), Some(out_path)) = (options.deps.as_ref(), out_path) {
+            try!(self.write_file(deps_path, &manifest::dependency_rule(&out_path, &paths)));
+        }
+        Ok(())
+    }
+
+    // Empty the cache of the project described by a manifest.
+    fn cache_clean(&self, manifest_path: &str) -> Result<(), Error> {
+        let base_dir = Path::new(manifest_path).parent().unwrap_or(Path::new("."));
+        let removed = try!(Cache::for_project(base_dir).clean());
+        println!("removed {} cached output{}", removed, if removed == 1 { "" } else { "s" });
+        Ok(())
+    }
+
+    // Compile a program to C with the C compiler named by $CC (cc by
+    // default) and run it with our stdin and stdout.  Arithmetic is
+    // checked, as in the interpreter.
+    fn run_via_c(&self, path: &str) -> Result<(), Error> {
+        let mut ctx = Context::new(try!(self.read_file(path)));
+        ctx.syntax = self.syntax;
+        ctx.identifiers = self.identifiers;
+        ctx.strict = self.strict;
+        ctx.overflow = Overflow::Checked;
+        let result = pass::standard_pipeline().run(&mut ctx);
+        for diagnostic in &ctx.diagnostics {
+            let _ = writeln!(stderr(), "{}", diagnostic);
+        }
+        try!(result);
+
+        let dir = env::temp_dir().join(format!("minilang-{}", process::id()));
+        let dir_name = dir.to_string_lossy().into_owned();
+        try!(fs::create_dir_all(&dir).map_err(|e| Error::Io(dir_name.clone(), e)));
+        let status = CBackend.executable(ctx.output.as_ref().unwrap(), &dir)
+            .and_then(|mut command| command.status().map_err(|e| Error::Io(dir_name, e)));
+        let _ = fs::remove_dir_all(&dir);
+        match try!(status).code() {
+            Some(0) => Ok(()),
+            Some(code) => process::exit(code),
+            None => process::exit(1),
+        }
+    }
+
+    // Interpret a program; `read` takes its input from stdin.
+    // With --cost-report, the cost of the run (see cost.rs) is printed
+    // to stderr at the end, as are the final values of the variables
+    // with --dump-env=json, and with --record, the input read is saved
+    // (see session.rs), even if the program fails.  With --replay, the
+    // input comes from a saved session instead of stdin.
+    fn run(&self, path: &str, options: &RunOptions) -> Result<(), Error> {
+        let src = try!(self.read_file(path));
+        let stdin = stdin();
+        let mut lines = stdin.lock().lines();
+        let mut words = VecDeque::new();
+        if let Some(ref replay) = options.replay {
+            let session = try!(Session::from_json(&try!(self.read_file(replay)))
+                               .map_err(|e| Error::InFile(replay.to_string(), Box::new(e))));
+            words.extend(session.inputs.into_iter().map(|input| input.word));
+        }
+        let replaying = options.replay.is_some();
+        let start = Instant::now();
+        let recorded = RefCell::new(Vec::new());
+
+        let mut env = Env::new();
+        env.set_limits(options.limits);
+        env.set_strict(self.strict);
+        if options.cost_report {
+            env.set_cost_model(CostModel::default());
+        }
+        let recorded_ref = &recorded;
+        env.on_read(move || {
+            while words.is_empty() && !replaying {
+                match lines.next() {
+                    Some(Ok(line)) => words.extend(line.split_whitespace().map(|s| s.to_string())),
+                    _ => return None,
+                }
+            }
+            let word = words.pop_front();
+            if let Some(ref word) = word {
+                let elapsed = start.elapsed();
+                let millis = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000;
+                recorded_ref.borrow_mut().push(Input { millis: millis, word: word.clone() });
+            }
+            word
+        });
+        env.on_print(|line| println!("{}", line));
+        env.on_prompt(|text| {
+            print!("{}", text);
+            let _ = stdout().flush();
+        });
+        let ast = try!(self.parse_source(&src));
+        let result = interp::eval_program(&ast, &mut env);
+        if let Some(report) = env.cost_report() {
+            let _ = write!(stderr(), "{}", report);
+        }
+        if options.dump_env {
+            let _ = write!(stderr(), "{}", env.to_json());
+        }
+        if let Some(ref record) = options.record {
+            let session = Session { program: path.to_string(), inputs: recorded.borrow().clone() };
+            try!(self.write_file(record, &session.to_json()));
+        }
+        try!(result);
+        if let Some(status) = env.exit_status() {
+            process::exit(status as i32);
+        }
+        Ok(())
+    }
+
+    // Run a program on every test case of `dir` and print which ones
+    // passed.  Each case runs within `limits`, by default 10 seconds.
+    fn run_batch(&self, path: &str, dir: &str, mut limits: Limits) -> Result<(), Error> {
+        if limits.timeout.is_none() && limits.max_steps.is_none() {
+            limits.timeout = Some(Duration::from_secs(10));
+        }
+        let ast = try!(self.parse_source(&try!(self.read_file(path))));
+        let fixtures = try!(grade::fixtures(Path::new(dir)));
+        self.report_outcomes(&grade::batch(&ast, &fixtures, limits, self.strict));
+        Ok(())
+    }
+
+    // Run a program on the cases of its spec (see spec.rs), like
+    // `run_batch`.
+    fn run_spec(&self, path: &str, mut limits: Limits) -> Result<(), Error> {
+        if limits.timeout.is_none() && limits.max_steps.is_none() {
+            limits.timeout = Some(Duration::from_secs(10));
+        }
+        let src = try!(self.read_file(path));
+        let ast = try!(self.parse_source(&src));
+        let fixtures = try!(spec::fixtures(Path::new(path), &src).map_err(|e| match e {
+            Error::InFile(..) => e,
+            e => Error::InFile(path.to_string(), Box::new(e)),
+        }));
+        self.report_outcomes(&grade::batch(&ast, &fixtures, limits, self.strict));
+        Ok(())
+    }
+
+    // Print which test cases passed; exit with status 1 if any failed.
+    fn report_outcomes(&self, outcomes: &[grade::Outcome]) {
+        let width = outcomes.iter().map(|o| o.name.len()).max().unwrap_or(0);
+        let mut failed = 0;
+        for outcome in outcomes {
+            match outcome.failure {
+                None => println!("{:<w$}  pass  {:>6} ms", outcome.name, outcome.millis, w = width),
+                Some(ref reason) => {
+                    failed += 1;
+                    println!("{:<w$}  FAIL  {:>6} ms  {}", outcome.name, outcome.millis, reason, w = width);
+                }
+            }
+        }
+        println!("{} passed, {} failed", outcomes.len() - failed, failed);
+        if failed > 0 {
+            process::exit(1);
+        }
+    }
+
+    fn grammar(&self, format: &str) -> Result<(), Error> {
+        match format {
+            "ebnf" => print!("{}", grammar::to_ebnf()),
+            "antlr" => print!("{}", grammar::to_antlr()),
+            _ => return Err(Error::InvalidArgument("--format", format.to_string())),
+        }
+        Ok(())
+    }
+
+    // Print the scoreboard of every target; fail if any program fails.
+    fn conformance(&self, dir: &str) -> Result<(), Error> {
+        let scoreboards = try!(conformance::run(Path::new(dir), self.syntax));
+        let mut failed = false;
+        for scoreboard in &scoreboards {
+            println!("{:<8} {} passed, {} failed, {} skipped", scoreboard.target, scoreboard.passed,
+                     scoreboard.failed.len(), scoreboard.skipped.len());
+            for &(ref name, ref reason) in &scoreboard.failed {
+                println!("    {}: {}", name, reason);
+            }
+            failed |= !scoreboard.failed.is_empty();
+        }
+        if failed {
+            process::exit(1);
+        }
+        Ok(())
+    }
+
+    // Print the grading report of the submissions of `dir`; fail if any
+    // submission did not get its expected result.
+    fn grade(&self, dir: &str, as_json: bool) -> Result<(), Error> {
+        let grades = try!(grade::run(Path::new(dir), self.syntax, self.identifiers, self.strict));
+        if as_json {
+            print!("{}", grade::to_json(&grades));
+        } else {
+            print!("{}", grade::to_csv(&grades));
+        }
+        if grades.iter().any(|g| !g.ok()) {
+            process::exit(1);
+        }
+        Ok(())
+    }
+
+    // Apply the machine-applicable suggestions of the diagnostics of a
+    // program, listing them on stderr, and print the result or, with
+    // `apply`, write it back to the file.
+    fn fix(&self, path: &str, apply: bool) -> Result<(), Error> {
+        let src = try!(self.read_file(path));
+        let (fixed, applied) = fix::fix(&src, self.syntax, self.identifiers, self.strict);
+        for suggestion in &applied {
+            let _ = writeln!(stderr(), "{}: {}", suggestion.start, suggestion.message);
+        }
+        if apply {
+            if !applied.is_empty() {
+                try!(self.write_file(path, &fixed));
+            }
+        } else {
+            print!("{}", fixed);
+        }
+        Ok(())
+    }
+
+    // Print the program laid out by the formatter or, with `write`,
+    // write it back to the file if it changed.
+    fn format(&self, path: &str, options: &FormatOptions) -> Result<(), Error> {
+        let src = try!(self.read_file(path));
+        let formatted = match options.range {
+            Some((first, last)) => try!(format::format_range(&src, self.syntax, options.max_width, first, last)),
+            None => try!(format::format(&src, self.syntax, options.max_width)),
+        };
+        if !options.write {
+            print!("{}", formatted);
+        } else if formatted != src {
+            try!(self.write_file(path, &formatted));
+        }
+        Ok(())
+    }
+
+    // Compile a program for `target` and write the explorer page of
+    // all its stages, to `output` or stdout.
+    fn explore(&self, path: &str, target: &str, output: Option<&str>) -> Result<(), Error> {
+        let mut ctx = Context::new(try!(self.read_file(path)));
+        ctx.syntax = self.syntax;
+        ctx.identifiers = self.identifiers;
+        ctx.strict = self.strict;
+        let result = pass::pipeline(try!(backend::find(target))).run(&mut ctx);
+        for diagnostic in &ctx.diagnostics {
+            let _ = writeln!(stderr(), "{}", diagnostic);
+        }
+        try!(result);
+        let code = ctx.output.as_ref().map_or("", |s| &s[..]);
+        let page = explore::page(path, &ctx.source, ctx.program(), &ctx.symtable, &ctx.expr_table,
+                                 target, code, &ctx.source_map);
+        match output {
+            Some(output) => self.write_file(output, &page),
+            None => {
+                print!("{}", page);
+                Ok(())
+            }
+        }
+    }
+
+    // Print the throughput of the front end on a generated program;
+    // fail if a phase is slower than in the baseline.
+    fn bench(&self, options: &BenchOptions) -> Result<(), Error> {
+        let baseline = match options.baseline {
+            Some(ref path) => Some(try!(bench::parse_baseline(&try!(self.read_file(path)))
+                                        .map_err(|e| Error::InFile(path.clone(), Box::new(e))))),
+            None => None,
+        };
+        let source = bench::generate(options.size);
+        let measurements = try!(bench::run(&source, options.iterations));
+        println!("{} bytes, {} iterations", source.len(), options.iterations);
+        for m in &measurements {
+            println!("{:<10} {:>8.1} MB/s  {:>10.3} ms", m.phase, m.mb_per_sec(), m.secs * 1e3);
+        }
+        if let Some(ref path) = options.save {
+            try!(self.write_file(path, &bench::to_json(&measurements)));
+        }
+        if let Some(ref baseline) = baseline {
+            let regressions = bench::regressions(&measurements, baseline, options.tolerance);
+            for regression in &regressions {
+                println!("    {}", regression);
+            }
+            if !regressions.is_empty() {
+                process::exit(1);
+            }
+        }
+        Ok(())
+    }
+
+    // Print the parsing events, one per line.
+    fn cst_events(&self) -> Result<(), Error> {
+        struct Printer;
+        impl cst::Handler for Printer {
+            fn enter(&mut self, rule: &'static str, start: usize) {
+                println!("enter {}@{}", rule, start);
+            }
+            fn exit(&mut self, rule: &'static str, span: cst::Span) {
+                println!("exit {}@{}..{}", rule, span.start, span.end);
+            }
+            fn token(&mut self, tok: &cst::CstToken) {
+                println!("token {} {:?}@{}..{}", tok.token.typ, tok.text, tok.span.start, tok.span.end);
+            }
+        }
+        cst::parse_events(&try!(self.read_stdin()), self.syntax, &mut Printer)
+    }
+
+    // With `check`, verify that the CST round-trips instead of printing it.
+    fn cst(&self, check: bool) -> Result<(), Error> {
+        let src = try!(self.read_stdin());
+        let tree = try!(cst::parse(&src, self.syntax));
+        if !check {
+            print!("{}", tree);
+            return Ok(());
+        }
+        let text = tree.to_source();
+        if text != src {
+            let offset = text.bytes().zip(src.bytes()).take_while(|&(a, b)| a == b).count();
+            return Err(Error::PassVerification(
+                "cst", format!("source differs from byte {}", offset)));
+        }
+        let ast = format!("{:?}", try!(tree.to_ast()));
+        if ast != format!("{:?}", try!(self.parse_source(&src))) {
+            return Err(Error::PassVerification("cst", "AST differs".to_string()));
         }
         Ok(())
     }
 }
 
+// Parse the value of a numeric command-line option.
+fn parse_arg<T: std::str::FromStr>(name: &'static str, value: Option<&str>) -> Result<Option<T>, Error> {
+    match value {
+        Some(s) => s.parse().map(Some).map_err(|_| Error::InvalidArgument(name, s.to_string())),
+        None => Ok(None),
+    }
+}
 
+fn bench_options(m: &clap::ArgMatches) -> Result<BenchOptions, Error> {
+    let iterations = try!(parse_arg("--iterations", m.value_of("iterations"))).unwrap_or(10);
+    if iterations == 0 {
+        return Err(Error::InvalidArgument("--iterations", "0".to_string()));
+    }
+    let tolerance: f64 = try!(parse_arg("--tolerance", m.value_of("tolerance"))).unwrap_or(10.0);
+    if !(tolerance >= 0.0) {
+        return Err(Error::InvalidArgument("--tolerance", tolerance.to_string()));
+    }
+    Ok(BenchOptions {
+        size: try!(parse_arg("--size", m.value_of("size"))).unwrap_or(2000),
+        iterations: iterations,
+        save: m.value_of("save").map(|s| s.to_string()),
+        baseline: m.value_of("baseline").map(|s| s.to_string()),
+        tolerance: tolerance,
+    })
+}
+
+fn format_options(m: &clap::ArgMatches) -> Result<FormatOptions, Error> {
+    let max_width = try!(parse_arg("--max-width", m.value_of("max-width"))).unwrap_or(format::DEFAULT_MAX_WIDTH);
+    if max_width == 0 {
+        return Err(Error::InvalidArgument("--max-width", "0".to_string()));
+    }
+    let range = match m.value_of("range") {
+        Some(s) => Some(try!(parse_line_range(s))),
+        None => None,
+    };
+    Ok(FormatOptions { max_width: max_width, range: range, write: m.is_present("write") })
+}
+
+fn run_limits(m: &clap::ArgMatches) -> Result<Limits, Error> {
+    let timeout: Option<f64> = try!(parse_arg("--timeout", m.value_of("timeout")));
+    let mut limits = Limits::default();
+    limits.max_steps = try!(parse_arg("--max-steps", m.value_of("max-steps")));
+    limits.max_output = try!(parse_arg("--max-output", m.value_of("max-output")));
+    limits.timeout = timeout.map(|secs| Duration::from_millis((secs * 1000.0) as u64));
+    if let Some(depth) = try!(parse_arg("--max-call-depth", m.value_of("max-call-depth"))) {
+        limits = limits.max_call_depth(depth);
+    }
+    Ok(limits)
+}
+
+// The options of the `codegen` and `c` subcommands.
+fn codegen_args<'a>(cmd: App<'a, 'a>) -> App<'a, 'a> {
+    cmd
+        .arg(Arg::with_name("stop-after")
+             .long("stop-after")
+             .takes_value(true)
+             .value_name("PASS")
+             .help("Stop after the named pass (scan, parse, typecheck, eliminate, codegen)"))
+        .arg(Arg::with_name("time-passes")
+             .long("time-passes")
+             .help("Print the time taken by each pass"))
+        .arg(Arg::with_name("verify")
+             .long("verify")
+             .help("Verify the results of each pass (default in debug builds)"))
+        .arg(Arg::with_name("emit-sourcemap")
+             .long("emit-sourcemap")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Write a JSON map from generated lines to source positions"))
+        .arg(Arg::with_name("emit-abi")
+             .long("emit-abi")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Write a JSON description of the variables, structs and runtime helpers of the generated C"))
+        .arg(Arg::with_name("overflow")
+             .long("overflow")
+             .takes_value(true)
+             .value_name("MODE")
+             .help("Integer overflow behavior: wrap (default) or checked"))
+        .arg(Arg::with_name("opt-level")
+             .short("O")
+             .long("opt-level")
+             .takes_value(true)
+             .value_name("LEVEL")
+             .help("Optimization level: 0 (default), 1 or 2"))
+        .arg(Arg::with_name("max-unroll-factor")
+             .long("max-unroll-factor")
+             .takes_value(true)
+             .value_name("N")
+             .help("Unroll counting loops of at most N iterations at -O2 (default: 8)"))
+        .arg(Arg::with_name("compact")
+             .long("compact")
+             .help("Do not indent the generated code or separate it with blank lines"))
+        .arg(Arg::with_name("inline-strings")
+             .long("inline-strings")
+             .help("Emit each string literal where it is used instead of once per distinct text"))
+        .arg(Arg::with_name("ident-prefix")
+             .long("ident-prefix")
+             .takes_value(true)
+             .value_name("PREFIX")
+             .help("Prepend PREFIX (e.g. v_) to the C names of variables"))
+        .arg(Arg::with_name("temp-names")
+             .long("temp-names")
+             .takes_value(true)
+             .value_name("SCHEME")
+             .help("Names of temporaries: numbered (tmp_1, default), short (t1) or line (tmp_LINE_1)"))
+        .arg(Arg::with_name("line-comments")
+             .long("line-comments")
+             .help("Emit a comment with the source line above the code of each statement"))
+        .arg(Arg::with_name("show-eliminated")
+             .long("show-eliminated")
+             .help("Report the unused variables removed from the output"))
+        .arg(Arg::with_name("max-errors")
+             .long("max-errors")
+             .takes_value(true)
+             .value_name("N")
+             .help("Stop reporting errors after the first N (default: 20)"))
+        .arg(Arg::with_name("deny-warnings")
+             .long("deny-warnings")
+             .help("Report warnings as errors"))
+        .arg(Arg::with_name("allow")
+             .long("allow")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("LINT")
+             .help("Do not report the warning with this name or ID (e.g. W0001)"))
+        .arg(Arg::with_name("deny")
+             .long("deny")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("LINT")
+             .help("Report the warning with this name or ID as an error"))
+        .arg(Arg::with_name("error-format")
+             .long("error-format")
+             .takes_value(true)
+             .value_name("FORMAT")
+             .help("Format of the errors and warnings: text (default) or json"))
+}
+
+fn c_options(m: &clap::ArgMatches) -> Result<COptions, Error> {
+    let overflow = match m.value_of("overflow") {
+        Some(name) => try!(Overflow::from_name(name)
+                           .ok_or(Error::InvalidArgument("--overflow", name.to_string()))),
+        None => Overflow::Wrap,
+    };
+    let ident_prefix = m.value_of("ident-prefix").unwrap_or("").to_string();
+    let valid_prefix = ident_prefix.chars().enumerate()
+        .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if !valid_prefix {
+        return Err(Error::InvalidArgument("--ident-prefix", ident_prefix));
+    }
+    let temp_names = match m.value_of("temp-names") {
+        Some(name) => try!(TempNames::from_name(name)
+                           .ok_or(Error::InvalidArgument("--temp-names", name.to_string()))),
+        None => TempNames::Numbered,
+    };
+    let json_diagnostics = match m.value_of("error-format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => return Err(Error::InvalidArgument("--error-format", format.to_string())),
+    };
+    let opt_level = try!(parse_arg("--opt-level", m.value_of("opt-level"))).unwrap_or(0);
+    if opt_level > 2 {
+        return Err(Error::InvalidArgument("--opt-level", opt_level.to_string()));
+    }
+    let allowed = try!(lint_args("--allow", m.values_of_lossy("allow")));
+    let denied = try!(lint_args("--deny", m.values_of_lossy("deny")));
+    if let Some(lint) = allowed.iter().find(|lint| denied.contains(lint)) {
+        return Err(Error::InvalidArgument("--deny", format!("{} (also allowed)", lint)));
+    }
+    Ok(COptions {
+        target: m.value_of("target").unwrap_or("c").to_string(),
+        stop_after: m.value_of("stop-after").map(|s| s.to_string()),
+        time_passes: m.is_present("time-passes"),
+        verify: m.is_present("verify"),
+        sourcemap: m.value_of("emit-sourcemap").map(|s| s.to_string()),
+        abi: m.value_of("emit-abi").map(|s| s.to_string()),
+        overflow: overflow,
+        opt_level: opt_level,
+        max_unroll: try!(parse_arg("--max-unroll-factor", m.value_of("max-unroll-factor")))
+            .unwrap_or(pass::DEFAULT_MAX_UNROLL),
+        compact: m.is_present("compact"),
+        inline_strings: m.is_present("inline-strings"),
+        ident_prefix: ident_prefix,
+        temp_names: temp_names,
+        line_comments: m.is_present("line-comments"),
+        show_eliminated: m.is_present("show-eliminated"),
+        max_errors: try!(parse_arg("--max-errors", m.value_of("max-errors")))
+            .unwrap_or(pass::DEFAULT_MAX_ERRORS),
+        deny_warnings: m.is_present("deny-warnings"),
+        allowed: allowed,
+        denied: denied,
+        json_diagnostics: json_diagnostics,
+    })
+}
+
+// The warnings named by the values of `--allow` or `--deny`, by name or
+// ID.
+fn lint_args(flag: &'static str, values: Option<Vec<String>>) -> Result<Vec<&'static str>, Error> {
+    let mut lints = Vec::new();
+    for value in values.unwrap_or_default() {
+        match diagnostic::find_lint(&value) {
+            Some(lint) => lints.push(lint),
+            None => return Err(Error::InvalidArgument(flag, value)),
+        }
+    }
+    Ok(lints)
+}
+
+// Parse a position of the form LINE:COL.
+fn parse_position(s: &str) -> Result<Pos, Error> {
+    let mut parts = s.splitn(2, ':');
+    let line = parts.next().and_then(|x| x.parse::<usize>().ok());
+    let col = parts.next().and_then(|x| x.parse::<usize>().ok());
+    match (line, col) {
+        (Some(line), Some(col)) => Ok(Pos { line: line, col: col }),
+        _ => Err(Error::InvalidPosition(s.to_string())),
+    }
+}
+
+// Parse a range of lines of the form FIRST:LAST.
+fn parse_line_range(s: &str) -> Result<(usize, usize), Error> {
+    let mut parts = s.splitn(2, ':');
+    let first = parts.next().and_then(|x| x.parse::<usize>().ok());
+    let last = parts.next().and_then(|x| x.parse::<usize>().ok());
+    match (first, last) {
+        (Some(first), Some(last)) if 1 <= first && first <= last => Ok((first, last)),
+        _ => Err(Error::InvalidArgument("--range", s.to_string())),
+    }
+}
+
+// The passes recurse on the stack over nested statements and
+// expressions, and a debug build takes kilobytes for each level; the
+// compiler runs on a thread with a stack that leaves room to spare.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
+    ice::install();
+    let compiler = thread::Builder::new().stack_size(STACK_SIZE).spawn(compiler_main)
+        .expect("cannot start the compiler thread");
+    if compiler.join().is_err() {
+        // The panic was reported as an internal compiler error.
+        process::exit(101);
+    }
+}
+
+fn compiler_main() {
     let compiler_match = App::new("Minilang compiler")
         .version("0.1")
         .author("Vincent Foley <vfoley@gmail.com>")
+        .arg(Arg::with_name("syntax")
+             .long("syntax")
+             .takes_value(true)
+             .value_name("SYNTAX")
+             .help("Surface syntax of the programs: classic (default) or braces"))
+        .arg(Arg::with_name("identifiers")
+             .long("identifiers")
+             .takes_value(true)
+             .value_name("RULES")
+             .help("Characters allowed in identifiers: ascii (default), unicode, dollar or unicode,dollar"))
+        .arg(Arg::with_name("strict")
+             .long("strict")
+             .help("Require bool rather than int conditions in if and while"))
         .subcommand(SubCommand::with_name("scan")
-                    .about("Scan a program; return 0 if valid, 1 otherwise"))
+                    .about("Scan a program; return 0 if valid, 1 otherwise")
+                    .arg(Arg::with_name("bench")
+                         .long("bench")
+                         .takes_value(true)
+                         .value_name("ITERATIONS")
+                         .help("Scan the program ITERATIONS times and print the throughput")))
 
         .subcommand(SubCommand::with_name("tokens")
-                    .about("Scan a program and print its tokens one per line"))
+                    .about("Scan a program and print its tokens one per line")
+                    .arg(Arg::with_name("context")
+                         .long("context")
+                         .help("Print the byte span and source text of each token")))
 
         .subcommand(SubCommand::with_name("parse")
                     .about("Parse a program; return 0 if valid, 1 otherwise"))
 
         .subcommand(SubCommand::with_name("ast")
-                    .about("Parse a program and print its AST"))
+                    .about("Parse a program and print its AST")
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .value_name("FORMAT")
+                         .help("Format of the AST: text (default) or bin, a compact encoding of the typed AST"))
+                    .arg(Arg::with_name("load")
+                         .long("load")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .conflicts_with("format")
+                         .help("Print the AST of a file written by --format=bin instead of parsing stdin")))
 
         .subcommand(SubCommand::with_name("typecheck")
-                    .about("Typecheck a program; return 0 if valid, 1 otherwise"))
+                    .about("Typecheck a program; return 0 if valid, 1 otherwise")
+                    .arg(Arg::with_name("emit-symbols")
+                         .long("emit-symbols")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .help("Write the symbol table as JSON")))
 
         .subcommand(SubCommand::with_name("typetables")
                     .about("Typecheck a program and print its typed AST"))
 
-        .subcommand(SubCommand::with_name("mips")
-                    .about("Generate MIPS code for a program"))
+        .subcommand(SubCommand::with_name("annotate")
+                    .about("Typecheck a program and print it with its types as comments"))
+
+        .subcommand(SubCommand::with_name("type-at")
+                    .about("Print the type of the innermost expression at LINE:COL")
+                    .arg(Arg::with_name("FILE").required(true).index(1))
+                    .arg(Arg::with_name("POSITION").required(true).index(2)))
+
+        .subcommand(SubCommand::with_name("query")
+                    .about("Print the AST nodes matching a selector, e.g. 'stmt:while > expr:binop[op=div]'")
+                    .arg(Arg::with_name("SELECTOR").required(true).index(1))
+                    .arg(Arg::with_name("FILE").required(true).multiple(true).index(2)))
+
+        .subcommand(SubCommand::with_name("metrics")
+                    .about("Print complexity metrics (cyclomatic complexity, nesting, counts) of programs")
+                    .arg(Arg::with_name("json").long("json").help("Print the metrics as JSON"))
+                    .arg(Arg::with_name("FILE").required(true).multiple(true).index(1)))
+
+        .subcommand(codegen_args(SubCommand::with_name("codegen")
+                                 .about("Generate code for a program")
+                                 .arg(Arg::with_name("target")
+                                      .long("target")
+                                      .takes_value(true)
+                                      .value_name("BACKEND")
+                                      .help("The language to generate (default: c)"))))
+
+        .subcommand(codegen_args(SubCommand::with_name("c")
+                                 .about("Generate C code for a program (same as codegen --target=c)")))
+
+        .subcommand(SubCommand::with_name("build")
+                    .about("Build the project described by a manifest")
+                    .arg(Arg::with_name("manifest")
+                         .long("manifest")
+                         .takes_value(true)
+                         .value_name("PATH")
+                         .help("Path of the manifest (default: minilang.toml)"))
+                    .arg(Arg::with_name("show-eliminated")
+                         .long("show-eliminated")
+                         .help("Report the unused variables removed from the output"))
+                    .arg(Arg::with_name("emit-deps")
+                         .long("emit-deps")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .help("Write a Make rule listing the manifest and sources the output depends on"))
+                    .arg(Arg::with_name("no-cache")
+                         .long("no-cache")
+                         .help("Generate the output even if it is cached, and do not cache it")))
+
+        .subcommand(SubCommand::with_name("cache")
+                    .about("Maintain the cache of generated code of build")
+                    .subcommand(SubCommand::with_name("clean")
+                                .about("Remove the cached outputs of a project")
+                                .arg(Arg::with_name("manifest")
+                                     .long("manifest")
+                                     .takes_value(true)
+                                     .value_name("PATH")
+                                     .help("Path of the manifest (default: minilang.toml)"))))
+
+        .subcommand(SubCommand::with_name("run")
+                    .about("Interpret a program, reading its input from stdin")
+                    .arg(Arg::with_name("max-steps")
+                         .long("max-steps")
+                         .takes_value(true)
+                         .value_name("N")
+                         .help("Stop after executing N statements and loop iterations"))
+                    .arg(Arg::with_name("max-output")
+                         .long("max-output")
+                         .takes_value(true)
+                         .value_name("BYTES")
+                         .help("Stop when the program prints more than BYTES bytes"))
+                    .arg(Arg::with_name("timeout")
+                         .long("timeout")
+                         .takes_value(true)
+                         .value_name("SECONDS")
+                         .help("Stop when the program runs for more than SECONDS seconds"))
+                    .arg(Arg::with_name("max-call-depth")
+                         .long("max-call-depth")
+                         .takes_value(true)
+                         .value_name("N")
+                         .help("Stop at a call nested more than N deep (default: 1000)"))
+                    .arg(Arg::with_name("via-c")
+                         .long("via-c")
+                         .help("Compile the program with the C compiler of $CC (default: cc) and run it"))
+                    .arg(Arg::with_name("batch")
+                         .long("batch")
+                         .takes_value(true)
+                         .value_name("DIR")
+                         .help("Run the program on each NAME.in of DIR and compare with NAME.out"))
+                    .arg(Arg::with_name("check-spec")
+                         .long("check-spec")
+                         .conflicts_with_all(&["via-c", "batch"])
+                         .help("Run the program on the cases of its `# expect-output:` comments and NAME.spec"))
+                    .arg(Arg::with_name("cost-report")
+                         .long("cost-report")
+                         .conflicts_with_all(&["via-c", "batch", "check-spec"])
+                         .help("Print the weighted cost of the run, in total and per loop, to stderr"))
+                    .arg(Arg::with_name("record")
+                         .long("record")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .conflicts_with_all(&["via-c", "batch", "check-spec"])
+                         .help("Save the input read by the program, with its timing, as JSON"))
+                    .arg(Arg::with_name("replay")
+                         .long("replay")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .conflicts_with_all(&["via-c", "batch", "record", "check-spec"])
+                         .help("Read the input saved by --record instead of stdin"))
+                    .arg(Arg::with_name("dump-env")
+                         .long("dump-env")
+                         .takes_value(true)
+                         .value_name("FORMAT")
+                         .conflicts_with_all(&["via-c", "batch", "check-spec"])
+                         .help("Print the final values of the variables to stderr; FORMAT: json"))
+                    .arg(Arg::with_name("FILE").required(true).index(1)))
+
+        .subcommand(SubCommand::with_name("grammar")
+                    .about("Print the grammar of the language")
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .value_name("FORMAT")
+                         .help("Output format: ebnf (default) or antlr")))
+
+        .subcommand(SubCommand::with_name("cst")
+                    .about("Display the lossless concrete syntax tree of a program")
+                    .arg(Arg::with_name("check")
+                         .long("check")
+                         .help("Check that the tree reproduces the program and its AST instead"))
+                    .arg(Arg::with_name("events")
+                         .long("events")
+                         .conflicts_with("check")
+                         .help("Print the parsing events (rule entries and exits, tokens) instead")))
+
+        .subcommand(SubCommand::with_name("conformance")
+                    .about("Run the conformance programs with the interpreter and every backend")
+                    .arg(Arg::with_name("DIR")
+                         .index(1)
+                         .help("Directory of the programs (default: conformance)")))
+        .subcommand(SubCommand::with_name("grade")
+                    .about("Run student programs through the phases given by DIR/grade.spec and report the results")
+                    .arg(Arg::with_name("DIR")
+                         .required(true)
+                         .index(1)
+                         .help("Directory of the submissions"))
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .value_name("FORMAT")
+                         .help("Format of the report: csv (default) or json")))
+        .subcommand(SubCommand::with_name("fix")
+                    .about("Apply the fixes suggested by the diagnostics of a program and print the result")
+                    .arg(Arg::with_name("apply")
+                         .long("apply")
+                         .help("Write the result back to FILE"))
+                    .arg(Arg::with_name("FILE").required(true).index(1)))
+        .subcommand(SubCommand::with_name("fmt")
+                    .about("Print a program laid out in the standard style")
+                    .arg(Arg::with_name("max-width")
+                         .long("max-width")
+                         .takes_value(true)
+                         .value_name("N")
+                         .help("Break expressions that do not fit in N columns (default: 80)"))
+                    .arg(Arg::with_name("range")
+                         .long("range")
+                         .takes_value(true)
+                         .value_name("FIRST:LAST")
+                         .help("Only lay out the declarations and statements within these lines"))
+                    .arg(Arg::with_name("write")
+                         .long("write")
+                         .help("Write the result back to FILE"))
+                    .arg(Arg::with_name("FILE").required(true).index(1)))
+        .subcommand(SubCommand::with_name("explore")
+                    .about("Write an HTML page showing a program's source, typed AST, control flow graph and generated code side by side")
+                    .arg(Arg::with_name("target")
+                         .long("target")
+                         .takes_value(true)
+                         .value_name("BACKEND")
+                         .help("The language to generate (default: c)"))
+                    .arg(Arg::with_name("output")
+                         .long("output")
+                         .takes_value(true)
+                         .value_name("PAGE")
+                         .help("Write the page to PAGE instead of stdout"))
+                    .arg(Arg::with_name("FILE").required(true).index(1)))
+        .subcommand(SubCommand::with_name("bench")
+                    .about("Measure the throughput of the scanner, parser and type checker on a generated program")
+                    .arg(Arg::with_name("size")
+                         .long("size")
+                         .takes_value(true)
+                         .value_name("N")
+                         .help("Generate N blocks of about ten statements (default: 2000)"))
+                    .arg(Arg::with_name("iterations")
+                         .long("iterations")
+                         .takes_value(true)
+                         .value_name("N")
+                         .help("Run each phase N times (default: 10)"))
+                    .arg(Arg::with_name("save")
+                         .long("save")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .help("Save the throughput of each phase as a baseline"))
+                    .arg(Arg::with_name("baseline")
+                         .long("baseline")
+                         .takes_value(true)
+                         .value_name("FILE")
+                         .help("Fail if a phase is slower than in the baseline saved by --save"))
+                    .arg(Arg::with_name("tolerance")
+                         .long("tolerance")
+                         .takes_value(true)
+                         .value_name("PERCENT")
+                         .help("The slowdown allowed by --baseline (default: 10)")))
+
 
 
 
         .get_matches();
 
-    let cm = CompileManager;
+    let syntax = match compiler_match.value_of("syntax") {
+        Some(name) => Syntax::from_name(name).unwrap_or_else(|| {
+            println!("{}", Error::InvalidArgument("--syntax", name.to_string()));
+            process::exit(1);
+        }),
+        None => Syntax::Classic,
+    };
+    let identifiers = match compiler_match.value_of("identifiers") {
+        Some(names) => IdentifierRules::from_names(names).unwrap_or_else(|| {
+            println!("{}", Error::InvalidArgument("--identifiers", names.to_string()));
+            process::exit(1);
+        }),
+        None => IdentifierRules::default(),
+    };
+    let cm = CompileManager {
+        syntax: syntax,
+        identifiers: identifiers,
+        strict: compiler_match.is_present("strict"),
+    };
     match compiler_match.subcommand_name() {
-        Some("scan") => { cm.perform_action(CompileAction::Scan) }
-        Some("tokens") => { cm.perform_action(CompileAction::DisplayTokens) }
+        Some("scan") => {
+            let m = compiler_match.subcommand_matches("scan").unwrap();
+            match parse_arg("--bench", m.value_of("bench")).unwrap_or_else(|e| cm.error(e)) {
+                Some(iterations) => cm.perform_action(CompileAction::ScanBench(iterations)),
+                None => cm.perform_action(CompileAction::Scan),
+            }
+        }
+        Some("tokens") => {
+            let m = compiler_match.subcommand_matches("tokens").unwrap();
+            if m.is_present("context") {
+                cm.perform_action(CompileAction::DisplayTokensWithContext)
+            } else {
+                cm.perform_action(CompileAction::DisplayTokens)
+            }
+        }
         Some("parse") => { cm.perform_action(CompileAction::Parse) }
-        Some("ast") => { cm.perform_action(CompileAction::DisplayAst) }
-        Some("typecheck") => { cm.perform_action(CompileAction::Typecheck) }
+        Some("ast") => {
+            let m = compiler_match.subcommand_matches("ast").unwrap();
+            let action = match (m.value_of("load"), m.value_of("format")) {
+                (Some(path), _) => CompileAction::LoadAst(path.to_string()),
+                (None, None) | (None, Some("text")) => CompileAction::DisplayAst,
+                (None, Some("bin")) => CompileAction::AstBinary,
+                (None, Some(format)) => cm.error(Error::InvalidArgument("--format", format.to_string())),
+            };
+            cm.perform_action(action)
+        }
+        Some("typecheck") => {
+            let m = compiler_match.subcommand_matches("typecheck").unwrap();
+            cm.perform_action(CompileAction::Typecheck(m.value_of("emit-symbols").map(|s| s.to_string())))
+        }
         Some("typetables") => { cm.perform_action(CompileAction::TypeTables) }
+        Some("annotate") => { cm.perform_action(CompileAction::Annotate) }
+        Some("type-at") => {
+            let m = compiler_match.subcommand_matches("type-at").unwrap();
+            cm.perform_action(CompileAction::TypeAt(m.value_of("FILE").unwrap().to_string(),
+                                                    m.value_of("POSITION").unwrap().to_string()))
+        }
+        Some("query") => {
+            let m = compiler_match.subcommand_matches("query").unwrap();
+            let paths = m.values_of("FILE").unwrap().map(|s| s.to_string()).collect();
+            cm.perform_action(CompileAction::Query(m.value_of("SELECTOR").unwrap().to_string(), paths))
+        }
+        Some("metrics") => {
+            let m = compiler_match.subcommand_matches("metrics").unwrap();
+            let paths = m.values_of("FILE").unwrap().map(|s| s.to_string()).collect();
+            cm.perform_action(CompileAction::Metrics(paths, m.is_present("json")))
+        }
+        Some(name @ "c") | Some(name @ "codegen") => {
+            let m = compiler_match.subcommand_matches(name).unwrap();
+            let options = c_options(m).unwrap_or_else(|e| cm.error(e));
+            cm.perform_action(CompileAction::CodegenC(options))
+        }
+        Some("build") => {
+            let m = compiler_match.subcommand_matches("build").unwrap();
+            let options = BuildOptions {
+                manifest: m.value_of("manifest").unwrap_or("minilang.toml").to_string(),
+                show_eliminated: m.is_present("show-eliminated"),
+                deps: m.value_of("emit-deps").map(|s| s.to_string()),
+                cache: !m.is_present("no-cache"),
+            };
+            cm.perform_action(CompileAction::Build(options))
+        }
+        Some("cache") => {
+            let m = compiler_match.subcommand_matches("cache").unwrap();
+            match m.subcommand_matches("clean") {
+                Some(m) => {
+                    let path = m.value_of("manifest").unwrap_or("minilang.toml").to_string();
+                    cm.perform_action(CompileAction::CacheClean(path))
+                }
+                None => {
+                    println!("{}", m.usage());
+                }
+            }
+        }
+        Some("run") => {
+            let m = compiler_match.subcommand_matches("run").unwrap();
+            let limits = run_limits(m).unwrap_or_else(|e| cm.error(e));
+            let via_c = m.is_present("via-c");
+            for &limit in &["--max-steps", "--max-output", "--timeout", "--max-call-depth", "--batch"] {
+                if via_c && m.is_present(&limit[2..]) {
+                    cm.error(Error::ConflictingArguments("--via-c", limit));
+                }
+            }
+            let path = m.value_of("FILE").unwrap().to_string();
+            match m.value_of("batch") {
+                Some(dir) => cm.perform_action(CompileAction::RunBatch(path, dir.to_string(), limits)),
+                None if m.is_present("check-spec") => cm.perform_action(CompileAction::RunSpec(path, limits)),
+                None => {
+                    let dump_env = match m.value_of("dump-env") {
+                        None => false,
+                        Some("json") => true,
+                        Some(format) => cm.error(Error::InvalidArgument("--dump-env", format.to_string())),
+                    };
+                    let options = RunOptions {
+                        limits: limits,
+                        via_c: via_c,
+                        cost_report: m.is_present("cost-report"),
+                        record: m.value_of("record").map(|s| s.to_string()),
+                        replay: m.value_of("replay").map(|s| s.to_string()),
+                        dump_env: dump_env,
+                    };
+                    cm.perform_action(CompileAction::Run(path, options))
+                }
+            }
+        }
+        Some("grammar") => {
+            let m = compiler_match.subcommand_matches("grammar").unwrap();
+            cm.perform_action(CompileAction::Grammar(m.value_of("format").unwrap_or("ebnf").to_string()))
+        }
+        Some("cst") => {
+            let m = compiler_match.subcommand_matches("cst").unwrap();
+            if m.is_present("events") {
+                cm.perform_action(CompileAction::CstEvents)
+            } else {
+                cm.perform_action(CompileAction::Cst(m.is_present("check")))
+            }
+        }
+        Some("conformance") => {
+            let m = compiler_match.subcommand_matches("conformance").unwrap();
+            cm.perform_action(CompileAction::Conformance(m.value_of("DIR").unwrap_or("conformance").to_string()))
+        }
+        Some("grade") => {
+            let m = compiler_match.subcommand_matches("grade").unwrap();
+            let as_json = match m.value_of("format") {
+                None | Some("csv") => false,
+                Some("json") => true,
+                Some(format) => cm.error(Error::InvalidArgument("--format", format.to_string())),
+            };
+            cm.perform_action(CompileAction::Grade(m.value_of("DIR").unwrap().to_string(), as_json))
+        }
+        Some("fix") => {
+            let m = compiler_match.subcommand_matches("fix").unwrap();
+            cm.perform_action(CompileAction::Fix(m.value_of("FILE").unwrap().to_string(), m.is_present("apply")))
+        }
+        Some("fmt") => {
+            let m = compiler_match.subcommand_matches("fmt").unwrap();
+            let options = format_options(m).unwrap_or_else(|e| cm.error(e));
+            cm.perform_action(CompileAction::Format(m.value_of("FILE").unwrap().to_string(), options))
+        }
+        Some("explore") => {
+            let m = compiler_match.subcommand_matches("explore").unwrap();
+            cm.perform_action(CompileAction::Explore(m.value_of("FILE").unwrap().to_string(),
+                                                     m.value_of("target").unwrap_or("c").to_string(),
+                                                     m.value_of("output").map(|s| s.to_string())))
+        }
+        Some("bench") => {
+            let m = compiler_match.subcommand_matches("bench").unwrap();
+            let options = bench_options(m).unwrap_or_else(|e| cm.error(e));
+            cm.perform_action(CompileAction::Bench(options))
+        }
         Some(_) => {}
         None => {
             println!("{}", compiler_match.usage());
@@ -0,0 +1,160 @@
+// Project manifests (minilang.toml).
+//
+// Only the small subset of TOML that a manifest needs is supported:
+// `key = value` lines, where a value is a string, an integer or a
+// single-line array of strings, and `#` comments.
+//
+//     sources = ["globals.min", "main.min"]
+//     target = "c"
+//     opt-level = 0
+//     output = "main.c"
+//
+// `build --emit-deps=FILE` writes the rule `main.c: minilang.toml
+// globals.min main.min` to FILE, so that Make rebuilds main.c when the
+// manifest or one of the sources changes.
+
+use error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    C,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "c" => Some(Target::C),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Manifest {
+    pub sources: Vec<String>,
+    pub target: Target,
+    pub opt_level: u32,
+    pub output: Option<String>,
+}
+
+// A value of the manifest format.
+pub enum Value {
+    Str(String),
+    Int(u32),
+    Array(Vec<String>),
+}
+
+impl Manifest {
+    pub fn parse(src: &str) -> Result<Manifest, Error> {
+        let mut manifest = Manifest {
+            sources: Vec::new(),
+            target: Target::C,
+            opt_level: 0,
+            output: None,
+        };
+
+        for (lineno, key, value) in try!(entries(src)) {
+            let key = &key[..];
+            match (key, value) {
+                ("sources", Value::Array(files)) => { manifest.sources = files; }
+                ("target", Value::Str(name)) => {
+                    manifest.target = try!(Target::from_name(&name).ok_or_else(||
+                        invalid(lineno, &format!("unknown target '{}'", name))));
+                }
+                ("opt-level", Value::Int(n)) if n <= 2 => { manifest.opt_level = n; }
+                ("opt-level", _) => { return Err(invalid(lineno, "opt-level must be 0, 1 or 2")); }
+                ("output", Value::Str(path)) => { manifest.output = Some(path); }
+                ("sources", _) | ("target", _) | ("output", _) => {
+                    return Err(invalid(lineno, &format!("wrong type of value for '{}'", key)));
+                }
+                _ => { return Err(invalid(lineno, &format!("unknown key '{}'", key))); }
+            }
+        }
+
+        if manifest.sources.is_empty() {
+            return Err(invalid(0, "no source files listed in 'sources'"));
+        }
+        Ok(manifest)
+    }
+}
+
+// A Make rule saying that `target` must be rebuilt when one of
+// `prerequisites` changes, like the `.d` files of C compilers.  Spaces
+// in paths are escaped.
+pub fn dependency_rule(target: &str, prerequisites: &[String]) -> String {
+    let escape = |path: &str| path.replace(' ', "\\ ");
+    let mut rule = format!("{}:", escape(target));
+    for path in prerequisites {
+        rule.push(' ');
+        rule.push_str(&escape(path));
+    }
+    rule.push('\n');
+    rule
+}
+
+// The `key = value` lines of a file in the manifest format, with their
+// line numbers.  Other files that configure the compiler, like the
+// specifications of `grade`, use the same format.
+pub fn entries(src: &str) -> Result<Vec<(usize, String, Value)>, Error> {
+    let mut entries = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let lineno = i + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let eq = try!(line.find('=').ok_or_else(|| invalid(lineno, "expected 'key = value'")));
+        let key = line[..eq].trim().to_string();
+        let value = try!(parse_value(line[eq + 1..].trim(), lineno));
+        entries.push((lineno, key, value));
+    }
+    Ok(entries)
+}
+
+// Remove a trailing comment, taking care of '#' inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => { in_string = !in_string; }
+            '#' if !in_string => { return &line[..i]; }
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(s: &str, lineno: usize) -> Result<Value, Error> {
+    if s.starts_with('[') {
+        if !s.ends_with(']') {
+            return Err(invalid(lineno, "unterminated array"));
+        }
+        let mut items = Vec::new();
+        for item in s[1..s.len() - 1].split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            items.push(try!(parse_string(item, lineno)));
+        }
+        Ok(Value::Array(items))
+    } else if s.starts_with('"') {
+        Ok(Value::Str(try!(parse_string(s, lineno))))
+    } else {
+        s.parse::<u32>()
+            .map(Value::Int)
+            .map_err(|_| invalid(lineno, &format!("invalid value '{}'", s)))
+    }
+}
+
+fn parse_string(s: &str, lineno: usize) -> Result<String, Error> {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        Ok(s[1..s.len() - 1].to_string())
+    } else {
+        Err(invalid(lineno, &format!("expected a string, found '{}'", s)))
+    }
+}
+
+fn invalid(lineno: usize, msg: &str) -> Error {
+    Error::InvalidManifest(lineno, msg.to_string())
+}
@@ -0,0 +1,74 @@
+use ast::*;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    // Number of decision points (if, while) plus one.
+    pub cyclomatic: usize,
+    // Deepest level of nested if/while blocks.
+    pub max_depth: usize,
+    // Number of statements, including nested ones.
+    pub stmts: usize,
+    // Number of declared variables.
+    pub vars: usize,
+}
+
+impl Metrics {
+    // Combine the metrics of two parts of a program: the cyclomatic
+    // complexities add up, as for a graph with one component per
+    // function.
+    fn add(&mut self, other: &Metrics) {
+        self.cyclomatic += other.cyclomatic;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.stmts += other.stmts;
+        self.vars += other.vars;
+    }
+}
+
+// The metrics of the whole program, its functions included.
+pub fn program_metrics(program: &Program) -> Metrics {
+    let mut m = body_metrics(program.decls.len(), &program.stmts);
+    for func in &program.funcs {
+        m.add(&function_metrics(func));
+    }
+    m
+}
+
+// The metrics of a function; its parameters count as variables.
+pub fn function_metrics(func: &FuncDecl) -> Metrics {
+    body_metrics(func.params.len() + func.decls.len(), &func.stmts)
+}
+
+fn body_metrics(vars: usize, stmts: &[Stmt]) -> Metrics {
+    let mut m = Metrics {
+        cyclomatic: 1,
+        vars: vars,
+        ..Metrics::default()
+    };
+    stmts_metrics(stmts, 0, &mut m);
+    m
+}
+
+fn stmts_metrics(stmts: &[Stmt], depth: usize, m: &mut Metrics) {
+    if !stmts.is_empty() && depth > m.max_depth {
+        m.max_depth = depth;
+    }
+    for stmt in stmts {
+        m.stmts += 1;
+        match *stmt {
+            Stmt::If(ref stmt_) => {
+                m.cyclomatic += 1;
+                stmts_metrics(&stmt_.then_stmts, depth + 1, m);
+                stmts_metrics(&stmt_.else_stmts, depth + 1, m);
+            }
+            Stmt::While(ref stmt_) => {
+                m.cyclomatic += 1;
+                stmts_metrics(&stmt_.stmts, depth + 1, m);
+            }
+            Stmt::For(ref stmt_) => {
+                m.cyclomatic += 1;
+                stmts_metrics(&stmt_.stmts, depth + 1, m);
+            }
+            _ => {}
+        }
+    }
+}
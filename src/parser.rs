@@ -1,41 +1,203 @@
-use token::{Token, TokenType};
+use std::cmp;
+use std::mem;
+
+use token::{Token, TokenType, PREC_PRODUCT, PREC_SUM};
 use ast::*;
 use pos::Pos;
 use error::Error;
+use ice;
+use grammar;
 use types::Type;
 
 
+// The surface syntax accepted by the parser.  Both produce the same AST.
+//
+// Classic:  var x: int;  if e then ... else ... endif  while e do ... done
+// Braces:   int x;       if (e) { ... } else { ... }    while (e) { ... }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Classic,
+    Braces,
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name {
+            "classic" => Some(Syntax::Classic),
+            "braces" => Some(Syntax::Braces),
+            _ => None,
+        }
+    }
+}
+
+// The default limits of the parser.  Deeper or larger programs would
+// risk overflowing the stack, here or in the passes that recurse over
+// the AST; within them, the front end and the interpreter fit in the
+// 2 MB stack of a spawned thread, even in a debug build.  Chains of
+// operators cost little stack, since the passes walk them in a loop.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+pub const DEFAULT_MAX_CHAIN: usize = 4096;
+pub const DEFAULT_MAX_NODES: usize = 1_000_000;
+
+// The most digits that `print e : N;` may ask for after the decimal
+// point; the output of a larger precision would be mostly zeros, and
+// would have to be allocated all at once.
+pub const MAX_PRECISION: usize = 100;
+
 pub struct Parser {
     tokens: Vec<Token>,
     index: usize,
     curr_id: u64,
+    first_id: u64,
+    syntax: Syntax,
+    // The nesting of the node being parsed: each statement inside
+    // another, parenthesized or negated expression and field access is
+    // one level deeper.  The operators of a chain like `a + b + c` are
+    // parsed in a loop, so a long chain is not deep.
+    depth: usize,
+    max_depth: usize,
+    // The operators of the chains being parsed, which still make the
+    // AST taller: `1 + 1 + 1` is `(1 + 1) + 1`.
+    chain: usize,
+    max_chain: usize,
+    max_nodes: usize,
+    // Whether syntax errors are recorded in `errors` and skipped over
+    // rather than returned (`parse_program_recovering`).
+    recovering: bool,
+    errors: Vec<Error>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        Parser::with_first_id(tokens, 0)
+    }
+
+    // Create a parser whose node ids start at `first_id`; used to give
+    // the ASTs of several files distinct node ids.
+    //
+    // The tokens need not end with Eof (`scanner::tokenize` adds it):
+    // the parser adds one if needed, and reads it again if it is asked
+    // for tokens past the end, so it returns an error rather than
+    // panicking on any sequence of tokens.
+    pub fn with_first_id(mut tokens: Vec<Token>, first_id: u64) -> Self {
+        if tokens.last().map_or(true, |tok| tok.typ != TokenType::Eof) {
+            let end = tokens.last().map_or(Pos { line: 1, col: 1 }, |tok| tok.end);
+            tokens.push(Token { typ: TokenType::Eof, lexeme: None, pos: end, end: end });
+        }
         Parser {
             tokens: tokens,
             index: 0,
-            curr_id: 0,
+            curr_id: first_id,
+            first_id: first_id,
+            syntax: Syntax::Classic,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            chain: 0,
+            max_chain: DEFAULT_MAX_CHAIN,
+            max_nodes: DEFAULT_MAX_NODES,
+            recovering: false,
+            errors: Vec::new(),
         }
     }
 
+    pub fn set_syntax(&mut self, syntax: Syntax) {
+        self.syntax = syntax;
+    }
+
+    // The deepest nesting of expressions and statements accepted.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // The most operators accepted in chains like `a + b + c`, counting
+    // those of the chains that the chain is an operand of.
+    pub fn set_max_chain(&mut self, max_chain: usize) {
+        self.max_chain = max_chain;
+    }
+
+    // The largest number of expressions and statements accepted.
+    pub fn set_max_nodes(&mut self, max_nodes: usize) {
+        self.max_nodes = max_nodes;
+    }
+
+    // The id that the next AST node will receive.
+    pub fn next_node_id(&self) -> u64 {
+        self.curr_id
+    }
+
+    // Node ids.  Every record, declaration, statement and expression,
+    // error nodes included, takes the next id when the parser finishes
+    // it: the nodes are numbered in post-order, children before their
+    // parent and left to right, from `first_id` on.  The parser never
+    // backtracks, so the ids depend only on the types and lexemes of
+    // the tokens and on the syntax, not on positions: the same program
+    // has the same ids in every run, and an edit of whitespace or of a
+    // comment keeps them.  Passes that rewrite the AST keep the ids of
+    // the nodes they keep, and binast.rs stores them.
     fn next_id(&mut self) -> u64 {
         let x = self.curr_id;
         self.curr_id += 1;
         x
     }
 
+    // Go one level deeper; `leave` goes back.
+    fn enter(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(Error::NestingTooDeep(self.token_pos(), self.max_depth));
+        }
+        self.check_nodes()
+    }
+
+    // Add an operator to the chains being parsed; `leave_chain` takes
+    // the operators of a chain off once it is parsed.
+    fn extend_chain(&mut self) -> Result<(), Error> {
+        self.chain += 1;
+        if self.chain > self.max_chain {
+            return Err(Error::ChainTooLong(self.token_pos(), self.max_chain));
+        }
+        self.check_nodes()
+    }
+
+    fn leave_chain(&mut self, operators: usize) {
+        self.chain -= operators;
+    }
+
+    fn check_nodes(&self) -> Result<(), Error> {
+        if self.curr_id - self.first_id >= self.max_nodes as u64 {
+            return Err(Error::TooManyNodes(self.token_pos(), self.max_nodes));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, levels: usize) {
+        self.depth -= levels;
+    }
+
+    // The current token, or the final Eof once it has been eaten.
+    fn token(&self) -> &Token {
+        &self.tokens[cmp::min(self.index, self.tokens.len() - 1)]
+    }
+
     fn peek(&self) -> TokenType {
-        self.tokens[self.index].typ
+        self.token().typ
     }
 
     fn curr_token(&self) -> Token {
-        self.tokens[self.index].clone()
+        self.token().clone()
     }
 
     fn token_pos(&self) -> Pos {
-        self.tokens[self.index].pos
+        self.token().pos
+    }
+
+    // End position of the last consumed token.
+    fn prev_end(&self) -> Pos {
+        if self.index == 0 {
+            self.token_pos()
+        } else {
+            self.tokens[cmp::min(self.index, self.tokens.len()) - 1].end
+        }
     }
 
     fn eat(&mut self, t: TokenType) -> Result<(), Error> {
@@ -48,12 +210,12 @@ impl Parser {
     }
 
     fn eat_lexeme(&mut self, t: TokenType) -> Result<String, Error> {
-        let token = &self.tokens[self.index];
+        let token = self.curr_token();
         if token.typ == t {
             match token.lexeme {
-                Some(ref lexeme) => {
+                Some(lexeme) => {
                     self.index += 1;
-                    Ok(lexeme.clone())
+                    Ok(lexeme)
                 }
                 None => Err(Error::UnexpectedToken(self.curr_token(), vec![t]))
             }
@@ -62,17 +224,162 @@ impl Parser {
         }
     }
 
+    fn peek_at(&self, offset: usize) -> TokenType {
+        self.tokens.get(self.index + offset).map_or(TokenType::Eof, |tok| tok.typ)
+    }
+
     pub fn parse_program(&mut self) -> Result<Program, Error> {
-        let decls = try!(self.parse_decls());
-        let stmts = try!(self.parse_stmts());
+        let mut records = Vec::new();
+        while self.syntax == Syntax::Classic && self.peek() == TokenType::Record {
+            records.push(try!(self.parse_record()));
+        }
+        let mut funcs = Vec::new();
+        let decls = try!(self.parse_decls(&mut records, &mut funcs));
+        while self.syntax == Syntax::Classic && self.peek() == TokenType::Function {
+            funcs.push(try!(self.parse_function()));
+        }
+        let mut stmts = try!(self.parse_stmts());
+        // Tokens that end a block outside of one.
+        while self.recovering && self.peek() != TokenType::Eof {
+            let (start, pos) = (self.index, self.token_pos());
+            let err = Error::UnexpectedToken(self.curr_token(), vec![TokenType::Eof]);
+            try!(self.recover(err));
+            self.index += 1;
+            self.skip_to_sync();
+            stmts.push(self.error_stmt(start, pos));
+            stmts.extend(try!(self.parse_stmts()));
+        }
         try!(self.eat(TokenType::Eof));
 
         Ok(Program {
+            records: records,
             decls: decls,
+            funcs: funcs,
             stmts: stmts,
         })
     }
 
+    // Parse a program like `parse_program`, but go on after a syntax
+    // error: the tokens of a statement or an expression that does not
+    // parse are skipped, up to the next `;` or keyword that can start or
+    // end a statement, and replaced by a `Stmt::Error` or an
+    // `Expr_::Error` node, and a declaration that does not parse is
+    // left out.  This returns the program and the syntax errors, in the
+    // order of the source, so that the passes after parsing can work on
+    // the rest of the program.  Errors in record definitions and in the
+    // headers of functions, and programs too deep or too large, still
+    // stop the parser.
+    pub fn parse_program_recovering(&mut self) -> Result<(Program, Vec<Error>), Error> {
+        self.recovering = true;
+        let program = self.parse_program();
+        self.recovering = false;
+        let program = try!(program);
+        Ok((program, mem::replace(&mut self.errors, Vec::new())))
+    }
+
+    // Record `err` if recovering from it, or return it.
+    fn recover(&mut self, err: Error) -> Result<(), Error> {
+        match err {
+            Error::NestingTooDeep(..) | Error::ChainTooLong(..) | Error::TooManyNodes(..) => Err(err),
+            _ if self.recovering => {
+                self.errors.push(err);
+                Ok(())
+            }
+            _ => Err(err),
+        }
+    }
+
+    // Skip the tokens up to the next one that a statement can start or
+    // end with, consuming a `;`.
+    fn skip_to_sync(&mut self) {
+        while !self.at_sync() {
+            self.index += 1;
+        }
+        if self.peek() == TokenType::Semicolon {
+            self.index += 1;
+        }
+    }
+
+    fn at_sync(&self) -> bool {
+        match self.peek() {
+            TokenType::Semicolon | TokenType::Read | TokenType::Print | TokenType::Exit |
+            TokenType::If | TokenType::Then | TokenType::Else | TokenType::EndIf |
+            TokenType::While | TokenType::Do | TokenType::Done | TokenType::For | TokenType::Return | TokenType::End |
+            TokenType::LBrace | TokenType::RBrace | TokenType::Var | TokenType::Eof => true,
+            _ => false,
+        }
+    }
+
+    // The tokens skipped since the token at `start` and position `pos`.
+    fn skipped_end(&self, start: usize, pos: Pos) -> Pos {
+        if self.index == start { pos } else { self.prev_end() }
+    }
+
+    fn error_stmt(&mut self, start: usize, pos: Pos) -> Stmt {
+        let end = self.skipped_end(start, pos);
+        Stmt::Error(StmtError { pos: pos, end: end, node_id: self.next_id() })
+    }
+
+    // An expression followed by `follow`; when recovering, the tokens
+    // of one that does not parse are skipped up to `follow`, or a
+    // statement boundary, and replaced by an `Expr_::Error`.
+    fn parse_expr_or_error(&mut self, follow: TokenType) -> Result<Expr, Error> {
+        let (start, depth, chain, pos) = (self.index, self.depth, self.chain, self.token_pos());
+        match self.parse_expr() {
+            Ok(e) => Ok(e),
+            Err(err) => {
+                try!(self.recover(err));
+                self.depth = depth;
+                self.chain = chain;
+                while self.peek() != follow && !self.at_sync() {
+                    self.index += 1;
+                }
+                let end = self.skipped_end(start, pos);
+                Ok(Expr { pos: pos, end: end, node_id: self.next_id(), expr: Expr_::Error })
+            }
+        }
+    }
+
+    // A single expression followed by the end of the input, such as
+    // `x * 2 + y` typed at a prompt.
+    pub fn parse_expression(&mut self) -> Result<Expr, Error> {
+        let expr = try!(self.parse_expr());
+        try!(self.eat(TokenType::Eof));
+        Ok(expr)
+    }
+
+    // record Point { x: float; y: float; }
+    // record Point { float x; float y; }     (braces syntax)
+    fn parse_record(&mut self) -> Result<RecordDecl, Error> {
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::Record));
+        let id = try!(self.eat_lexeme(TokenType::Id));
+        try!(self.eat(TokenType::LBrace));
+        let mut fields = Vec::new();
+        while grammar::first_in(self.syntax, "field").contains(&self.peek()) {
+            fields.push(try!(self.parse_field()));
+        }
+        try!(self.eat(TokenType::RBrace));
+        Ok(RecordDecl { pos: pos, node_id: self.next_id(), id: id, fields: fields })
+    }
+
+    fn parse_field(&mut self) -> Result<Field, Error> {
+        let pos = self.token_pos();
+        let (id, ty) = match self.syntax {
+            Syntax::Classic => {
+                let id = try!(self.eat_lexeme(TokenType::Id));
+                try!(self.eat(TokenType::Colon));
+                (id, try!(self.parse_type()))
+            }
+            Syntax::Braces => {
+                let ty = try!(self.parse_type());
+                (try!(self.eat_lexeme(TokenType::Id)), ty)
+            }
+        };
+        try!(self.eat(TokenType::Semicolon));
+        Ok(Field { pos: pos, id: id, ty: ty })
+    }
+
     fn parse_type(&mut self) -> Result<Type, Error> {
         match self.peek() {
             TokenType::TypeInt => {
@@ -83,61 +390,271 @@ impl Parser {
                 let _ = try!(self.eat(TokenType::TypeFloat));
                 Ok(Type::Float)
             }
+            TokenType::TypeInt32 => {
+                let _ = try!(self.eat(TokenType::TypeInt32));
+                Ok(Type::Int32)
+            }
+            TokenType::TypeInt64 => {
+                let _ = try!(self.eat(TokenType::TypeInt64));
+                Ok(Type::Int)
+            }
+            TokenType::TypeFloat32 => {
+                let _ = try!(self.eat(TokenType::TypeFloat32));
+                Ok(Type::Float32)
+            }
+            TokenType::TypeFloat64 => {
+                let _ = try!(self.eat(TokenType::TypeFloat64));
+                Ok(Type::Float)
+            }
             TokenType::TypeString => {
                 let _ = try!(self.eat(TokenType::TypeString));
                 Ok(Type::String)
             }
+            TokenType::TypeBool => {
+                let _ = try!(self.eat(TokenType::TypeBool));
+                Ok(Type::Bool)
+            }
+            TokenType::Id if self.syntax == Syntax::Classic => {
+                let id = try!(self.eat_lexeme(TokenType::Id));
+                Ok(Type::record(&id))
+            }
+            TokenType::LParen => {
+                try!(self.eat(TokenType::LParen));
+                let mut elements = vec![try!(self.parse_type())];
+                while self.peek() == TokenType::Comma {
+                    try!(self.eat(TokenType::Comma));
+                    elements.push(try!(self.parse_type()));
+                }
+                if elements.len() == 1 {
+                    return Err(Error::UnexpectedToken(self.curr_token(), vec![TokenType::Comma]));
+                }
+                try!(self.eat(TokenType::RParen));
+                Ok(Type::tuple(&elements))
+            }
+            TokenType::Record if self.syntax == Syntax::Braces => {
+                try!(self.eat(TokenType::Record));
+                let id = try!(self.eat_lexeme(TokenType::Id));
+                Ok(Type::record(&id))
+            }
             _ => {
-                Err(Error::UnexpectedToken(self.curr_token(),
-                                           vec![TokenType::TypeInt,
-                                                TokenType::TypeFloat,
-                                                TokenType::TypeString
-                                           ]))
+                Err(Error::UnexpectedToken(self.curr_token(), grammar::first_in(self.syntax, "type")))
             }
         }
     }
 
-    fn parse_decls(&mut self) -> Result<Vec<Decl>, Error> {
+    // In the braces syntax, record and function definitions may appear
+    // among the declarations; they are added to `records` and `funcs`.
+    fn parse_decls(&mut self, records: &mut Vec<RecordDecl>, funcs: &mut Vec<FuncDecl>)
+                   -> Result<Vec<Decl>, Error> {
         let mut decls: Vec<Decl> = Vec::new();
-        while self.peek() == TokenType::Var {
-            let decl = try!(self.parse_decl());
-            decls.push(decl);
+        while self.is_decl_start() {
+            if self.peek() == TokenType::Record && self.peek_at(2) == TokenType::LBrace {
+                records.push(try!(self.parse_record()));
+            } else if self.is_braces_function_start() {
+                funcs.push(try!(self.parse_function()));
+            } else {
+                let (start, depth, chain) = (self.index, self.depth, self.chain);
+                match self.parse_decl() {
+                    Ok(decl) => decls.push(decl),
+                    Err(err) => {
+                        try!(self.recover(err));
+                        self.depth = depth;
+                        self.chain = chain;
+                        if self.index == start {
+                            self.index += 1;
+                        }
+                        self.skip_to_sync();
+                    }
+                }
+            }
         }
         Ok(decls)
     }
 
     fn parse_decl(&mut self) -> Result<Decl, Error> {
+        ice::at(self.token_pos());
+        if self.syntax == Syntax::Braces {
+            return self.parse_braces_decl();
+        }
         let pos = self.token_pos();
         try!(self.eat(TokenType::Var));
         let id = try!(self.eat_lexeme(TokenType::Id));
-        try!(self.eat(TokenType::Colon));
-        let ty = try!(self.parse_type());
+        let ty = if self.peek() == TokenType::Colon {
+            try!(self.eat(TokenType::Colon));
+            Some(try!(self.parse_type()))
+        } else {
+            None
+        };
+        self.parse_decl_end(pos, id, ty)
+    }
+
+    // The optional initializer of a declaration and the semicolon.
+    // Without a type, the initializer is required.
+    fn parse_decl_end(&mut self, pos: Pos, id: String, ty: Option<Type>) -> Result<Decl, Error> {
+        let init = if self.peek() == TokenType::Equal {
+            try!(self.eat(TokenType::Equal));
+            Some(try!(self.parse_expr_or_error(TokenType::Semicolon)))
+        } else if ty.is_none() {
+            return Err(Error::MissingInitializer(pos, id));
+        } else {
+            None
+        };
         try!(self.eat(TokenType::Semicolon));
-        Ok(Decl { pos: pos, id: id, ty: ty })
+        Ok(Decl { pos: pos, node_id: self.next_id(), id: id, ty: ty, init: init })
+    }
+
+
+    fn parse_braces_decl(&mut self) -> Result<Decl, Error> {
+        let pos = self.token_pos();
+        let ty = if self.peek() == TokenType::Var {
+            try!(self.eat(TokenType::Var));
+            None
+        } else {
+            Some(try!(self.parse_type()))
+        };
+        let id = try!(self.eat_lexeme(TokenType::Id));
+        self.parse_decl_end(pos, id, ty)
+    }
+
+    // In the braces syntax, a function starts like a declaration, with
+    // its return type, or `void`, and its name, but goes on with `(`.
+    fn is_braces_function_start(&self) -> bool {
+        if self.syntax != Syntax::Braces {
+            return false;
+        }
+        match self.peek() {
+            TokenType::Void => true,
+            TokenType::Var => false,
+            TokenType::Record => self.peek_at(3) == TokenType::LParen,
+            _ => self.peek_at(2) == TokenType::LParen,
+        }
     }
 
+    // function f(a: int, b: int): int var c: int; ... return e; end
+    // int f(int a, int b) { int c; ... return e; }       (braces syntax)
+    //
+    // The return type and the final `return` are left out for a
+    // function that returns no value (`void` in the braces syntax).
+    fn parse_function(&mut self) -> Result<FuncDecl, Error> {
+        ice::at(self.token_pos());
+        let pos = self.token_pos();
+        let mut ret = None;
+        match self.syntax {
+            Syntax::Classic => try!(self.eat(TokenType::Function)),
+            Syntax::Braces if self.peek() == TokenType::Void => try!(self.eat(TokenType::Void)),
+            Syntax::Braces => ret = Some(try!(self.parse_type())),
+        }
+        let id = try!(self.eat_lexeme(TokenType::Id));
+        try!(self.eat(TokenType::LParen));
+        let mut params = Vec::new();
+        if self.peek() != TokenType::RParen {
+            params.push(try!(self.parse_param()));
+            while self.peek() == TokenType::Comma {
+                try!(self.eat(TokenType::Comma));
+                params.push(try!(self.parse_param()));
+            }
+        }
+        try!(self.eat(TokenType::RParen));
+        if self.syntax == Syntax::Classic && self.peek() == TokenType::Colon {
+            try!(self.eat(TokenType::Colon));
+            if self.peek() == TokenType::Void {
+                try!(self.eat(TokenType::Void));
+            } else {
+                ret = Some(try!(self.parse_type()));
+            }
+        }
+        if self.syntax == Syntax::Braces {
+            try!(self.eat(TokenType::LBrace));
+        }
+
+        let (mut records, mut funcs) = (Vec::new(), Vec::new());
+        let decls = try!(self.parse_decls(&mut records, &mut funcs));
+        if let Some(pos) = records.iter().map(|r| r.pos).chain(funcs.iter().map(|f| f.pos)).next() {
+            return Err(Error::NestedDefinition(pos));
+        }
+        let stmts = try!(self.parse_stmts());
+        match self.syntax {
+            Syntax::Classic => try!(self.eat(TokenType::End)),
+            Syntax::Braces => try!(self.eat(TokenType::RBrace)),
+        }
+        Ok(FuncDecl {
+            pos: pos,
+            node_id: self.next_id(),
+            id: id,
+            params: params,
+            ret: ret,
+            decls: decls,
+            stmts: stmts,
+        })
+    }
+
+    fn parse_param(&mut self) -> Result<Param, Error> {
+        let pos = self.token_pos();
+        let (id, ty) = match self.syntax {
+            Syntax::Classic => {
+                let id = try!(self.eat_lexeme(TokenType::Id));
+                try!(self.eat(TokenType::Colon));
+                (id, try!(self.parse_type()))
+            }
+            Syntax::Braces => {
+                let ty = try!(self.parse_type());
+                (try!(self.eat_lexeme(TokenType::Id)), ty)
+            }
+        };
+        Ok(Param { pos: pos, id: id, ty: ty })
+    }
 
     fn parse_stmts(&mut self) -> Result<Vec<Stmt>, Error> {
         let mut stmts: Vec<Stmt> = Vec::new();
-        while self.is_stmt_start() {
-            let stmt = try!(self.parse_stmt());
-            stmts.push(stmt);
+        loop {
+            let (start, depth, chain, pos) = (self.index, self.depth, self.chain, self.token_pos());
+            if self.is_stmt_start() {
+                match self.parse_stmt() {
+                    Ok(stmt) => {
+                        stmts.push(stmt);
+                        continue;
+                    }
+                    Err(err) => try!(self.recover(err)),
+                }
+                // The levels entered by the statement were not left.
+                self.depth = depth;
+                self.chain = chain;
+            } else if self.recovering && !self.is_block_end() {
+                let err = Error::UnexpectedToken(self.curr_token(), grammar::first_in(self.syntax, "statement"));
+                try!(self.recover(err));
+            } else {
+                break;
+            }
+            if self.index == start {
+                self.index += 1;
+            }
+            self.skip_to_sync();
+            stmts.push(self.error_stmt(start, pos));
         }
         Ok(stmts)
     }
 
     fn parse_stmt(&mut self) -> Result<Stmt, Error> {
+        ice::at(self.token_pos());
+        try!(self.enter());
+        let stmt = self.parse_stmt_kind();
+        self.leave(1);
+        stmt
+    }
+
+    fn parse_stmt_kind(&mut self) -> Result<Stmt, Error> {
         match self.peek() {
             TokenType::Read => { self.parse_read() }
             TokenType::Print => { self.parse_print() }
+            TokenType::Exit => { self.parse_exit() }
+            TokenType::Return => { self.parse_return() }
+            TokenType::Id if self.peek_at(1) == TokenType::LParen => { self.parse_call_stmt() }
             TokenType::Id => { self.parse_assign() }
             TokenType::If => { self.parse_if() }
             TokenType::While => { self.parse_while() }
+            TokenType::For => { self.parse_for() }
             _ => {
-                Err(Error::UnexpectedToken(
-                    self.curr_token(),
-                    vec![TokenType::Read, TokenType::Print,
-                         TokenType::Id, TokenType::If, TokenType::While]))
+                Err(Error::UnexpectedToken(self.curr_token(), grammar::first("statement")))
             }
         }
     }
@@ -145,32 +662,151 @@ impl Parser {
     fn parse_read(&mut self) -> Result<Stmt, Error> {
         let pos = self.token_pos();
         try!(self.eat(TokenType::Read));
-        let id = try!(self.eat_lexeme(TokenType::Id));
+        let mut ids = vec![try!(self.eat_lexeme(TokenType::Id))];
+        while self.peek() == TokenType::Comma {
+            try!(self.eat(TokenType::Comma));
+            ids.push(try!(self.eat_lexeme(TokenType::Id)));
+        }
+        let prompt = if self.peek() == TokenType::Prompt {
+            try!(self.eat(TokenType::Prompt));
+            Some(try!(self.eat_lexeme(TokenType::String)))
+        } else {
+            None
+        };
         try!(self.eat(TokenType::Semicolon));
-        Ok(Stmt::Read(StmtRead { pos: pos, id: id }))
+        Ok(Stmt::Read(StmtRead { pos: pos, node_id: self.next_id(), ids: ids, prompt: prompt }))
     }
 
     fn parse_print(&mut self) -> Result<Stmt, Error> {
         let pos = self.token_pos();
         try!(self.eat(TokenType::Print));
-        let e = try!(self.parse_expr());
+        let e = try!(self.parse_expr_or_error(TokenType::Semicolon));
+        let precision = if self.peek() == TokenType::Colon {
+            try!(self.eat(TokenType::Colon));
+            let digits_pos = self.token_pos();
+            let digits = try!(self.eat_lexeme(TokenType::Int));
+            match digits.parse::<usize>() {
+                Ok(n) if n <= MAX_PRECISION => Some(n),
+                _ => return Err(Error::PrecisionTooLarge(digits_pos, digits)),
+            }
+        } else {
+            None
+        };
         try!(self.eat(TokenType::Semicolon));
-        Ok(Stmt::Print(StmtPrint { pos: pos, expr: e }))
+        Ok(Stmt::Print(StmtPrint { pos: pos, node_id: self.next_id(), expr: e, precision: precision }))
     }
 
+    fn parse_exit(&mut self) -> Result<Stmt, Error> {
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::Exit));
+        let e = try!(self.parse_expr_or_error(TokenType::Semicolon));
+        try!(self.eat(TokenType::Semicolon));
+        Ok(Stmt::Exit(StmtExit { pos: pos, node_id: self.next_id(), expr: e }))
+    }
+
+    fn parse_return(&mut self) -> Result<Stmt, Error> {
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::Return));
+        let e = if self.peek() == TokenType::Semicolon {
+            None
+        } else {
+            Some(try!(self.parse_expr_or_error(TokenType::Semicolon)))
+        };
+        try!(self.eat(TokenType::Semicolon));
+        Ok(Stmt::Return(StmtReturn { pos: pos, node_id: self.next_id(), expr: e }))
+    }
+
+    // f(a, b);
+    fn parse_call_stmt(&mut self) -> Result<Stmt, Error> {
+        let pos = self.token_pos();
+        let e = try!(self.parse_id());
+        try!(self.eat(TokenType::Semicolon));
+        Ok(Stmt::Call(StmtCall { pos: pos, node_id: self.next_id(), expr: e }))
+    }
+
+    // x = e;  x.y = e;  x, y = e1, e2;
     fn parse_assign(&mut self) -> Result<Stmt, Error> {
         let pos = self.token_pos();
-        let id = try!(self.eat_lexeme(TokenType::Id));
+        let mut targets = vec![try!(self.parse_lvalue())];
+        while self.peek() == TokenType::Comma {
+            try!(self.eat(TokenType::Comma));
+            targets.push(try!(self.parse_lvalue()));
+        }
         try!(self.eat(TokenType::Equal));
-        let e = try!(self.parse_expr());
+        let e = try!(self.parse_expr_list(TokenType::Semicolon));
         try!(self.eat(TokenType::Semicolon));
-        Ok(Stmt::Assign(StmtAssign { pos: pos, id: id, expr: e }))
+        if targets.len() > 1 {
+            return Ok(Stmt::Destructure(StmtDestructure {
+                pos: pos,
+                node_id: self.next_id(),
+                targets: targets,
+                expr: e,
+            }));
+        }
+        let target = targets.pop().unwrap();
+        Ok(Stmt::Assign(StmtAssign {
+            pos: pos,
+            node_id: self.next_id(),
+            id: target.id,
+            fields: target.fields,
+            expr: e,
+        }))
+    }
+
+    fn parse_lvalue(&mut self) -> Result<LValue, Error> {
+        let id = try!(self.eat_lexeme(TokenType::Id));
+        let mut fields = Vec::new();
+        while self.peek() == TokenType::Dot {
+            try!(self.eat(TokenType::Dot));
+            fields.push(try!(self.eat_lexeme(TokenType::Id)));
+        }
+        Ok(LValue { id: id, fields: fields })
+    }
+
+    // One expression, or a tuple of several separated by commas, before
+    // `follow`.
+    fn parse_expr_list(&mut self, follow: TokenType) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let e = try!(self.parse_expr_or_error(follow));
+        if self.peek() != TokenType::Comma {
+            return Ok(e);
+        }
+        let mut exprs = vec![e];
+        while self.peek() == TokenType::Comma {
+            try!(self.eat(TokenType::Comma));
+            exprs.push(try!(self.parse_expr_or_error(follow)));
+        }
+        Ok(Expr {
+            pos: pos,
+            end: self.prev_end(),
+            node_id: self.next_id(),
+            expr: Expr_::Tuple(ExprTuple { exprs: exprs }),
+        })
+    }
+
+    // A braced block: "{" { statement } "}".
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, Error> {
+        try!(self.eat(TokenType::LBrace));
+        let stmts = try!(self.parse_stmts());
+        try!(self.eat(TokenType::RBrace));
+        Ok(stmts)
+    }
+
+    // A parenthesized condition, as in "if (e)" and "while (e)".
+    fn parse_paren_expr(&mut self) -> Result<Expr, Error> {
+        try!(self.eat(TokenType::LParen));
+        let e = try!(self.parse_expr_or_error(TokenType::RParen));
+        try!(self.eat(TokenType::RParen));
+        Ok(e)
     }
 
     fn parse_if(&mut self) -> Result<Stmt, Error> {
+        if self.syntax == Syntax::Braces {
+            return self.parse_braces_if();
+        }
         let pos = self.token_pos();
         try!(self.eat(TokenType::If));
-        let e = try!(self.parse_expr());
+        let e = try!(self.parse_expr_or_error(TokenType::Then));
         try!(self.eat(TokenType::Then));
         let then_stmts = try!(self.parse_stmts());
 
@@ -185,6 +821,30 @@ impl Parser {
         try!(self.eat(TokenType::EndIf));
         Ok(Stmt::If(StmtIf {
             pos: pos,
+            node_id: self.next_id(),
+            expr: e,
+            then_stmts: then_stmts,
+            else_stmts: else_stmts,
+        }))
+    }
+
+    fn parse_braces_if(&mut self) -> Result<Stmt, Error> {
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::If));
+        let e = try!(self.parse_paren_expr());
+        let then_stmts = try!(self.parse_block());
+
+        let else_stmts =
+            if self.peek() == TokenType::Else {
+                try!(self.eat(TokenType::Else));
+                try!(self.parse_block())
+            } else {
+                vec![]
+            };
+
+        Ok(Stmt::If(StmtIf {
+            pos: pos,
+            node_id: self.next_id(),
             expr: e,
             then_stmts: then_stmts,
             else_stmts: else_stmts,
@@ -192,22 +852,189 @@ impl Parser {
     }
 
     fn parse_while(&mut self) -> Result<Stmt, Error> {
+        if self.syntax == Syntax::Braces {
+            return self.parse_braces_while();
+        }
         let pos = self.token_pos();
         try!(self.eat(TokenType::While));
-        let e = try!(self.parse_expr());
+        let e = try!(self.parse_expr_or_error(TokenType::Do));
         try!(self.eat(TokenType::Do));
         let stmts = try!(self.parse_stmts());
         try!(self.eat(TokenType::Done));
         Ok(Stmt::While(StmtWhile {
             pos: pos,
+            node_id: self.next_id(),
+            expr: e,
+            stmts: stmts,
+        }))
+    }
+
+    fn parse_braces_while(&mut self) -> Result<Stmt, Error> {
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::While));
+        let e = try!(self.parse_paren_expr());
+        let stmts = try!(self.parse_block());
+        Ok(Stmt::While(StmtWhile {
+            pos: pos,
+            node_id: self.next_id(),
             expr: e,
             stmts: stmts,
         }))
     }
 
+    fn parse_for(&mut self) -> Result<Stmt, Error> {
+        if self.syntax == Syntax::Braces {
+            return self.parse_braces_for();
+        }
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::For));
+        let id = try!(self.eat_lexeme(TokenType::Id));
+        try!(self.eat(TokenType::Equal));
+        let first = try!(self.parse_expr_or_error(TokenType::To));
+        try!(self.eat(TokenType::To));
+        let last = try!(self.parse_expr_or_error(TokenType::Do));
+        try!(self.eat(TokenType::Do));
+        let stmts = try!(self.parse_stmts());
+        try!(self.eat(TokenType::Done));
+        Ok(Stmt::For(StmtFor {
+            pos: pos,
+            node_id: self.next_id(),
+            id: id,
+            first: first,
+            last: last,
+            stmts: stmts,
+        }))
+    }
+
+    // for (i = 1 to n) { ... }
+    fn parse_braces_for(&mut self) -> Result<Stmt, Error> {
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::For));
+        try!(self.eat(TokenType::LParen));
+        let id = try!(self.eat_lexeme(TokenType::Id));
+        try!(self.eat(TokenType::Equal));
+        let first = try!(self.parse_expr_or_error(TokenType::To));
+        try!(self.eat(TokenType::To));
+        let last = try!(self.parse_expr_or_error(TokenType::RParen));
+        try!(self.eat(TokenType::RParen));
+        let stmts = try!(self.parse_block());
+        Ok(Stmt::For(StmtFor {
+            pos: pos,
+            node_id: self.next_id(),
+            id: id,
+            first: first,
+            last: last,
+            stmts: stmts,
+        }))
+    }
+
     fn parse_expr(&mut self) -> Result<Expr, Error> {
+        try!(self.enter());
+        let e = self.parse_or();
+        self.leave(1);
+        e
+    }
+
+    // `or` binds less tightly than `and`; both associate to the left.
+    fn parse_or(&mut self) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let mut e = try!(self.parse_and());
+        let mut operators = 0;
+        while self.peek() == TokenType::Or {
+            try!(self.eat(TokenType::Or));
+            try!(self.extend_chain());
+            operators += 1;
+            let e2 = try!(self.parse_and());
+            e = Expr {
+                pos: pos,
+                end: self.prev_end(),
+                node_id: self.next_id(),
+                expr: Expr_::Logic(ExprLogic {
+                    op: Logic::Or,
+                    expr1: Box::new(e),
+                    expr2: Box::new(e2),
+                })
+            };
+        }
+        self.leave_chain(operators);
+        Ok(e)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let mut e = try!(self.parse_not());
+        let mut operators = 0;
+        while self.peek() == TokenType::And {
+            try!(self.eat(TokenType::And));
+            try!(self.extend_chain());
+            operators += 1;
+            let e2 = try!(self.parse_not());
+            e = Expr {
+                pos: pos,
+                end: self.prev_end(),
+                node_id: self.next_id(),
+                expr: Expr_::Logic(ExprLogic {
+                    op: Logic::And,
+                    expr1: Box::new(e),
+                    expr2: Box::new(e2),
+                })
+            };
+        }
+        self.leave_chain(operators);
+        Ok(e)
+    }
+
+    // `not` applies to a comparison: `not a < b` is `not (a < b)`.
+    fn parse_not(&mut self) -> Result<Expr, Error> {
+        if self.peek() != TokenType::Not {
+            return self.parse_comparison();
+        }
+        let pos = self.token_pos();
+        try!(self.eat(TokenType::Not));
+        try!(self.enter());
+        let e = self.parse_not();
+        self.leave(1);
+        let e = try!(e);
+        Ok(Expr {
+            pos: pos,
+            end: self.prev_end(),
+            node_id: self.next_id(),
+            expr: Expr_::Not(ExprNot { expr: Box::new(e) }),
+        })
+    }
+
+    // Comparisons do not associate: `a < b < c` is a syntax error.
+    fn parse_comparison(&mut self) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let sum = try!(self.parse_sum());
+        let op = match self.peek() {
+            TokenType::EqualEqual => Binop::Eq,
+            TokenType::NotEqual => Binop::Ne,
+            TokenType::Less => Binop::Lt,
+            TokenType::LessEqual => Binop::Le,
+            TokenType::Greater => Binop::Gt,
+            TokenType::GreaterEqual => Binop::Ge,
+            _ => { return Ok(sum); }
+        };
+        let tok = self.peek();
+        try!(self.eat(tok));
+        let sum2 = try!(self.parse_sum());
+        Ok(Expr {
+            pos: pos,
+            end: self.prev_end(),
+            node_id: self.next_id(),
+            expr: Expr_::Binop(ExprBinop {
+                op: op,
+                expr1: Box::new(sum),
+                expr2: Box::new(sum2),
+            })
+        })
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, Error> {
         let pos = self.token_pos();
         let mut term = try!(self.parse_term());
+        let mut operators = 0;
         while self.next_is_add() {
             let tok = self.peek();
             let op =
@@ -221,9 +1048,12 @@ impl Parser {
                     }
                 };
             try!(self.eat(tok));
+            try!(self.extend_chain());
+            operators += 1;
             let t2 = try!(self.parse_term());
             term = Expr {
                 pos: pos,
+                end: self.prev_end(),
                 node_id: self.next_id(),
                 expr: Expr_::Binop(ExprBinop {
                     op: op,
@@ -232,12 +1062,14 @@ impl Parser {
                 })
             };
         }
+        self.leave_chain(operators);
         Ok(term)
     }
 
     fn parse_term(&mut self) -> Result<Expr, Error> {
         let pos = self.token_pos();
         let mut fact = try!(self.parse_factor());
+        let mut operators = 0;
         while self.next_is_mul() {
             let tok = self.peek();
             let op =
@@ -251,9 +1083,12 @@ impl Parser {
                     }
                 };
             try!(self.eat(tok));
+            try!(self.extend_chain());
+            operators += 1;
             let f2 = try!(self.parse_factor());
             fact = Expr {
                 pos: pos,
+                end: self.prev_end(),
                 node_id: self.next_id(),
                 expr: Expr_::Binop(ExprBinop {
                     op: op,
@@ -262,77 +1097,197 @@ impl Parser {
                 })
             };
         }
+        self.leave_chain(operators);
         Ok(fact)
     }
 
+    // An atom followed by field accesses.
     fn parse_factor(&mut self) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let mut e = try!(self.parse_atom());
+        let mut levels = 0;
+        while self.peek() == TokenType::Dot {
+            try!(self.eat(TokenType::Dot));
+            try!(self.enter());
+            levels += 1;
+            let field = try!(self.eat_lexeme(TokenType::Id));
+            e = Expr {
+                pos: pos,
+                end: self.prev_end(),
+                node_id: self.next_id(),
+                expr: Expr_::Field(ExprField {
+                    expr: Box::new(e),
+                    field: field,
+                })
+            };
+        }
+        self.leave(levels);
+        Ok(e)
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr, Error> {
         let pos = self.token_pos();
         match self.peek() {
-            TokenType::Int => { self.parse_int() }
+            TokenType::Int => { self.parse_int(None) }
             TokenType::Float => { self.parse_float() }
             TokenType::String => { self.parse_string() }
+            TokenType::InterpStart => { self.parse_interpolation() }
+            TokenType::True | TokenType::False => { self.parse_bool() }
             TokenType::Id => { self.parse_id() }
             TokenType::LParen => {
                 try!(self.eat(TokenType::LParen));
-                let e = try!(self.parse_expr());
+                let mut e = try!(self.parse_expr_list(TokenType::RParen));
                 try!(self.eat(TokenType::RParen));
+                if let Expr_::Tuple(_) = e.expr {
+                    e.pos = pos;
+                    e.end = self.prev_end();
+                }
                 Ok(e)
             }
             TokenType::Minus => {
-                try!(self.eat(TokenType::Minus));
-                let e = try!(self.parse_expr());
-                Ok(Expr {
-                    pos: pos,
-                    node_id: self.next_id(),
-                    expr: Expr_::Negate(ExprNegate {
-                        expr: Box::new(e)
-                    })
-                })
+                // A run of minuses is read in a loop rather than by
+                // recursion, since each negates the rest of the sum.
+                let mut minuses = Vec::new();
+                while self.peek() == TokenType::Minus {
+                    minuses.push(self.token_pos());
+                    try!(self.eat(TokenType::Minus));
+                    try!(self.enter());
+                }
+                let levels = minuses.len();
+                // A literal on its own is negated as it is read, so that
+                // the smallest int, whose magnitude is too large for an
+                // int, can be written -9223372036854775808.
+                let operand_ends = match self.peek_at(1) {
+                    TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash => false,
+                    _ => true,
+                };
+                let mut e = if self.peek() == TokenType::Int && operand_ends {
+                    let minus = minuses.pop();
+                    try!(self.parse_int(minus))
+                } else {
+                    try!(self.parse_sum())
+                };
+                while let Some(pos) = minuses.pop() {
+                    e = Expr {
+                        pos: pos,
+                        end: self.prev_end(),
+                        node_id: self.next_id(),
+                        expr: Expr_::Negate(ExprNegate {
+                            expr: Box::new(e)
+                        })
+                    };
+                }
+                self.leave(levels);
+                Ok(e)
             }
             _ => {
-                Err(Error::UnexpectedToken(
-                    self.curr_token(),
-                    vec![TokenType::Int, TokenType::Float, TokenType::Id,
-                         TokenType::Minus, TokenType::LParen]))
+                Err(Error::UnexpectedToken(self.curr_token(), grammar::first("atom")))
             }
         }
     }
 
-    fn parse_int(&mut self) -> Result<Expr, Error> {
-        let pos = self.token_pos();
-        let lexeme = try!(self.eat_lexeme(TokenType::Int));
-        match lexeme.parse::<i64>() {
-            Ok(n) => Ok(Expr {
+    // `minus` is the position of the `-` before the literal, if it is
+    // negated.
+    fn parse_int(&mut self, minus: Option<Pos>) -> Result<Expr, Error> {
+        let pos = minus.unwrap_or(self.token_pos());
+        let mut lexeme = try!(self.eat_lexeme(TokenType::Int));
+        if minus.is_some() {
+            lexeme.insert(0, '-');
+        }
+        let (digits, ty) = split_suffix(&lexeme, Type::Int);
+        let value = match ty {
+            Type::Int32 => digits.parse::<i32>().ok().map(|n| n as i64),
+            _ => digits.parse::<i64>().ok(),
+        };
+        match value {
+            Some(n) => Ok(Expr {
                 pos: pos,
+                end: self.prev_end(),
                 node_id: self.next_id(),
                 expr: Expr_::Int(ExprInt {
-                    value: n
+                    value: n,
+                    ty: ty,
                 })
             }),
-            Err(_) => Err(Error::InvalidIntLiteral(pos, lexeme))
+            None => Err(Error::IntLiteralOverflow(pos, self.prev_end(), lexeme, ty))
         }
     }
 
     fn parse_float(&mut self) -> Result<Expr, Error> {
         let pos = self.token_pos();
         let lexeme = try!(self.eat_lexeme(TokenType::Float));
-        match lexeme.parse::<f64>() {
+        let (digits, ty) = split_suffix(&lexeme, Type::Float);
+        match digits.parse::<f64>() {
             Ok(n) => Ok(Expr {
                 pos: pos,
+                end: self.prev_end(),
                 node_id: self.next_id(),
                 expr: Expr_::Float(ExprFloat {
-                    value: n
+                    value: if ty == Type::Float32 { n as f32 as f64 } else { n },
+                    ty: ty,
+                    lexeme: lexeme.clone(),
                 })
             }),
             Err(_) => Err(Error::InvalidFloatLiteral(pos, lexeme))
         }
     }
 
+    // "a ${x} b" is sugar for "a " + <text of x> + " b".
+    fn parse_interpolation(&mut self) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let text = try!(self.eat_lexeme(TokenType::InterpStart));
+        let mut result = self.string_expr(pos, text);
+        loop {
+            let e = try!(self.parse_expr());
+            let text = Expr {
+                pos: e.pos,
+                end: e.end,
+                node_id: self.next_id(),
+                expr: Expr_::ToString(ExprToString { expr: Box::new(e) }),
+            };
+            result = self.concat(pos, result, text);
+
+            let part_pos = self.token_pos();
+            let (part, done) = match self.peek() {
+                TokenType::InterpMid => (try!(self.eat_lexeme(TokenType::InterpMid)), false),
+                TokenType::InterpEnd => (try!(self.eat_lexeme(TokenType::InterpEnd)), true),
+                _ => return Err(Error::UnexpectedToken(
+                    self.curr_token(), vec![TokenType::InterpMid, TokenType::InterpEnd])),
+            };
+            if !part.is_empty() {
+                let part = self.string_expr(part_pos, part);
+                result = self.concat(pos, result, part);
+            }
+            if done {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn string_expr(&mut self, pos: Pos, value: String) -> Expr {
+        Expr {
+            pos: pos,
+            end: self.prev_end(),
+            node_id: self.next_id(),
+            expr: Expr_::String(ExprString { value: value }),
+        }
+    }
+
+    fn concat(&mut self, pos: Pos, e1: Expr, e2: Expr) -> Expr {
+        Expr {
+            pos: pos,
+            end: self.prev_end(),
+            node_id: self.next_id(),
+            expr: Expr_::Binop(ExprBinop { op: Binop::Add, expr1: Box::new(e1), expr2: Box::new(e2) }),
+        }
+    }
+
     fn parse_string(&mut self) -> Result<Expr, Error> {
         let pos = self.token_pos();
         let lexeme = try!(self.eat_lexeme(TokenType::String));
         Ok(Expr {
             pos: pos,
+            end: self.prev_end(),
             node_id: self.next_id(),
             expr: Expr_::String(ExprString {
                 value: lexeme
@@ -340,11 +1295,46 @@ impl Parser {
         })
     }
 
+    fn parse_bool(&mut self) -> Result<Expr, Error> {
+        let pos = self.token_pos();
+        let value = self.peek() == TokenType::True;
+        let tok = self.peek();
+        try!(self.eat(tok));
+        Ok(Expr {
+            pos: pos,
+            end: self.prev_end(),
+            node_id: self.next_id(),
+            expr: Expr_::Bool(ExprBool {
+                value: value
+            })
+        })
+    }
+
+    // A variable, or a call `f(a, b)`.
     fn parse_id(&mut self) -> Result<Expr, Error> {
         let pos = self.token_pos();
         let lexeme = try!(self.eat_lexeme(TokenType::Id));
+        if self.peek() == TokenType::LParen {
+            try!(self.eat(TokenType::LParen));
+            let mut args = Vec::new();
+            if self.peek() != TokenType::RParen {
+                args.push(try!(self.parse_expr_or_error(TokenType::RParen)));
+                while self.peek() == TokenType::Comma {
+                    try!(self.eat(TokenType::Comma));
+                    args.push(try!(self.parse_expr_or_error(TokenType::RParen)));
+                }
+            }
+            try!(self.eat(TokenType::RParen));
+            return Ok(Expr {
+                pos: pos,
+                end: self.prev_end(),
+                node_id: self.next_id(),
+                expr: Expr_::Call(ExprCall { id: lexeme, args: args }),
+            });
+        }
         Ok(Expr {
             pos: pos,
+            end: self.prev_end(),
             node_id: self.next_id(),
             expr: Expr_::Id(ExprId {
                 id: lexeme
@@ -352,19 +1342,47 @@ impl Parser {
         })
     }
 
+    fn is_decl_start(&self) -> bool {
+        grammar::first_in(self.syntax, "declaration").contains(&self.peek())
+    }
+
     fn is_stmt_start(&self) -> bool {
-        self.peek() == TokenType::Id
-            || self.peek() == TokenType::If
-            || self.peek() == TokenType::While
-            || self.peek() == TokenType::Read
-            || self.peek() == TokenType::Print
+        grammar::first_in(self.syntax, "statement").contains(&self.peek())
+    }
+
+    // A token that ends the statements of a block.
+    fn is_block_end(&self) -> bool {
+        match (self.syntax, self.peek()) {
+            (_, TokenType::Eof) => true,
+            (Syntax::Classic, TokenType::Done) | (Syntax::Classic, TokenType::EndIf) |
+            (Syntax::Classic, TokenType::Else) | (Syntax::Classic, TokenType::End) => true,
+            (Syntax::Braces, TokenType::RBrace) => true,
+            _ => false,
+        }
     }
 
     fn next_is_add(&self) -> bool {
-        self.peek() == TokenType::Plus || self.peek() == TokenType::Minus
+        self.peek().precedence() == Some(PREC_SUM)
     }
 
     fn next_is_mul(&self) -> bool {
-        self.peek() == TokenType::Star || self.peek() == TokenType::Slash
+        self.peek().precedence() == Some(PREC_PRODUCT)
+    }
+}
+
+// Split a numeric literal into its digits and the type given by its
+// suffix, or `default` if it has none.
+fn split_suffix(lexeme: &str, default: Type) -> (&str, Type) {
+    match lexeme.find(|c: char| c == 'i' || c == 'f') {
+        Some(i) => {
+            let ty = match &lexeme[i..] {
+                "i32" => Type::Int32,
+                "i64" => Type::Int,
+                "f32" => Type::Float32,
+                _ => Type::Float,
+            };
+            (&lexeme[..i], ty)
+        }
+        None => (lexeme, default),
     }
 }
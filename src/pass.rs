@@ -0,0 +1,779 @@
+// The compiler pipeline as a sequence of passes.
+//
+// Every phase of the compiler (scanning, parsing, type checking, code
+// generation) is a `Pass` that reads and updates a shared `Context`.
+// A `PassManager` runs the passes in order, optionally timing them,
+// verifying their results, and stopping after a given pass.
+//
+// Library users can register their own passes, for instance a style
+// checker that runs on the typed program before code generation:
+//
+// ```ignore
+// struct ShortNames;
+//
+// impl Pass for ShortNames {
+//     fn name(&self) -> &'static str { "short-names" }
+//
+//     fn run(&mut self, ctx: &mut Context) -> Result<(), Error> {
+//         let long: Vec<_> = ctx.program().decls.iter()
+//             .filter(|d| d.id.len() > 8)
+//             .map(|d| (d.pos, d.id.clone()))
+//             .collect();
+//         for (pos, id) in long {
+//             ctx.warning(pos, format!("variable name '{}' is too long", id));
+//         }
+//         Ok(())
+//     }
+// }
+//
+// let mut pm = standard_pipeline();
+// try!(pm.insert_pass_after("typecheck", Box::new(ShortNames)));
+// let mut ctx = Context::new(source);
+// try!(pm.run(&mut ctx));
+// ```
+
+use std::collections::HashSet;
+use std::io::{Write, stderr};
+use std::time::{Duration, Instant};
+
+use ast::*;
+use backend::{Backend, CBackend};
+use cgen::{Overflow, TempNames};
+use consteval;
+use eliminate;
+use fix;
+use ice;
+use induction;
+use diagnostic::{Diagnostic, Severity, Suggestion};
+use error::Error;
+use parser::{Parser, Syntax};
+use scanner::{self, IdentifierRules};
+use sourcemap::Mapping;
+use suppress::Suppressions;
+use pos::Pos;
+use interp::Value;
+use token::{Token, TokenType};
+use typecheck::{self, Symtable, Exprtable, TypeChecker};
+use types::Type;
+
+// The default of `Context::max_unroll`.
+pub const DEFAULT_MAX_UNROLL: usize = 8;
+
+// The default of `Context::max_errors`.
+pub const DEFAULT_MAX_ERRORS: usize = 20;
+
+// The state shared by the passes.  Each pass fills in the fields
+// that the following passes need.
+pub struct Context {
+    // The program text.
+    pub source: String,
+    // The surface syntax of the program text.
+    pub syntax: Syntax,
+    // The characters allowed in identifiers.
+    pub identifiers: IdentifierRules,
+    // Whether conditions must be bool rather than int.
+    pub strict: bool,
+    // Whether the `eliminate` pass reports the globals it removes.
+    pub show_eliminated: bool,
+    // What the generated C code does on integer overflow.
+    pub overflow: Overflow,
+    // The optimization level; loops are unrolled from level 2.
+    pub opt_level: u32,
+    // The largest number of iterations of an unrolled loop.
+    pub max_unroll: usize,
+    // Whether the generated C is left unindented, without blank lines.
+    pub compact: bool,
+    // Whether the generated C repeats string literals where they are
+    // used rather than sharing one per distinct text.
+    pub inline_strings: bool,
+    // The prefix of the C names of variables.
+    pub ident_prefix: String,
+    // How the temporaries of the generated C are named.
+    pub temp_names: TempNames,
+    // Whether the generated C has a comment with the source line above
+    // the code of each declaration and statement.
+    pub line_comments: bool,
+    // The number of errors after which the following ones are
+    // dropped.
+    pub max_errors: usize,
+    // Whether warnings are reported as errors.
+    pub deny_warnings: bool,
+    // The warnings dropped everywhere (`--allow`), by name.
+    pub allowed: Vec<&'static str>,
+    // The warnings reported as errors (`--deny`), by name.
+    pub denied: Vec<&'static str>,
+    // The tokens of the program, filled in by the `scan` pass.
+    pub tokens: Vec<Token>,
+    // The AST of the program, filled in by the `parse` pass.
+    pub program: Option<Program>,
+    // The warnings allowed by `# minilang: allow` comments, filled in
+    // by the `parse` pass.
+    pub suppressions: Suppressions,
+    // The types of variables, filled in by the `typecheck` pass.
+    pub symtable: Symtable,
+    // The types of expressions (by node id), filled in by the
+    // `typecheck` pass.
+    pub expr_table: Exprtable,
+    // The generated code, filled in by the `codegen` pass.
+    pub output: Option<String>,
+    // The source map of the generated code, filled in by the
+    // `codegen` pass.
+    pub source_map: Vec<Mapping>,
+    // Warnings and errors reported by passes.  The pass manager stops
+    // after any pass that reports an error.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Context {
+    pub fn new(source: String) -> Self {
+        Context {
+            source: source,
+            syntax: Syntax::Classic,
+            identifiers: IdentifierRules::default(),
+            strict: false,
+            show_eliminated: false,
+            overflow: Overflow::Wrap,
+            opt_level: 0,
+            max_unroll: DEFAULT_MAX_UNROLL,
+            compact: false,
+            inline_strings: false,
+            ident_prefix: String::new(),
+            temp_names: TempNames::Numbered,
+            line_comments: false,
+            max_errors: DEFAULT_MAX_ERRORS,
+            deny_warnings: false,
+            allowed: Vec::new(),
+            denied: Vec::new(),
+            tokens: Vec::new(),
+            program: None,
+            suppressions: Suppressions::new(),
+            symtable: Symtable::new(),
+            expr_table: Exprtable::new(),
+            output: None,
+            source_map: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // The AST of the program.  Panics if called before the `parse`
+    // pass has run.
+    pub fn program(&self) -> &Program {
+        self.program.as_ref().expect("pass run before parsing")
+    }
+
+    // Report a diagnostic.  Warnings that `suppressions` or `allowed`
+    // allow are dropped, and the others are errors if `deny_warnings`
+    // is set or they are `denied`.
+    // Errors are dropped once `max_errors` have been reported; the
+    // last one is followed by a note saying so.  The first error is
+    // always reported.
+    pub fn report(&mut self, mut diagnostic: Diagnostic) {
+        if diagnostic.severity == Severity::Warning {
+            if diagnostic.lint.map_or(false, |lint| {
+                self.allowed.contains(&lint) || self.suppressions.allows(lint, diagnostic.pos)
+            }) {
+                return;
+            }
+            if self.deny_warnings || diagnostic.lint.map_or(false, |lint| self.denied.contains(&lint)) {
+                diagnostic.severity = Severity::Error;
+            }
+        }
+        if diagnostic.severity != Severity::Error {
+            self.diagnostics.push(diagnostic);
+            return;
+        }
+        if self.has_errors() && self.too_many_errors() {
+            return;
+        }
+        let pos = diagnostic.pos;
+        self.diagnostics.push(diagnostic);
+        if self.too_many_errors() {
+            self.note(pos, "too many errors, aborting".to_string());
+        }
+    }
+
+    pub fn error(&mut self, pos: Pos, message: String) {
+        self.report(Diagnostic::error(pos, message));
+    }
+
+    // Whether `max_errors` errors have been reported; passes that
+    // report many errors can stop early.
+    pub fn too_many_errors(&self) -> bool {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Error).count() >= self.max_errors
+    }
+
+    pub fn warning(&mut self, pos: Pos, message: String) {
+        self.report(Diagnostic::warning(pos, message));
+    }
+
+    // Report the warning named `lint`, one of `diagnostic::LINTS`.
+    pub fn lint(&mut self, lint: &'static str, pos: Pos, message: String) {
+        self.report(Diagnostic::warning(pos, message).with_lint(lint));
+    }
+
+    pub fn note(&mut self, pos: Pos, message: String) {
+        self.report(Diagnostic::note(pos, message));
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+}
+
+// A step of the compiler pipeline.
+pub trait Pass {
+    // The name used by `--stop-after` and in timing reports.
+    fn name(&self) -> &'static str;
+
+    // Run the pass.  Fatal errors are returned; other problems are
+    // reported in `ctx.diagnostics`.
+    fn run(&mut self, ctx: &mut Context) -> Result<(), Error>;
+
+    // Check the invariants that the pass guarantees to the following
+    // ones.  Called after `run` when verification is enabled.
+    fn verify(&self, _ctx: &Context) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    stop_after: Option<String>,
+    time_passes: bool,
+    verify: bool,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            stop_after: None,
+            time_passes: false,
+            verify: cfg!(debug_assertions),
+        }
+    }
+
+    // Add a pass at the end of the pipeline.
+    pub fn add_pass(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+    }
+
+    // Add a pass right after the pass called `name`.
+    pub fn insert_pass_after(&mut self, name: &str, pass: Box<dyn Pass>) -> Result<(), Error> {
+        match self.passes.iter().position(|p| p.name() == name) {
+            Some(i) => {
+                self.passes.insert(i + 1, pass);
+                Ok(())
+            }
+            None => Err(Error::UnknownPass(name.to_string(), self.pass_names())),
+        }
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn stop_after(&mut self, name: &str) -> Result<(), Error> {
+        if self.passes.iter().any(|p| p.name() == name) {
+            self.stop_after = Some(name.to_string());
+            Ok(())
+        } else {
+            Err(Error::UnknownPass(name.to_string(), self.pass_names()))
+        }
+    }
+
+    pub fn time_passes(&mut self, enable: bool) {
+        self.time_passes = enable;
+    }
+
+    pub fn verify(&mut self, enable: bool) {
+        self.verify = enable;
+    }
+
+    // Run the passes in order and return how long each one took.
+    pub fn run(&mut self, ctx: &mut Context) -> Result<Vec<(&'static str, Duration)>, Error> {
+        let mut timings = Vec::new();
+        for pass in self.passes.iter_mut() {
+            ice::enter_phase(pass.name());
+            let start = Instant::now();
+            try!(pass.run(ctx));
+            let elapsed = start.elapsed();
+            timings.push((pass.name(), elapsed));
+
+            if self.time_passes {
+                let ms = elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 / 1e6;
+                let _ = writeln!(stderr(), "{:>12}: {:.3} ms", pass.name(), ms);
+            }
+            if ctx.has_errors() {
+                return Err(Error::PassFailed(pass.name()));
+            }
+            if self.verify {
+                try!(pass.verify(ctx));
+            }
+            if self.stop_after.as_ref().map_or(false, |name| name == pass.name()) {
+                break;
+            }
+        }
+        Ok(timings)
+    }
+}
+
+// The scanning, parsing, type checking, dead global elimination and
+// C generation passes.
+pub fn standard_pipeline() -> PassManager {
+    pipeline(Box::new(CBackend))
+}
+
+// The standard pipeline, generating code with `backend`.
+pub fn pipeline(backend: Box<dyn Backend>) -> PassManager {
+    let mut pm = PassManager::new();
+    pm.add_pass(Box::new(ScanPass));
+    pm.add_pass(Box::new(ParsePass));
+    pm.add_pass(Box::new(TypecheckPass));
+    pm.add_pass(Box::new(EliminatePass));
+    pm.add_pass(Box::new(CodegenPass { backend: backend }));
+    pm
+}
+
+pub struct ScanPass;
+pub struct ParsePass;
+pub struct TypecheckPass;
+pub struct EliminatePass;
+pub struct CodegenPass {
+    pub backend: Box<dyn Backend>,
+}
+
+impl Pass for ScanPass {
+    fn name(&self) -> &'static str { "scan" }
+
+    fn run(&mut self, ctx: &mut Context) -> Result<(), Error> {
+        ctx.tokens = try!(scanner::tokenize_with(&ctx.source, &ctx.identifiers));
+        Ok(())
+    }
+}
+
+impl Pass for ParsePass {
+    fn name(&self) -> &'static str { "parse" }
+
+    fn run(&mut self, ctx: &mut Context) -> Result<(), Error> {
+        let mut parser = Parser::new(ctx.tokens.clone());
+        parser.set_syntax(ctx.syntax);
+        // The program is kept despite syntax errors, with error nodes
+        // in place of what did not parse.
+        let errors = match parser.parse_program_recovering() {
+            Ok((program, errors)) => {
+                ctx.program = Some(program);
+                errors
+            }
+            Err(err) => vec![err],
+        };
+        for err in errors {
+            let diagnostic = match err.pos() {
+                Some(pos) => parse_error(&err, pos, &ctx.tokens),
+                None => return Err(err),
+            };
+            ctx.report(diagnostic);
+            if ctx.too_many_errors() {
+                break;
+            }
+        }
+        if ctx.program.is_none() {
+            return Ok(());
+        }
+        let (suppressions, problems) = Suppressions::collect(&ctx.source, &ctx.tokens, ctx.program());
+        ctx.suppressions = suppressions;
+        for (pos, message) in problems {
+            ctx.lint("invalid-directive", pos, message);
+        }
+        Ok(())
+    }
+
+    // Node ids must be unique: they are the keys of the expression table.
+    fn verify(&self, ctx: &Context) -> Result<(), Error> {
+        let mut seen = HashSet::new();
+        let mut ok = true;
+        for_each_expr(ctx.program(), &mut |expr| ok &= seen.insert(expr.node_id));
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::PassVerification(self.name(), "duplicate node ids".to_string()))
+        }
+    }
+}
+
+impl Pass for TypecheckPass {
+    fn name(&self) -> &'static str { "typecheck" }
+
+    fn run(&mut self, ctx: &mut Context) -> Result<(), Error> {
+        let mut tc = TypeChecker::new();
+        tc.set_strict(ctx.strict);
+        let errors = tc.tc_program_all(ctx.program());
+        let well_typed = errors.is_empty();
+        for err in errors {
+            let diagnostic = match err.pos() {
+                Some(pos) => type_error(&err, pos, ctx.program(), &ctx.tokens, &tc.symtable, &tc.expr_table),
+                None => return Err(err),
+            };
+            ctx.report(diagnostic);
+            if ctx.too_many_errors() {
+                break;
+            }
+        }
+        // Constants can only be evaluated in a well-typed program.
+        if well_typed {
+            for (pos, n, ty) in lossy_conversions(ctx.program(), &tc) {
+                ctx.lint("lossy-conversion", pos, format!("{} is converted to {}, which cannot represent it exactly", n, ty));
+            }
+            for (pos, message) in induction::endless_loops(ctx.program(), &tc.symtable, &tc.expr_table) {
+                ctx.lint("endless-loop", pos, message);
+            }
+        }
+        ctx.symtable = tc.symtable;
+        ctx.expr_table = tc.expr_table;
+        Ok(())
+    }
+
+    // Every expression must have a type for code generation.
+    fn verify(&self, ctx: &Context) -> Result<(), Error> {
+        let mut missing = None;
+        for_each_expr(ctx.program(), &mut |expr| {
+            if !ctx.expr_table.contains_key(&expr.node_id) {
+                missing = Some(expr.pos);
+            }
+        });
+        match missing {
+            None => Ok(()),
+            Some(pos) => Err(Error::PassVerification(
+                self.name(), format!("{}: expression has no type", pos))),
+        }
+    }
+}
+
+// The diagnostic of a syntax error, with a semicolon to insert after
+// the previous token if one is missing.
+fn parse_error(err: &Error, pos: Pos, tokens: &[Token]) -> Diagnostic {
+    let diagnostic = Diagnostic::error(pos, err.message());
+    match *err {
+        Error::UnexpectedToken(_, ref choices) if choices.contains(&TokenType::Semicolon) => {
+            match tokens.iter().rev().find(|tok| tok.pos < pos && tok.end <= pos) {
+                Some(prev) => diagnostic.with_suggestion(Suggestion {
+                    start: prev.end,
+                    end: prev.end,
+                    replacement: ";".to_string(),
+                    message: "insert ';'".to_string(),
+                    applicable: true,
+                }),
+                None => diagnostic,
+            }
+        }
+        _ => diagnostic,
+    }
+}
+
+// The diagnostic of a type error, with the positions that explain it
+// and, for an undeclared variable, the declared one it may be a typo of.
+fn type_error(err: &Error, pos: Pos, program: &Program, tokens: &[Token], symtable: &Symtable,
+              expr_table: &Exprtable) -> Diagnostic {
+    let diagnostic = Diagnostic::error(pos, err.message());
+    match *err {
+        Error::UndeclaredVariable(_, ref id) => {
+            // The position is that of the statement for `read`.
+            let tok = tokens.iter()
+                .find(|tok| tok.pos >= pos && tok.typ == TokenType::Id && tok.lexeme.as_ref() == Some(id));
+            match (tok, fix::similar_name(id, symtable.keys())) {
+                (Some(tok), Some(name)) => diagnostic.with_suggestion(Suggestion {
+                    start: tok.pos,
+                    end: tok.end,
+                    replacement: name.clone(),
+                    message: format!("a variable with a similar name exists: '{}'", name),
+                    applicable: true,
+                }),
+                _ => diagnostic,
+            }
+        }
+        Error::DuplicateVariable(_, ref id) => {
+            match symtable.get(id) {
+                Some(sym) => diagnostic.with_label(sym.pos, format!("'{}' is first declared here", id)),
+                None => diagnostic,
+            }
+        }
+        Error::IllTypedBinop { ref lhs, ref rhs, .. } => {
+            // The operation has no type, unlike its operands; nested
+            // operations can start at the same position.
+            let mut operands = None;
+            for_each_expr(program, &mut |expr| {
+                if let Expr_::Binop(ref expr_) = expr.expr {
+                    if expr.pos == pos && !expr_table.contains_key(&expr.node_id)
+                        && expr_table.get(&expr_.expr1.node_id) == Some(lhs)
+                        && expr_table.get(&expr_.expr2.node_id) == Some(rhs) {
+                        operands = Some((expr_.expr1.pos, expr_.expr2.pos));
+                    }
+                }
+            });
+            match operands {
+                Some((pos1, pos2)) => diagnostic.with_label(pos1, format!("this is {}", lhs))
+                    .with_label(pos2, format!("this is {}", rhs)),
+                None => diagnostic,
+            }
+        }
+        _ => diagnostic,
+    }
+}
+
+// The integer constants that are implicitly converted to a float type
+// with too few digits to hold them, with the position of the integer
+// expression: initializers, assigned and returned values whose target
+// is a float, and int operands of operations on floats.  The variables
+// of a function are looked up among its locals.
+fn lossy_conversions(program: &Program, tc: &TypeChecker) -> Vec<(Pos, i64, Type)> {
+    fn target(tc: &TypeChecker, symtable: &Symtable, id: &str, fields: &[String]) -> Option<Type> {
+        let mut ty = match symtable.get(id) {
+            Some(sym) => sym.ty.clone(),
+            None => return None,
+        };
+        for field in fields {
+            ty = match typecheck::field_type(&tc.records, &ty, field) {
+                Some(ty) => ty,
+                None => return None,
+            };
+        }
+        Some(ty)
+    }
+
+    fn decls(symtable: &Symtable, decls_: &[Decl], out: &mut Vec<(Pos, i64, Type)>) {
+        for decl in decls_ {
+            if let (Some(ref init), Some(sym)) = (decl.init.as_ref(), symtable.get(&decl.id)) {
+                converted(init, &sym.ty, out);
+            }
+        }
+    }
+
+    // `ret` is the return type of the function that `stmts_` belong to.
+    fn stmts(tc: &TypeChecker, symtable: &Symtable, ret: Option<&Type>, stmts_: &[Stmt],
+             out: &mut Vec<(Pos, i64, Type)>) {
+        for stmt in stmts_ {
+            match *stmt {
+                Stmt::Assign(ref stmt_) => {
+                    if let Some(ty) = target(tc, symtable, &stmt_.id, &stmt_.fields) {
+                        converted(&stmt_.expr, &ty, out);
+                    }
+                }
+                Stmt::Destructure(ref stmt_) => {
+                    let tys: Option<Vec<Type>> = stmt_.targets.iter()
+                        .map(|t| target(tc, symtable, &t.id, &t.fields))
+                        .collect();
+                    if let Some(tys) = tys {
+                        converted(&stmt_.expr, &Type::tuple(&tys), out);
+                    }
+                }
+                Stmt::Return(ref stmt_) => {
+                    if let (Some(ref expr), Some(ty)) = (stmt_.expr.as_ref(), ret) {
+                        converted(expr, ty, out);
+                    }
+                }
+                Stmt::If(ref stmt_) => {
+                    stmts(tc, symtable, ret, &stmt_.then_stmts, out);
+                    stmts(tc, symtable, ret, &stmt_.else_stmts, out);
+                }
+                Stmt::While(ref stmt_) => stmts(tc, symtable, ret, &stmt_.stmts, out),
+                Stmt::For(ref stmt_) => stmts(tc, symtable, ret, &stmt_.stmts, out),
+                Stmt::Read(_) | Stmt::Print(_) | Stmt::Exit(_) | Stmt::Call(_) | Stmt::Error(_) => {}
+            }
+        }
+    }
+
+    // The elements of a tuple are looked at one by one, for their
+    // positions.
+    fn converted(expr: &Expr, ty: &Type, out: &mut Vec<(Pos, i64, Type)>) {
+        match (&expr.expr, ty) {
+            (&Expr_::Tuple(ref expr_), &Type::Tuple(ref tys)) => {
+                for (e, ty) in expr_.exprs.iter().zip(tys.iter()) {
+                    converted(e, ty, out);
+                }
+            }
+            _ => {
+                if let Ok(Some(value)) = consteval::eval(expr) {
+                    value_converted(&value, ty, expr.pos, out);
+                }
+            }
+        }
+    }
+
+    fn value_converted(value: &Value, ty: &Type, pos: Pos, out: &mut Vec<(Pos, i64, Type)>) {
+        let exact = match (value, ty) {
+            (&Value::Int(n), &Type::Float) => Some((n, n as f64 as i128 == n as i128)),
+            (&Value::Int32(n), &Type::Float32) => Some((n as i64, n as f32 as i64 == n as i64)),
+            (&Value::Tuple(ref values), &Type::Tuple(ref tys)) => {
+                for (value, ty) in values.iter().zip(tys.iter()) {
+                    value_converted(value, ty, pos, out);
+                }
+                None
+            }
+            _ => None,
+        };
+        if let Some((n, false)) = exact {
+            out.push((pos, n, ty.clone()));
+        }
+    }
+
+    let mut out = Vec::new();
+    decls(&tc.symtable, &program.decls, &mut out);
+    for func in &program.funcs {
+        let locals = typecheck::locals(func, &tc.expr_table);
+        decls(&locals, &func.decls, &mut out);
+        stmts(tc, &locals, func.ret.as_ref(), &func.stmts, &mut out);
+    }
+    stmts(tc, &tc.symtable, None, &program.stmts, &mut out);
+    for_each_expr(program, &mut |expr| {
+        if let Expr_::Binop(ref expr_) = expr.expr {
+            let t1 = tc.expr_table.get(&expr_.expr1.node_id).cloned();
+            let t2 = tc.expr_table.get(&expr_.expr2.node_id).cloned();
+            if let (Some(t1), Some(t2)) = (t1, t2) {
+                if let Some(ty) = Type::widen(&t1, &t2) {
+                    if !ty.is_integer() {
+                        converted(&expr_.expr1, &ty, &mut out);
+                        converted(&expr_.expr2, &ty, &mut out);
+                    }
+                }
+            }
+        }
+    });
+    out.sort_by_key(|&(pos, _, _)| pos);
+    out
+}
+
+impl Pass for EliminatePass {
+    fn name(&self) -> &'static str { "eliminate" }
+
+    fn run(&mut self, ctx: &mut Context) -> Result<(), Error> {
+        let program = ctx.program.as_mut().expect("pass run before parsing");
+        let removed = eliminate::dead_globals(program, &mut ctx.symtable);
+        if ctx.show_eliminated {
+            for (pos, id) in removed {
+                ctx.note(pos, format!("removed unused variable '{}'", id));
+            }
+        }
+        Ok(())
+    }
+
+    // The variables that are still used must still be declared.
+    fn verify(&self, ctx: &Context) -> Result<(), Error> {
+        let program = ctx.program();
+        let mut used: Vec<String> = eliminate::used_variables(program).into_iter().collect();
+        used.sort();
+        for id in used {
+            if !ctx.symtable.contains_key(&id) || !program.decls.iter().any(|d| d.id == id) {
+                return Err(Error::PassVerification(
+                    self.name(), format!("used variable '{}' was removed", id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pass for CodegenPass {
+    fn name(&self) -> &'static str { "codegen" }
+
+    fn run(&mut self, ctx: &mut Context) -> Result<(), Error> {
+        let output = try!(self.backend.generate(ctx));
+        ctx.output = Some(output.code);
+        ctx.source_map = output.source_map;
+        Ok(())
+    }
+
+    fn verify(&self, ctx: &Context) -> Result<(), Error> {
+        let output = ctx.output.as_ref().expect("codegen verified before it ran");
+        self.backend.verify(output).map_err(|msg| Error::PassVerification(self.name(), msg))
+    }
+}
+
+fn for_each_expr<F: FnMut(&Expr)>(program: &Program, f: &mut F) {
+    fn stmts<F: FnMut(&Expr)>(stmts_: &[Stmt], f: &mut F) {
+        for stmt in stmts_ {
+            match *stmt {
+                Stmt::Read(_) | Stmt::Error(_) => {}
+                Stmt::Print(ref stmt_) => expr(&stmt_.expr, f),
+                Stmt::Exit(ref stmt_) => expr(&stmt_.expr, f),
+                // The call of a function without a value has no type;
+                // only its arguments are expressions.
+                Stmt::Call(ref stmt_) => {
+                    if let Expr_::Call(ref call) = stmt_.expr.expr {
+                        for arg in &call.args {
+                            expr(arg, f);
+                        }
+                    }
+                }
+                Stmt::Return(ref stmt_) => {
+                    if let Some(ref e) = stmt_.expr {
+                        expr(e, f);
+                    }
+                }
+                Stmt::Assign(ref stmt_) => expr(&stmt_.expr, f),
+                Stmt::Destructure(ref stmt_) => expr(&stmt_.expr, f),
+                Stmt::If(ref stmt_) => {
+                    expr(&stmt_.expr, f);
+                    stmts(&stmt_.then_stmts, f);
+                    stmts(&stmt_.else_stmts, f);
+                }
+                Stmt::While(ref stmt_) => {
+                    expr(&stmt_.expr, f);
+                    stmts(&stmt_.stmts, f);
+                }
+                Stmt::For(ref stmt_) => {
+                    expr(&stmt_.first, f);
+                    expr(&stmt_.last, f);
+                    stmts(&stmt_.stmts, f);
+                }
+            }
+        }
+    }
+
+    // The operators of a chain are visited in a loop, before their
+    // operands (see `Expr::left_spine`).
+    fn expr<F: FnMut(&Expr)>(e: &Expr, f: &mut F) {
+        let (spine, e) = e.left_spine();
+        for op in &spine {
+            f(op);
+        }
+        f(e);
+        match e.expr {
+            Expr_::Negate(ref expr_) => expr(&expr_.expr, f),
+            Expr_::ToString(ref expr_) => expr(&expr_.expr, f),
+            Expr_::Field(ref expr_) => expr(&expr_.expr, f),
+            Expr_::Tuple(ref expr_) => {
+                for e in &expr_.exprs {
+                    expr(e, f);
+                }
+            }
+            Expr_::Call(ref expr_) => {
+                for e in &expr_.args {
+                    expr(e, f);
+                }
+            }
+            Expr_::Not(ref expr_) => expr(&expr_.expr, f),
+            _ => {}
+        }
+        for op in spine.into_iter().rev() {
+            match op.expr {
+                Expr_::Binop(ref expr_) => expr(&expr_.expr2, f),
+                Expr_::Logic(ref expr_) => expr(&expr_.expr2, f),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn decls<F: FnMut(&Expr)>(decls_: &[Decl], f: &mut F) {
+        for decl in decls_ {
+            if let Some(ref init) = decl.init {
+                expr(init, f);
+            }
+        }
+    }
+
+    decls(&program.decls, f);
+    for func in &program.funcs {
+        decls(&func.decls, f);
+        stmts(&func.stmts, f);
+    }
+    stmts(&program.stmts, f);
+}
@@ -0,0 +1,133 @@
+// A width-aware layout engine, after Wadler's "A prettier printer".
+//
+// A `Doc` is text with the places where it may be broken into lines.
+// A `group` is laid out on one line if it fits in what is left of the
+// line, its `line`s printing as a space and its `softline`s as
+// nothing; otherwise each of them starts a new line, indented by the
+// `nest`s around it.  Groups are decided from the outside in, so the
+// groups inside a broken one can still be laid out flat.  A `hardline`
+// always starts a new line, and the groups around it are broken.
+//
+//     group(concat(vec![text("a"), nest(2, concat(vec![line(), text("+ b")]))]))
+//
+// renders as `a + b` in a width of 5 or more, and as `a` and `  + b` on
+// two lines in a smaller one.
+
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Text(String),
+    Line,
+    SoftLine,
+    HardLine,
+    Concat(Vec<Doc>),
+    Nest(usize, Box<Doc>),
+    Group(Box<Doc>),
+}
+
+pub fn text<S: Into<String>>(s: S) -> Doc {
+    Doc::Text(s.into())
+}
+
+pub fn line() -> Doc {
+    Doc::Line
+}
+
+pub fn softline() -> Doc {
+    Doc::SoftLine
+}
+
+pub fn hardline() -> Doc {
+    Doc::HardLine
+}
+
+pub fn concat(docs: Vec<Doc>) -> Doc {
+    Doc::Concat(docs)
+}
+
+pub fn nest(indent: usize, doc: Doc) -> Doc {
+    Doc::Nest(indent, Box::new(doc))
+}
+
+pub fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+// Lay out `doc` in lines of at most `width` characters where possible;
+// text that does not fit even broken overflows.  Lines have no
+// trailing spaces.
+pub fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack: Vec<(usize, Mode, &Doc)> = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match *doc {
+            Doc::Text(ref s) => {
+                out.push_str(s);
+                column = match s.rfind('\n') {
+                    Some(i) => s[i + 1..].chars().count(),
+                    None => column + s.chars().count(),
+                };
+            }
+            Doc::Line if mode == Mode::Flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                let trimmed = out.trim_end_matches(' ').len();
+                out.truncate(trimmed);
+                out.push('\n');
+                out.extend((0..indent).map(|_| ' '));
+                column = indent;
+            }
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc));
+                }
+            }
+            Doc::Nest(more, ref doc) => stack.push((indent + more, mode, doc)),
+            Doc::Group(ref doc) => {
+                let left = width as isize - column as isize;
+                let mode = if mode == Mode::Flat || fits(left, doc, &stack) { Mode::Flat } else { Mode::Break };
+                stack.push((indent, mode, doc));
+            }
+        }
+    }
+    out
+}
+
+// Whether `doc`, laid out flat, and what follows it up to the next line
+// break fit in `left` characters.
+fn fits(mut left: isize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack: Vec<(Mode, &Doc)> = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+    while left >= 0 {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match *doc {
+            Doc::Text(ref s) => left -= s.chars().count() as isize,
+            Doc::Line if mode == Mode::Flat => left -= 1,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine => return true,
+            Doc::HardLine => return mode == Mode::Break,
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((mode, doc));
+                }
+            }
+            Doc::Nest(_, ref doc) | Doc::Group(ref doc) => stack.push((mode, doc)),
+        }
+    }
+    false
+}
@@ -0,0 +1,370 @@
+// A tiny selector language for structural searches over the AST.
+//
+//     selector = compound { [">"] compound } .
+//     compound = ("*" | kind [":" name]) { "[" attr "=" value "]" } .
+//     kind     = "func" | "decl" | "stmt" | "expr" .
+//
+// Two compounds separated by whitespace match a node and one of its
+// descendants; separated by ">" they match a node and one of its
+// direct children.  For example, `stmt:while > expr:binop[op=div]`
+// finds the divisions that appear directly in a while condition, and
+// `func[id=fact] expr:call` the calls in the function `fact`.
+
+use ast::*;
+use error::Error;
+use pos::Pos;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug)]
+struct Compound {
+    kind: Option<String>,
+    name: Option<String>,
+    attrs: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub struct Selector {
+    // The combinator of the first step is never consulted.
+    steps: Vec<(Combinator, Compound)>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Node<'a> {
+    Func(&'a FuncDecl),
+    Decl(&'a Decl),
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+impl<'a> Node<'a> {
+    pub fn pos(&self) -> Pos {
+        match *self {
+            Node::Func(func) => func.pos,
+            Node::Decl(decl) => decl.pos,
+            Node::Stmt(stmt) => stmt.pos(),
+            Node::Expr(expr) => expr.pos,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Node::Func(_) => "func",
+            Node::Decl(_) => "decl",
+            Node::Stmt(_) => "stmt",
+            Node::Expr(_) => "expr",
+        }
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            Node::Func(_) | Node::Decl(_) => None,
+            Node::Stmt(stmt) => Some(match *stmt {
+                Stmt::Read(_) => "read",
+                Stmt::Print(_) => "print",
+                Stmt::Exit(_) => "exit",
+                Stmt::Assign(_) => "assign",
+                Stmt::Destructure(_) => "destructure",
+                Stmt::Call(_) => "call",
+                Stmt::Return(_) => "return",
+                Stmt::If(_) => "if",
+                Stmt::While(_) => "while",
+                Stmt::For(_) => "for",
+                Stmt::Error(_) => "error",
+            }),
+            Node::Expr(expr) => Some(match expr.expr {
+                Expr_::Id(_) => "id",
+                Expr_::Int(_) => "int",
+                Expr_::Float(_) => "float",
+                Expr_::String(_) => "string",
+                Expr_::Bool(_) => "bool",
+                Expr_::Negate(_) => "negate",
+                Expr_::ToString(_) => "tostring",
+                Expr_::Binop(_) => "binop",
+                Expr_::Logic(_) => "logic",
+                Expr_::Not(_) => "not",
+                Expr_::Field(_) => "field",
+                Expr_::Tuple(_) => "tuple",
+                Expr_::Call(_) => "call",
+                Expr_::Error => "error",
+            }),
+        }
+    }
+
+    pub fn attr(&self, attr: &str) -> Option<String> {
+        match (*self, attr) {
+            (Node::Func(func), "id") => Some(func.id.clone()),
+            (Node::Func(func), "type") => func.ret.as_ref().map(|ty| ty.to_string()),
+            (Node::Decl(decl), "id") => Some(decl.id.clone()),
+            (Node::Decl(decl), "type") => decl.ty.as_ref().map(|ty| ty.to_string()),
+            (Node::Stmt(&Stmt::Read(ref stmt_)), "id") => Some(stmt_.ids.join(",")),
+            (Node::Stmt(&Stmt::Assign(ref stmt_)), "id") => Some(stmt_.id.clone()),
+            (Node::Stmt(&Stmt::For(ref stmt_)), "id") => Some(stmt_.id.clone()),
+            (Node::Expr(expr), _) => {
+                match (&expr.expr, attr) {
+                    (&Expr_::Id(ref expr_), "id") => Some(expr_.id.clone()),
+                    (&Expr_::Int(ref expr_), "value") => Some(expr_.value.to_string()),
+                    (&Expr_::Float(ref expr_), "value") => Some(expr_.value.to_string()),
+                    (&Expr_::String(ref expr_), "value") => Some(expr_.value.clone()),
+                    (&Expr_::Bool(ref expr_), "value") => Some(expr_.value.to_string()),
+                    (&Expr_::Binop(ref expr_), "op") => Some(binop_name(expr_.op).to_string()),
+                    (&Expr_::Logic(ref expr_), "op") => Some(expr_.op.to_string()),
+                    (&Expr_::Field(ref expr_), "field") => Some(expr_.field.clone()),
+                    (&Expr_::Call(ref expr_), "id") => Some(expr_.id.clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // A short description of the node, in selector syntax.
+    pub fn describe(&self) -> String {
+        let mut s = self.kind().to_string();
+        if let Some(name) = self.name() {
+            s.push(':');
+            s.push_str(name);
+        }
+        for attr in &["id", "op", "field"] {
+            if let Some(value) = self.attr(attr) {
+                s.push_str(&format!("[{}={}]", attr, value));
+            }
+        }
+        s
+    }
+
+    pub fn children(&self) -> Vec<Node<'a>> {
+        match *self {
+            Node::Func(func) => {
+                let mut v: Vec<Node<'a>> = func.decls.iter().map(Node::Decl).collect();
+                v.extend(func.stmts.iter().map(Node::Stmt));
+                v
+            }
+            Node::Decl(decl) => decl.init.iter().map(Node::Expr).collect(),
+            Node::Stmt(stmt) => {
+                match *stmt {
+                    Stmt::Read(_) | Stmt::Error(_) => vec![],
+                    Stmt::Print(ref stmt_) => vec![Node::Expr(&stmt_.expr)],
+                    Stmt::Exit(ref stmt_) => vec![Node::Expr(&stmt_.expr)],
+                    Stmt::Assign(ref stmt_) => vec![Node::Expr(&stmt_.expr)],
+                    Stmt::Destructure(ref stmt_) => vec![Node::Expr(&stmt_.expr)],
+                    Stmt::Call(ref stmt_) => vec![Node::Expr(&stmt_.expr)],
+                    Stmt::Return(ref stmt_) => stmt_.expr.iter().map(Node::Expr).collect(),
+                    Stmt::If(ref stmt_) => {
+                        let mut v = vec![Node::Expr(&stmt_.expr)];
+                        v.extend(stmt_.then_stmts.iter().map(Node::Stmt));
+                        v.extend(stmt_.else_stmts.iter().map(Node::Stmt));
+                        v
+                    }
+                    Stmt::While(ref stmt_) => {
+                        let mut v = vec![Node::Expr(&stmt_.expr)];
+                        v.extend(stmt_.stmts.iter().map(Node::Stmt));
+                        v
+                    }
+                    Stmt::For(ref stmt_) => {
+                        let mut v = vec![Node::Expr(&stmt_.first), Node::Expr(&stmt_.last)];
+                        v.extend(stmt_.stmts.iter().map(Node::Stmt));
+                        v
+                    }
+                }
+            }
+            Node::Expr(expr) => {
+                match expr.expr {
+                    Expr_::Negate(ref expr_) => vec![Node::Expr(&expr_.expr)],
+                    Expr_::ToString(ref expr_) => vec![Node::Expr(&expr_.expr)],
+                    Expr_::Field(ref expr_) => vec![Node::Expr(&expr_.expr)],
+                    Expr_::Tuple(ref expr_) => expr_.exprs.iter().map(Node::Expr).collect(),
+                    Expr_::Call(ref expr_) => expr_.args.iter().map(Node::Expr).collect(),
+                    Expr_::Binop(ref expr_) => {
+                        vec![Node::Expr(&expr_.expr1), Node::Expr(&expr_.expr2)]
+                    }
+                    Expr_::Logic(ref expr_) => {
+                        vec![Node::Expr(&expr_.expr1), Node::Expr(&expr_.expr2)]
+                    }
+                    Expr_::Not(ref expr_) => vec![Node::Expr(&expr_.expr)],
+                    _ => vec![],
+                }
+            }
+        }
+    }
+}
+
+fn binop_name(op: Binop) -> &'static str {
+    match op {
+        Binop::Add => "add",
+        Binop::Sub => "sub",
+        Binop::Mul => "mul",
+        Binop::Div => "div",
+        Binop::Eq => "eq",
+        Binop::Ne => "ne",
+        Binop::Lt => "lt",
+        Binop::Le => "le",
+        Binop::Gt => "gt",
+        Binop::Ge => "ge",
+    }
+}
+
+impl Selector {
+    pub fn parse(src: &str) -> Result<Selector, Error> {
+        let chars: Vec<char> = src.chars().collect();
+        let mut i = 0;
+        let mut steps = Vec::new();
+        let mut comb = Combinator::Descendant;
+
+        loop {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if i == chars.len() {
+                break;
+            }
+            if chars[i] == '>' {
+                if steps.is_empty() || comb == Combinator::Child {
+                    return Err(invalid(src, "unexpected '>'"));
+                }
+                comb = Combinator::Child;
+                i += 1;
+                continue;
+            }
+            let compound = try!(parse_compound(src, &chars, &mut i));
+            steps.push((comb, compound));
+            comb = Combinator::Descendant;
+        }
+
+        if steps.is_empty() {
+            return Err(invalid(src, "empty selector"));
+        }
+        if comb == Combinator::Child {
+            return Err(invalid(src, "expected a selector after '>'"));
+        }
+        Ok(Selector { steps: steps })
+    }
+
+    // Return every node of the program matched by the selector, in
+    // source order.
+    pub fn select<'a>(&self, program: &'a Program) -> Vec<Node<'a>> {
+        let mut matches = Vec::new();
+        let mut path = Vec::new();
+        for decl in &program.decls {
+            self.select_node(Node::Decl(decl), &mut path, &mut matches);
+        }
+        for func in &program.funcs {
+            self.select_node(Node::Func(func), &mut path, &mut matches);
+        }
+        for stmt in &program.stmts {
+            self.select_node(Node::Stmt(stmt), &mut path, &mut matches);
+        }
+        matches
+    }
+
+    fn select_node<'a>(&self, node: Node<'a>, path: &mut Vec<Node<'a>>, matches: &mut Vec<Node<'a>>) {
+        path.push(node);
+        if self.matches(self.steps.len(), path) {
+            matches.push(node);
+        }
+        for child in node.children() {
+            self.select_node(child, path, matches);
+        }
+        path.pop();
+    }
+
+    // Does steps[..n] match with its last step on the last node of `path`?
+    fn matches(&self, n: usize, path: &[Node]) -> bool {
+        let (comb, ref compound) = self.steps[n - 1];
+        let (node, ancestors) = match path.split_last() {
+            Some((node, ancestors)) => (node, ancestors),
+            None => return false,
+        };
+        if !compound.matches(node) {
+            return false;
+        }
+        if n == 1 {
+            return true;
+        }
+        match comb {
+            Combinator::Child => self.matches(n - 1, ancestors),
+            Combinator::Descendant => {
+                (1..ancestors.len() + 1).any(|k| self.matches(n - 1, &ancestors[..k]))
+            }
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, node: &Node) -> bool {
+        if let Some(ref kind) = self.kind {
+            if kind != node.kind() {
+                return false;
+            }
+        }
+        if let Some(ref name) = self.name {
+            if node.name() != Some(&name[..]) {
+                return false;
+            }
+        }
+        self.attrs.iter().all(|&(ref attr, ref value)| {
+            node.attr(attr).as_ref() == Some(value)
+        })
+    }
+}
+
+fn parse_compound(src: &str, chars: &[char], i: &mut usize) -> Result<Compound, Error> {
+    let mut compound = Compound { kind: None, name: None, attrs: Vec::new() };
+
+    if chars[*i] == '*' {
+        *i += 1;
+    } else {
+        let kind = parse_word(chars, i);
+        match &*kind {
+            "func" | "decl" | "stmt" | "expr" => { compound.kind = Some(kind); }
+            "" => { return Err(invalid(src, &format!("unexpected '{}'", chars[*i]))); }
+            _ => { return Err(invalid(src, &format!("unknown node kind '{}'", kind))); }
+        }
+        if *i < chars.len() && chars[*i] == ':' {
+            *i += 1;
+            let name = parse_word(chars, i);
+            if name.is_empty() {
+                return Err(invalid(src, "expected a node name after ':'"));
+            }
+            compound.name = Some(name);
+        }
+    }
+
+    while *i < chars.len() && chars[*i] == '[' {
+        *i += 1;
+        let attr = parse_word(chars, i);
+        if attr.is_empty() || *i == chars.len() || chars[*i] != '=' {
+            return Err(invalid(src, "expected '[attr=value]'"));
+        }
+        *i += 1;
+        let mut value = String::new();
+        while *i < chars.len() && chars[*i] != ']' {
+            value.push(chars[*i]);
+            *i += 1;
+        }
+        if *i == chars.len() {
+            return Err(invalid(src, "unterminated '['"));
+        }
+        *i += 1;
+        compound.attrs.push((attr, value));
+    }
+
+    Ok(compound)
+}
+
+fn parse_word(chars: &[char], i: &mut usize) -> String {
+    let mut word = String::new();
+    while *i < chars.len() && (chars[*i].is_alphanumeric() || chars[*i] == '_') {
+        word.push(chars[*i]);
+        *i += 1;
+    }
+    word
+}
+
+fn invalid(src: &str, msg: &str) -> Error {
+    Error::InvalidSelector(src.to_string(), msg.to_string())
+}
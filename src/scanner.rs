@@ -5,20 +5,96 @@ use token::{Token, TokenType};
 use std::str::Chars;
 use std::iter::Peekable;
 
+// The longest numeric literal, suffix included.  No int fits in more
+// than 20 characters, and digits past the 17th do not change a float.
+pub const MAX_LITERAL_LENGTH: usize = 64;
+
+// Which characters identifiers are made of.  The scanner asks its
+// classifier about every character that is not otherwise part of the
+// syntax, so that the variants of the language given to different
+// classes can be scanned without changing the scanner.
+pub trait CharClassifier {
+    fn is_id_start(&self, c: char) -> bool;
+    fn is_id_char(&self, c: char) -> bool;
+}
+
+// The usual identifier variants: by default, ASCII letters, digits and
+// underscores, not starting with a digit.  `unicode` permits the
+// letters and digits of every alphabet, and `dollar` a leading `$`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IdentifierRules {
+    pub unicode: bool,
+    pub dollar: bool,
+}
+
+impl IdentifierRules {
+    // Parse a comma-separated list of `ascii`, `unicode` and `dollar`.
+    pub fn from_names(names: &str) -> Option<IdentifierRules> {
+        let mut rules = IdentifierRules::default();
+        for name in names.split(',') {
+            match name.trim() {
+                "ascii" => {}
+                "unicode" => rules.unicode = true,
+                "dollar" => rules.dollar = true,
+                _ => return None,
+            }
+        }
+        Some(rules)
+    }
+}
+
+impl CharClassifier for IdentifierRules {
+    fn is_id_start(&self, c: char) -> bool {
+        (c >= 'a' && c <= 'z') ||
+        c == '_' ||
+        (c >= 'A' && c <= 'Z') ||
+        (self.dollar && c == '$') ||
+        (self.unicode && c.is_alphabetic())
+    }
+
+    fn is_id_char(&self, c: char) -> bool {
+        (c >= 'a' && c <= 'z') ||
+        c == '_' ||
+        c.is_digit(10) ||
+        (c >= 'A' && c <= 'Z') ||
+        (self.unicode && c.is_alphanumeric())
+    }
+}
+
+static ASCII_IDENTIFIERS: IdentifierRules = IdentifierRules { unicode: false, dollar: false };
+
 pub struct Scanner<'a> {
+    source: &'a str,
+    classifier: &'a dyn CharClassifier,
     data: Peekable<Chars<'a>>,
+    // The byte offset of the current character in `source`.
+    offset: usize,
     start_pos: Pos,
     curr_pos: Pos,
+    // The number of `${` of interpolated strings not yet closed by a
+    // `}`.  Expressions contain no braces, so a `}` seen while it is
+    // positive always resumes the innermost string.
+    interpolations: usize,
 }
 
 impl<'a> Scanner<'a> {
     // Create a new scanner from a given program represented as a
     // String.
     pub fn new<'b>(data: &'b str) -> Scanner<'b> {
+        Scanner::with_classifier(data, &ASCII_IDENTIFIERS)
+    }
+
+    // Create a scanner whose identifiers are made of the characters
+    // accepted by `classifier`.
+    pub fn with_classifier<'b>(data: &'b str, classifier: &'b dyn CharClassifier) -> Scanner<'b> {
         Scanner {
+            source: data,
+            classifier: classifier,
             data: data.chars().peekable(),
+            offset: 0,
             start_pos: Pos { line: 1, col: 1 },
             curr_pos: Pos { line: 1, col: 1 },
+            interpolations: 0,
         }
     }
 
@@ -27,6 +103,11 @@ impl<'a> Scanner<'a> {
         self.data.peek().map(|&c|c).unwrap_or('\x00')
     }
 
+    // Internal function: return the character after the current one.
+    fn peek_next(&self) -> char {
+        self.source[self.offset..].chars().nth(1).unwrap_or('\x00')
+    }
+
     // Internal function: return the character at the current index
     // and increment the index by one.
     fn advance(&mut self) -> char {
@@ -37,7 +118,9 @@ impl<'a> Scanner<'a> {
         } else {
             self.curr_pos.col += 1
         }
-        let _ = self.data.next();
+        if let Some(c) = self.data.next() {
+            self.offset += c.len_utf8();
+        }
         c
     }
 
@@ -68,82 +151,145 @@ impl<'a> Scanner<'a> {
             '-' => { Ok(self.single_char_tok(TokenType::Minus)) }
             '*' => { Ok(self.single_char_tok(TokenType::Star)) }
             '/' => { Ok(self.single_char_tok(TokenType::Slash)) }
-            '=' => { Ok(self.single_char_tok(TokenType::Equal)) }
+            '=' => { Ok(self.one_or_two_char_tok('=', TokenType::Equal, TokenType::EqualEqual)) }
+            '<' => { Ok(self.one_or_two_char_tok('=', TokenType::Less, TokenType::LessEqual)) }
+            '>' => { Ok(self.one_or_two_char_tok('=', TokenType::Greater, TokenType::GreaterEqual)) }
+            '!' => {
+                self.advance();
+                if self.peek() == '=' {
+                    self.advance();
+                    Ok(self.empty_tok(TokenType::NotEqual))
+                } else {
+                    Err(Error::IllegalCharacter(self.start_pos, '!'))
+                }
+            }
             '(' => { Ok(self.single_char_tok(TokenType::LParen)) }
             ')' => { Ok(self.single_char_tok(TokenType::RParen)) }
+            '{' => { Ok(self.single_char_tok(TokenType::LBrace)) }
+            '}' if self.interpolations > 0 => {
+                self.interpolations -= 1;
+                self.scan_string_part(TokenType::InterpMid, TokenType::InterpEnd)
+            }
+            '}' => { Ok(self.single_char_tok(TokenType::RBrace)) }
             ':' => { Ok(self.single_char_tok(TokenType::Colon)) }
             ';' => { Ok(self.single_char_tok(TokenType::Semicolon)) }
-            '"' => { self.scan_string_lit() }
+            ',' => { Ok(self.single_char_tok(TokenType::Comma)) }
+            '.' if self.peek_next().is_digit(10) => { self.scan_int_or_float() }
+            '.' => { Ok(self.single_char_tok(TokenType::Dot)) }
+            '"' => { self.scan_string_part(TokenType::InterpStart, TokenType::String) }
             c if c.is_digit(10) => { self.scan_int_or_float() }
-            c if is_id_start(c) => { self.scan_id_or_keyword() }
+            c if self.classifier.is_id_start(c) => { self.scan_id_or_keyword() }
             c   => { Err(Error::IllegalCharacter(self.curr_pos, c)) }
         }
     }
 
-    // Scan digits into an Int or Float token.
+    // Scan digits into an Int or Float token.  The lexeme includes
+    // the type suffix, if any: 1i32, 1i64, 1.5f32, 1f64.  Either side
+    // of the decimal point may be empty, as in `1.` and `.5`.
     fn scan_int_or_float(&mut self) -> Result<Token, Error> {
-        let mut val = String::new();
+        // The literal is sliced from the source once it is complete, so
+        // that an overlong one costs nothing before it is rejected.
+        let start = self.offset;
         while self.peek().is_digit(10) {
-            val.push(self.advance());
+            self.advance();
         }
 
-        if self.peek() != '.' {
-            return Ok(self.lexeme_tok(TokenType::Int, val));
+        let mut token_type = TokenType::Int;
+        if self.peek() == '.' {
+            token_type = TokenType::Float;
+            self.advance(); // Skip the decimal point.
+
+            while self.peek().is_digit(10) {
+                self.advance();
+            }
         }
 
-        val.push(self.advance()); // Add decimal point.
+        // `1.2.3` is one bad literal, not `1.2` followed by `.3`.
+        let malformed = token_type == TokenType::Float && self.peek() == '.';
+        if malformed {
+            while self.peek() == '.' || self.peek().is_digit(10) {
+                self.advance();
+            }
+        }
 
-        while self.peek().is_digit(10) {
-            val.push(self.advance());
+        let suffix_start = self.offset;
+        if self.peek() == 'i' || self.peek() == 'f' {
+            while self.classifier.is_id_char(self.peek()) {
+                self.advance();
+            }
+        }
+
+        let source = self.source;
+        let val = &source[start..self.offset];
+        if val.len() > MAX_LITERAL_LENGTH {
+            return Err(Error::LiteralTooLong(self.start_pos, self.curr_pos, val.len()));
+        }
+        if malformed {
+            return Err(Error::InvalidFloatLiteral(self.start_pos, val.to_string()));
+        }
+        match (token_type, &source[suffix_start..self.offset]) {
+            (_, "") => {}
+            (TokenType::Int, "i32") | (TokenType::Int, "i64") => {}
+            (_, "f32") | (_, "f64") => token_type = TokenType::Float,
+            (TokenType::Int, _) => return Err(Error::InvalidIntLiteral(self.start_pos, val.to_string())),
+            _ => return Err(Error::InvalidFloatLiteral(self.start_pos, val.to_string())),
         }
 
-        Ok(self.lexeme_tok(TokenType::Float, val))
+        Ok(self.lexeme_tok(token_type, val.to_string()))
     }
 
-    fn scan_string_lit(&mut self) -> Result<Token, Error> {
+    // Scan the text after a double quote or after the `}` closing an
+    // interpolation, up to the closing double quote (a `closed` token)
+    // or the next `${` (an `open` token).  The lexeme is the text with
+    // its escape sequences replaced by the characters they stand for.
+    fn scan_string_part(&mut self, open: TokenType, closed: TokenType) -> Result<Token, Error> {
         self.advance();
         let mut strbuf = String::new();
         while !self.is_eof() && self.peek() != '"' {
-            strbuf.push(self.peek());
-            self.advance();
+            let pos = self.curr_pos;
+            let c = self.advance();
+            if c == '$' && self.peek() == '{' {
+                self.advance();
+                self.interpolations += 1;
+                return Ok(self.lexeme_tok(open, strbuf));
+            }
+            if c == '\\' && !self.is_eof() {
+                let escaped = match self.advance() {
+                    'n' => '\n',
+                    't' => '\t',
+                    '0' => '\0',
+                    '\\' => '\\',
+                    '"' => '"',
+                    other => return Err(Error::InvalidEscape(pos, other)),
+                };
+                strbuf.push(escaped);
+                continue;
+            }
+            strbuf.push(c);
         }
 
         if self.is_eof() {
             Err(Error::UnterminatedString(self.start_pos))
         } else {
             self.advance(); // skip over the closing double quote
-            Ok(self.lexeme_tok(TokenType::String, strbuf))
+            Ok(self.lexeme_tok(closed, strbuf))
         }
     }
 
     // Scan alpha-numeric characters into an Id or a keyword token.
+    // Keywords are recognized on the source text, so that only
+    // identifiers allocate their lexeme.
     fn scan_id_or_keyword(&mut self) -> Result<Token, Error> {
-        let mut lexeme = String::new();
-        while is_id_char(self.peek()) {
-            lexeme.push(self.advance());
+        let start = self.offset;
+        self.advance(); // The start, which need not be an id char, like `$`.
+        while self.classifier.is_id_char(self.peek()) {
+            self.advance();
         }
 
-        let token_type = match &*lexeme {
-            "if" => TokenType::If,
-            "then" => TokenType::Then,
-            "else" => TokenType::Else,
-            "endif" => TokenType::EndIf,
-            "while" => TokenType::While,
-            "do" => TokenType::Do,
-            "done" => TokenType::Done,
-            "read" => TokenType::Read,
-            "print" => TokenType::Print,
-            "var" => TokenType::Var,
-            "int" => TokenType::TypeInt,
-            "float" => TokenType::TypeFloat,
-            "string" => TokenType::TypeString,
-            _ => TokenType::Id,
-        };
-
-        let token = if token_type == TokenType::Id {
-            self.lexeme_tok(token_type, lexeme)
-        } else {
-            self.empty_tok(token_type)
+        let word = &self.source[start..self.offset];
+        let token = match keyword(word) {
+            TokenType::Id => self.lexeme_tok(TokenType::Id, word.to_string()),
+            token_type => self.empty_tok(token_type),
         };
 
         Ok(token)
@@ -167,8 +313,9 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    // A comment ends at the end of its line or of the program.
     fn skip_comment(&mut self) {
-        while self.peek() != '\n' {
+        while !self.is_eof() && self.peek() != '\n' {
             self.advance();
         }
     }
@@ -178,6 +325,7 @@ impl<'a> Scanner<'a> {
             typ: t,
             lexeme: None,
             pos: self.start_pos,
+            end: self.curr_pos,
         }
     }
 
@@ -186,26 +334,122 @@ impl<'a> Scanner<'a> {
             typ: t,
             lexeme: Some(lexeme),
             pos: self.start_pos,
+            end: self.curr_pos,
         }
     }
 
     fn single_char_tok(&mut self, t: TokenType) -> Token {
-        let t = self.empty_tok(t);
         self.advance();
-        t
+        self.empty_tok(t)
+    }
+
+    // Scan `one`, or `two` if the character is followed by `second`.
+    fn one_or_two_char_tok(&mut self, second: char, one: TokenType, two: TokenType) -> Token {
+        self.advance();
+        if self.peek() == second {
+            self.advance();
+            self.empty_tok(two)
+        } else {
+            self.empty_tok(one)
+        }
     }
 }
 
-fn is_id_start(c: char) -> bool {
-    (c >= 'a' && c <= 'z') ||
-    c == '_' ||
-    (c >= 'A' && c <= 'Z')
+// Scan a whole program, up to and including the Eof token.
+pub fn tokenize(data: &str) -> Result<Vec<Token>, Error> {
+    tokenize_with(data, &ASCII_IDENTIFIERS)
 }
 
+// Scan a whole program with the given identifier rules.
+pub fn tokenize_with(data: &str, classifier: &dyn CharClassifier) -> Result<Vec<Token>, Error> {
+    let mut scanner = Scanner::with_classifier(data, classifier);
+    let mut tokens = Vec::new();
+    loop {
+        let tok = try!(scanner.next_token());
+        let is_eof = tok.typ == TokenType::Eof;
+        tokens.push(tok);
+        if is_eof {
+            break;
+        }
+    }
+    Ok(tokens)
+}
+
+// `s` as a string literal that scans back to it, with the characters
+// that have an escape sequence escaped.
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
 
-fn is_id_char(c: char) -> bool {
-    (c >= 'a' && c <= 'z') ||
-    c == '_' ||
-    c.is_digit(10) ||
-    (c >= 'A' && c <= 'Z')
+// The keyword spelled `word`, or Id.  Words are first sorted by length,
+// so that an identifier is compared with a few keywords at most.
+fn keyword(word: &str) -> TokenType {
+    match word.len() {
+        2 => match word {
+            "if" => TokenType::If,
+            "do" => TokenType::Do,
+            "or" => TokenType::Or,
+            "to" => TokenType::To,
+            _ => TokenType::Id,
+        },
+        3 => match word {
+            "var" => TokenType::Var,
+            "for" => TokenType::For,
+            "int" => TokenType::TypeInt,
+            "and" => TokenType::And,
+            "not" => TokenType::Not,
+            "end" => TokenType::End,
+            _ => TokenType::Id,
+        },
+        4 => match word {
+            "then" => TokenType::Then,
+            "else" => TokenType::Else,
+            "done" => TokenType::Done,
+            "read" => TokenType::Read,
+            "exit" => TokenType::Exit,
+            "bool" => TokenType::TypeBool,
+            "true" => TokenType::True,
+            "void" => TokenType::Void,
+            _ => TokenType::Id,
+        },
+        5 => match word {
+            "endif" => TokenType::EndIf,
+            "while" => TokenType::While,
+            "print" => TokenType::Print,
+            "float" => TokenType::TypeFloat,
+            "int32" => TokenType::TypeInt32,
+            "int64" => TokenType::TypeInt64,
+            "false" => TokenType::False,
+            _ => TokenType::Id,
+        },
+        6 => match word {
+            "prompt" => TokenType::Prompt,
+            "string" => TokenType::TypeString,
+            "record" => TokenType::Record,
+            "return" => TokenType::Return,
+            _ => TokenType::Id,
+        },
+        7 => match word {
+            "float32" => TokenType::TypeFloat32,
+            "float64" => TokenType::TypeFloat64,
+            _ => TokenType::Id,
+        },
+        8 => match word {
+            "function" => TokenType::Function,
+            _ => TokenType::Id,
+        },
+        _ => TokenType::Id,
+    }
 }
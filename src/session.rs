@@ -0,0 +1,79 @@
+// Recorded input of interactive runs.
+//
+// `run --record FILE` saves every word that `read` statements consume,
+// with the time it was read (in milliseconds since the start of the
+// run), and `run --replay FILE` feeds the same words to the program
+// instead of reading stdin.  Since the input is the only thing that
+// varies between runs of the interpreter, a replay behaves exactly like
+// the recorded run, which helps reproduce a grading discrepancy or an
+// interpreter bug from a student's session.  The times are only kept
+// for the reader of the file: a replay does not wait.
+//
+//     {
+//       "program": "guess.min",
+//       "inputs": [
+//         {"ms": 1520, "word": "50"},
+//         {"ms": 3047, "word": "25"}
+//       ]
+//     }
+
+use error::Error;
+use json;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    pub millis: u64,
+    pub word: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    // The path of the program that was run.
+    pub program: String,
+    pub inputs: Vec<Input>,
+}
+
+impl Session {
+    pub fn to_json(&self) -> String {
+        let inputs: Vec<String> = self.inputs.iter()
+            .map(|input| format!("    {{\"ms\": {}, \"word\": {}}}", input.millis, json::quote(&input.word)))
+            .collect();
+        let mut out = String::new();
+        out.push_str("{\n");
+        out.push_str(&format!("  \"program\": {},\n", json::quote(&self.program)));
+        if inputs.is_empty() {
+            out.push_str("  \"inputs\": []\n");
+        } else {
+            out.push_str(&format!("  \"inputs\": [\n{}\n  ]\n", inputs.join(",\n")));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn from_json(text: &str) -> Result<Session, Error> {
+        let value = try!(json::parse(text));
+        let program = match value.get("program") {
+            Some(&json::Value::String(ref program)) => program.clone(),
+            _ => return Err(invalid("expected a string 'program'")),
+        };
+        let items = match value.get("inputs") {
+            Some(&json::Value::Array(ref items)) => items,
+            _ => return Err(invalid("expected an array 'inputs'")),
+        };
+        let mut inputs = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            match (item.get("ms"), item.get("word")) {
+                (Some(&json::Value::Number(ms)), Some(&json::Value::String(ref word)))
+                    if ms >= 0.0 && !word.is_empty() && !word.contains(char::is_whitespace) => {
+                    inputs.push(Input { millis: ms as u64, word: word.clone() });
+                }
+                _ => return Err(invalid(&format!("input {} must have an 'ms' time and a 'word' without spaces", i + 1))),
+            }
+        }
+        Ok(Session { program: program, inputs: inputs })
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidSession(msg.to_string())
+}
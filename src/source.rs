@@ -0,0 +1,141 @@
+// The source files of a compilation.
+//
+// A `SourceManager` reads source files through a `FileLoader` and gives
+// each one a `FileId`, by which diagnostics (`Diagnostic::file`) and
+// errors refer to it.  `Disk` reads the real filesystem; `Memory` holds
+// the files in a map, so that library users, an editor integration or
+// a test can compile sources that were never saved.  Text that does
+// not come from a file, such as stdin, is added under a name with
+// `add`.  Sources are read with `decode`, which skips a byte order mark
+// and rejects text that is not UTF-8 with the position of the first
+// invalid byte.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+
+use diagnostic::Diagnostic;
+use error::Error;
+use pos::Pos;
+
+const BOM: char = '\u{feff}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub usize);
+
+pub trait FileLoader {
+    fn load(&self, path: &str) -> Result<String, Error>;
+}
+
+pub struct Disk;
+
+impl FileLoader for Disk {
+    fn load(&self, path: &str) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        let mut file = try!(File::open(path).map_err(|e| Error::Io(path.to_string(), e)));
+        try!(file.read_to_end(&mut buf).map_err(|e| Error::Io(path.to_string(), e)));
+        decode(buf).map_err(|e| Error::InFile(path.to_string(), Box::new(e)))
+    }
+}
+
+// The text of a source, without the UTF-8 byte order mark that some
+// editors write at its start.  Invalid UTF-8 is an `InvalidEncoding`
+// error at the line and column of the first invalid byte, rather than
+// text that the scanner would reject far from the cause, or an empty
+// program.
+pub fn decode(bytes: Vec<u8>) -> Result<String, Error> {
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(strip_bom(&text).to_string()),
+        Err(err) => {
+            let valid = &err.as_bytes()[..err.utf8_error().valid_up_to()];
+            let valid = strip_bom(::std::str::from_utf8(valid).unwrap());
+            let line = valid.matches('\n').count() + 1;
+            let col = valid.rsplit('\n').next().unwrap().chars().count() + 1;
+            Err(Error::InvalidEncoding(Pos { line: line, col: col }))
+        }
+    }
+}
+
+pub fn strip_bom(text: &str) -> &str {
+    if text.starts_with(BOM) { &text[BOM.len_utf8()..] } else { text }
+}
+
+#[derive(Default)]
+pub struct Memory {
+    files: HashMap<String, String>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory { files: HashMap::new() }
+    }
+
+    pub fn insert<P: Into<String>, T: Into<String>>(&mut self, path: P, text: T) {
+        self.files.insert(path.into(), text.into());
+    }
+}
+
+impl FileLoader for Memory {
+    fn load(&self, path: &str) -> Result<String, Error> {
+        match self.files.get(path) {
+            Some(text) => Ok(text.clone()),
+            None => Err(Error::Io(path.to_string(), io::Error::new(io::ErrorKind::NotFound, "no such file"))),
+        }
+    }
+}
+
+pub struct SourceManager {
+    loader: Box<dyn FileLoader>,
+    // The name and text of each file, by id.
+    files: Vec<(String, String)>,
+}
+
+impl SourceManager {
+    pub fn new(loader: Box<dyn FileLoader>) -> Self {
+        SourceManager { loader: loader, files: Vec::new() }
+    }
+
+    // The id of the file at `path`, which is read the first time only.
+    pub fn load(&mut self, path: &str) -> Result<FileId, Error> {
+        if let Some(i) = self.files.iter().position(|&(ref name, _)| name == path) {
+            return Ok(FileId(i));
+        }
+        let text = try!(self.loader.load(path));
+        Ok(self.add(path, text))
+    }
+
+    // Add a source that is not read through the loader, such as stdin.
+    pub fn add<N: Into<String>, T: Into<String>>(&mut self, name: N, text: T) -> FileId {
+        let text = text.into();
+        self.files.push((name.into(), strip_bom(&text).to_string()));
+        FileId(self.files.len() - 1)
+    }
+
+    // Replace the text of a file, e.g. after an edit in an editor.
+    pub fn set_text<T: Into<String>>(&mut self, file: FileId, text: T) {
+        let text = text.into();
+        self.files[file.0].1 = strip_bom(&text).to_string();
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        &self.files[file.0].0
+    }
+
+    pub fn text(&self, file: FileId) -> &str {
+        &self.files[file.0].1
+    }
+
+    // `err`, as having occurred in `file`.
+    pub fn in_file(&self, file: FileId, err: Error) -> Error {
+        Error::InFile(self.name(file).to_string(), Box::new(err))
+    }
+
+    // The diagnostic as printed, preceded by the name of its file if it
+    // has one.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        match diagnostic.file {
+            Some(file) => format!("{}:{}", self.name(file), diagnostic),
+            None => diagnostic.to_string(),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Source maps: which statement of the Minilang program each line of
+// generated code comes from.
+
+use json;
+use pos::Pos;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mapping {
+    // Line of the generated code, starting at 1.
+    pub line: usize,
+    // Node id of the declaration or statement that produced the line.
+    pub node_id: u64,
+    // Position of that declaration or statement in the source.
+    pub pos: Pos,
+}
+
+pub fn to_json(mappings: &[Mapping], source: &str, generated: &str) -> String {
+    let mut out = String::new();
+    out.push_str("{\n");
+    out.push_str("  \"version\": 1,\n");
+    out.push_str(&format!("  \"source\": {},\n", json::quote(source)));
+    out.push_str(&format!("  \"generated\": {},\n", json::quote(generated)));
+    out.push_str("  \"mappings\": [");
+    for (i, m) in mappings.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!("    {{\"line\": {}, \"node_id\": {}, \"source_line\": {}, \"source_col\": {}}}",
+                              m.line, m.node_id, m.pos.line, m.pos.col));
+    }
+    out.push_str("\n  ]\n}\n");
+    out
+}
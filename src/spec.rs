@@ -0,0 +1,100 @@
+// Input/output contracts of programs.
+//
+// Comments in a program can say what it must print for a given input:
+//
+//     # input: 5
+//     # expect-output: 120
+//
+// Each `input:` is a line of input and each `expect-output:` a line of
+// the expected output, in order; `expect-status:` gives the exit status
+// (0 by default).  `case: NAME` starts another case, so that a program
+// can be checked on several inputs; the directives before the first
+// one form a case of their own.  The same directives, without `#`, can
+// be kept in a sidecar file NAME.spec next to NAME.min, where the other
+// lines must be blank or comments.  `run --check-spec FILE` runs every
+// case of the program and of its sidecar, like `run --batch` runs the
+// test cases of a directory, which turns each example program into a
+// test without a script around it.
+
+use std::path::Path;
+
+use conformance;
+use cst::{self, TriviaKind};
+use error::Error;
+use grade::Fixture;
+use scanner;
+
+const KEYS: [&'static str; 4] = ["case", "input", "expect-output", "expect-status"];
+
+// The cases of a program and of its sidecar, if it has one.
+pub fn fixtures(path: &Path, source: &str) -> Result<Vec<Fixture>, Error> {
+    let mut directives = Vec::new();
+    for tok in cst::attach_trivia(source, try!(scanner::tokenize(source))) {
+        for trivia in tok.leading.iter().filter(|trivia| trivia.kind == TriviaKind::Comment) {
+            if let Some(directive) = directive(&trivia.text[1..]) {
+                let line = source[..trivia.span.start].matches('\n').count() + 1;
+                directives.push((line, directive));
+            }
+        }
+    }
+    let mut fixtures = try!(cases(&directives));
+
+    let sidecar = path.with_extension("spec");
+    let sidecar_name = sidecar.to_string_lossy().into_owned();
+    let text = try!(conformance::read_optional(&sidecar));
+    let mut directives = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        match directive(line) {
+            Some(directive) => directives.push((i + 1, directive)),
+            None => {
+                let err = Error::InvalidSpec(format!("line {}: expected one of {}", i + 1, KEYS.join(", ")));
+                return Err(Error::InFile(sidecar_name, Box::new(err)));
+            }
+        }
+    }
+    fixtures.extend(try!(cases(&directives).map_err(|e| Error::InFile(sidecar_name, Box::new(e)))));
+    if fixtures.is_empty() {
+        return Err(Error::InvalidSpec("the program has no `# expect-output:` or other directive".to_string()));
+    }
+    Ok(fixtures)
+}
+
+// The key and value of a directive such as ` expect-output: 120`.  The
+// value is the text after the colon, without the space that follows it.
+fn directive(text: &str) -> Option<(&'static str, String)> {
+    let text = text.trim_start();
+    for &key in KEYS.iter() {
+        if text.starts_with(key) && text[key.len()..].starts_with(':') {
+            let value = &text[key.len() + 1..];
+            let value = if value.starts_with(' ') { &value[1..] } else { value };
+            return Some((key, value.trim_end().to_string()));
+        }
+    }
+    None
+}
+
+fn cases(directives: &[(usize, (&'static str, String))]) -> Result<Vec<Fixture>, Error> {
+    let mut fixtures: Vec<Fixture> = Vec::new();
+    for &(line, (key, ref value)) in directives {
+        if key == "case" || fixtures.is_empty() {
+            let name = if key == "case" { value.clone() } else { (fixtures.len() + 1).to_string() };
+            fixtures.push(Fixture { name: name, input: String::new(), output: String::new(), status: 0 });
+        }
+        let fixture = fixtures.last_mut().unwrap();
+        match key {
+            "input" => fixture.input.push_str(&format!("{}\n", value)),
+            "expect-output" => fixture.output.push_str(&format!("{}\n", value)),
+            "expect-status" => {
+                fixture.status = try!(value.trim().parse()
+                                      .map_err(|_| Error::InvalidSpec(format!("line {}: invalid exit status '{}'",
+                                                                              line, value))));
+            }
+            _ => {}
+        }
+    }
+    Ok(fixtures)
+}
@@ -0,0 +1,149 @@
+// Suppressing warnings with directive comments.
+//
+// A comment of the form
+//
+//     # minilang: allow lossy-conversion, W0002, ...
+//
+// suppresses the named warnings (those of `diagnostic::LINTS`, by name
+// or ID, or `all` for every warning) in the declaration or statement that follows it,
+// including the statements nested in it, so that generated or
+// intentionally odd code can be compiled with `--deny-warnings`.  The
+// directives are attached to the node ids of their declarations and
+// statements after parsing; a warning belongs to the innermost
+// declaration or statement that contains its position.
+
+use std::collections::HashMap;
+
+use ast::*;
+use cst::{self, TriviaKind};
+use diagnostic;
+use pos::Pos;
+use token::Token;
+
+const PREFIX: &'static str = "minilang:";
+
+pub struct Suppressions {
+    // The positions of the declarations and statements, in order, with
+    // their node ids.
+    nodes: Vec<(Pos, u64)>,
+    // The statement or function that each nested node belongs to, by
+    // node id.
+    parents: HashMap<u64, u64>,
+    // The warnings allowed in each declaration or statement, by node id.
+    allowed: HashMap<u64, Vec<String>>,
+}
+
+impl Suppressions {
+    pub fn new() -> Self {
+        Suppressions { nodes: Vec::new(), parents: HashMap::new(), allowed: HashMap::new() }
+    }
+
+    // The directives of `source` attached to the declarations and
+    // statements of `program`, and the directives that are malformed
+    // or followed by nothing, with the reason.
+    pub fn collect(source: &str, tokens: &[Token], program: &Program) -> (Suppressions, Vec<(Pos, String)>) {
+        let mut suppressions = Suppressions::new();
+        for decl in &program.decls {
+            suppressions.nodes.push((decl.pos, decl.node_id));
+        }
+        for func in &program.funcs {
+            suppressions.nodes.push((func.pos, func.node_id));
+            for decl in &func.decls {
+                suppressions.nodes.push((decl.pos, decl.node_id));
+                suppressions.parents.insert(decl.node_id, func.node_id);
+            }
+            suppressions.add_stmts(&func.stmts, Some(func.node_id));
+        }
+        suppressions.add_stmts(&program.stmts, None);
+        suppressions.nodes.sort();
+
+        let mut problems = Vec::new();
+        for tok in cst::attach_trivia(source, tokens.to_vec()) {
+            for trivia in &tok.leading {
+                if trivia.kind != TriviaKind::Comment {
+                    continue;
+                }
+                let text = trivia.text[1..].trim();
+                if !text.starts_with(PREFIX) {
+                    continue;
+                }
+                let pos = position(source, trivia.span.start);
+                let names = match parse(&text[PREFIX.len()..]) {
+                    Ok(names) => names,
+                    Err(message) => {
+                        problems.push((pos, message));
+                        continue;
+                    }
+                };
+                match suppressions.nodes.iter().find(|&&(start, _)| start >= tok.token.pos) {
+                    Some(&(_, id)) => suppressions.allowed.entry(id).or_insert_with(Vec::new).extend(names),
+                    None => problems.push((pos, "no declaration or statement follows this directive".to_string())),
+                }
+            }
+        }
+        (suppressions, problems)
+    }
+
+    fn add_stmts(&mut self, stmts: &[Stmt], parent: Option<u64>) {
+        for stmt in stmts {
+            let id = stmt.node_id();
+            self.nodes.push((stmt.pos(), id));
+            if let Some(parent) = parent {
+                self.parents.insert(id, parent);
+            }
+            match *stmt {
+                Stmt::If(ref stmt_) => {
+                    self.add_stmts(&stmt_.then_stmts, Some(id));
+                    self.add_stmts(&stmt_.else_stmts, Some(id));
+                }
+                Stmt::While(ref stmt_) => self.add_stmts(&stmt_.stmts, Some(id)),
+                Stmt::For(ref stmt_) => self.add_stmts(&stmt_.stmts, Some(id)),
+                _ => {}
+            }
+        }
+    }
+
+    // Whether the warning `lint` at `pos` is suppressed.  The last
+    // declaration or statement that starts before `pos` is the
+    // innermost one that contains it.
+    pub fn allows(&self, lint: &str, pos: Pos) -> bool {
+        let mut node = self.nodes.iter().rev().find(|&&(start, _)| start <= pos).map(|&(_, id)| id);
+        while let Some(id) = node {
+            let allowed = self.allowed.get(&id)
+                .map_or(false, |names| names.iter().any(|name| name == lint || name == "all"));
+            if allowed {
+                return true;
+            }
+            node = self.parents.get(&id).cloned();
+        }
+        false
+    }
+}
+
+// The warning names of `allow NAME, ...`, whether given by name or ID.
+fn parse(text: &str) -> Result<Vec<String>, String> {
+    let text = text.trim();
+    let mut words = text.splitn(2, char::is_whitespace);
+    if words.next() != Some("allow") {
+        return Err(format!("unknown directive '{}'; expected 'allow'", text));
+    }
+    let mut names = Vec::new();
+    for name in words.next().unwrap_or("").split(',').map(str::trim) {
+        if name.is_empty() {
+            return Err("expected the name of a warning after 'allow'".to_string());
+        }
+        match diagnostic::find_lint(name) {
+            Some(lint) => names.push(lint.to_string()),
+            None if name == "all" => names.push(name.to_string()),
+            None => return Err(format!("unknown warning '{}'; expected one of {}, all", name, diagnostic::lint_list())),
+        }
+    }
+    Ok(names)
+}
+
+// The line and column of a byte offset of `source`.
+fn position(source: &str, offset: usize) -> Pos {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Pos { line: before.matches('\n').count() + 1, col: before[line_start..].chars().count() + 1 }
+}
@@ -1,6 +1,8 @@
 use std::fmt;
 
+use error::Error;
 use pos::Pos;
+use scanner::{self, IdentifierRules};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
@@ -8,6 +10,11 @@ pub enum TokenType {
     Int,
     Float,
     String,
+    // The parts of an interpolated string "a ${x} b ${y} c": InterpStart
+    // is `"a ${`, InterpMid is `} b ${` and InterpEnd is `} c"`.
+    InterpStart,
+    InterpMid,
+    InterpEnd,
     Id,
 
     // Punctuation and operators
@@ -16,10 +23,20 @@ pub enum TokenType {
     Star,
     Slash,
     Equal,
+    EqualEqual,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     LParen,
     RParen,
+    LBrace,
+    RBrace,
     Colon,
     Semicolon,
+    Comma,
+    Dot,
 
     // Keywords
     If,
@@ -29,47 +46,179 @@ pub enum TokenType {
     While,
     Do,
     Done,
+    For,
+    To,
     Read,
+    Prompt,
     Print,
+    Exit,
     Var,
     TypeInt,
     TypeFloat,
+    TypeInt32,
+    TypeInt64,
+    TypeFloat32,
+    TypeFloat64,
     TypeString,
+    TypeBool,
+    True,
+    False,
+    And,
+    Or,
+    Not,
+    Record,
+    Function,
+    Return,
+    Void,
+    End,
 
     // Others
     Eof,
 }
 
+// Every token type, in the order of the enum, for tools that list
+// them (a highlighter's keywords, a grammar's terminals).
+pub static TOKEN_TYPES: &'static [TokenType] = &[
+    TokenType::Int, TokenType::Float, TokenType::String, TokenType::InterpStart, TokenType::InterpMid,
+    TokenType::InterpEnd, TokenType::Id,
+    TokenType::Plus, TokenType::Minus, TokenType::Star, TokenType::Slash, TokenType::Equal,
+    TokenType::EqualEqual, TokenType::NotEqual, TokenType::Less, TokenType::LessEqual, TokenType::Greater,
+    TokenType::GreaterEqual, TokenType::LParen, TokenType::RParen, TokenType::LBrace, TokenType::RBrace,
+    TokenType::Colon, TokenType::Semicolon, TokenType::Comma, TokenType::Dot,
+    TokenType::If, TokenType::Then, TokenType::Else, TokenType::EndIf, TokenType::While, TokenType::Do,
+    TokenType::Done, TokenType::For, TokenType::To, TokenType::Read, TokenType::Prompt, TokenType::Print,
+    TokenType::Exit, TokenType::Var,
+    TokenType::TypeInt, TokenType::TypeFloat, TokenType::TypeInt32, TokenType::TypeInt64,
+    TokenType::TypeFloat32, TokenType::TypeFloat64, TokenType::TypeString, TokenType::TypeBool,
+    TokenType::True, TokenType::False, TokenType::And, TokenType::Or, TokenType::Not, TokenType::Record,
+    TokenType::Function, TokenType::Return, TokenType::Void, TokenType::End,
+    TokenType::Eof,
+];
+
+// The binding powers of the operators, from `TokenType::precedence`:
+// the higher, the tighter.
+pub const PREC_OR: u8 = 1;
+pub const PREC_AND: u8 = 2;
+pub const PREC_NOT: u8 = 3;
+pub const PREC_COMPARISON: u8 = 4;
+pub const PREC_SUM: u8 = 5;
+pub const PREC_PRODUCT: u8 = 6;
+
+impl TokenType {
+    // The text of the tokens that are always spelled the same, the
+    // keywords and punctuation; None for the others.
+    pub fn text(self) -> Option<&'static str> {
+        let text = match self {
+            TokenType::Int | TokenType::Float | TokenType::String | TokenType::InterpStart |
+            TokenType::InterpMid | TokenType::InterpEnd | TokenType::Id | TokenType::Eof => return None,
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Star => "*",
+            TokenType::Slash => "/",
+            TokenType::Equal => "=",
+            TokenType::EqualEqual => "==",
+            TokenType::NotEqual => "!=",
+            TokenType::Less => "<",
+            TokenType::LessEqual => "<=",
+            TokenType::Greater => ">",
+            TokenType::GreaterEqual => ">=",
+            TokenType::LParen => "(",
+            TokenType::RParen => ")",
+            TokenType::LBrace => "{",
+            TokenType::RBrace => "}",
+            TokenType::Colon => ":",
+            TokenType::Semicolon => ";",
+            TokenType::Comma => ",",
+            TokenType::Dot => ".",
+            TokenType::If => "if",
+            TokenType::Then => "then",
+            TokenType::Else => "else",
+            TokenType::EndIf => "endif",
+            TokenType::While => "while",
+            TokenType::Do => "do",
+            TokenType::Done => "done",
+            TokenType::For => "for",
+            TokenType::To => "to",
+            TokenType::Read => "read",
+            TokenType::Prompt => "prompt",
+            TokenType::Print => "print",
+            TokenType::Exit => "exit",
+            TokenType::Var => "var",
+            TokenType::TypeInt => "int",
+            TokenType::TypeFloat => "float",
+            TokenType::TypeInt32 => "int32",
+            TokenType::TypeInt64 => "int64",
+            TokenType::TypeFloat32 => "float32",
+            TokenType::TypeFloat64 => "float64",
+            TokenType::TypeString => "string",
+            TokenType::TypeBool => "bool",
+            TokenType::True => "true",
+            TokenType::False => "false",
+            TokenType::And => "and",
+            TokenType::Or => "or",
+            TokenType::Not => "not",
+            TokenType::Record => "record",
+            TokenType::Function => "function",
+            TokenType::Return => "return",
+            TokenType::Void => "void",
+            TokenType::End => "end",
+        };
+        Some(text)
+    }
+
+    // Whether tokens of this type carry their text: literals,
+    // identifiers and the parts of interpolated strings.
+    pub fn has_lexeme(self) -> bool {
+        match self {
+            TokenType::Int | TokenType::Float | TokenType::String | TokenType::InterpStart |
+            TokenType::InterpMid | TokenType::InterpEnd | TokenType::Id => true,
+            _ => false,
+        }
+    }
+
+    // Whether this is a reserved word, which cannot be an identifier.
+    pub fn is_keyword(self) -> bool {
+        self.text().map_or(false, |text| text.starts_with(|c: char| c.is_ascii_alphabetic()))
+    }
+
+    // Whether this is an operator of expressions, `and`, `or` and `not`
+    // included; the `=` of assignments is not.
+    pub fn is_operator(self) -> bool {
+        self.precedence().is_some()
+    }
+
+    // The binding power of an operator, one of the `PREC_` constants.
+    // `-` binds as tightly as a binary operator as it does as a
+    // negation, whose operand is a sum.
+    pub fn precedence(self) -> Option<u8> {
+        match self {
+            TokenType::Or => Some(PREC_OR),
+            TokenType::And => Some(PREC_AND),
+            TokenType::Not => Some(PREC_NOT),
+            TokenType::EqualEqual | TokenType::NotEqual | TokenType::Less | TokenType::LessEqual |
+            TokenType::Greater | TokenType::GreaterEqual => Some(PREC_COMPARISON),
+            TokenType::Plus | TokenType::Minus => Some(PREC_SUM),
+            TokenType::Star | TokenType::Slash => Some(PREC_PRODUCT),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(text) = self.text() {
+            return write!(f, "'{}'", text);
+        }
         match *self {
             TokenType::Int => write!(f, "integer"),
             TokenType::Float => write!(f, "float"),
             TokenType::String => write!(f, "string"),
+            TokenType::InterpStart => write!(f, "interpolated string"),
+            TokenType::InterpMid => write!(f, "'}}' followed by string text and '${{'"),
+            TokenType::InterpEnd => write!(f, "'}}' followed by the end of the string"),
             TokenType::Id => write!(f, "identifier"),
-            TokenType::Plus => write!(f, "'+'"),
-            TokenType::Minus => write!(f, "'-'"),
-            TokenType::Star => write!(f, "'*'"),
-            TokenType::Slash => write!(f, "'/'"),
-            TokenType::Equal => write!(f, "'='"),
-            TokenType::LParen => write!(f, "'('"),
-            TokenType::RParen => write!(f, "')'"),
-            TokenType::Colon => write!(f, "':'"),
-            TokenType::Semicolon => write!(f, "';'"),
-            TokenType::If => write!(f, "'if'"),
-            TokenType::Then => write!(f, "'then'"),
-            TokenType::Else => write!(f, "'else'"),
-            TokenType::EndIf => write!(f, "'endif'"),
-            TokenType::While => write!(f, "'while'"),
-            TokenType::Do => write!(f, "'do'"),
-            TokenType::Done => write!(f, "'done'"),
-            TokenType::Read => write!(f, "'read'"),
-            TokenType::Print => write!(f, "'print'"),
-            TokenType::Var => write!(f, "'var'"),
-            TokenType::TypeInt => write!(f, "'int'"),
-            TokenType::TypeFloat => write!(f, "'float'"),
-            TokenType::TypeString => write!(f, "'string'"),
-            TokenType::Eof => write!(f, "<eof>"),
+            // Eof, the only other type without text.
+            _ => write!(f, "<eof>"),
         }
     }
 }
@@ -80,13 +229,86 @@ pub struct Token {
     pub typ: TokenType,
     pub lexeme: Option<String>,
     pub pos: Pos,
+    pub end: Pos,
+}
+
+// Printed in place of the lexeme of a token that should have one but
+// was built without it.
+const MISSING_LEXEME: &'static str = "<no text>";
+
+impl Token {
+    // A builder of a token of type `typ`, for tools and tests that make
+    // tokens without the scanner.
+    pub fn build(typ: TokenType) -> TokenBuilder {
+        TokenBuilder { typ: typ, lexeme: None, pos: Pos { line: 1, col: 1 }, end: None }
+    }
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.lexeme {
+            Some(ref s) if self.typ == TokenType::String => write!(f, "{} ({})", self.typ, scanner::quote(s)),
             Some(ref s) => write!(f, "{} ({})", self.typ, s),
+            None if self.typ.has_lexeme() => write!(f, "{} ({})", self.typ, MISSING_LEXEME),
             None => write!(f, "{}", self.typ)
         }
     }
 }
+
+// A token under construction:
+//
+//     Token::build(TokenType::Id).lexeme("x").at(Pos { line: 2, col: 5 }).finish()
+//
+// `finish` checks that the token could come from the scanner: a
+// lexeme exactly for the types that have one, and for numbers and
+// identifiers, one that scans as a single token of that type (with
+// unicode and `$` allowed in identifiers).  The position is 1:1 and
+// the end the position itself unless they are given.
+pub struct TokenBuilder {
+    typ: TokenType,
+    lexeme: Option<String>,
+    pos: Pos,
+    end: Option<Pos>,
+}
+
+impl TokenBuilder {
+    pub fn lexeme<S: Into<String>>(mut self, lexeme: S) -> Self {
+        self.lexeme = Some(lexeme.into());
+        self
+    }
+
+    pub fn at(mut self, pos: Pos) -> Self {
+        self.pos = pos;
+        self
+    }
+
+    pub fn end(mut self, end: Pos) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    pub fn finish(self) -> Result<Token, Error> {
+        match (self.typ.has_lexeme(), &self.lexeme) {
+            (true, &None) => return Err(Error::InvalidToken(format!("{} needs a lexeme", self.typ))),
+            (false, &Some(_)) => return Err(Error::InvalidToken(format!("{} has no lexeme", self.typ))),
+            _ => {}
+        }
+        if let Some(ref lexeme) = self.lexeme {
+            if self.typ == TokenType::Int || self.typ == TokenType::Float || self.typ == TokenType::Id {
+                let rules = IdentifierRules { unicode: true, dollar: true };
+                let single = match scanner::tokenize_with(lexeme, &rules) {
+                    Ok(tokens) => tokens.len() == 2 && tokens[0].typ == self.typ
+                        && tokens[0].lexeme.as_ref() == Some(lexeme),
+                    Err(_) => false,
+                };
+                if !single {
+                    return Err(Error::InvalidToken(format!("'{}' is not a valid {}", lexeme, self.typ)));
+                }
+            }
+        }
+        if self.end.map_or(false, |end| end < self.pos) {
+            return Err(Error::InvalidToken(format!("{} ends before it starts", self.typ)));
+        }
+        Ok(Token { typ: self.typ, lexeme: self.lexeme, pos: self.pos, end: self.end.unwrap_or(self.pos) })
+    }
+}
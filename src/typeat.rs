@@ -0,0 +1,81 @@
+use ast::*;
+use pos::Pos;
+
+// Return the innermost expression of the program whose span covers
+// `pos`, or None if `pos` is not inside any expression.
+pub fn expr_at(program: &Program, pos: Pos) -> Option<&Expr> {
+    decls_expr_at(&program.decls, pos)
+        .or_else(|| program.funcs.iter().filter_map(|func| func_expr_at(func, pos)).next())
+        .or_else(|| stmts_expr_at(&program.stmts, pos))
+}
+
+fn decls_expr_at(decls: &[Decl], pos: Pos) -> Option<&Expr> {
+    decls.iter()
+        .filter_map(|decl| decl.init.as_ref().and_then(|init| expr_expr_at(init, pos)))
+        .next()
+}
+
+fn func_expr_at(func: &FuncDecl, pos: Pos) -> Option<&Expr> {
+    decls_expr_at(&func.decls, pos)
+        .or_else(|| stmts_expr_at(&func.stmts, pos))
+}
+
+fn stmts_expr_at(stmts: &[Stmt], pos: Pos) -> Option<&Expr> {
+    for stmt in stmts {
+        let found = stmt_expr_at(stmt, pos);
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+fn stmt_expr_at(stmt: &Stmt, pos: Pos) -> Option<&Expr> {
+    match *stmt {
+        Stmt::Read(_) | Stmt::Error(_) => None,
+        Stmt::Print(ref stmt_) => expr_expr_at(&stmt_.expr, pos),
+        Stmt::Exit(ref stmt_) => expr_expr_at(&stmt_.expr, pos),
+        Stmt::Call(ref stmt_) => expr_expr_at(&stmt_.expr, pos),
+        Stmt::Return(ref stmt_) => stmt_.expr.as_ref().and_then(|expr| expr_expr_at(expr, pos)),
+        Stmt::Assign(ref stmt_) => expr_expr_at(&stmt_.expr, pos),
+        Stmt::Destructure(ref stmt_) => expr_expr_at(&stmt_.expr, pos),
+        Stmt::If(ref stmt_) => {
+            expr_expr_at(&stmt_.expr, pos)
+                .or_else(|| stmts_expr_at(&stmt_.then_stmts, pos))
+                .or_else(|| stmts_expr_at(&stmt_.else_stmts, pos))
+        }
+        Stmt::While(ref stmt_) => {
+            expr_expr_at(&stmt_.expr, pos)
+                .or_else(|| stmts_expr_at(&stmt_.stmts, pos))
+        }
+        Stmt::For(ref stmt_) => {
+            expr_expr_at(&stmt_.first, pos)
+                .or_else(|| expr_expr_at(&stmt_.last, pos))
+                .or_else(|| stmts_expr_at(&stmt_.stmts, pos))
+        }
+    }
+}
+
+fn expr_expr_at(expr: &Expr, pos: Pos) -> Option<&Expr> {
+    if pos < expr.pos || pos >= expr.end {
+        return None;
+    }
+    let inner = match expr.expr {
+        Expr_::Negate(ref expr_) => expr_expr_at(&expr_.expr, pos),
+        Expr_::ToString(ref expr_) => expr_expr_at(&expr_.expr, pos),
+        Expr_::Field(ref expr_) => expr_expr_at(&expr_.expr, pos),
+        Expr_::Tuple(ref expr_) => expr_.exprs.iter().filter_map(|e| expr_expr_at(e, pos)).next(),
+        Expr_::Call(ref expr_) => expr_.args.iter().filter_map(|e| expr_expr_at(e, pos)).next(),
+        Expr_::Binop(ref expr_) => {
+            expr_expr_at(&expr_.expr1, pos)
+                .or_else(|| expr_expr_at(&expr_.expr2, pos))
+        }
+        Expr_::Logic(ref expr_) => {
+            expr_expr_at(&expr_.expr1, pos)
+                .or_else(|| expr_expr_at(&expr_.expr2, pos))
+        }
+        Expr_::Not(ref expr_) => expr_expr_at(&expr_.expr, pos),
+        _ => None,
+    };
+    inner.or(Some(expr))
+}
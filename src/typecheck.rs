@@ -1,16 +1,156 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
 use ast::*;
+use consteval;
+use ice;
 use pos::Pos;
 use types::Type;
 use error::Error;
+use json;
 
-pub type Symtable = HashMap<String, Type>;
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub pos: Pos,
+    pub ty: Type,
+    // Whether the type comes from the initializer rather than the
+    // declaration.
+    pub inferred: bool,
+}
+
+pub type Symtable = HashMap<String, Symbol>;
 pub type Exprtable = HashMap<u64, Type>;
+// The fields of each record type, in declaration order.
+pub type Recordtable = HashMap<String, Vec<(String, Type)>>;
+
+// The parameter types and return type of a function, by name.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub pos: Pos,
+    pub params: Vec<Type>,
+    pub ret: Option<Type>,
+}
+
+pub type Functable = HashMap<String, Signature>;
+
+pub fn recordtable(records: &[RecordDecl]) -> Recordtable {
+    records.iter()
+        .map(|r| (r.id.clone(), r.fields.iter().map(|f| (f.id.clone(), f.ty.clone())).collect()))
+        .collect()
+}
+
+// The type of `field` in values of type `ty`, if `ty` is a record
+// with such a field.
+pub fn field_type(records: &Recordtable, ty: &Type, field: &str) -> Option<Type> {
+    match *ty {
+        Type::Record(ref name) => {
+            records.get(&name[..])
+                .and_then(|fields| fields.iter().find(|&&(ref id, _)| id == field))
+                .map(|&(_, ref ty)| ty.clone())
+        }
+        _ => None,
+    }
+}
+
+// The parameters and local variables of a type checked function, as
+// its body sees them.  The types of the locals declared without one
+// come from the types of their initializers in `exprtable`.
+pub fn locals(func: &FuncDecl, exprtable: &Exprtable) -> Symtable {
+    let mut symtable: Symtable = func.params.iter()
+        .map(|param| (param.id.clone(), Symbol { pos: param.pos, ty: param.ty.clone(), inferred: false }))
+        .collect();
+    for decl in &func.decls {
+        let ty = decl.ty.clone().or_else(|| decl.init.as_ref().and_then(|init| exprtable.get(&init.node_id).cloned()));
+        if let Some(ty) = ty {
+            symtable.insert(decl.id.clone(), Symbol { pos: decl.pos, ty: ty, inferred: decl.ty.is_none() });
+        }
+    }
+    symtable
+}
+
+// Whether every path through the statements ends with `return`.  A
+// loop may not run, so only an `if` with both branches returning counts.
+fn returns(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match *stmt {
+        Stmt::Return(_) => true,
+        Stmt::If(ref stmt_) => returns(&stmt_.then_stmts) && returns(&stmt_.else_stmts),
+        _ => false,
+    })
+}
+
+// Where a name is looked up by `decl_of`: among the globals, or the
+// locals of the function with the given node id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Function(u64),
+}
+
+// The type of the node with id `node_id`: the type of an expression, or
+// that of the variable of a declaration.  Node ids are deterministic
+// (see `Parser::next_id`), so a tool can keep them from an earlier run
+// on the same tokens and join its own data with these types.
+pub fn type_of(program: &Program, symtable: &Symtable, exprtable: &Exprtable, node_id: u64) -> Option<Type> {
+    if let Some(ty) = exprtable.get(&node_id) {
+        return Some(ty.clone());
+    }
+    if let Some(decl) = program.decls.iter().find(|decl| decl.node_id == node_id) {
+        return symtable.get(&decl.id).map(|sym| sym.ty.clone());
+    }
+    program.funcs.iter()
+        .filter_map(|func| func.decls.iter().find(|decl| decl.node_id == node_id).map(|decl| (func, decl)))
+        .next()
+        .and_then(|(func, decl)| locals(func, exprtable).get(&decl.id).map(|sym| sym.ty.clone()))
+}
+
+// The declaration of the variable `name` in `scope`.
+pub fn decl_of<'a>(program: &'a Program, name: &str, scope: Scope) -> Option<&'a Decl> {
+    match scope {
+        Scope::Global => program.decls.iter().find(|decl| decl.id == name),
+        Scope::Function(node_id) => {
+            program.funcs.iter()
+                .find(|func| func.node_id == node_id)
+                .and_then(|func| func.decls.iter().find(|decl| decl.id == name))
+        }
+    }
+}
+
+// The symbols in declaration order.
+pub fn symbols_in_order(symtable: &Symtable) -> Vec<(&String, &Symbol)> {
+    let mut symbols: Vec<(&String, &Symbol)> = symtable.iter().collect();
+    symbols.sort_by_key(|&(id, sym)| (sym.pos, id));
+    symbols
+}
+
+// The symbol table as JSON, sorted by declaration position.  Minilang
+// has no constants yet, and the table only has the globals, so every
+// symbol is a mutable global.
+pub fn symbols_json(symtable: &Symtable) -> String {
+    let symbols = symbols_in_order(symtable);
+
+    let mut out = String::from("[");
+    for (i, &(id, sym)) in symbols.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        out.push_str(&format!("  {{\"name\": {}, \"type\": {}, \"line\": {}, \"col\": {}, \"const\": false, \"scope\": \"global\", \"inferred\": {}}}",
+                              json::quote(id), json::quote(&sym.ty.to_string()), sym.pos.line, sym.pos.col,
+                              sym.inferred));
+    }
+    out.push_str("\n]\n");
+    out
+}
 
 pub struct TypeChecker {
     pub symtable: Symtable,
     pub expr_table: Exprtable,
+    pub records: Recordtable,
+    pub functions: Functable,
+    // The name and return type of the function whose body is being
+    // checked, if any; it has its own symbol table, in place of that of
+    // the globals.
+    function: Option<(String, Option<Type>)>,
+    // In strict mode, conditions must be bool; otherwise int is also
+    // accepted, as it was before the bool type existed.
+    strict: bool,
 }
 
 impl TypeChecker {
@@ -18,14 +158,195 @@ impl TypeChecker {
         TypeChecker {
             symtable: HashMap::new(),
             expr_table: HashMap::new(),
+            records: HashMap::new(),
+            functions: HashMap::new(),
+            function: None,
+            strict: false,
         }
     }
 
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // Declare a variable that is defined outside of the program, e.g.
+    // by a host application that embeds the interpreter.
+    pub fn declare_extern(&mut self, id: &str, ty: Type) {
+        self.symtable.insert(id.to_string(), Symbol { pos: Pos { line: 0, col: 0 }, ty: ty, inferred: false });
+    }
+
     pub fn tc_program(&mut self, p: &Program) -> Result<(), Error> {
+        try!(self.tc_records(&p.records));
+        for func in &p.funcs {
+            try!(self.tc_signature(func));
+        }
         try!(self.tc_decls(&p.decls));
+        for func in &p.funcs {
+            let mut errors = Vec::new();
+            self.tc_function(func, &mut errors);
+            if !errors.is_empty() {
+                return Err(errors.remove(0));
+            }
+        }
         self.tc_stmts(&p.stmts)
     }
 
+    // Type check an expression on its own, such as one returned by
+    // `Parser::parse_expression`, against the variables of `symtable`
+    // and the records of `records`.
+    pub fn tc_expression(&mut self, expr: &Expr) -> Result<Type, Error> {
+        self.tc_expr(expr)
+    }
+
+    // Type check a program like `tc_program`, but go on after a record,
+    // declaration or statement with an error, and return all the
+    // errors.  A variable whose declaration fails stays undeclared, so
+    // only its first use is reported.
+    pub fn tc_program_all(&mut self, p: &Program) -> Vec<Error> {
+        let mut errors = Vec::new();
+        if let Err(err) = self.tc_records(&p.records) {
+            errors.push(err);
+        }
+        for func in &p.funcs {
+            if let Err(err) = self.tc_signature(func) {
+                errors.push(err);
+            }
+        }
+        for decl in &p.decls {
+            if let Err(err) = self.tc_decl(decl) {
+                errors.push(err);
+            }
+        }
+        for func in &p.funcs {
+            self.tc_function(func, &mut errors);
+        }
+        self.tc_stmts_all(&p.stmts, &mut errors);
+
+        let (mut undeclared, mut undeclared_functions) = (HashSet::new(), HashSet::new());
+        errors.retain(|err| match *err {
+            Error::UndeclaredVariable(_, ref id) => undeclared.insert(id.clone()),
+            Error::UndeclaredFunction(_, ref id) => undeclared_functions.insert(id.clone()),
+            Error::SyntaxErrorNode(_) => false,
+            _ => true,
+        });
+        errors
+    }
+
+    fn tc_stmts_all(&mut self, stmts: &[Stmt], errors: &mut Vec<Error>) {
+        for stmt in stmts {
+            let (cond, bodies) = match *stmt {
+                Stmt::If(ref stmt_) => (&stmt_.expr, vec![&stmt_.then_stmts, &stmt_.else_stmts]),
+                Stmt::While(ref stmt_) => (&stmt_.expr, vec![&stmt_.stmts]),
+                Stmt::For(ref stmt_) => {
+                    if let Err(err) = self.tc_for_range(stmt_) {
+                        errors.push(err);
+                    }
+                    self.tc_stmts_all(&stmt_.stmts, errors);
+                    continue;
+                }
+                _ => {
+                    if let Err(err) = self.tc_stmt(stmt) {
+                        errors.push(err);
+                    }
+                    continue;
+                }
+            };
+            if let Err(err) = self.tc_condition(cond, stmt.pos()) {
+                errors.push(err);
+            }
+            for body in bodies {
+                self.tc_stmts_all(body, errors);
+            }
+        }
+    }
+
+    // A record can only use the records declared before it, so that
+    // records cannot contain themselves.
+    fn tc_records(&mut self, records: &[RecordDecl]) -> Result<(), Error> {
+        for record in records {
+            if self.records.contains_key(&record.id) {
+                return Err(Error::DuplicateRecord(record.pos, record.id.clone()));
+            }
+            let mut fields: Vec<(String, Type)> = Vec::new();
+            for field in &record.fields {
+                if fields.iter().any(|&(ref id, _)| *id == field.id) {
+                    return Err(Error::DuplicateField(field.pos, field.id.clone()));
+                }
+                try!(self.tc_type(&field.ty, field.pos));
+                fields.push((field.id.clone(), field.ty.clone()));
+            }
+            self.records.insert(record.id.clone(), fields);
+        }
+        Ok(())
+    }
+
+    // Functions are declared before the globals and the bodies are
+    // checked, so that they can be called from anywhere, themselves
+    // included.
+    fn tc_signature(&mut self, func: &FuncDecl) -> Result<(), Error> {
+        if self.functions.contains_key(&func.id) {
+            return Err(Error::DuplicateFunction(func.pos, func.id.clone()));
+        }
+        for param in &func.params {
+            try!(self.tc_type(&param.ty, param.pos));
+        }
+        if let Some(ref ret) = func.ret {
+            try!(self.tc_type(ret, func.pos));
+        }
+        let signature = Signature {
+            pos: func.pos,
+            params: func.params.iter().map(|param| param.ty.clone()).collect(),
+            ret: func.ret.clone(),
+        };
+        self.functions.insert(func.id.clone(), signature);
+        Ok(())
+    }
+
+    // A body sees its parameters and locals, which are checked like
+    // the globals, and the functions, but not the globals.  Its errors
+    // are added to `errors`, as in `tc_program_all`.
+    fn tc_function(&mut self, func: &FuncDecl, errors: &mut Vec<Error>) {
+        ice::at(func.pos);
+        let globals = mem::replace(&mut self.symtable, HashMap::new());
+        self.function = Some((func.id.clone(), func.ret.clone()));
+        for param in &func.params {
+            if self.symtable.contains_key(&param.id) {
+                errors.push(Error::DuplicateVariable(param.pos, param.id.clone()));
+            }
+            self.symtable.insert(param.id.clone(), Symbol { pos: param.pos, ty: param.ty.clone(), inferred: false });
+        }
+        for decl in &func.decls {
+            if let Err(err) = self.tc_decl(decl) {
+                errors.push(err);
+            }
+        }
+        self.tc_stmts_all(&func.stmts, errors);
+        if func.ret.is_some() && !returns(&func.stmts) {
+            errors.push(Error::MissingReturn(func.pos, func.id.clone()));
+        }
+        self.function = None;
+        self.symtable = globals;
+    }
+
+    fn tc_type(&self, ty: &Type, pos: Pos) -> Result<(), Error> {
+        match *ty {
+            Type::Record(ref name) if !self.records.contains_key(&name[..]) => {
+                Err(Error::UnknownType(pos, name.to_string()))
+            }
+            Type::Tuple(ref elements) => {
+                for element in elements.iter() {
+                    try!(self.tc_type(element, pos));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn field_type(&self, ty: &Type, field: &str, pos: Pos) -> Result<Type, Error> {
+        field_type(&self.records, ty, field).ok_or_else(|| Error::UnknownField(pos, ty.clone(), field.to_string()))
+    }
+
     fn tc_decls(&mut self, decls: &[Decl]) -> Result<(), Error> {
         for decl in decls {
             try!(self.tc_decl(&decl));
@@ -33,11 +354,40 @@ impl TypeChecker {
         Ok(())
     }
 
+    // The initializer is checked before the variable is declared, so
+    // it can only use the variables declared before.  Constant
+    // initializers are evaluated, to report overflows and divisions by
+    // zero at compile time.
     fn tc_decl(&mut self, decl: &Decl) -> Result<(), Error> {
+        ice::at(decl.pos);
+        let init_ty = match decl.init {
+            Some(ref init) => {
+                let init_ty = try!(self.tc_expr(init));
+                try!(consteval::eval(init));
+                Some(init_ty)
+            }
+            None => None,
+        };
+        let ty = match (decl.ty.clone(), init_ty) {
+            (Some(ty), Some(init_ty)) => {
+                try!(self.tc_type(&ty, decl.pos));
+                if !TypeChecker::assignable(&ty, &init_ty) {
+                    return Err(Error::UnexpectedType { pos: decl.pos, expected: ty, actual: init_ty });
+                }
+                ty
+            }
+            (Some(ty), None) => {
+                try!(self.tc_type(&ty, decl.pos));
+                ty
+            }
+            (None, Some(init_ty)) => init_ty,
+            (None, None) => return Err(Error::MissingInitializer(decl.pos, decl.id.clone())),
+        };
         if self.symtable.contains_key(&decl.id) {
             Err(Error::DuplicateVariable(decl.pos, decl.id.clone()))
         } else {
-            self.symtable.insert(decl.id.clone(), decl.ty);
+            let symbol = Symbol { pos: decl.pos, ty: ty, inferred: decl.ty.is_none() };
+            self.symtable.insert(decl.id.clone(), symbol);
             Ok(())
         }
     }
@@ -50,12 +400,19 @@ impl TypeChecker {
     }
 
     fn tc_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        ice::at(stmt.pos());
         match *stmt {
             Stmt::Assign(ref stmt_) => self.tc_stmt_assign(stmt_),
+            Stmt::Destructure(ref stmt_) => self.tc_stmt_destructure(stmt_),
             Stmt::Read(ref stmt_) => self.tc_stmt_read(stmt_),
             Stmt::Print(ref stmt_) => self.tc_stmt_print(stmt_),
+            Stmt::Exit(ref stmt_) => self.tc_stmt_exit(stmt_),
+            Stmt::Call(ref stmt_) => self.tc_stmt_call(stmt_),
+            Stmt::Return(ref stmt_) => self.tc_stmt_return(stmt_),
             Stmt::If(ref stmt_) => self.tc_stmt_if(stmt_),
             Stmt::While(ref stmt_) => self.tc_stmt_while(stmt_),
+            Stmt::For(ref stmt_) => self.tc_stmt_for(stmt_),
+            Stmt::Error(_) => Ok(()),
         }
     }
 
@@ -65,91 +422,307 @@ impl TypeChecker {
      * float := float
      * float := int
      * string := string
+     * bool := bool
+     * R := R, for a record type R
+     * (t1, ..., tn) := (u1, ..., un), if ti := ui for every i
      */
+    fn assignable(target: &Type, value: &Type) -> bool {
+        match (target, value) {
+            // A number can be assigned to a wider type.
+            (t, u) if t.is_numeric() => Type::widen(t, u).as_ref() == Some(t),
+            (&Type::String, &Type::String) => true,
+            (&Type::Bool, &Type::Bool) => true,
+            (&Type::Record(ref r1), &Type::Record(ref r2)) => r1 == r2,
+            (&Type::Tuple(ref ts), &Type::Tuple(ref us)) => {
+                ts.len() == us.len() && ts.iter().zip(us.iter()).all(|(t, u)| TypeChecker::assignable(t, u))
+            }
+            _ => false,
+        }
+    }
+
+    fn lvalue_type(&self, id: &str, fields: &[String], pos: Pos) -> Result<Type, Error> {
+        let mut ty = match self.symtable.get(id) {
+            Some(sym) => sym.ty.clone(),
+            None => return Err(Error::UndeclaredVariable(pos, id.to_string())),
+        };
+        for field in fields {
+            ty = try!(self.field_type(&ty, field, pos));
+        }
+        Ok(ty)
+    }
+
     fn tc_stmt_assign(&mut self, stmt: &StmtAssign) -> Result<(), Error> {
         let expr_ty = try!(self.tc_expr(&stmt.expr));
-        match self.symtable.get(&stmt.id) {
-            Some(&id_ty) => {
-                match (id_ty, expr_ty) {
-                    (Type::Int, Type::Int) => Ok(()),
-                    (Type::Float, Type::Int) => Ok(()),
-                    (Type::Float, Type::Float) => Ok(()),
-                    (Type::String, Type::String) => Ok(()),
-                    (t1, t2) =>
-                        Err(Error::UnexpectedType { pos: stmt.pos, expected: t1, actual: t2 }),
-                }
+        let ty = try!(self.lvalue_type(&stmt.id, &stmt.fields, stmt.pos));
+        if TypeChecker::assignable(&ty, &expr_ty) {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedType { pos: stmt.pos, expected: ty, actual: expr_ty })
+        }
+    }
+
+    fn tc_stmt_destructure(&mut self, stmt: &StmtDestructure) -> Result<(), Error> {
+        let elements = match try!(self.tc_expr(&stmt.expr)) {
+            Type::Tuple(elements) => elements,
+            ty => return Err(Error::UnsupportedOperation(stmt.pos, "destructure", ty)),
+        };
+        if elements.len() != stmt.targets.len() {
+            return Err(Error::ArityMismatch(stmt.pos, stmt.targets.len(), elements.len()));
+        }
+        for (target, expr_ty) in stmt.targets.iter().zip(elements.iter()) {
+            let ty = try!(self.lvalue_type(&target.id, &target.fields, stmt.pos));
+            if !TypeChecker::assignable(&ty, expr_ty) {
+                return Err(Error::UnexpectedType { pos: stmt.pos, expected: ty, actual: expr_ty.clone() });
             }
-            None => Err(Error::UndeclaredVariable(stmt.pos, stmt.id.clone()))
         }
+        Ok(())
     }
 
     fn tc_stmt_read(&mut self, stmt: &StmtRead) -> Result<(), Error> {
-        if self.symtable.contains_key(&stmt.id) {
-            Ok(())
-        } else {
-            Err(Error::UndeclaredVariable(stmt.pos, stmt.id.clone()))
+        for id in &stmt.ids {
+            match self.symtable.get(id) {
+                Some(&Symbol { ty: ref ty @ Type::Record(_), .. }) |
+                Some(&Symbol { ty: ref ty @ Type::Tuple(_), .. }) =>
+                    return Err(Error::UnsupportedOperation(stmt.pos, "read", ty.clone())),
+                Some(_) => {}
+                None => return Err(Error::UndeclaredVariable(stmt.pos, id.clone())),
+            }
         }
+        Ok(())
     }
 
     fn tc_stmt_print(&mut self, stmt: &StmtPrint) -> Result<(), Error> {
-        try!(self.tc_expr(&stmt.expr));
-        Ok(())
+        match try!(self.tc_expr(&stmt.expr)) {
+            ty @ Type::Record(_) | ty @ Type::Tuple(_) =>
+                Err(Error::UnsupportedOperation(stmt.pos, "print", ty)),
+            ty if ty.is_numeric() => Ok(()),
+            ty if stmt.precision.is_some() =>
+                Err(Error::UnexpectedType { pos: stmt.expr.pos, expected: Type::Float, actual: ty }),
+            _ => Ok(()),
+        }
     }
 
-    fn tc_stmt_if(&mut self, stmt: &StmtIf) -> Result<(), Error> {
-        let t = try!(self.tc_expr(&stmt.expr));
-        match t {
-            Type::Int => {
-                try!(self.tc_stmts(&stmt.then_stmts));
-                try!(self.tc_stmts(&stmt.else_stmts));
+    // `exit` would have to unwind the calls in every backend.
+    fn tc_stmt_exit(&mut self, stmt: &StmtExit) -> Result<(), Error> {
+        if self.function.is_some() {
+            return Err(Error::ExitInFunction(stmt.pos));
+        }
+        match try!(self.tc_expr(&stmt.expr)) {
+            ty if ty.is_integer() => Ok(()),
+            ty => Err(Error::UnexpectedType { pos: stmt.expr.pos, expected: Type::Int, actual: ty }),
+        }
+    }
+
+    // The function may return no value here.
+    fn tc_stmt_call(&mut self, stmt: &StmtCall) -> Result<(), Error> {
+        match stmt.expr.expr {
+            Expr_::Call(ref call) => {
+                if let Some(ty) = try!(self.tc_call(call, stmt.expr.pos)) {
+                    self.expr_table.insert(stmt.expr.node_id, ty);
+                }
                 Ok(())
             }
-            _ => { Err(Error::UnexpectedType{ pos: stmt.pos, expected: Type::Int, actual: t }) }
+            _ => self.tc_expr(&stmt.expr).map(|_| ()),
         }
     }
 
+    fn tc_stmt_return(&mut self, stmt: &StmtReturn) -> Result<(), Error> {
+        let (id, ret) = match self.function {
+            Some((ref id, ref ret)) => (id.clone(), ret.clone()),
+            None => return Err(Error::ReturnOutsideFunction(stmt.pos)),
+        };
+        match (ret, &stmt.expr) {
+            (Some(ret), &Some(ref expr)) => {
+                let ty = try!(self.tc_expr(expr));
+                if TypeChecker::assignable(&ret, &ty) {
+                    Ok(())
+                } else {
+                    Err(Error::UnexpectedType { pos: expr.pos, expected: ret, actual: ty })
+                }
+            }
+            (Some(ret), &None) => Err(Error::MissingReturnValue(stmt.pos, id, ret)),
+            (None, &Some(ref expr)) => Err(Error::UnexpectedReturn(expr.pos, id)),
+            (None, &None) => Ok(()),
+        }
+    }
+
+    fn tc_stmt_if(&mut self, stmt: &StmtIf) -> Result<(), Error> {
+        try!(self.tc_condition(&stmt.expr, stmt.pos));
+        try!(self.tc_stmts(&stmt.then_stmts));
+        try!(self.tc_stmts(&stmt.else_stmts));
+        Ok(())
+    }
+
     fn tc_stmt_while(&mut self, stmt: &StmtWhile) -> Result<(), Error> {
-        let t = try!(self.tc_expr(&stmt.expr));
-        match t {
-            Type::Int => { self.tc_stmts(&stmt.stmts) }
-            _ => { Err(Error::UnexpectedType { pos: stmt.pos, expected: Type::Int, actual: t }) }
+        try!(self.tc_condition(&stmt.expr, stmt.pos));
+        self.tc_stmts(&stmt.stmts)
+    }
+
+    fn tc_stmt_for(&mut self, stmt: &StmtFor) -> Result<(), Error> {
+        try!(self.tc_for_range(stmt));
+        self.tc_stmts(&stmt.stmts)
+    }
+
+    // The loop variable is an integer, and both bounds can be assigned
+    // to it.
+    fn tc_for_range(&mut self, stmt: &StmtFor) -> Result<(), Error> {
+        let ty = try!(self.lvalue_type(&stmt.id, &[], stmt.pos));
+        if !ty.is_integer() {
+            return Err(Error::UnexpectedType { pos: stmt.pos, expected: Type::Int, actual: ty });
+        }
+        for bound in &[&stmt.first, &stmt.last] {
+            let bound_ty = try!(self.tc_expr(bound));
+            if !TypeChecker::assignable(&ty, &bound_ty) {
+                return Err(Error::UnexpectedType { pos: bound.pos, expected: ty, actual: bound_ty });
+            }
+        }
+        Ok(())
+    }
+
+    fn tc_condition(&mut self, expr: &Expr, pos: Pos) -> Result<(), Error> {
+        match try!(self.tc_expr(expr)) {
+            Type::Bool => Ok(()),
+            t if t.is_integer() && !self.strict => Ok(()),
+            t => Err(Error::UnexpectedType { pos: pos, expected: Type::Bool, actual: t }),
         }
     }
 
     fn tc_expr(&mut self, expr: &Expr) -> Result<Type, Error> {
         let ty = try!(match expr.expr {
-            Expr_::Int(_) => Ok(Type::Int),
-            Expr_::Float(_) => Ok(Type::Float),
+            Expr_::Int(ref expr_) => Ok(expr_.ty.clone()),
+            Expr_::Float(ref expr_) => Ok(expr_.ty.clone()),
             Expr_::String(_) => Ok(Type::String),
+            Expr_::Bool(_) => Ok(Type::Bool),
             Expr_::Id(ref expr_) => self.tc_expr_id(expr_, &expr.pos),
-            Expr_::Negate(ref expr_) => self.tc_expr_negate(expr_),
-            Expr_::Binop(ref expr_) => self.tc_expr_binop(expr_, &expr.pos),
+            Expr_::Negate(ref expr_) => self.tc_expr_negate(expr_, &expr.pos),
+            Expr_::Binop(_) | Expr_::Logic(_) => return self.tc_chain(expr),
+            Expr_::Not(ref expr_) => {
+                try!(self.tc_bool_operand(&expr_.expr));
+                Ok(Type::Bool)
+            }
+            Expr_::Field(ref expr_) => {
+                let ty = try!(self.tc_expr(&expr_.expr));
+                self.field_type(&ty, &expr_.field, expr.pos)
+            }
+            Expr_::Call(ref expr_) => {
+                match try!(self.tc_call(expr_, expr.pos)) {
+                    Some(ty) => Ok(ty),
+                    None => Err(Error::NoValue(expr.pos, expr_.id.clone())),
+                }
+            }
+            Expr_::Tuple(ref expr_) => {
+                let mut elements = Vec::new();
+                for e in &expr_.exprs {
+                    elements.push(try!(self.tc_expr(e)));
+                }
+                Ok(Type::tuple(&elements))
+            }
+            // Interpolated values are those that can be printed.
+            Expr_::ToString(ref expr_) => {
+                match try!(self.tc_expr(&expr_.expr)) {
+                    ty @ Type::Record(_) | ty @ Type::Tuple(_) =>
+                        Err(Error::UnsupportedOperation(expr_.expr.pos, "interpolate", ty)),
+                    _ => Ok(Type::String),
+                }
+            }
+            // The error was reported by the parser; `tc_program_all`
+            // drops this one, so that it is not reported twice.
+            Expr_::Error => Err(Error::SyntaxErrorNode(expr.pos)),
         });
 
-        self.expr_table.insert(expr.node_id, ty);
+        self.expr_table.insert(expr.node_id, ty.clone());
         Ok(ty)
     }
 
     fn tc_expr_id(&mut self, expr: &ExprId, pos: &Pos) -> Result<Type, Error> {
         match self.symtable.get(&expr.id) {
-            Some(ty) => Ok(*ty),
+            Some(sym) => Ok(sym.ty.clone()),
             None => Err(Error::UndeclaredVariable(*pos, expr.id.clone())),
         }
     }
 
-    fn tc_expr_negate(&mut self, expr: &ExprNegate) -> Result<Type, Error> {
-        self.tc_expr(&expr.expr)
+    // The return type of the function, after checking the arguments
+    // like assignments to the parameters.
+    fn tc_call(&mut self, call: &ExprCall, pos: Pos) -> Result<Option<Type>, Error> {
+        let (params, ret) = match self.functions.get(&call.id) {
+            Some(sig) => (sig.params.clone(), sig.ret.clone()),
+            None => return Err(Error::UndeclaredFunction(pos, call.id.clone())),
+        };
+        if call.args.len() != params.len() {
+            return Err(Error::ArgumentCount(pos, call.id.clone(), params.len(), call.args.len()));
+        }
+        for (arg, ty) in call.args.iter().zip(params) {
+            let arg_ty = try!(self.tc_expr(arg));
+            if !TypeChecker::assignable(&ty, &arg_ty) {
+                return Err(Error::UnexpectedType { pos: arg.pos, expected: ty, actual: arg_ty });
+            }
+        }
+        Ok(ret)
+    }
+
+    // The operands of `and`, `or` and `not` are bools, even outside
+    // of strict mode.
+    fn tc_bool_operand(&mut self, expr: &Expr) -> Result<(), Error> {
+        let ty = try!(self.tc_expr(expr));
+        TypeChecker::bool_operand(expr, ty)
+    }
+
+    fn bool_operand(expr: &Expr, ty: Type) -> Result<(), Error> {
+        match ty {
+            Type::Bool => Ok(()),
+            ty => Err(Error::UnexpectedType { pos: expr.pos, expected: Type::Bool, actual: ty }),
+        }
+    }
+
+    fn tc_expr_negate(&mut self, expr: &ExprNegate, pos: &Pos) -> Result<Type, Error> {
+        match try!(self.tc_expr(&expr.expr)) {
+            t @ Type::Bool | t @ Type::Record(_) | t @ Type::Tuple(_) => Err(Error::IllTypedUnop { pos: *pos, op: "-", ty: t }),
+            t => Ok(t),
+        }
+    }
+
+    // A chain of operators is checked from its leftmost operand up,
+    // in a loop (see `Expr::left_spine`); each operator is checked
+    // once both of its operands are.
+    fn tc_chain(&mut self, expr: &Expr) -> Result<Type, Error> {
+        let (spine, leftmost) = expr.left_spine();
+        let mut t1 = try!(self.tc_expr(leftmost));
+        for e in spine.into_iter().rev() {
+            let ty = match e.expr {
+                Expr_::Binop(ref expr_) => {
+                    let t2 = try!(self.tc_expr(&expr_.expr2));
+                    try!(TypeChecker::binop_type(expr_.op, t1, t2, &e.pos))
+                }
+                Expr_::Logic(ref expr_) => {
+                    try!(TypeChecker::bool_operand(&expr_.expr1, t1));
+                    try!(self.tc_bool_operand(&expr_.expr2));
+                    Type::Bool
+                }
+                _ => unreachable!(),
+            };
+            self.expr_table.insert(e.node_id, ty.clone());
+            t1 = ty;
+        }
+        Ok(t1)
     }
 
-    fn tc_expr_binop(&mut self, expr: &ExprBinop, pos: &Pos) -> Result<Type, Error> {
-        let t1 = try!(self.tc_expr(&expr.expr1));
-        let t2 = try!(self.tc_expr(&expr.expr2));
+    fn binop_type(op: Binop, t1: Type, t2: Type, pos: &Pos) -> Result<Type, Error> {
+        // Comparisons apply to two numbers, two strings (compared
+        // lexicographically) or, for == and !=, two bools.
+        if op.is_comparison() {
+            return match (op, t1, t2) {
+                (_, ref t1, ref t2) if Type::widen(t1, t2).is_some() => Ok(Type::Bool),
+                (_, Type::String, Type::String) |
+                (Binop::Eq, Type::Bool, Type::Bool) |
+                (Binop::Ne, Type::Bool, Type::Bool) => Ok(Type::Bool),
+                (op, t1, t2) => Err(Error::IllTypedBinop { pos: *pos, op: op, lhs: t1, rhs: t2 }),
+            };
+        }
 
-        match (expr.op, t1, t2) {
-            (_, Type::Int, Type::Int) => Ok(Type::Int),
-            (_, Type::Int, Type::Float) => Ok(Type::Float),
-            (_, Type::Float, Type::Int) => Ok(Type::Float),
-            (_, Type::Float, Type::Float) => Ok(Type::Float),
+        if let Some(ty) = Type::widen(&t1, &t2) {
+            return Ok(ty);
+        }
+        match (op, t1, t2) {
             (Binop::Add, Type::String, Type::String) => Ok(Type::String),
             (Binop::Sub, Type::String, Type::String) => Ok(Type::String),
             (op, t1, t2) => Err(Error::IllTypedBinop {
@@ -1,10 +1,57 @@
 use std::fmt;
+use std::rc::Rc;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Type {
+    // int and float are 64 bits wide; int64 and float64 are other
+    // names for them.
     Int,
     Float,
+    Int32,
+    Float32,
     String,
+    Bool,
+    // Records are compared by name.  The name is shared so that types
+    // stay cheap to clone.
+    Record(Rc<str>),
+    // Tuples have at least two elements; their element lists are
+    // shared like record names.
+    Tuple(Rc<[Type]>),
+}
+
+impl Type {
+    pub fn record(name: &str) -> Type {
+        Type::Record(Rc::from(name))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        *self == Type::Int || *self == Type::Int32
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            Type::Int | Type::Float | Type::Int32 | Type::Float32 => true,
+            _ => false,
+        }
+    }
+
+    // The type of arithmetic on numbers of types `t1` and `t2`: the
+    // narrowest type to which both widen without changing their kind
+    // of precision, e.g. int32 and float32 give float32 but int and
+    // float32 give float.  None if either type is not a number.
+    pub fn widen(t1: &Type, t2: &Type) -> Option<Type> {
+        match (t1, t2) {
+            _ if !t1.is_numeric() || !t2.is_numeric() => None,
+            _ if t1 == t2 => Some(t1.clone()),
+            (&Type::Int32, &Type::Int) | (&Type::Int, &Type::Int32) => Some(Type::Int),
+            (&Type::Int32, &Type::Float32) | (&Type::Float32, &Type::Int32) => Some(Type::Float32),
+            _ => Some(Type::Float),
+        }
+    }
+
+    pub fn tuple(elements: &[Type]) -> Type {
+        Type::Tuple(Rc::from(elements))
+    }
 }
 
 impl fmt::Display for Type {
@@ -12,7 +59,18 @@ impl fmt::Display for Type {
         match *self {
             Type::Int => write!(f, "int"),
             Type::Float => write!(f, "float"),
+            Type::Int32 => write!(f, "int32"),
+            Type::Float32 => write!(f, "float32"),
             Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Record(ref name) => write!(f, "{}", name),
+            Type::Tuple(ref elements) => {
+                try!(write!(f, "("));
+                for (i, ty) in elements.iter().enumerate() {
+                    try!(write!(f, "{}{}", if i == 0 { "" } else { ", " }, ty));
+                }
+                write!(f, ")")
+            }
         }
     }
 }
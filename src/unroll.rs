@@ -0,0 +1,148 @@
+// Trip counts of counting loops, for loop unrolling.
+//
+// A loop can be unrolled when it has the shape
+//
+//     while i < END {
+//         ...
+//         i = i + STEP;
+//     }
+//
+// where `i` holds an integer constant when the loop is entered, END
+// and STEP are constant expressions (whose variables, if any, hold
+// constants that the body does not change), the comparison is one of
+// <, <=, > and >= (with `i` on either side), and the body assigns `i`
+// nowhere else.  The values of variables come from the constant
+// propagation of dataflow.rs.  The number of iterations is then known
+// at compile time, and the C backend repeats the body instead of
+// emitting a loop.
+
+use std::collections::HashMap;
+
+use ast::*;
+use cfg::{Cfg, Node};
+use consteval;
+use dataflow::{self, ConstantPropagation, Lattice, Constants};
+use interp::{self, Value};
+
+// The constants known when each while loop of `program`, functions
+// included, is entered, by node id.
+pub fn loop_constants(program: &Program) -> HashMap<u64, HashMap<String, Value>> {
+    let mut result = HashMap::new();
+    body_loop_constants(&Cfg::new(program), &mut result);
+    for func in &program.funcs {
+        body_loop_constants(&Cfg::function(func), &mut result);
+    }
+    result
+}
+
+fn body_loop_constants(cfg: &Cfg, result: &mut HashMap<u64, HashMap<String, Value>>) {
+    let solution = dataflow::solve(cfg, &ConstantPropagation);
+    for (i, node) in cfg.nodes.iter().enumerate() {
+        if let Node::Cond(stmt @ &Stmt::While(_)) = *node {
+            // The other predecessors are the ends of the body.
+            let entering = cfg.preds[i].iter().filter(|&&p| p < i)
+                .fold(Constants::bottom(), |acc, &p| acc.join(&solution.after[p]));
+            if let Some(constants) = entering.0 {
+                result.insert(stmt.node_id(), constants);
+            }
+        }
+    }
+}
+
+// The number of iterations of `stmt`, if it is a counting loop that
+// runs at most `max` times when entered with `constants`.
+pub fn trip_count(constants: &HashMap<String, Value>, stmt: &StmtWhile, max: usize) -> Option<usize> {
+    // END and STEP may only use the constants that the body keeps.
+    let stable: HashMap<String, Value> = constants.iter()
+        .filter(|&(id, _)| !stmt.stmts.iter().any(|s| assigns(s, id)))
+        .map(|(id, value)| (id.clone(), value.clone()))
+        .collect();
+    let (cond_op, bound, counter_first, id) = match stmt.expr.expr {
+        Expr_::Binop(ref binop) => match (&binop.expr1.expr, &binop.expr2.expr) {
+            (&Expr_::Id(ref id), _) => (binop.op, constant(&binop.expr2, &stable), true, &id.id[..]),
+            (_, &Expr_::Id(ref id)) => (binop.op, constant(&binop.expr1, &stable), false, &id.id[..]),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let bound = match (cond_op, bound) {
+        (Binop::Lt, Some(v)) | (Binop::Le, Some(v)) | (Binop::Gt, Some(v)) | (Binop::Ge, Some(v)) => v,
+        _ => return None,
+    };
+    let start = match constants.get(id) {
+        Some(v @ &Value::Int(_)) | Some(v @ &Value::Int32(_)) => v.clone(),
+        _ => return None,
+    };
+
+    let step = match stmt.stmts.last() {
+        Some(&Stmt::Assign(ref assign)) if assign.id == id && assign.fields.is_empty() => assign,
+        _ => return None,
+    };
+    let (step_op, step_value) = match step.expr.expr {
+        Expr_::Binop(ref binop) if is_id(&binop.expr1, id) => {
+            match (binop.op, constant(&binop.expr2, &stable)) {
+                (Binop::Add, Some(v)) => (Binop::Add, v),
+                (Binop::Sub, Some(v)) => (Binop::Sub, v),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if stmt.stmts[..stmt.stmts.len() - 1].iter().any(|s| assigns(s, id)) {
+        return None;
+    }
+
+    // Run the loop on the counter alone; any error (such as an overflow)
+    // is left for run time.
+    let pos = stmt.pos;
+    let mut value = start;
+    let mut count = 0;
+    loop {
+        let test = if counter_first {
+            interp::eval_binop(cond_op, value.clone(), bound.clone(), pos)
+        } else {
+            interp::eval_binop(cond_op, bound.clone(), value.clone(), pos)
+        };
+        match test {
+            Ok(Value::Bool(true)) => {}
+            Ok(Value::Bool(false)) => return Some(count),
+            _ => return None,
+        }
+        count += 1;
+        if count > max {
+            return None;
+        }
+        value = match interp::eval_binop(step_op, value, step_value.clone(), pos) {
+            Ok(v) => v,
+            Err(_) => return None,
+        };
+    }
+}
+
+// Whether `expr` is the variable `id`.
+pub fn is_id(expr: &Expr, id: &str) -> bool {
+    match expr.expr {
+        Expr_::Id(ref expr_) => expr_.id == id,
+        _ => false,
+    }
+}
+
+// The value of `expr` if it only uses literals and `constants`.
+pub fn constant(expr: &Expr, constants: &HashMap<String, Value>) -> Option<Value> {
+    consteval::eval_with(expr, constants).unwrap_or(None)
+}
+
+// Whether `stmt` may change the variable `id`.
+pub fn assigns(stmt: &Stmt, id: &str) -> bool {
+    match *stmt {
+        Stmt::Read(ref stmt_) => stmt_.ids.iter().any(|x| x == id),
+        Stmt::Print(_) | Stmt::Exit(_) | Stmt::Call(_) | Stmt::Return(_) | Stmt::Error(_) => false,
+        Stmt::Assign(ref stmt_) => stmt_.id == id,
+        Stmt::Destructure(ref stmt_) => stmt_.targets.iter().any(|target| target.id == id),
+        Stmt::If(ref stmt_) => {
+            stmt_.then_stmts.iter().chain(&stmt_.else_stmts).any(|s| assigns(s, id))
+        }
+        Stmt::While(ref stmt_) => stmt_.stmts.iter().any(|s| assigns(s, id)),
+        Stmt::For(ref stmt_) => stmt_.id == id || stmt_.stmts.iter().any(|s| assigns(s, id)),
+    }
+}
@@ -0,0 +1,68 @@
+// Inputs shared by the tests: the example and conformance programs,
+// programs at the limits of the parser, and pseudo-random numbers
+// (xorshift, seeded, so that a failure can be reproduced).
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+// The programs of examples/ and conformance/, with their paths.
+pub fn corpus() -> Vec<(String, String)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut programs = Vec::new();
+    for dir in &["examples", "conformance"] {
+        let mut paths: Vec<_> = fs::read_dir(root.join(dir)).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("min"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let mut source = String::new();
+            fs::File::open(&path).unwrap().read_to_string(&mut source).unwrap();
+            programs.push((path.display().to_string(), source));
+        }
+    }
+    assert!(!programs.is_empty());
+    programs
+}
+
+// A chain of `n` additions, which prints n + 1.
+pub fn deep_chain(n: usize) -> String {
+    format!("var x: int;\nx = 1{};\nprint x;\n", " + 1".repeat(n))
+}
+
+// A concatenation of n + 1 strings: "a" and n times "b".
+pub fn long_concat(n: usize) -> String {
+    format!("var s: string;\ns = \"a\"{};\nprint s;\n", " + \"b\"".repeat(n))
+}
+
+// `expr` in `n` pairs of parentheses, assigned to an int and printed.
+pub fn deep_parens(n: usize, expr: &str) -> String {
+    format!("var x: int;\nx = {}{}{};\nprint x;\n", "(".repeat(n), expr, ")".repeat(n))
+}
+
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // A number below `n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
@@ -0,0 +1,50 @@
+// The concrete syntax tree reproduces its source byte for byte, and
+// converts to the AST that the parser builds from the same source.
+
+extern crate minilang;
+
+mod common;
+
+use minilang::cst;
+use minilang::parser::{Parser, Syntax};
+use minilang::scanner;
+
+use common::{corpus, Rng};
+
+fn round_trip(name: &str, source: &str) {
+    let tree = cst::parse(source, Syntax::Classic).unwrap_or_else(|err| panic!("{}: {}", name, err));
+    assert_eq!(tree.to_source(), source, "{}", name);
+    let ast = Parser::new(scanner::tokenize(source).unwrap()).parse_program().unwrap();
+    assert_eq!(format!("{:?}", tree.to_ast().unwrap()), format!("{:?}", ast), "{}", name);
+}
+
+#[test]
+fn corpus_round_trips() {
+    for (name, source) in corpus() {
+        round_trip(&name, &source);
+    }
+}
+
+// The programs of the corpus with their whitespace and comments
+// replaced at random.  Tokens that were not separated stay together,
+// so that they are read the same.
+#[test]
+fn generated_round_trips() {
+    const TRIVIA: [&str; 6] = [" ", "\t", "\n", "   ", "\n\n  ", " # a comment\n"];
+    let mut rng = Rng::new(670);
+    for (name, source) in corpus() {
+        let tokens = cst::attach_trivia(&source, scanner::tokenize(&source).unwrap());
+        for _ in 0..10 {
+            let mut generated = String::new();
+            for tok in &tokens {
+                if !tok.leading.is_empty() {
+                    for _ in 0..rng.below(3) + 1 {
+                        generated.push_str(rng.choose(&TRIVIA));
+                    }
+                }
+                generated.push_str(&tok.text);
+            }
+            round_trip(&name, &generated);
+        }
+    }
+}
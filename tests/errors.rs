@@ -0,0 +1,23 @@
+// How errors read once they are attributed to a file.
+
+extern crate minilang;
+
+use minilang::error::Error;
+use minilang::pos::Pos;
+
+fn in_file(err: Error) -> String {
+    Error::InFile("tests.toml".to_string(), Box::new(err)).to_string()
+}
+
+#[test]
+fn in_file_separates_path_and_message() {
+    let pos = Pos { line: 3, col: 7 };
+    assert_eq!(in_file(Error::UndeclaredVariable(pos, "x".to_string())),
+               format!("tests.toml:{}", Error::UndeclaredVariable(pos, "x".to_string())));
+    assert_eq!(in_file(Error::InvalidManifest(4, "unknown key 'x'".to_string())),
+               "tests.toml:4: Invalid manifest: unknown key 'x'");
+    assert_eq!(in_file(Error::InvalidManifest(0, "no tests".to_string())),
+               "tests.toml: Invalid manifest: no tests");
+    assert_eq!(in_file(Error::InvalidJson(12, "expected ','".to_string())),
+               "tests.toml: Invalid JSON at byte 12: expected ','");
+}
@@ -0,0 +1,46 @@
+// The warnings of the type checking pass, on code at the top level and
+// in function bodies alike.
+
+extern crate minilang;
+
+use minilang::Compiler;
+
+// The lines and names of the warnings, in source order.
+fn lints(source: &str) -> Vec<(usize, &'static str)> {
+    let compilation = Compiler::new().source(source).compile();
+    assert!(compilation.error.is_none(), "{:?}", compilation.error);
+    let mut lints: Vec<(usize, &'static str)> = compilation.diagnostics.iter()
+        .filter_map(|d| d.lint.map(|lint| (d.pos.line, lint)))
+        .collect();
+    lints.sort();
+    lints
+}
+
+#[test]
+fn lints_in_functions() {
+    let source = "\
+var j: int = 0;
+var f: float;
+function g(n: int): float
+  var k: int = n;
+  var h: float = 9007199254740993;
+  while k < 10 do
+    k = k - 1;
+  done
+  h = 9007199254740993;
+  return 9007199254740993;
+end
+while j < 10 do
+  j = j - 1;
+done
+f = 9007199254740993;
+";
+    assert_eq!(lints(source), vec![
+        (5, "lossy-conversion"),
+        (6, "endless-loop"),
+        (9, "lossy-conversion"),
+        (10, "lossy-conversion"),
+        (12, "endless-loop"),
+        (15, "lossy-conversion"),
+    ]);
+}
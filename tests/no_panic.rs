@@ -0,0 +1,150 @@
+// The front end returns an error, rather than panicking, hanging or
+// overflowing the stack, on arbitrary input: random bytes, random
+// sequences of tokens, the truncated and corrupted programs of the
+// corpus, and programs at and past the limits of the parser.
+
+extern crate minilang;
+
+mod common;
+
+use minilang::cst;
+use minilang::parser::{Parser, Syntax, DEFAULT_MAX_CHAIN, DEFAULT_MAX_DEPTH};
+use minilang::scanner;
+use minilang::token::Token;
+use minilang::typecheck::TypeChecker;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use common::{corpus, deep_chain, deep_parens, long_concat, Rng};
+
+// The longest that one input may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn front_end(source: &str) {
+    let tokens = match scanner::tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(_) => return,
+    };
+    for &syntax in &[Syntax::Classic, Syntax::Braces] {
+        parse(tokens.clone(), syntax);
+        let mut parser = Parser::new(tokens.clone());
+        parser.set_syntax(syntax);
+        let _ = parser.parse_program_recovering();
+        let _ = cst::parse(source, syntax);
+    }
+}
+
+fn parse(tokens: Vec<Token>, syntax: Syntax) {
+    let mut parser = Parser::new(tokens);
+    parser.set_syntax(syntax);
+    if let Ok(program) = parser.parse_program() {
+        let _ = TypeChecker::new().tc_program(&program);
+    }
+}
+
+// Run `check` on each input on a thread with the stack that std gives
+// spawned threads, and fail on the first input that panics or takes
+// longer than TIMEOUT.  An input that overflows the stack aborts the
+// test.
+fn check_all<T: Send + 'static, F: Fn(T) + Send + 'static>(inputs: Vec<(String, T)>, check: F) {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (name, input) in inputs {
+            sender.send(Some(name)).unwrap();
+            check(input);
+        }
+        sender.send(None).unwrap();
+    });
+    let mut last = None;
+    loop {
+        match receiver.recv_timeout(TIMEOUT) {
+            Ok(Some(name)) => last = Some(name),
+            Ok(None) => return,
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("{:?} panics", last),
+            Err(mpsc::RecvTimeoutError::Timeout) => panic!("{:?} takes too long", last),
+        }
+    }
+}
+
+#[test]
+fn random_bytes() {
+    let mut rng = Rng::new(707);
+    let inputs = (0..2000)
+        .map(|i| {
+            let bytes: Vec<u8> = (0..rng.below(200)).map(|_| rng.next() as u8).collect();
+            (format!("random input {}", i), String::from_utf8_lossy(&bytes).into_owned())
+        })
+        .collect();
+    check_all(inputs, |source: String| front_end(&source));
+}
+
+#[test]
+fn truncated_programs() {
+    let mut inputs = Vec::new();
+    for (name, source) in corpus() {
+        for (i, _) in source.char_indices() {
+            inputs.push((format!("{} up to byte {}", name, i), source[..i].to_string()));
+        }
+    }
+    check_all(inputs, |source: String| front_end(&source));
+}
+
+// The programs of the corpus with a few characters replaced by
+// characters that matter to the scanner and the parser.
+#[test]
+fn corrupted_programs() {
+    const CHARS: [char; 16] = ['(', ')', '{', '}', '"', '$', '#', ';', ':', ',', '.', '-', '=', '<', '\n', 'x'];
+    let mut rng = Rng::new(7070);
+    let mut inputs = Vec::new();
+    for (name, source) in corpus() {
+        for i in 0..100 {
+            let mut chars: Vec<char> = source.chars().collect();
+            for _ in 0..rng.below(4) + 1 {
+                let at = rng.below(chars.len());
+                chars[at] = rng.choose(&CHARS);
+            }
+            inputs.push((format!("{} corrupted {}", name, i), chars.into_iter().collect()));
+        }
+    }
+    check_all(inputs, |source: String| front_end(&source));
+}
+
+// Long chains of operators, deep parentheses and long concatenations,
+// within the limits and past them, and parentheses around a chain.
+#[test]
+fn deep_programs() {
+    let mut inputs = Vec::new();
+    for &n in &[DEFAULT_MAX_CHAIN / 2, DEFAULT_MAX_CHAIN, DEFAULT_MAX_CHAIN + 1, 4 * DEFAULT_MAX_CHAIN] {
+        inputs.push((format!("chain of {}", n), deep_chain(n)));
+        inputs.push((format!("concat of {}", n), long_concat(n)));
+    }
+    let chain = format!("1{}", " + 1".repeat(DEFAULT_MAX_CHAIN));
+    for &n in &[DEFAULT_MAX_DEPTH / 2, DEFAULT_MAX_DEPTH - 2, DEFAULT_MAX_DEPTH, 100 * DEFAULT_MAX_DEPTH] {
+        inputs.push((format!("{} parentheses", n), deep_parens(n, "1")));
+        inputs.push((format!("chain in {} parentheses", n), deep_parens(n, &chain)));
+    }
+    check_all(inputs, |source: String| front_end(&source));
+}
+
+// The parser takes any sequence of tokens, including those that the
+// scanner never returns, such as a sequence without Eof.
+#[test]
+fn random_tokens() {
+    let mut tokens = Vec::new();
+    for (_, source) in corpus() {
+        tokens.extend(scanner::tokenize(&source).unwrap());
+    }
+    let mut rng = Rng::new(77);
+    let inputs = (0..2000)
+        .map(|i| {
+            let sequence: Vec<Token> = (0..rng.below(100)).map(|_| tokens[rng.below(tokens.len())].clone()).collect();
+            (format!("random tokens {}", i), sequence)
+        })
+        .collect();
+    check_all(inputs, |tokens: Vec<Token>| {
+        parse(tokens.clone(), Syntax::Classic);
+        parse(tokens, Syntax::Braces);
+    });
+}
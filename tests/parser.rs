@@ -0,0 +1,53 @@
+// The limits of the parser: long chains of operators are not nesting,
+// and the precision of `print` is bounded.
+
+extern crate minilang;
+
+use minilang::Compiler;
+use minilang::error::Error;
+use minilang::parser::Parser;
+use minilang::scanner;
+
+fn sum(terms: usize) -> String {
+    let terms: Vec<&str> = (0..terms).map(|_| "1").collect();
+    format!("var x: int = {};\nprint x;\n", terms.join(" + "))
+}
+
+#[test]
+fn long_flat_sum() {
+    let compilation = Compiler::new().source(sum(300)).compile();
+    assert!(compilation.error.is_none(), "{:?}", compilation.error);
+    assert!(compilation.diagnostics.is_empty());
+}
+
+#[test]
+fn too_long_chain() {
+    let tokens = scanner::tokenize(&sum(10000)).unwrap();
+    match Parser::new(tokens).parse_program() {
+        Err(Error::ChainTooLong(_, 4096)) => {}
+        result => panic!("{:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn too_deep() {
+    let source = format!("var x: int = {}1{};", "(".repeat(100), ")".repeat(100));
+    let tokens = scanner::tokenize(&source).unwrap();
+    match Parser::new(tokens).parse_program() {
+        Err(Error::NestingTooDeep(_, 64)) => {}
+        result => panic!("{:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn too_large_precision() {
+    let tokens = scanner::tokenize("print 1.5 : 100;").unwrap();
+    assert!(Parser::new(tokens).parse_program().is_ok());
+    for precision in &["101", "4000000000", "99999999999999999999999"] {
+        let tokens = scanner::tokenize(&format!("print 1.5 : {};", precision)).unwrap();
+        match Parser::new(tokens).parse_program() {
+            Err(Error::PrecisionTooLarge(pos, ref s)) if pos.col == 13 && s == precision => {}
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// Recursion up to the limit on nested calls runs on the stack of an
+// ordinary thread, and a recursion past it stops with an error.
+
+extern crate minilang;
+
+use minilang::interp::{Limits, MAX_CALL_DEPTH};
+use minilang::{eval, Env, Value};
+
+use std::thread;
+
+// A program that recurses `depth` calls deep, through a body with some
+// nesting.
+fn recursion(depth: usize) -> String {
+    format!("var r: int;
+function f(n: int): int
+  var i: int = 0;
+  while i < 1 do
+    if n > 1 then
+      if i == 0 then
+        return f(n - 1) + 1;
+      endif
+    endif
+    i = i + 1;
+  done
+  return 1;
+end
+r = f({});
+", depth)
+}
+
+fn run(depth: usize, limits: Limits) -> Result<i64, String> {
+    let source = recursion(depth);
+    thread::spawn(move || {
+        let mut env = Env::new();
+        env.set_limits(limits);
+        try!(eval(&source, &mut env).map_err(|e| e.to_string()));
+        match env.get("r") {
+            Some(&Value::Int(n)) => Ok(n),
+            r => Err(format!("unexpected result {:?}", r)),
+        }
+    }).join().unwrap()
+}
+
+#[test]
+fn recursion_up_to_the_limit_runs() {
+    assert_eq!(run(MAX_CALL_DEPTH, Limits::default()), Ok(MAX_CALL_DEPTH as i64));
+}
+
+#[test]
+fn recursion_past_the_limit_is_an_error() {
+    let err = run(MAX_CALL_DEPTH + 1, Limits::default()).unwrap_err();
+    assert!(err.contains("stack overflow"), "{}", err);
+}
+
+#[test]
+fn recursion_past_a_lower_limit_is_an_error() {
+    let limits = Limits::default().max_call_depth(5);
+    assert_eq!(run(5, limits), Ok(5));
+    assert!(run(6, limits).unwrap_err().contains("more than 5 nested calls"));
+}
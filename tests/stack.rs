@@ -0,0 +1,64 @@
+// Programs at the limits of the parser compile and run on the stack of
+// an ordinary thread, even in a debug build; programs past the limits
+// are rejected with an error rather than overflowing it.
+
+extern crate minilang;
+
+mod common;
+
+use minilang::parser::{DEFAULT_MAX_CHAIN, DEFAULT_MAX_DEPTH};
+use minilang::{eval, Compiler, Env};
+
+use std::thread;
+
+// Compile `source`, then run it, on a thread spawned with the defaults
+// of std.  The result is the output of the run, or the messages of the
+// errors.
+fn compile_and_run(source: String) -> Result<String, String> {
+    thread::spawn(move || {
+        let compilation = Compiler::new().source(source.clone()).compile();
+        if compilation.error.is_some() {
+            let messages: Vec<String> = compilation.diagnostics.iter().map(|d| d.message.clone()).collect();
+            return Err(messages.join("\n"));
+        }
+        let mut env = Env::new();
+        try!(eval(&source, &mut env).map_err(|e| e.to_string()));
+        Ok(env.output().to_string())
+    }).join().unwrap()
+}
+
+#[test]
+fn longest_chain() {
+    let output = compile_and_run(common::deep_chain(DEFAULT_MAX_CHAIN)).unwrap();
+    assert_eq!(output, format!("{}\n", DEFAULT_MAX_CHAIN + 1));
+}
+
+#[test]
+fn longest_concat() {
+    let output = compile_and_run(common::long_concat(DEFAULT_MAX_CHAIN)).unwrap();
+    assert_eq!(output, format!("a{}\n", "b".repeat(DEFAULT_MAX_CHAIN)));
+}
+
+// The assignment and its expression take two levels.
+#[test]
+fn deepest_parens() {
+    let output = compile_and_run(common::deep_parens(DEFAULT_MAX_DEPTH - 2, "1")).unwrap();
+    assert_eq!(output, "1\n");
+}
+
+#[test]
+fn longest_chain_in_deepest_parens() {
+    let chain = format!("1{}", " + 1".repeat(DEFAULT_MAX_CHAIN));
+    let output = compile_and_run(common::deep_parens(DEFAULT_MAX_DEPTH - 2, &chain)).unwrap();
+    assert_eq!(output, format!("{}\n", DEFAULT_MAX_CHAIN + 1));
+}
+
+#[test]
+fn past_the_limits() {
+    let err = compile_and_run(common::deep_chain(4 * DEFAULT_MAX_CHAIN)).unwrap_err();
+    assert!(err.contains("Expression too long"), "{}", err);
+    let err = compile_and_run(common::long_concat(4 * DEFAULT_MAX_CHAIN)).unwrap_err();
+    assert!(err.contains("Expression too long"), "{}", err);
+    let err = compile_and_run(common::deep_parens(4 * DEFAULT_MAX_DEPTH, "1")).unwrap_err();
+    assert!(err.contains("nested too deeply"), "{}", err);
+}